@@ -0,0 +1,40 @@
+#!/bin/bash
+# Checks that concat and zip output run in constant memory: generates a source tree with
+# a few multi-GB text files and reports the peak RSS of each run. Peak memory should stay
+# in the low megabytes regardless of SIZE_MB.
+#
+# Usage: benches/memory.sh [SIZE_MB] [FILES]
+set -euo pipefail
+
+SIZE_MB=${1:-2048}
+FILES=${2:-3}
+BIN=${LM_BIN:-target/release/lm}
+WORK=$(mktemp -d)
+trap 'rm -rf "$WORK"' EXIT
+
+if [ ! -x "$BIN" ]; then
+    cargo build --release
+fi
+
+echo "Generating $FILES x ${SIZE_MB}MB files in $WORK"
+mkdir -p "$WORK/src"
+for i in $(seq 1 "$FILES"); do
+    (yes "fn line_$i() { let value = \"some moderately long line of source text\"; }" || true) \
+        | head -c "$((SIZE_MB * 1024 * 1024))" > "$WORK/src/big_$i.rs"
+done
+
+peak_rss() {
+    if [ "$(uname)" = "Darwin" ]; then
+        /usr/bin/time -l "$@" 2>&1 >/dev/null | awk '/maximum resident set size/ { printf "%.1f MB\n", $1 / 1048576 }'
+    else
+        /usr/bin/time -v "$@" 2>&1 >/dev/null | awk -F: '/Maximum resident set size/ { printf "%.1f MB\n", $2 / 1024 }'
+    fi
+}
+
+CONFIG="$WORK/config.yml"
+printf 'allowed_extensions: [rs]\ntree: false\n' > "$CONFIG"
+
+echo -n "concat peak RSS: "
+peak_rss "$BIN" "$WORK" -c "$CONFIG" --concat
+echo -n "zip peak RSS:    "
+peak_rss "$BIN" "$WORK" -c "$CONFIG" --zip
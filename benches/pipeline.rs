@@ -0,0 +1,136 @@
+//! Throughput of the pipeline stages (walk + filter, naming, copy, zip) on generated
+//! repositories. Run with `cargo bench`; set `LMPREP_BENCH_LARGE=1` to also generate the
+//! 100k-file repository, which takes a while to create.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use lmprep::{Config, FileProcessor};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A throwaway source tree under the temp directory, removed on drop.
+struct SyntheticRepo {
+    name: &'static str,
+    root: PathBuf,
+    included_files: u64,
+    included_bytes: u64,
+}
+
+impl SyntheticRepo {
+    /// Creates `files` files spread over directories nested `depth` levels deep. Every fourth
+    /// file has an extension that is filtered out and every tenth directory is gitignored, so
+    /// the filters have real work to do.
+    fn generate(name: &'static str, files: usize, depth: usize, file_size: usize) -> Self {
+        let root = env::temp_dir().join(format!("lmprep-bench-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(".gitignore"), "ignored_*/\n").unwrap();
+
+        let line = "fn main() { println!(\"hello\"); }\n";
+        let contents = line.repeat(file_size / line.len() + 1);
+        let contents = &contents[..file_size];
+        let dirs = (files / 100).max(1);
+
+        let mut included_files = 0;
+        let mut included_bytes = 0;
+        for i in 0..files {
+            let dir_index = i % dirs;
+            let mut dir = root.clone();
+            for level in 0..depth {
+                dir.push(format!("level{}_{}", level, dir_index % (level + 2)));
+            }
+            let ignored = dir_index % 10 == 9;
+            dir.push(if ignored { format!("ignored_{}", dir_index) } else { format!("dir_{}", dir_index) });
+            fs::create_dir_all(&dir).unwrap();
+
+            let extension = if i % 4 == 3 { "bin" } else { "rs" };
+            fs::write(dir.join(format!("file_{}.{}", i, extension)), contents).unwrap();
+            if !ignored && extension == "rs" {
+                included_files += 1;
+                included_bytes += file_size as u64;
+            }
+        }
+
+        Self { name, root, included_files, included_bytes }
+    }
+
+    fn source(&self) -> &str {
+        self.root.to_str().expect("temp dir is valid UTF-8")
+    }
+}
+
+impl Drop for SyntheticRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn bench_config() -> Config {
+    Config {
+        allowed_extensions: vec!["rs".to_string()],
+        subfolder: "lmprep-bench-output".to_string(),
+        tree: false,
+        ..Config::default()
+    }
+}
+
+fn repos() -> Vec<SyntheticRepo> {
+    let mut repos = vec![
+        SyntheticRepo::generate("10k-files", 10_000, 2, 1024),
+        SyntheticRepo::generate("deep-nesting", 2_000, 40, 1024),
+        SyntheticRepo::generate("large-files", 8, 1, 32 * 1024 * 1024),
+    ];
+    if env::var_os("LMPREP_BENCH_LARGE").is_some() {
+        repos.push(SyntheticRepo::generate("100k-files", 100_000, 3, 512));
+    }
+    repos
+}
+
+fn pipeline(c: &mut Criterion) {
+    let config = bench_config();
+
+    for repo in repos() {
+        let processor = FileProcessor::new(repo.source(), &config, false).unwrap();
+        let walked = processor.walk().unwrap();
+        assert_eq!(walked.files.len() as u64, repo.included_files);
+
+        let mut group = c.benchmark_group(repo.name);
+        group.sample_size(10).measurement_time(Duration::from_secs(20));
+
+        group.throughput(Throughput::Elements(repo.included_files));
+        group.bench_function(BenchmarkId::from_parameter("walk"), |b| {
+            b.iter(|| processor.walk().unwrap())
+        });
+        group.bench_function(BenchmarkId::from_parameter("names"), |b| {
+            b.iter(|| processor.collect_files(&walked).unwrap())
+        });
+
+        group.throughput(Throughput::Bytes(repo.included_bytes));
+        group.bench_function(BenchmarkId::from_parameter("copy"), |b| {
+            b.iter_batched(
+                || {
+                    processor.prepare_output_directory().unwrap();
+                    processor.collect_files(&walked).unwrap()
+                },
+                |files| processor.copy_files(files).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+        group.bench_function(BenchmarkId::from_parameter("zip"), |b| {
+            b.iter_batched(
+                || {
+                    processor.prepare_output_directory().unwrap();
+                    processor.collect_files(&walked).unwrap()
+                },
+                |files| processor.create_zip(files, None).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
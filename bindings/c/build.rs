@@ -0,0 +1,14 @@
+use std::env;
+use std::path::PathBuf;
+
+// Regenerates include/lmprep.h from the `extern "C"` functions in src/lib.rs.
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate lmprep.h")
+        .write_to_file(crate_dir.join("include/lmprep.h"));
+}
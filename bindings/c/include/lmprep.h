@@ -0,0 +1,40 @@
+#ifndef LMPREP_H
+#define LMPREP_H
+
+/* Generated by cbindgen from bindings/c/src/lib.rs, do not edit. */
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Concatenates the files selected from `source` (NULL for the current directory) into a
+// markdown document, using the config at `config_path` or the usual `.lmprep.yml` search if
+// NULL. Stores the length without the terminator in `out_len` if it is not NULL.
+//
+// Returns a buffer to release with `lmprep_free_buffer`, or NULL on error, in which case
+// `lmprep_last_error` describes the failure.
+//
+// # Safety
+//
+// `source` and `config_path` must be NULL or valid NUL-terminated strings, and `out_len`
+// must be NULL or point to writable memory.
+char *lmprep_pack_to_buffer(const char *source, const char *config_path, size_t *out_len);
+
+// Releases a buffer returned by `lmprep_pack_to_buffer`. NULL is ignored.
+//
+// # Safety
+//
+// `buffer` must come from `lmprep_pack_to_buffer` and must not be used or freed again.
+void lmprep_free_buffer(char *buffer);
+
+// Message of the last error on this thread, or NULL if no call has failed. The string
+// stays valid until the next failing call on the same thread.
+const char *lmprep_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LMPREP_H */
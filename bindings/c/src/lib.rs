@@ -0,0 +1,85 @@
+//! Minimal C API: pack a source tree into a buffer owned by lmprep, free it, and read the
+//! error message of the last failed call. All strings are NUL-terminated UTF-8.
+
+use lmprep::config;
+use lmprep::FileProcessor;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Borrows a C string argument; NULL maps to `None`.
+unsafe fn optional_str<'a>(s: *const c_char) -> anyhow::Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(CStr::from_ptr(s).to_str()?))
+}
+
+fn pack(source: Option<&str>, config_path: Option<&str>) -> anyhow::Result<CString> {
+    let config = config::load_config(&config_path.map(str::to_string))?;
+    let context = FileProcessor::new(source.unwrap_or("."), &config, false)?.pack()?;
+    Ok(CString::new(context.replace('\0', ""))?)
+}
+
+/// Concatenates the files selected from `source` (NULL for the current directory) into a
+/// markdown document, using the config at `config_path` or the usual `.lmprep.yml` search if
+/// NULL. Stores the length without the terminator in `out_len` if it is not NULL.
+///
+/// Returns a buffer to release with `lmprep_free_buffer`, or NULL on error, in which case
+/// `lmprep_last_error` describes the failure.
+///
+/// # Safety
+///
+/// `source` and `config_path` must be NULL or valid NUL-terminated strings, and `out_len`
+/// must be NULL or point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn lmprep_pack_to_buffer(
+    source: *const c_char,
+    config_path: *const c_char,
+    out_len: *mut usize,
+) -> *mut c_char {
+    let result = optional_str(source)
+        .and_then(|source| Ok((source, optional_str(config_path)?)))
+        .and_then(|(source, config_path)| pack(source, config_path));
+
+    match result {
+        Ok(context) => {
+            if !out_len.is_null() {
+                *out_len = context.as_bytes().len();
+            }
+            context.into_raw()
+        }
+        Err(e) => {
+            set_last_error(format!("{:#}", e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases a buffer returned by `lmprep_pack_to_buffer`. NULL is ignored.
+///
+/// # Safety
+///
+/// `buffer` must come from `lmprep_pack_to_buffer` and must not be used or freed again.
+#[no_mangle]
+pub unsafe extern "C" fn lmprep_free_buffer(buffer: *mut c_char) {
+    if !buffer.is_null() {
+        drop(CString::from_raw(buffer));
+    }
+}
+
+/// Message of the last error on this thread, or NULL if no call has failed. The string
+/// stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn lmprep_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
@@ -0,0 +1,6 @@
+/** Returns the selected files of `source` concatenated into a single markdown document. */
+export function pack(source: string, configPath?: string | undefined | null): string
+/** Returns the file tree of everything lmprep would include from `source`. */
+export function tree(source: string, configPath?: string | undefined | null): string
+/** Estimated number of tokens in `text`. */
+export function tokenCount(text: string): number
@@ -0,0 +1,16 @@
+// Loads the prebuilt binary for this platform, named by `napi build --platform`.
+const { join } = require('path');
+
+const suffixes = {
+  'linux-x64': 'linux-x64-gnu',
+  'win32-x64': 'win32-x64-msvc',
+  'darwin-x64': 'darwin-universal',
+  'darwin-arm64': 'darwin-universal',
+};
+
+const suffix = suffixes[`${process.platform}-${process.arch}`];
+if (!suffix) {
+  throw new Error(`lmprep has no prebuilt binary for ${process.platform} ${process.arch}`);
+}
+
+module.exports = require(join(__dirname, `lmprep.${suffix}.node`));
@@ -0,0 +1,40 @@
+//! Native Node.js module exposing lmprep's selection and concatenation, so editors and
+//! agent frameworks can prepare context without spawning `lm`.
+
+use lmprep::config::{self, Config};
+use lmprep::{tokens, FileProcessor};
+use napi::{Error, Result};
+use napi_derive::napi;
+
+fn to_js_err(e: anyhow::Error) -> Error {
+    Error::from_reason(format!("{:#}", e))
+}
+
+/// Loads `config_path` if given, otherwise searches for `.lmprep.yml` the same way `lm` does.
+fn load(config_path: Option<String>) -> Result<Config> {
+    config::load_config(&config_path).map_err(to_js_err)
+}
+
+/// Returns the selected files of `source` concatenated into a single markdown document.
+#[napi]
+pub fn pack(source: String, config_path: Option<String>) -> Result<String> {
+    let config = load(config_path)?;
+    FileProcessor::new(&source, &config, false)
+        .and_then(|processor| processor.pack())
+        .map_err(to_js_err)
+}
+
+/// Returns the file tree of everything lmprep would include from `source`.
+#[napi]
+pub fn tree(source: String, config_path: Option<String>) -> Result<String> {
+    let config = load(config_path)?;
+    FileProcessor::new(&source, &config, false)
+        .and_then(|processor| processor.tree())
+        .map_err(to_js_err)
+}
+
+/// Estimated number of tokens in `text`.
+#[napi]
+pub fn token_count(text: String) -> u32 {
+    tokens::estimate(&text).try_into().unwrap_or(u32::MAX)
+}
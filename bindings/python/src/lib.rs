@@ -0,0 +1,56 @@
+//! `lmprep._native`: the library API exposed to Python, so pipelines can prepare context
+//! in-process instead of running the `lm` binary.
+
+use lmprep::config::{self, Config};
+use lmprep::{tokens, FileProcessor};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", e))
+}
+
+/// Loads `config_path` if given, otherwise searches for `.lmprep.yml` the same way `lm` does.
+fn load(config_path: Option<String>) -> PyResult<Config> {
+    config::load_config(&config_path).map_err(to_py_err)
+}
+
+/// Returns `(source_path, output_name)` for every file lmprep would include.
+#[pyfunction]
+#[pyo3(signature = (source = ".", config_path = None))]
+fn collect(py: Python<'_>, source: &str, config_path: Option<String>) -> PyResult<Vec<(String, String)>> {
+    let config = load(config_path)?;
+    py.allow_threads(|| -> anyhow::Result<_> {
+        let processor = FileProcessor::new(source, &config, false)?;
+        let walked = processor.walk()?;
+        Ok(processor
+            .collect_files(&walked)?
+            .into_iter()
+            .map(|f| (f.path.to_string_lossy().into_owned(), f.output_name))
+            .collect())
+    })
+    .map_err(to_py_err)
+}
+
+/// Returns the selected files concatenated into a single markdown document.
+#[pyfunction]
+#[pyo3(signature = (source = ".", config_path = None))]
+fn pack(py: Python<'_>, source: &str, config_path: Option<String>) -> PyResult<String> {
+    let config = load(config_path)?;
+    py.allow_threads(|| FileProcessor::new(source, &config, false)?.pack())
+        .map_err(to_py_err)
+}
+
+/// Estimated number of tokens in `text`.
+#[pyfunction]
+fn token_count(text: &str) -> usize {
+    tokens::estimate(text)
+}
+
+#[pymodule]
+fn _native(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(collect, m)?)?;
+    m.add_function(wrap_pyfunction!(pack, m)?)?;
+    m.add_function(wrap_pyfunction!(token_count, m)?)?;
+    Ok(())
+}
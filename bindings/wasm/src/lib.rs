@@ -0,0 +1,75 @@
+//! WebAssembly entry points. The caller supplies the files (a virtual filesystem), so the
+//! same selection, naming, concatenation and token estimates as `lm` run in the browser.
+
+use lmprep::config::{self, Config};
+use lmprep::tokens;
+use lmprep::virtual_fs::{self, VirtualFile};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// A file as passed from JavaScript: `{ path: "src/main.rs", contents: "..." }`.
+#[derive(Deserialize)]
+struct InputFile {
+    path: String,
+    contents: String,
+}
+
+#[derive(Serialize)]
+struct Selected<'f> {
+    path: &'f str,
+    #[serde(rename = "outputName")]
+    output_name: String,
+}
+
+fn to_js_err(e: impl std::fmt::Display) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+/// Parses a `.lmprep.yml` document; `undefined` gives the default config.
+fn load(config_yaml: Option<String>) -> Result<Config, JsError> {
+    match config_yaml {
+        Some(yaml) => config::parse_config(&yaml).map_err(to_js_err),
+        None => Ok(Config::default()),
+    }
+}
+
+fn files(files: JsValue) -> Result<Vec<VirtualFile>, JsError> {
+    let files: Vec<InputFile> = serde_wasm_bindgen::from_value(files).map_err(to_js_err)?;
+    Ok(files
+        .into_iter()
+        .map(|f| VirtualFile { path: f.path, contents: f.contents.into_bytes() })
+        .collect())
+}
+
+/// Returns `[{ path, outputName }]` for every file lmprep would include.
+#[wasm_bindgen]
+pub fn select(input: JsValue, config_yaml: Option<String>) -> Result<JsValue, JsError> {
+    let config = load(config_yaml)?;
+    let files = files(input)?;
+    let selected: Vec<Selected> = virtual_fs::select(&files, &config)
+        .map_err(to_js_err)?
+        .into_iter()
+        .map(|(file, output_name)| Selected { path: &file.path, output_name })
+        .collect();
+    serde_wasm_bindgen::to_value(&selected).map_err(to_js_err)
+}
+
+/// Returns the file tree of the selected files.
+#[wasm_bindgen]
+pub fn tree(input: JsValue, config_yaml: Option<String>) -> Result<String, JsError> {
+    let config = load(config_yaml)?;
+    virtual_fs::render_tree(&files(input)?, &config).map_err(to_js_err)
+}
+
+/// Returns the selected files concatenated into a single markdown document.
+#[wasm_bindgen]
+pub fn pack(input: JsValue, config_yaml: Option<String>) -> Result<String, JsError> {
+    let config = load(config_yaml)?;
+    virtual_fs::pack(&files(input)?, &config).map_err(to_js_err)
+}
+
+/// Estimated number of tokens in `text`.
+#[wasm_bindgen(js_name = tokenCount)]
+pub fn token_count(text: &str) -> usize {
+    tokens::estimate(text)
+}
@@ -3,3 +3,9 @@ lmprep - A tool for preparing your codebase for use with LLMs
 """
 
 __version__ = "0.4.1"
+
+try:
+    # In-process API, available when the wheel was built with the native extension
+    from ._native import collect, pack, token_count
+except ImportError:
+    pass
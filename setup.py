@@ -6,6 +6,7 @@ import sys
 from setuptools import setup, Command
 from setuptools.command.build_py import build_py
 from wheel.bdist_wheel import bdist_wheel
+from setuptools_rust import Binding, RustExtension
 
 def get_platform_tag():
     """Get the platform tag for the wheel"""
@@ -47,9 +48,10 @@ class CustomBdistWheel(bdist_wheel):
         self.plat_name = get_platform_tag()
         
     def get_tag(self):
-        # Override get_tag to ensure we use our custom platform tag
-        python_tag = 'py3'
-        abi_tag = 'none'
+        # Override get_tag to ensure we use our custom platform tag; the native
+        # extension targets the stable ABI so one wheel covers every CPython >= 3.8
+        python_tag = 'cp38'
+        abi_tag = 'abi3'
         platform_tag = self.plat_name
         return python_tag, abi_tag, platform_tag
 
@@ -81,6 +83,16 @@ if __name__ == "__main__":
         version="0.4.1",
         packages=["lmprep"],
         include_package_data=True,
+        rust_extensions=[
+            RustExtension(
+                "lmprep._native",
+                path="bindings/python/Cargo.toml",
+                binding=Binding.PyO3,
+                py_limited_api=True,
+                optional=True,
+            ),
+        ],
+        zip_safe=False,
         cmdclass={
             'build_py': CustomBuildPy,
             'bdist_wheel': CustomBdistWheel,
@@ -0,0 +1,451 @@
+//! Experimental `--anonymize`: replaces identifiers defined in the project, configured
+//! business terms, string literals and domain names with neutral placeholders, the same
+//! placeholder for the same name in every file. The reverse map is written to a local file
+//! and never into the output.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Words that put a definition's name next on the line.
+const DEFINITIONS: &[&str] = &[
+    "fn", "def", "class", "struct", "enum", "trait", "interface", "type", "function", "fun", "mod",
+    "namespace", "let", "var", "val", "const", "union", "record",
+];
+
+/// Words that may come before a definition keyword or between it and the name.
+const MODIFIERS: &[&str] = &[
+    "pub", "crate", "super", "export", "default", "async", "static", "public", "private", "protected",
+    "internal", "final", "abstract", "sealed", "unsafe", "extern", "mut", "declare", "data", "open",
+    "override", "readonly",
+];
+
+/// Names that are structure rather than business, kept even when the project defines them.
+const KEEP: &[&str] = &[
+    "main", "new", "default", "self", "this", "cls", "init", "setup", "test", "tests", "config",
+    "string", "int", "bool", "void", "error", "result", "option", "value", "data", "item", "index",
+];
+
+/// Top-level domains that mark `a.b` as a domain name rather than a member access.
+const TLDS: &[&str] = &[
+    "com", "net", "org", "io", "ai", "co", "cloud", "internal", "corp", "biz", "info", "eu", "us", "uk",
+    "de", "fr", "nl", "ch", "se", "jp", "ca", "au",
+];
+
+/// Extensions of documentation files, which `learn` skips.
+const PROSE: &[&str] = &["md", "markdown", "txt", "rst", "adoc", "org"];
+
+/// Languages where `'x'` is a character (or a lifetime) rather than a string.
+const CHAR_QUOTE_LANGUAGES: &[&str] = &[
+    "rs", "c", "h", "cpp", "cc", "cxx", "hpp", "hh", "java", "cs", "go", "kt", "kts", "scala", "swift",
+];
+
+/// Placeholders handed out so far, by the original they stand for. This is what the map file
+/// holds, placeholder first so it reads as a lookup table.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ReverseMap {
+    identifiers: BTreeMap<String, String>,
+    terms: BTreeMap<String, String>,
+    strings: BTreeMap<String, String>,
+    domains: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Names the project defines, collected by `learn`.
+    defined: HashSet<String>,
+    identifiers: HashMap<String, String>,
+    terms: HashMap<String, usize>,
+    strings: HashMap<String, String>,
+    domains: HashMap<String, String>,
+    reverse: ReverseMap,
+}
+
+impl State {
+    fn identifier(&mut self, name: &str) -> String {
+        if let Some(placeholder) = self.identifiers.get(name) {
+            return placeholder.clone();
+        }
+        let n = self.identifiers.len() + 1;
+        let placeholder = match case_of(name) {
+            Case::Upper => format!("IDENT_{}", n),
+            Case::Capitalized => format!("Ident{}", n),
+            Case::Lower => format!("ident_{}", n),
+        };
+        self.identifiers.insert(name.to_string(), placeholder.clone());
+        self.reverse.identifiers.insert(placeholder.clone(), name.to_string());
+        placeholder
+    }
+
+    /// Placeholder for `term` (lowercase), cased like `occurrence`.
+    fn term(&mut self, term: &str, occurrence: &str) -> String {
+        let next = self.terms.len() + 1;
+        let n = *self.terms.entry(term.to_string()).or_insert(next);
+        self.reverse.terms.insert(format!("term{}", n), term.to_string());
+        match case_of(occurrence) {
+            Case::Upper => format!("TERM{}", n),
+            Case::Capitalized => format!("Term{}", n),
+            Case::Lower => format!("term{}", n),
+        }
+    }
+
+    fn string(&mut self, contents: &str) -> String {
+        let next = self.strings.len() + 1;
+        let placeholder = self.strings.entry(contents.to_string()).or_insert_with(|| format!("str_{}", next));
+        self.reverse.strings.insert(placeholder.clone(), contents.to_string());
+        placeholder.clone()
+    }
+
+    fn domain(&mut self, domain: &str) -> String {
+        let domain = domain.to_lowercase();
+        let next = self.domains.len() + 1;
+        let placeholder = self.domains.entry(domain.clone()).or_insert_with(|| format!("domain{}.example", next));
+        self.reverse.domains.insert(placeholder.clone(), domain);
+        placeholder.clone()
+    }
+}
+
+enum Case {
+    Upper,
+    Capitalized,
+    Lower,
+}
+
+fn case_of(word: &str) -> Case {
+    let mut letters = word.chars().filter(|c| c.is_alphabetic());
+    match letters.next() {
+        Some(first) if first.is_uppercase() && word.len() > 1 && letters.all(|c| c.is_uppercase()) => Case::Upper,
+        Some(first) if first.is_uppercase() => Case::Capitalized,
+        _ => Case::Lower,
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Consistent placeholders for one run (and later runs reusing the same map file).
+pub struct Anonymizer {
+    terms: Vec<String>,
+    map_path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Anonymizer {
+    /// Starts from the map at `map_path` if there is one, so placeholders stay the same from
+    /// run to run. `terms` are replaced wherever they appear, also inside longer names.
+    pub fn load(map_path: &Path, terms: &[String]) -> Result<Self> {
+        let mut state = State::default();
+        let existing = match fs::symlink_metadata(map_path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                bail!("Refusing to read anonymize map {}: it is a symbolic link", map_path.display())
+            }
+            Ok(_) => true,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e).with_context(|| format!("Could not read anonymize map {}", map_path.display())),
+        };
+        if existing {
+            let contents = fs::read_to_string(map_path)?;
+            let reverse: ReverseMap = serde_json::from_str(&contents)
+                .with_context(|| format!("Could not read anonymize map {}", map_path.display()))?;
+            state.identifiers = reverse.identifiers.iter().map(|(p, o)| (o.clone(), p.clone())).collect();
+            state.strings = reverse.strings.iter().map(|(p, o)| (o.clone(), p.clone())).collect();
+            state.domains = reverse.domains.iter().map(|(p, o)| (o.clone(), p.clone())).collect();
+            state.terms = reverse
+                .terms
+                .iter()
+                .filter_map(|(p, o)| Some((o.clone(), p.strip_prefix("term")?.parse().ok()?)))
+                .collect();
+            state.reverse = reverse;
+        }
+
+        let mut terms: Vec<String> = terms.iter().map(|t| t.to_lowercase()).filter(|t| !t.is_empty()).collect();
+        // longest first, so `acme_corp` wins over `acme`
+        terms.sort_by_key(|t| std::cmp::Reverse(t.len()));
+        Ok(Self { terms, map_path: map_path.to_path_buf(), state: Mutex::new(state) })
+    }
+
+    pub fn map_path(&self) -> &Path {
+        &self.map_path
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Collects the names the code in `text` defines (`fn name`, `class Name`, `const NAME`,
+    /// ...) so they are replaced everywhere they are used, in every file. Prose files are
+    /// skipped: "type of" or "function that" define nothing.
+    pub fn learn(&self, text: &str, extension: Option<&str>) {
+        if extension.is_some_and(|e| PROSE.contains(&e)) {
+            return;
+        }
+        let mut state = self.state();
+        // comments start with punctuation, and definitions never do
+        for line in text.lines().filter(|l| l.trim_start().starts_with(is_word_char)) {
+            let mut saw_definition = false;
+            for word in line.split(|c: char| !is_word_char(c)).filter(|w| !w.is_empty()) {
+                if DEFINITIONS.contains(&word) {
+                    saw_definition = true;
+                } else if MODIFIERS.contains(&word) {
+                    continue;
+                } else {
+                    if saw_definition && is_project_name(word) {
+                        state.defined.insert(word.to_string());
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Anonymizes file contents whose language has the extension `extension`. Contents that
+    /// are not UTF-8 are returned as they are.
+    pub fn contents(&self, contents: &[u8], extension: Option<&str>) -> Vec<u8> {
+        let Ok(text) = std::str::from_utf8(contents) else {
+            return contents.to_vec();
+        };
+        let single_quotes = !extension.is_some_and(|e| CHAR_QUOTE_LANGUAGES.contains(&e));
+        self.rewrite(text, true, single_quotes).into_bytes()
+    }
+
+    /// Anonymizes paths and the file tree: names and terms, but no string literals.
+    pub fn names(&self, text: &str) -> String {
+        self.rewrite(text, false, false)
+    }
+
+    fn rewrite(&self, text: &str, strings: bool, single_quotes: bool) -> String {
+        let mut state = self.state();
+        let mut out = String::with_capacity(text.len());
+        let mut previous = None;
+        let mut rest = text;
+
+        while let Some(c) = rest.chars().next() {
+            let after_word = previous.is_some_and(|p: char| is_word_char(p) || p == '.' || p == '-');
+
+            if !after_word && c.is_ascii_alphanumeric() {
+                if let Some(len) = domain_len(rest) {
+                    out.push_str(&state.domain(&rest[..len]));
+                    previous = rest[..len].chars().last();
+                    rest = &rest[len..];
+                    continue;
+                }
+            }
+
+            if is_word_char(c) && !after_word {
+                let len = rest.find(|c: char| !is_word_char(c)).unwrap_or(rest.len());
+                let word = &rest[..len];
+                out.push_str(&self.word(&mut state, word));
+                previous = word.chars().last();
+                rest = &rest[len..];
+                continue;
+            }
+
+            let quote = c == '"' || (c == '\'' && single_quotes && !previous.is_some_and(char::is_alphanumeric));
+            if strings && quote {
+                if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+                    // docstrings are prose: anonymized word by word like comments
+                    out.push_str(&rest[..3]);
+                    previous = Some(c);
+                    rest = &rest[3..];
+                    continue;
+                }
+                if let Some(len) = string_len(rest, c) {
+                    let contents = &rest[1..len - 1];
+                    out.push(c);
+                    if contents.chars().any(char::is_alphabetic) {
+                        out.push_str(&state.string(contents));
+                    } else {
+                        out.push_str(contents);
+                    }
+                    out.push(c);
+                    previous = Some(c);
+                    rest = &rest[len..];
+                    continue;
+                }
+            }
+
+            out.push(c);
+            previous = Some(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        out
+    }
+
+    fn word(&self, state: &mut State, word: &str) -> String {
+        if state.defined.contains(word) {
+            return state.identifier(word);
+        }
+        self.replace_terms(state, word)
+    }
+
+    /// Replaces every configured term inside `word`, keeping the rest of it.
+    fn replace_terms(&self, state: &mut State, word: &str) -> String {
+        // lowercasing keeps byte offsets only for ASCII; other names are left alone rather
+        // than cut inside a character
+        if !word.is_ascii() {
+            return word.to_string();
+        }
+        let lower = word.to_ascii_lowercase();
+        let Some((at, term)) = self.terms.iter().find_map(|t| Some((lower.find(t.as_str())?, t))) else {
+            return word.to_string();
+        };
+        let end = at + term.len();
+        let placeholder = state.term(term, &word[at..end]);
+        format!(
+            "{}{}{}",
+            self.replace_terms(state, &word[..at]),
+            placeholder,
+            self.replace_terms(state, &word[end..])
+        )
+    }
+
+    /// Writes the reverse map, so anonymized answers can be translated back. It holds the
+    /// originals, so it is readable by the user only, and is written to a new file renamed
+    /// into place: a symbolic link at `map_path` is replaced, never followed.
+    pub fn save(&self) -> Result<()> {
+        let state = self.state();
+        if let Some(parent) = self.map_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file_name = self.map_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let staged = self.map_path.with_file_name(format!(".{}.{}.new", file_name, std::process::id()));
+        let written = write_private(&staged, serde_json::to_string_pretty(&state.reverse)?.as_bytes())
+            .and_then(|()| fs::rename(&staged, &self.map_path));
+        if written.is_err() {
+            let _ = fs::remove_file(&staged);
+        }
+        written.with_context(|| format!("Could not write anonymize map {}", self.map_path.display()))
+    }
+}
+
+/// Writes `contents` to `path`, which must not exist yet, readable by the user only.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)
+}
+
+fn is_project_name(word: &str) -> bool {
+    word.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && word.chars().count() >= 3
+        && !(word.starts_with("__") && word.ends_with("__"))
+        && !KEEP.contains(&word.to_lowercase().as_str())
+        && !DEFINITIONS.contains(&word)
+}
+
+/// Length of a quoted string starting at `text` (which starts with `quote`), closed on the
+/// same line.
+fn string_len(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            '\n' => return None,
+            '\\' if !escaped => escaped = true,
+            c if c == quote && !escaped => return Some(i + 1),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Length of a domain name such as `api.example.com` at the start of `text`.
+fn domain_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let label = |start: usize| {
+        bytes[start..].iter().take_while(|b| b.is_ascii_alphanumeric() || **b == b'-').count()
+    };
+
+    let mut labels = Vec::new();
+    let mut end = 0;
+    loop {
+        let len = label(end);
+        if len == 0 {
+            break;
+        }
+        labels.push(&text[end..end + len]);
+        end += len;
+        if bytes.get(end) == Some(&b'.') && bytes.get(end + 1).is_some_and(u8::is_ascii_alphanumeric) {
+            end += 1;
+        } else {
+            break;
+        }
+    }
+
+    let followed_by_word = text[end..].chars().next().is_some_and(is_word_char);
+    let tld = labels.last()?.to_lowercase();
+    (labels.len() >= 2 && !followed_by_word && TLDS.contains(&tld.as_str())).then_some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("lmprep-test-anonymize-{}-{}.json", name, std::process::id()))
+    }
+
+    /// `text` with every placeholder in the map at `path` replaced by its original.
+    fn restore(path: &Path, text: &str) -> String {
+        let reverse: ReverseMap = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let mut pairs: Vec<(&String, &String)> =
+            reverse.identifiers.iter().chain(&reverse.strings).chain(&reverse.domains).collect();
+        // `ident_12` before `ident_1`
+        pairs.sort_by_key(|(placeholder, _)| std::cmp::Reverse(placeholder.len()));
+        pairs.into_iter().fold(text.to_string(), |text, (placeholder, original)| text.replace(placeholder, original))
+    }
+
+    #[test]
+    fn renames_consistently_across_files() {
+        let path = map_path("consistent");
+        let anonymizer = Anonymizer::load(&path, &["acme".to_string()]).unwrap();
+        let billing = "class InvoiceBuilder:\n    URL = \"https://billing.acme.com\"\n";
+        let api =
+            "from billing import InvoiceBuilder\nbuilder = InvoiceBuilder(\"https://billing.acme.com\")\nAcmeClient()\n";
+        anonymizer.learn(billing, Some("py"));
+        anonymizer.learn(api, Some("py"));
+
+        let billing = String::from_utf8(anonymizer.contents(billing.as_bytes(), Some("py"))).unwrap();
+        let api = String::from_utf8(anonymizer.contents(api.as_bytes(), Some("py"))).unwrap();
+        assert_eq!(billing, "class Ident1:\n    URL = \"str_1\"\n");
+        assert_eq!(api, "from billing import Ident1\nbuilder = Ident1(\"str_1\")\nTerm1Client()\n");
+        assert_eq!(anonymizer.names("src/billing/InvoiceBuilder.py"), "src/billing/Ident1.py");
+        assert_eq!(anonymizer.names("see docs.acme.com"), "see domain1.example");
+    }
+
+    #[test]
+    fn reverse_map_round_trips() {
+        let path = map_path("round-trip");
+        let _ = fs::remove_file(&path);
+        let original = "const API_HOST: &str = \"api.example.com\";\nfn load_orders() -> Orders { fetch(API_HOST) }\n";
+
+        let anonymizer = Anonymizer::load(&path, &[]).unwrap();
+        anonymizer.learn(original, Some("rs"));
+        let anonymized = String::from_utf8(anonymizer.contents(original.as_bytes(), Some("rs"))).unwrap();
+        anonymizer.save().unwrap();
+        assert!(!anonymized.contains("API_HOST") && !anonymized.contains("load_orders"));
+        assert_eq!(restore(&path, &anonymized), original);
+
+        // a later run reading the map hands out the same placeholders, and new ones after them
+        let again = Anonymizer::load(&path, &[]).unwrap();
+        again.learn(original, Some("rs"));
+        again.learn("fn ship_orders() {}", Some("rs"));
+        let extended = format!("{}ship_orders();\n", original);
+        let second = String::from_utf8(again.contents(extended.as_bytes(), Some("rs"))).unwrap();
+        assert_eq!(second, format!("{}ident_3();\n", anonymized));
+        again.save().unwrap();
+        assert_eq!(restore(&path, &second), extended);
+        fs::remove_file(&path).unwrap();
+    }
+}
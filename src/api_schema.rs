@@ -0,0 +1,44 @@
+//! API definitions: the files that say what an API offers rather than how it is built, such
+//! as OpenAPI and Swagger documents, Protocol Buffers and GraphQL schemas. `api_first` keeps
+//! them in full while the implementation is cut down to outlines.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions of files that are always API definitions.
+const SCHEMA_EXTENSIONS: &[&str] = &["proto", "graphql", "graphqls", "gql", "thrift", "avdl", "smithy"];
+
+/// Extensions of files that are API definitions if they say so in their first bytes.
+const DOCUMENT_EXTENSIONS: &[&str] = &["yaml", "yml", "json"];
+
+/// Top-level keys that make a YAML or JSON document an API definition.
+const DOCUMENT_KEYS: &[&str] = &["openapi", "swagger", "asyncapi"];
+
+/// Bytes read from the start of a YAML or JSON file to find its `openapi` key.
+const HEADER_SIZE: u64 = 4096;
+
+/// Whether the file at `path` is an API definition: a schema by its extension, or a YAML or
+/// JSON document with a top-level `openapi`, `swagger` or `asyncapi` key near its start.
+pub fn is_api_definition(path: &Path) -> bool {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if SCHEMA_EXTENSIONS.contains(&extension.as_str()) {
+        return true;
+    }
+    if !DOCUMENT_EXTENSIONS.contains(&extension.as_str()) {
+        return false;
+    }
+    let mut header = Vec::new();
+    if File::open(path).and_then(|file| file.take(HEADER_SIZE).read_to_end(&mut header)).is_err() {
+        return false;
+    }
+    let header = String::from_utf8_lossy(&header);
+    if extension == "json" {
+        // `"openapi": "3.1.0"`, often on the first line of minified documents
+        return DOCUMENT_KEYS.iter().any(|key| {
+            header.match_indices(&format!("\"{}\"", key)).any(|(at, quoted)| header[at + quoted.len()..].trim_start().starts_with(':'))
+        });
+    }
+    // top-level YAML keys are not indented
+    header.lines().any(|line| DOCUMENT_KEYS.iter().any(|key| line.strip_prefix(key).is_some_and(|rest| rest.starts_with(':'))))
+}
@@ -0,0 +1,116 @@
+//! Zip and tar archives as the source: they are extracted to a temporary directory, which
+//! the normal pipeline then reads. Remote and container sources arrive the same way, as the
+//! output of a command.
+
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Extensions of the archives `extract` reads.
+const EXTENSIONS: &[&str] = &[".zip", ".tar.gz", ".tgz", ".tar"];
+
+/// An extracted archive, deleted when dropped.
+pub struct Extracted {
+    dir: PathBuf,
+    root: PathBuf,
+}
+
+impl Extracted {
+    /// An empty temporary directory; dropping the result removes it again if extraction fails.
+    fn new() -> Result<Self> {
+        let dir = env::temp_dir().join(format!("lmprep-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        Ok(Self { root: dir.clone(), dir })
+    }
+
+    /// Where the archive's files are: the extraction directory, or the one directory at the
+    /// top of the archive if everything is inside it, as in most release archives.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for Extracted {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Whether `path` is an archive file `extract` can read, judged by its name.
+pub fn is_archive(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    path.is_file() && EXTENSIONS.iter().any(|extension| name.ends_with(extension))
+}
+
+/// Extracts the archive at `path` into a new temporary directory. Entries that would land
+/// outside of it, such as `../` paths, are skipped.
+pub fn extract(path: &Path) -> Result<Extracted> {
+    let mut extracted = Extracted::new()?;
+    let file = fs::File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let name = path.to_string_lossy().to_lowercase();
+    let result = if name.ends_with(".zip") {
+        extract_zip(file, &extracted.dir)
+    } else if name.ends_with(".tar") {
+        tar::Archive::new(file).unpack(&extracted.dir).map_err(Into::into)
+    } else {
+        tar::Archive::new(flate2::read::GzDecoder::new(file))
+            .unpack(&extracted.dir)
+            .map_err(Into::into)
+    };
+    result.with_context(|| format!("Could not extract {}", path.display()))?;
+
+    let mut entries = fs::read_dir(&extracted.dir)?.collect::<io::Result<Vec<_>>>()?;
+    if entries.len() == 1 && entries[0].file_type()?.is_dir() {
+        extracted.root = entries.remove(0).path();
+    }
+    Ok(extracted)
+}
+
+/// Runs `command` and extracts the uncompressed tar archive it writes to stdout into a new
+/// temporary directory. The root is `root` inside it if the archive has that directory.
+pub fn extract_output(command: &mut Command, root: &Path) -> Result<Extracted> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Could not run {}", program))?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+
+    let mut extracted = Extracted::new()?;
+    let unpacked = tar::Archive::new(&mut stdout).unpack(&extracted.dir);
+    // tar pads the archive past its end marker; reading the rest keeps it from a broken pipe
+    io::copy(&mut stdout, &mut io::sink())?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{} failed ({})", program, status);
+    }
+    unpacked?;
+
+    if extracted.dir.join(root).is_dir() {
+        extracted.root = extracted.dir.join(root);
+    }
+    Ok(extracted)
+}
+
+fn extract_zip(file: fs::File, dir: &Path) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(io::BufReader::new(file))?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let Some(target) = entry.enclosed_name().map(|name| dir.join(name)) else {
+            continue;
+        };
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut fs::File::create(&target)?)?;
+    }
+    Ok(())
+}
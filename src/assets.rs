@@ -0,0 +1,179 @@
+//! `summarize_categories`: large files that are read by browsers rather than people, such as
+//! compiled stylesheets and asset manifests, described in a few lines instead of included
+//! in full. Component sources are left as they are.
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Names listed in a summary before the rest are counted.
+const MAX_LISTED: usize = 20;
+
+/// Characters of a manifest value shown before it is cut off.
+const MAX_VALUE_CHARS: usize = 200;
+
+/// The description of the file shown as `name` that replaces `text` in the output.
+pub fn summarize(name: &str, text: &str) -> String {
+    let extension = Path::new(name).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let mut summary = format!("Summary of {} ({} bytes, {} lines), not included in full.\n", name, text.len(), text.lines().count());
+    let details = match extension.as_str() {
+        "css" | "scss" | "sass" | "less" | "styl" => stylesheet(text),
+        "map" => source_map(text),
+        "json" => manifest(text),
+        _ => None,
+    };
+    if let Some(details) = details {
+        summary.push('\n');
+        summary.push_str(&details);
+    }
+    summary
+}
+
+/// `names` in the order given, the first `MAX_LISTED` of them and how many more there are.
+fn listed(names: &[String]) -> String {
+    let mut list = names.iter().take(MAX_LISTED).cloned().collect::<Vec<_>>().join(", ");
+    if names.len() > MAX_LISTED {
+        write!(list, " and {} more", names.len() - MAX_LISTED).ok();
+    }
+    list
+}
+
+/// Rules, the most used classes, custom properties and variables, media queries, animations
+/// and imports of a stylesheet.
+fn stylesheet(text: &str) -> Option<String> {
+    let css = without_comments(text);
+    let mut rules = 0;
+    let mut classes: HashMap<String, usize> = HashMap::new();
+    let mut properties = BTreeSet::new();
+    let mut variables = BTreeSet::new();
+    let mut media = BTreeSet::new();
+    let mut keyframes = Vec::new();
+    let mut imports = Vec::new();
+    let mut font_faces = 0;
+
+    // each part ends at a brace or semicolon: a selector or at-rule before `{`, a declaration
+    // or an at-rule statement before `;` or `}`
+    let mut start = 0;
+    for (at, delimiter) in css.match_indices(['{', '}', ';']) {
+        let part = css[start..at].trim();
+        start = at + 1;
+        if part.is_empty() {
+            continue;
+        }
+        if let Some(at_rule) = part.strip_prefix('@') {
+            let (name, rest) = at_rule.split_once(char::is_whitespace).unwrap_or((at_rule, ""));
+            let rest = rest.split_whitespace().collect::<Vec<_>>().join(" ");
+            match name {
+                "media" => {
+                    media.insert(rest);
+                }
+                "keyframes" | "-webkit-keyframes" => keyframes.push(rest),
+                "import" | "use" | "forward" => imports.push(rest.trim_matches(['"', '\'']).to_string()),
+                "font-face" => font_faces += 1,
+                _ => {}
+            }
+        } else if delimiter != "{" {
+            let name = part.split_once(':').map_or("", |(name, _)| name.trim());
+            if name.starts_with("--") {
+                properties.insert(name.to_string());
+            } else if name.starts_with('$') {
+                variables.insert(name.to_string());
+            }
+        } else {
+            rules += 1;
+            let words = part.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || c == '.'));
+            for class in words.flat_map(|word| word.split('.').skip(1)).filter(|class| !class.is_empty()) {
+                // not the `.5` of `0.5s` in a keyframe
+                if class.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '-') {
+                    *classes.entry(class.to_string()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let mut details = format!("{} rules\n", rules);
+    let mut by_use: Vec<(String, usize)> = classes.into_iter().collect();
+    by_use.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+    let sections: [(&str, Vec<String>); 6] = [
+        ("Most used classes", by_use.into_iter().map(|(class, _)| format!(".{}", class)).collect()),
+        ("Custom properties", properties.into_iter().collect()),
+        ("Variables", variables.into_iter().collect()),
+        ("Media queries", media.into_iter().collect()),
+        ("Animations", keyframes),
+        ("Imports", imports),
+    ];
+    for (heading, names) in sections.iter().filter(|(_, names)| !names.is_empty()) {
+        writeln!(details, "{}: {}", heading, listed(names)).ok();
+    }
+    if font_faces > 0 {
+        writeln!(details, "{} @font-face rules", font_faces).ok();
+    }
+    Some(details)
+}
+
+/// `text` without `/* */` comments, and without the `//` line comments SCSS and Less allow.
+fn without_comments(text: &str) -> String {
+    let mut css = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        css.push_str(&rest[..start]);
+        rest = rest[start + 2..].split_once("*/").map_or("", |(_, after)| after);
+    }
+    css.push_str(rest);
+    css.lines().filter(|line| !line.trim_start().starts_with("//")).collect::<Vec<_>>().join("\n")
+}
+
+/// The parts of a source map a summary names.
+#[derive(Deserialize)]
+struct SourceMap {
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    sources: Vec<String>,
+}
+
+/// The bundle a source map is for and the sources it maps.
+fn source_map(text: &str) -> Option<String> {
+    let SourceMap { file, sources } = serde_json::from_str(text).ok()?;
+    let mut details = String::new();
+    if let Some(file) = file {
+        writeln!(details, "Source map of {}", file).ok();
+    }
+    writeln!(details, "{} sources: {}", sources.len(), listed(&sources)).ok();
+    Some(details)
+}
+
+/// A value in an asset manifest: usually the name of a built file, sometimes a group of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    File(String),
+    Group(BTreeMap<String, Value>),
+    Other(Value),
+}
+
+/// The entries of an asset manifest, `{"main.js": "main.3f9a2c.js", ...}`, one per line;
+/// groups such as the `files` of `asset-manifest.json` with how many entries they have.
+fn manifest(text: &str) -> Option<String> {
+    let document: BTreeMap<String, Entry> = serde_json::from_str(text).ok()?;
+    let mut details = format!("{} entries\n", document.len());
+    for (key, entry) in document.iter().take(MAX_LISTED) {
+        match entry {
+            Entry::File(file) => writeln!(details, "- {} -> {}", key, file).ok(),
+            Entry::Group(group) => writeln!(details, "- {}: {} entries", key, group.len()).ok(),
+            Entry::Other(value) => {
+                let value = serde_json::to_string(value).unwrap_or_default();
+                match value.char_indices().nth(MAX_VALUE_CHARS) {
+                    Some((end, _)) => writeln!(details, "- {}: {}...", key, &value[..end]).ok(),
+                    None => writeln!(details, "- {}: {}", key, value).ok(),
+                }
+            }
+        };
+    }
+    if document.len() > MAX_LISTED {
+        writeln!(details, "- and {} more", document.len() - MAX_LISTED).ok();
+    }
+    Some(details)
+}
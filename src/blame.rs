@@ -0,0 +1,99 @@
+//! Line annotations from `git blame`: the commit that last changed each line and how long
+//! ago that was.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Shown instead of a hash for lines that are not committed yet.
+const UNCOMMITTED: &str = "-------";
+
+/// The commit behind one line of the working tree file.
+struct Line {
+    commit: String,
+    time: Option<i64>,
+}
+
+/// `output` with each line prefixed by the short hash and age of the commit that last
+/// changed it in the working tree file at `relative`, whose contents are `original`.
+/// `output` may be a transformed `original`: its lines are matched to the original ones in
+/// order, and lines with no match, such as ones a transform added, get an empty prefix.
+pub fn annotate(repo: &Path, relative: &Path, original: &[u8], output: &[u8]) -> Result<Vec<u8>> {
+    let (Ok(original), Ok(output)) = (std::str::from_utf8(original), std::str::from_utf8(output)) else {
+        return Ok(output.to_vec());
+    };
+    let blamed = blame(repo, relative)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    let prefixes: Vec<String> = blamed
+        .iter()
+        .map(|line| match line.time {
+            Some(time) => format!("{} {:>4} | ", &line.commit[..7.min(line.commit.len())], age(now - time)),
+            None => format!("{} {:>4} | ", UNCOMMITTED, "-"),
+        })
+        .collect();
+    let blank = format!("{:width$} | ", "", width = UNCOMMITTED.len() + 5);
+
+    let original: Vec<&str> = original.lines().collect();
+    let mut next = 0;
+    let mut annotated = String::with_capacity(output.len() + output.lines().count() * blank.len());
+    for line in output.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let found = original[next..].iter().position(|o| *o == content).map(|offset| next + offset);
+        match found.and_then(|i| prefixes.get(i).map(|prefix| (i, prefix))) {
+            Some((i, prefix)) => {
+                annotated.push_str(prefix);
+                next = i + 1;
+            }
+            None => annotated.push_str(&blank),
+        }
+        annotated.push_str(line);
+    }
+    Ok(annotated.into_bytes())
+}
+
+/// Runs `git blame --line-porcelain` on the working tree file at `relative`.
+fn blame(repo: &Path, relative: &Path) -> Result<Vec<Line>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["blame", "--line-porcelain", "--"])
+        .arg(relative)
+        .output()
+        .context("Could not run git blame")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let mut lines = Vec::new();
+    let mut current: Option<Line> = None;
+    for entry in String::from_utf8_lossy(&output.stdout).lines() {
+        if entry.starts_with('\t') {
+            lines.extend(current.take());
+        } else if let Some(time) = entry.strip_prefix("author-time ") {
+            // uncommitted lines are blamed on an all-zero commit
+            if let Some(line) = current.as_mut().filter(|line| line.commit.bytes().any(|b| b != b'0')) {
+                line.time = time.parse().ok();
+            }
+        } else if current.is_none() {
+            let commit = entry.split(' ').next().unwrap_or_default();
+            if commit.len() >= 40 && commit.bytes().all(|b| b.is_ascii_hexdigit()) {
+                current = Some(Line { commit: commit.to_string(), time: None });
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// `45m`, `3h`, `12d`, `5mo` or `2y`.
+fn age(seconds: i64) -> String {
+    let minutes = seconds.max(0) / 60;
+    let (hours, days) = (minutes / 60, minutes / (60 * 24));
+    match days {
+        _ if hours == 0 => format!("{}m", minutes),
+        0 => format!("{}h", hours),
+        1..=59 => format!("{}d", days),
+        60..=729 => format!("{}mo", days / 30),
+        _ => format!("{}y", days / 365),
+    }
+}
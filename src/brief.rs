@@ -0,0 +1,413 @@
+//! `brief`: a short orientation put before everything else in the output, so the model knows
+//! what it is looking at before it reads the files: the project's name, its languages and
+//! top-level directories, its main dependencies, entry points and build and test commands.
+//! Names and dependencies are read line by line from the manifests at the root of the source
+//! (`Cargo.toml`, `package.json`, `pyproject.toml`, `requirements.txt` and `go.mod`), whether
+//! or not the filters select them; commands are the ones `commands` finds.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::commands::{self, Kind};
+
+/// Heading of the document `render` returns, before the project's name.
+pub const HEADING: &str = "# Project brief";
+
+/// Dependencies listed per manifest; the rest are counted.
+const MAX_DEPENDENCIES: usize = 12;
+
+/// Languages and top-level directories listed; the rest are counted.
+const MAX_LANGUAGES: usize = 6;
+const MAX_DIRECTORIES: usize = 15;
+
+/// Extensions and the language they are written in.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "Rust"),
+    ("py", "Python"),
+    ("pyi", "Python"),
+    ("js", "JavaScript"),
+    ("mjs", "JavaScript"),
+    ("cjs", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("go", "Go"),
+    ("java", "Java"),
+    ("kt", "Kotlin"),
+    ("kts", "Kotlin"),
+    ("scala", "Scala"),
+    ("c", "C"),
+    ("h", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("cxx", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("fs", "F#"),
+    ("swift", "Swift"),
+    ("m", "Objective-C"),
+    ("rb", "Ruby"),
+    ("php", "PHP"),
+    ("pl", "Perl"),
+    ("lua", "Lua"),
+    ("r", "R"),
+    ("jl", "Julia"),
+    ("dart", "Dart"),
+    ("ex", "Elixir"),
+    ("exs", "Elixir"),
+    ("erl", "Erlang"),
+    ("hs", "Haskell"),
+    ("ml", "OCaml"),
+    ("clj", "Clojure"),
+    ("zig", "Zig"),
+    ("nim", "Nim"),
+    ("sh", "Shell"),
+    ("bash", "Shell"),
+    ("ps1", "PowerShell"),
+    ("sql", "SQL"),
+    ("vue", "Vue"),
+    ("svelte", "Svelte"),
+    ("html", "HTML"),
+    ("css", "CSS"),
+    ("scss", "CSS"),
+    ("proto", "Protocol Buffers"),
+    ("tf", "Terraform"),
+];
+
+/// Files that usually start a program or export a library, relative to the source.
+const ENTRY_POINTS: &[&str] = &[
+    "src/main.rs",
+    "src/lib.rs",
+    "main.py",
+    "__main__.py",
+    "app.py",
+    "manage.py",
+    "main.go",
+    "index.js",
+    "index.ts",
+    "src/index.js",
+    "src/index.ts",
+    "src/main.js",
+    "src/main.ts",
+    "src/App.tsx",
+    "src/App.jsx",
+    "Program.cs",
+    "src/main/java/Main.java",
+];
+
+/// Build, test and lint commands listed; `commands.md` has them all.
+const MAX_COMMANDS: usize = 10;
+
+/// What the manifests at the root of a source say about it.
+#[derive(Default)]
+struct Manifests {
+    name: Option<String>,
+    description: Option<String>,
+    dependencies: Vec<(&'static str, Vec<String>)>,
+    entry_points: Vec<String>,
+}
+
+/// The brief of the project at `source` whose selected files are `files`, relative to it.
+pub fn render(source: &Path, files: &[PathBuf]) -> String {
+    let manifests = read_manifests(source);
+    let name = manifests.name.clone().unwrap_or_else(|| {
+        source.canonicalize().ok().and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned())).unwrap_or_default()
+    });
+
+    let mut document = format!("{}: {}\n\n", HEADING, name);
+    if let Some(description) = &manifests.description {
+        document.push_str(&format!("{}\n\n", description));
+    }
+    if let Some(languages) = languages(files) {
+        document.push_str(&format!("Languages: {}\n\n", languages));
+    }
+
+    let layout = layout(files);
+    if !layout.is_empty() {
+        document.push_str("## Layout\n\n");
+        document.push_str(&layout.iter().map(|line| format!("- {}\n", line)).collect::<String>());
+        document.push('\n');
+    }
+
+    if !manifests.dependencies.is_empty() {
+        document.push_str("## Dependencies\n\n");
+        for (manifest, names) in &manifests.dependencies {
+            let shown: Vec<&str> = names.iter().take(MAX_DEPENDENCIES).map(String::as_str).collect();
+            let more = names.len().saturating_sub(MAX_DEPENDENCIES);
+            let more = if more > 0 { format!(" and {} more", more) } else { String::new() };
+            document.push_str(&format!("- `{}`: {}{}\n", manifest, shown.join(", "), more));
+        }
+        document.push('\n');
+    }
+
+    let found = files.iter().map(|file| slash(file)).filter(|file| {
+        ENTRY_POINTS.contains(&file.as_str())
+            || (file.starts_with("src/bin/") && file.ends_with(".rs"))
+            || (file.starts_with("cmd/") && file.ends_with("/main.go"))
+    });
+    let mut entry_points: Vec<String> = Vec::new();
+    for file in manifests.entry_points.iter().cloned().chain(found) {
+        if !entry_points.contains(&file) {
+            entry_points.push(file);
+        }
+    }
+    if !entry_points.is_empty() {
+        document.push_str("## Entry points\n\n");
+        document.push_str(&entry_points.iter().map(|file| format!("- `{}`\n", file)).collect::<String>());
+        document.push('\n');
+    }
+
+    let commands: Vec<String> = commands::extract(source)
+        .into_iter()
+        .filter(|command| matches!(command.kind, Kind::Build | Kind::Test | Kind::Lint))
+        .take(MAX_COMMANDS)
+        .map(|command| format!("- `{}`\n", command.command))
+        .collect();
+    if !commands.is_empty() {
+        document.push_str("## Commands\n\n");
+        document.push_str(&commands.concat());
+        document.push('\n');
+    }
+    document
+}
+
+/// `Rust (40 files), Shell (2 files)`, most files first, or `None` if no file is code.
+fn languages(files: &[PathBuf]) -> Option<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for file in files {
+        let extension = file.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if let Some((_, language)) = LANGUAGES.iter().find(|(e, _)| *e == extension) {
+            *counts.entry(language).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let mut listed: Vec<String> =
+        counts.iter().take(MAX_LANGUAGES).map(|(language, count)| format!("{} ({})", language, files_count(*count))).collect();
+    if counts.len() > MAX_LANGUAGES {
+        listed.push(format!("{} more", counts.len() - MAX_LANGUAGES));
+    }
+    (!listed.is_empty()).then(|| listed.join(", "))
+}
+
+/// A line per top-level directory with how many of `files` are in it, and one for the files
+/// at the root.
+fn layout(files: &[PathBuf]) -> Vec<String> {
+    let mut directories: BTreeMap<String, usize> = BTreeMap::new();
+    let mut root = 0;
+    for file in files {
+        let mut components = file.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(dir)), Some(_)) => *directories.entry(dir.to_string_lossy().into_owned()).or_default() += 1,
+            _ => root += 1,
+        }
+    }
+    let mut lines: Vec<String> = directories
+        .iter()
+        .take(MAX_DIRECTORIES)
+        .map(|(dir, count)| format!("`{}/`: {}", dir, files_count(*count)))
+        .collect();
+    if directories.len() > MAX_DIRECTORIES {
+        lines.push(format!("{} more directories", directories.len() - MAX_DIRECTORIES));
+    }
+    if root > 0 {
+        lines.push(format!("{} at the root", files_count(root)));
+    }
+    lines
+}
+
+fn files_count(count: usize) -> String {
+    format!("{} file{}", count, if count == 1 { "" } else { "s" })
+}
+
+fn slash(path: &Path) -> String {
+    path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn read_manifests(source: &Path) -> Manifests {
+    let mut manifests = Manifests::default();
+    let read = |name: &str| fs::read_to_string(source.join(name)).ok();
+    if let Some(text) = read("Cargo.toml") {
+        cargo_toml(&text, &mut manifests);
+    }
+    if let Some(text) = read("package.json") {
+        package_json(&text, &mut manifests);
+    }
+    if let Some(text) = read("pyproject.toml") {
+        pyproject_toml(&text, &mut manifests);
+    }
+    if let Some(text) = read("requirements.txt") {
+        let names: Vec<String> = text.lines().filter_map(requirement_name).collect();
+        if !names.is_empty() {
+            manifests.dependencies.push(("requirements.txt", names));
+        }
+    }
+    if let Some(text) = read("go.mod") {
+        go_mod(&text, &mut manifests);
+    }
+    manifests
+}
+
+/// The `key = value` lines of a TOML file as `(table, key, value)`, with the value as
+/// written. Enough for the plain manifests most projects have, not for TOML at large.
+fn toml_entries(text: &str) -> impl Iterator<Item = (String, &str, &str)> {
+    let mut table = String::new();
+    text.lines().filter_map(move |line| {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('[') {
+            table = name.trim_matches(|c| c == '[' || c == ']').trim().to_string();
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        let key = key.trim();
+        (!key.starts_with('#')).then(|| (table.clone(), key.trim_matches('"'), value.trim()))
+    })
+}
+
+/// A TOML string value without its quotes.
+fn unquote(value: &str) -> Option<String> {
+    let value = value.trim();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    Some(value[1..].split(quote).next()?.to_string())
+}
+
+fn cargo_toml(text: &str, manifests: &mut Manifests) {
+    let mut dependencies = Vec::new();
+    let mut binaries = Vec::new();
+    for (table, key, value) in toml_entries(text) {
+        match table.as_str() {
+            "package" if key == "name" => manifests.name = manifests.name.take().or(unquote(value)),
+            "package" if key == "description" => manifests.description = manifests.description.take().or(unquote(value)),
+            "dependencies" => dependencies.push(key.to_string()),
+            "bin" if key == "path" => binaries.extend(unquote(value)),
+            // `[dependencies.serde]`
+            _ => {
+                if let Some(name) = table.strip_prefix("dependencies.").filter(|name| !dependencies.iter().any(|d| d == name)) {
+                    dependencies.push(name.to_string());
+                }
+            }
+        }
+    }
+    if !dependencies.is_empty() {
+        manifests.dependencies.push(("Cargo.toml", dependencies));
+    }
+    manifests.entry_points.extend(binaries);
+}
+
+/// The parts of a `package.json` the brief uses.
+#[derive(Deserialize)]
+struct PackageJson {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    main: Option<String>,
+    #[serde(default)]
+    module: Option<String>,
+    #[serde(default)]
+    bin: Option<Bin>,
+}
+
+/// `"bin": "cli.js"`, or `"bin": {"name": "cli.js"}`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Bin {
+    One(String),
+    Named(BTreeMap<String, String>),
+}
+
+fn package_json(text: &str, manifests: &mut Manifests) {
+    let Ok(package) = serde_json::from_str::<PackageJson>(text) else {
+        return;
+    };
+    manifests.name = manifests.name.take().or(package.name);
+    manifests.description = manifests.description.take().or(package.description);
+    if !package.dependencies.is_empty() {
+        manifests.dependencies.push(("package.json", package.dependencies.into_keys().collect()));
+    }
+    let bins = match package.bin {
+        Some(Bin::One(bin)) => vec![bin],
+        Some(Bin::Named(bins)) => bins.into_values().collect(),
+        None => vec![],
+    };
+    for path in package.main.into_iter().chain(package.module).chain(bins) {
+        manifests.entry_points.push(path.trim_start_matches("./").to_string());
+    }
+}
+
+fn pyproject_toml(text: &str, manifests: &mut Manifests) {
+    let mut dependencies = Vec::new();
+    let mut in_list = false;
+    let mut table = String::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') && !in_list {
+            table = line.trim_matches(|c| c == '[' || c == ']').to_string();
+            continue;
+        }
+        if in_list {
+            dependencies.extend(unquote(line).as_deref().and_then(requirement_name));
+            in_list = !line.starts_with(']');
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match (table.as_str(), key.trim()) {
+            ("project" | "tool.poetry", "name") => manifests.name = manifests.name.take().or(unquote(value)),
+            ("project" | "tool.poetry", "description") => {
+                manifests.description = manifests.description.take().or(unquote(value));
+            }
+            ("project", "dependencies") => {
+                let value = value.trim();
+                // `dependencies = ["a", "b"]` on one line, or one per line up to `]`
+                let items = value.trim_start_matches('[').trim_end_matches(']');
+                dependencies.extend(items.split(',').filter_map(unquote).filter_map(|item| requirement_name(&item)));
+                in_list = !value.ends_with(']');
+            }
+            ("tool.poetry.dependencies", name) if name != "python" => dependencies.push(name.to_string()),
+            _ => {}
+        }
+    }
+    if !dependencies.is_empty() {
+        manifests.dependencies.push(("pyproject.toml", dependencies));
+    }
+}
+
+/// The package a line of `requirements.txt` or a PEP 508 string names: `requests` of
+/// `requests[socks]>=2.0 ; python_version > "3"`.
+fn requirement_name(line: &str) -> Option<String> {
+    let line = line.split('#').next()?.trim();
+    if line.is_empty() || line.starts_with('-') {
+        return None;
+    }
+    let name: String = line.chars().take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.')).collect();
+    (!name.is_empty()).then_some(name)
+}
+
+fn go_mod(text: &str, manifests: &mut Manifests) {
+    let mut dependencies = Vec::new();
+    let mut in_require = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with("//") {
+            continue;
+        } else if let Some(module) = line.strip_prefix("module ") {
+            manifests.name = manifests.name.take().or(Some(module.trim().to_string()));
+        } else if line == "require (" {
+            in_require = true;
+        } else if in_require && line == ")" {
+            in_require = false;
+        } else if let Some(require) = line.strip_prefix("require ").or(in_require.then_some(line)) {
+            if !require.contains("// indirect") {
+                dependencies.extend(require.split_whitespace().next().map(str::to_string));
+            }
+        }
+    }
+    if !dependencies.is_empty() {
+        manifests.dependencies.push(("go.mod", dependencies));
+    }
+}
@@ -0,0 +1,55 @@
+//! The per-user cache directory, for resources that are expensive to set up and the same for
+//! every run, such as the tokenizers `tokenizer: hf:<model-id>` downloads.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Where lmprep caches: `LMPREP_CACHE_DIR` if it is set, otherwise `lmprep` in the platform's
+/// cache directory (`$XDG_CACHE_HOME` or `~/.cache` on Linux, `~/Library/Caches` on macOS,
+/// `%LOCALAPPDATA%` on Windows). `None` without a home directory to put it in.
+pub fn dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("LMPREP_CACHE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    base.map(|base| base.join("lmprep"))
+}
+
+/// Where the resource `name` of `kind`, such as `tokenizers`, is cached, creating its
+/// directory if needed.
+pub fn path(kind: &str, name: &str) -> Result<PathBuf> {
+    let dir = dir().context("Could not find a cache directory, set LMPREP_CACHE_DIR")?.join(kind);
+    fs::create_dir_all(&dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    Ok(dir.join(name))
+}
+
+/// Deletes everything in the cache and returns how many bytes that freed.
+pub fn clear() -> Result<u64> {
+    let Some(dir) = dir().filter(|dir| dir.exists()) else {
+        return Ok(0);
+    };
+    let freed = size(&dir);
+    fs::remove_dir_all(&dir).with_context(|| format!("Could not remove {}", dir.display()))?;
+    Ok(freed)
+}
+
+fn size(path: &std::path::Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path)
+            .map(|entries| entries.flatten().map(|entry| size(&entry.path())).sum())
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
@@ -0,0 +1,64 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::classify::Category;
+use crate::config::{Layout, Order, CONFIG_SCHEMA_VERSION};
+use crate::copy_mode::CopyMode;
+use crate::flatten::FlattenScheme;
+use crate::manifest::MANIFEST_SCHEMA_VERSION;
+use crate::pii::PiiPolicy;
+use crate::pricing::PRESETS;
+use crate::secrets::SecretScan;
+
+/// Machine-readable description of what this build supports, printed by
+/// `lm --capabilities` so wrappers can feature-detect instead of parsing `--version`.
+#[derive(Serialize)]
+pub struct Capabilities {
+    name: &'static str,
+    version: &'static str,
+    config_schema_version: u32,
+    manifest_schema_version: u32,
+    formats: Vec<&'static str>,
+    layouts: Vec<String>,
+    flatten_schemes: Vec<String>,
+    copy_modes: Vec<String>,
+    categories: Vec<String>,
+    secret_scan_modes: Vec<String>,
+    pii_policies: Vec<String>,
+    orders: Vec<String>,
+    transforms: Vec<&'static str>,
+    tokenizers: Vec<&'static str>,
+    models: Vec<&'static str>,
+    commands: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn current() -> Self {
+        Self {
+            name: "lmprep",
+            version: env!("CARGO_PKG_VERSION"),
+            config_schema_version: CONFIG_SCHEMA_VERSION,
+            manifest_schema_version: MANIFEST_SCHEMA_VERSION,
+            formats: vec!["folder", "zip", "concat", "html", "sqlite"],
+            layouts: names::<Layout>(),
+            flatten_schemes: names::<FlattenScheme>(),
+            copy_modes: names::<CopyMode>(),
+            categories: names::<Category>(),
+            secret_scan_modes: names::<SecretScan>(),
+            pii_policies: names::<PiiPolicy>(),
+            orders: names::<Order>(),
+            transforms: vec!["base64", "notebook", "license_headers", "strip_docs", "ci_summary", "blame", "anonymize"],
+            tokenizers: vec!["estimate", "approx", "hf"],
+            models: PRESETS.iter().map(|preset| preset.name).collect(),
+            commands: vec!["add", "cache clear", "cache dir", "config migrate", "daemon", "pick", "remove", "review", "schema", "search", "self-update"],
+        }
+    }
+}
+
+fn names<T: ValueEnum>() -> Vec<String> {
+    T::value_variants()
+        .iter()
+        .filter_map(|v| v.to_possible_value())
+        .map(|v| v.get_name().to_string())
+        .collect()
+}
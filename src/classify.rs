@@ -0,0 +1,109 @@
+//! Heuristics that tell what a file is for from its path alone.
+
+use serde::Deserialize;
+use std::path::Path;
+
+/// Broad purpose of a file, for filtering without maintaining extension lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// Source code; anything not recognised as one of the other categories
+    Code,
+    /// Prose: READMEs, markdown, reStructuredText, plain text
+    Docs,
+    /// Settings and structured config: YAML, TOML, JSON, INI, dotfiles
+    Config,
+    /// Datasets and dumps: CSV, JSON Lines, Parquet, spreadsheets
+    Data,
+    /// Build scripts and package manifests: Makefile, Dockerfile, Cargo.toml, package.json
+    Build,
+    /// Frontend assets: stylesheets, source maps and the manifests bundlers write
+    Assets,
+}
+
+const BUILD_FILES: &[&str] = &[
+    "makefile", "gnumakefile", "dockerfile", "containerfile", "cmakelists.txt", "justfile",
+    "rakefile", "gemfile", "build", "build.bazel", "workspace", "meson.build", "pom.xml",
+    "build.xml", "cargo.toml", "package.json", "go.mod", "setup.py", "pyproject.toml",
+    "build.gradle", "build.gradle.kts", "settings.gradle", "settings.gradle.kts", "gradlew",
+];
+const BUILD_EXTENSIONS: &[&str] = &["mk", "cmake", "gradle", "bzl", "dockerfile"];
+const ASSET_FILES: &[&str] = &[
+    "asset-manifest.json", "assets-manifest.json", "mix-manifest.json", "webpack-stats.json",
+    "build-manifest.json", "react-loadable-manifest.json",
+];
+const ASSET_EXTENSIONS: &[&str] = &["css", "scss", "sass", "less", "styl", "map"];
+const DOC_NAMES: &[&str] = &["readme", "license", "licence", "changelog", "contributing", "authors", "notice"];
+const DOC_EXTENSIONS: &[&str] = &["md", "markdown", "mdx", "rst", "txt", "adoc", "asciidoc", "org", "tex"];
+const CONFIG_EXTENSIONS: &[&str] = &[
+    "yml", "yaml", "toml", "json", "json5", "jsonc", "ini", "cfg", "conf", "env", "properties",
+    "xml", "plist",
+];
+const DATA_EXTENSIONS: &[&str] = &[
+    "csv", "tsv", "jsonl", "ndjson", "parquet", "avro", "arrow", "xls", "xlsx", "sqlite", "db",
+    "dat", "npy", "npz", "pkl", "h5", "hdf5",
+];
+
+/// Category of the file at `path`, from its name and extension.
+pub fn category(path: &Path) -> Category {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_lowercase(),
+        None => return Category::Code,
+    };
+    let (stem, extension) = name.rsplit_once('.').unwrap_or((&name, ""));
+
+    if BUILD_FILES.contains(&name.as_str()) || BUILD_EXTENSIONS.contains(&extension) {
+        Category::Build
+    } else if ASSET_FILES.contains(&name.as_str()) || ASSET_EXTENSIONS.contains(&extension) {
+        Category::Assets
+    } else if DOC_NAMES.contains(&stem) || DOC_EXTENSIONS.contains(&extension) {
+        Category::Docs
+    } else if DATA_EXTENSIONS.contains(&extension) {
+        Category::Data
+    } else if CONFIG_EXTENSIONS.contains(&extension) || (stem.is_empty() && !extension.is_empty()) {
+        // `.eslintrc`, `.editorconfig` and other dotfiles are config
+        Category::Config
+    } else {
+        Category::Code
+    }
+}
+
+/// Directories that hold tests in common project layouts.
+const TEST_DIRECTORIES: &[&str] = &["test", "tests", "__tests__", "spec", "specs", "testing"];
+
+/// Whether the entry at `relative` is a test directory or a test file by naming convention:
+/// `tests/`, `__tests__/`, `test_*.py`, `*_test.go`, `*.spec.ts`, `*Test.java` and the like.
+pub fn is_test(relative: &Path, is_dir: bool) -> bool {
+    let name = match relative.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+
+    if is_dir {
+        return TEST_DIRECTORIES.contains(&name.to_lowercase().as_str());
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, extension.to_lowercase()),
+        None => return false,
+    };
+    let lower = stem.to_lowercase();
+
+    match extension.as_str() {
+        // test_foo.py, foo_test.py, conftest.py
+        "py" => lower.starts_with("test_") || lower.ends_with("_test") || lower == "conftest",
+        // foo_test.go
+        "go" => lower.ends_with("_test"),
+        // foo.test.ts, foo.spec.js
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => {
+            lower.ends_with(".test") || lower.ends_with(".spec")
+        }
+        // foo_spec.rb, foo_test.rb
+        "rb" => lower.ends_with("_spec") || lower.ends_with("_test"),
+        // FooTest.java, FooTests.kt, FooSpec.scala
+        "java" | "kt" | "scala" | "cs" | "swift" => {
+            stem.ends_with("Test") || stem.ends_with("Tests") || stem.ends_with("Spec")
+        }
+        _ => false,
+    }
+}
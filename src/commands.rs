@@ -0,0 +1,287 @@
+//! `commands`: how to build, test and lint a project, gathered into `commands.md` since it is
+//! the first thing a model working on the code needs to know. Commands come from the
+//! `Makefile`, `package.json` scripts, `Cargo.toml`, `go.mod` and `pyproject.toml` at the root
+//! of the source and from the `run` steps of GitHub Actions workflows and the `script` of
+//! GitLab CI jobs, whether or not the filters select those files.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Output name of what `render` writes, outside of `concat`.
+pub const COMMANDS_MD: &str = "commands.md";
+
+/// Heading of the document `render` returns.
+pub const HEADING: &str = "# Commands";
+
+/// What a command is for, judged from its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Build,
+    Test,
+    Lint,
+    Run,
+    Other,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Build => "Build",
+            Kind::Test => "Test",
+            Kind::Lint => "Lint and format",
+            Kind::Run => "Run",
+            Kind::Other => "Other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A command found in one of the files of a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub kind: Kind,
+    pub command: String,
+    /// What the command runs, when it is a name for something else such as a script.
+    pub runs: Option<String>,
+    /// The file it was found in, relative to the source.
+    pub found_in: String,
+}
+
+/// Words in a target, script or CI step that say what it is for; the first kind with a match
+/// wins.
+const KIND_WORDS: &[(Kind, &[&str])] = &[
+    (Kind::Lint, &["lint", "clippy", "fmt", "format", "eslint", "prettier", "ruff", "flake8", "black", "mypy", "typecheck", "vet", "check"]),
+    (Kind::Test, &["test", "pytest", "jest", "vitest", "spec", "coverage", "tox", "nox"]),
+    (Kind::Build, &["build", "compile", "dist", "bundle", "all"]),
+    (Kind::Run, &["start", "dev", "serve", "run", "watch"]),
+];
+
+fn kind(name: &str) -> Kind {
+    let words: Vec<String> =
+        name.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect();
+    KIND_WORDS
+        .iter()
+        .find(|(_, names)| words.iter().any(|word| names.contains(&word.as_str())))
+        .map_or(Kind::Other, |(kind, _)| *kind)
+}
+
+/// Every command found in `source`, without duplicates, in the order of the files they were
+/// found in.
+pub fn extract(source: &Path) -> Vec<Command> {
+    let read = |name: &str| fs::read_to_string(source.join(name)).ok();
+    let mut commands = Vec::new();
+    if let Some(text) = read("Makefile") {
+        commands.extend(makefile(&text));
+    }
+    if let Some(text) = read("package.json") {
+        commands.extend(package_json(&text, package_manager(source)));
+    }
+    if let Some(text) = read("Cargo.toml") {
+        commands.extend(cargo_toml(&text));
+    }
+    if read("go.mod").is_some() {
+        for (kind, command) in [(Kind::Build, "go build ./..."), (Kind::Test, "go test ./..."), (Kind::Lint, "go vet ./...")] {
+            commands.push(Command { kind, command: command.to_string(), runs: None, found_in: "go.mod".to_string() });
+        }
+    }
+    if let Some(text) = read("pyproject.toml") {
+        if text.lines().any(|line| line.trim().starts_with("[tool.pytest")) {
+            commands.push(Command { kind: Kind::Test, command: "pytest".to_string(), runs: None, found_in: "pyproject.toml".to_string() });
+        }
+    }
+    if let Ok(entries) = fs::read_dir(source.join(".github/workflows")) {
+        let mut workflows: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.ends_with(".yml") || name.ends_with(".yaml"))
+            .collect();
+        workflows.sort();
+        for name in workflows {
+            if let Some(text) = read(&format!(".github/workflows/{}", name)) {
+                commands.extend(github_workflow(&text, &format!(".github/workflows/{}", name)));
+            }
+        }
+    }
+    if let Some(text) = read(".gitlab-ci.yml") {
+        commands.extend(gitlab_ci(&text));
+    }
+
+    let mut unique: Vec<Command> = Vec::new();
+    for command in commands {
+        if !unique.iter().any(|seen| seen.command == command.command) {
+            unique.push(command);
+        }
+    }
+    unique
+}
+
+/// `commands.md`: the commands grouped by what they are for.
+pub fn render(commands: &[Command]) -> String {
+    let mut document = format!("{}\n\n", HEADING);
+    if commands.is_empty() {
+        document.push_str("No build, test or lint commands were found.\n");
+        return document;
+    }
+    let mut by_kind: BTreeMap<Kind, Vec<&Command>> = BTreeMap::new();
+    for command in commands {
+        by_kind.entry(command.kind).or_default().push(command);
+    }
+    for (kind, commands) in by_kind {
+        document.push_str(&format!("## {}\n\n", kind));
+        for command in commands {
+            match &command.runs {
+                Some(runs) => document.push_str(&format!("- `{}` runs `{}` ({})\n", command.command, runs, command.found_in)),
+                None => document.push_str(&format!("- `{}` ({})\n", command.command, command.found_in)),
+            }
+        }
+        document.push('\n');
+    }
+    document
+}
+
+/// The targets of a Makefile that say what they are for, in the order they appear.
+fn makefile(text: &str) -> Vec<Command> {
+    let mut commands = Vec::new();
+    let lines: Vec<&str> = text.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        let Some((targets, _)) = line.split_once(':') else {
+            continue;
+        };
+        // recipes are indented, variables are `NAME := value`, special targets start with a dot
+        if line.starts_with(char::is_whitespace) || line[targets.len()..].starts_with(":=") || targets.contains('=') {
+            continue;
+        }
+        let recipe = lines.get(i + 1).filter(|next| next.starts_with('\t')).map(|next| next.trim().to_string());
+        for target in targets.split_whitespace().filter(|target| !target.starts_with('.') && !target.contains('%')) {
+            let kind = kind(target);
+            if kind != Kind::Other {
+                commands.push(Command { kind, command: format!("make {}", target), runs: recipe.clone(), found_in: "Makefile".to_string() });
+            }
+        }
+    }
+    commands
+}
+
+/// The scripts of a `package.json`.
+#[derive(Deserialize)]
+struct PackageJson {
+    #[serde(default)]
+    scripts: BTreeMap<String, String>,
+}
+
+/// `npm`, or the package manager whose lock file `source` has.
+fn package_manager(source: &Path) -> &'static str {
+    [("pnpm-lock.yaml", "pnpm"), ("yarn.lock", "yarn"), ("bun.lockb", "bun"), ("bun.lock", "bun")]
+        .iter()
+        .find(|(lock, _)| source.join(lock).exists())
+        .map_or("npm", |(_, manager)| *manager)
+}
+
+fn package_json(text: &str, manager: &str) -> Vec<Command> {
+    let Ok(package) = serde_json::from_str::<PackageJson>(text) else {
+        return Vec::new();
+    };
+    // `pretest` and `postbuild` run on their own around the script they name
+    let hook = |name: &str| {
+        [name.strip_prefix("pre"), name.strip_prefix("post")].into_iter().flatten().any(|named| package.scripts.contains_key(named))
+    };
+    let scripts: Vec<(String, String)> = package.scripts.iter().filter(|(name, _)| !hook(name)).map(|(n, s)| (n.clone(), s.clone())).collect();
+    scripts
+        .into_iter()
+        .map(|(name, script)| {
+            let command = match (manager, name.as_str()) {
+                (_, "test" | "start") | ("yarn" | "pnpm", _) => format!("{} {}", manager, name),
+                _ => format!("{} run {}", manager, name),
+            };
+            Command { kind: kind(&name), command, runs: Some(script), found_in: "package.json".to_string() }
+        })
+        .collect()
+}
+
+fn cargo_toml(text: &str) -> Vec<Command> {
+    let workspace = text.lines().any(|line| line.trim() == "[workspace]");
+    let all = if workspace { " --workspace" } else { "" };
+    [(Kind::Build, "cargo build"), (Kind::Test, "cargo test"), (Kind::Lint, "cargo clippy"), (Kind::Lint, "cargo fmt --check")]
+        .into_iter()
+        .map(|(kind, command)| {
+            let command = if command == "cargo fmt --check" { command.to_string() } else { format!("{}{}", command, all) };
+            Command { kind, command, runs: None, found_in: "Cargo.toml".to_string() }
+        })
+        .collect()
+}
+
+/// The parts of a GitHub Actions workflow with commands in them.
+#[derive(Deserialize)]
+struct Workflow {
+    #[serde(default)]
+    jobs: BTreeMap<String, Job>,
+}
+
+#[derive(Deserialize)]
+struct Job {
+    #[serde(default)]
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+struct Step {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    run: Option<String>,
+}
+
+fn github_workflow(text: &str, found_in: &str) -> Vec<Command> {
+    let Ok(workflow) = serde_yaml::from_str::<Workflow>(text) else {
+        return Vec::new();
+    };
+    let mut commands = Vec::new();
+    for job in workflow.jobs.values() {
+        for step in &job.steps {
+            let Some(run) = &step.run else {
+                continue;
+            };
+            for line in script_lines(run) {
+                let kind = match kind(line) {
+                    Kind::Other => step.name.as_deref().map_or(Kind::Other, kind),
+                    kind => kind,
+                };
+                commands.push(Command { kind, command: line.to_string(), runs: None, found_in: found_in.to_string() });
+            }
+        }
+    }
+    commands
+}
+
+fn gitlab_ci(text: &str) -> Vec<Command> {
+    let Ok(jobs) = serde_yaml::from_str::<BTreeMap<String, serde_yaml::Value>>(text) else {
+        return Vec::new();
+    };
+    let mut commands = Vec::new();
+    for (name, job) in &jobs {
+        let script = match job.get("script") {
+            Some(serde_yaml::Value::Sequence(lines)) => lines.iter().filter_map(|line| line.as_str()).collect(),
+            Some(serde_yaml::Value::String(line)) => vec![line.as_str()],
+            _ => continue,
+        };
+        for line in script.into_iter().flat_map(script_lines) {
+            let kind = match kind(line) {
+                Kind::Other => kind(name),
+                kind => kind,
+            };
+            commands.push(Command { kind, command: line.to_string(), runs: None, found_in: ".gitlab-ci.yml".to_string() });
+        }
+    }
+    commands
+}
+
+/// The lines of a CI script that run something worth knowing, without comments, `echo`, `cd`
+/// and the like.
+fn script_lines(script: &str) -> impl Iterator<Item = &str> {
+    script.lines().map(str::trim).filter(|line| {
+        let first = line.split_whitespace().next().unwrap_or_default();
+        !line.is_empty() && !line.starts_with('#') && !matches!(first, "echo" | "cd" | "export" | "set" | "mkdir" | "ls" | "cat")
+    })
+}
@@ -0,0 +1,353 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::language;
+use crate::tokens::{self, Tokenize};
+
+const SCAN_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How `write_tree` starts the document.
+const TREE_START: &str = "# File tree\n\n```\n";
+
+/// How `write_footer` starts the end of the document.
+const FOOTER_START: &str = "---\n\n*Generated by lmprep ";
+
+/// Writes every selected file into a single markdown document. File contents are streamed
+/// through fixed-size buffers, so memory use does not depend on file sizes.
+pub struct ConcatWriter<W: Write> {
+    out: Counted<W>,
+    language_map: HashMap<String, String>,
+}
+
+/// Counts the tokens of what goes through it, for the footer.
+struct Counted<W: Write> {
+    inner: W,
+    tokens: tokens::Counter,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.tokens.add(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// What a first streaming pass over a file tells us before it is written out.
+struct Scan {
+    longest_backtick_run: usize,
+    binary: bool,
+    ends_with_newline: bool,
+}
+
+impl<W: Write> ConcatWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out: Counted { inner: out, tokens: tokens::Counter::default() }, language_map: HashMap::new() }
+    }
+
+    /// Counts the tokens for the footer with `tokenizer`, or estimates them without one.
+    pub fn with_tokenizer(mut self, tokenizer: Option<Arc<dyn Tokenize>>) -> Self {
+        self.out.tokens = tokens::Counter::new(tokenizer);
+        self
+    }
+
+    /// Overrides fence languages, see `Config::language_map`.
+    pub fn with_language_map(mut self, language_map: &BTreeMap<String, String>) -> Self {
+        self.language_map = language_map
+            .iter()
+            .map(|(key, language)| (key.to_lowercase(), language.clone()))
+            .collect();
+        self
+    }
+
+    pub fn write_tree(&mut self, tree: &str) -> Result<()> {
+        writeln!(self.out, "{}{}```\n", TREE_START, tree)?;
+        Ok(())
+    }
+
+    /// Adds `patch`, the diff against `base` of the files that follow, as `changes.patch`
+    /// under a heading saying so.
+    pub fn write_changes(&mut self, base: &str, patch: &str) -> Result<()> {
+        writeln!(
+            self.out,
+            "# Changes\n\n`changes.patch` is the exact diff against `{}` of the files below that changed.\n",
+            base
+        )?;
+        self.write_contents_as(patch.as_bytes(), "changes.patch", Some("diff"))?;
+        Ok(())
+    }
+
+    /// Starts a group of files under the heading `name`, see `sections`.
+    pub fn write_section(&mut self, name: &str) -> Result<()> {
+        writeln!(self.out, "# {}\n", name)?;
+        Ok(())
+    }
+
+    /// Copies `text`, part of an earlier document this one continues, as it is.
+    pub fn write_document(&mut self, text: &str) -> Result<()> {
+        self.out.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Ends the document with when, at `generated_at`, and by which version it was generated,
+    /// from `files` files selected by `filters`, and its estimated tokens.
+    pub fn write_footer(&mut self, generated_at: &str, files: usize, filters: &str) -> Result<()> {
+        let tokens = self.out.tokens.estimate();
+        writeln!(
+            self.out,
+            "{}{} on {}: {} files, ~{} tokens.*\n\n*Filters: {}.*",
+            FOOTER_START,
+            env!("CARGO_PKG_VERSION"),
+            generated_at,
+            files,
+            tokens,
+            filters
+        )?;
+        Ok(())
+    }
+
+    /// Appends `markdown` as it is, for sections that are not files.
+    pub fn write_markdown(&mut self, markdown: &str) -> Result<()> {
+        writeln!(self.out, "{}\n", markdown.trim_end())?;
+        Ok(())
+    }
+
+    /// Appends `source` as a fenced block headed by `display_name`. Returns `false` if the
+    /// file looks binary and was skipped.
+    pub fn write_file(&mut self, source: &Path, display_name: &str) -> Result<bool> {
+        let scan = scan(fs::File::open(source)?)?;
+        let language = self.language(source, || language::effective_extension(source));
+        self.write_block(&scan, display_name, &language, fs::File::open(source)?)
+    }
+
+    /// Like `write_file`, for contents that are already in memory. The fence language comes
+    /// from `display_name`'s extension, or from the contents if it has none.
+    pub fn write_contents(&mut self, contents: &[u8], display_name: &str) -> Result<bool> {
+        self.write_contents_as(contents, display_name, None)
+    }
+
+    /// Like `write_contents`, with the fence language given by the caller if it is known.
+    pub fn write_contents_as(&mut self, contents: &[u8], display_name: &str, language: Option<&str>) -> Result<bool> {
+        let scan = scan(contents)?;
+        let language = match language {
+            Some(language) => language.to_string(),
+            None => self.language(Path::new(display_name), || language::detect(contents).map(str::to_string)),
+        };
+        self.write_block(&scan, display_name, &language, contents)
+    }
+
+    /// Fence language for `path`: a `language_map` entry for its file name or extension,
+    /// otherwise its extension, otherwise whatever `sniff` detects from the content.
+    fn language(&self, path: &Path, sniff: impl FnOnce() -> Option<String>) -> String {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+        if let Some(language) = self.language_map.get(&name) {
+            return language.clone();
+        }
+
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .or_else(sniff)
+            .unwrap_or_default();
+        match self.language_map.get(&format!(".{}", extension)) {
+            Some(language) => language.clone(),
+            None => extension,
+        }
+    }
+
+    fn write_block(&mut self, scan: &Scan, display_name: &str, language: &str, mut body: impl Read) -> Result<bool> {
+        if scan.binary {
+            return Ok(false);
+        }
+
+        // a fence longer than any backtick run inside the file can never be closed early
+        let fence = "`".repeat(scan.longest_backtick_run.max(2) + 1);
+
+        writeln!(self.out, "## {}\n\n{}{}", display_name, fence, language)?;
+        io::copy(&mut body, &mut self.out)?;
+        if !scan.ends_with_newline {
+            writeln!(self.out)?;
+        }
+        writeln!(self.out, "{}\n", fence)?;
+
+        Ok(true)
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.out.flush()?;
+        Ok(self.out.inner)
+    }
+}
+
+/// `document` with the file tree `ConcatWriter::write_tree` put at its start replaced by
+/// `tree`, or with `tree` added if it has none.
+pub fn replace_tree(document: &str, tree: &str) -> String {
+    let rest = document
+        .strip_prefix(TREE_START)
+        .and_then(|tree| tree.find("```\n\n").map(|end| &tree[end + 5..]))
+        .unwrap_or(document);
+    format!("{}{}```\n\n{}", TREE_START, tree, rest)
+}
+
+/// `document` without the footer `ConcatWriter::write_footer` ended it with, or `None` if it
+/// has none.
+pub fn remove_footer(document: &str) -> Option<&str> {
+    match document.starts_with(FOOTER_START) {
+        true => Some(""),
+        false => document.rfind(&format!("\n{}", FOOTER_START)).map(|at| &document[..at + 1]),
+    }
+}
+
+/// `document` without the block `ConcatWriter` wrote for `display_name`, or `None` if it has
+/// no such block.
+pub fn remove_block(document: &str, display_name: &str) -> Option<String> {
+    let block = find_block(document, display_name)?;
+    Some(format!("{}{}", &document[..block.start], &document[block.end..]))
+}
+
+/// The contents of the file in the block `ConcatWriter` wrote for `display_name`, without
+/// the fences.
+pub fn block_contents<'a>(document: &'a str, display_name: &str) -> Option<&'a str> {
+    let block = find_block(document, display_name)?;
+    Some(&document[block.contents])
+}
+
+struct Block {
+    /// Where its heading starts.
+    start: usize,
+    /// Where the block after it starts.
+    end: usize,
+    contents: Range<usize>,
+}
+
+/// The block headed by `display_name`. The document is walked line by line, so that a heading
+/// inside the fenced contents of another file, such as a markdown file, is never taken for it.
+fn find_block(document: &str, display_name: &str) -> Option<Block> {
+    let heading = format!("## {}", display_name);
+    // the length of the fence the line is inside of
+    let mut open: Option<usize> = None;
+    let mut at = 0;
+    while at < document.len() {
+        let end = document[at..].find('\n').map_or(document.len(), |i| at + i + 1);
+        let line = document[at..end].trim_end_matches('\n');
+        let backticks = line.bytes().take_while(|&b| b == b'`').count();
+        match open {
+            Some(fence) if backticks >= fence && backticks == line.len() => open = None,
+            Some(_) => {}
+            None if backticks >= 3 => open = Some(backticks),
+            None if line == heading && document[end..].starts_with('\n') => return block_at(document, at, heading.len() + 2),
+            None => {}
+        }
+        at = end;
+    }
+    None
+}
+
+/// The block whose heading, `heading_len` bytes with the blank line after it, starts at
+/// `start`.
+fn block_at(document: &str, start: usize, heading_len: usize) -> Option<Block> {
+    let fence = start + heading_len;
+    let block = &document[fence..];
+    // no backtick run inside the block is as long as its fence
+    let fence_len = block.bytes().take_while(|&b| b == b'`').count();
+    let closing = format!("\n{}\n\n", "`".repeat(fence_len));
+    let closing_at = block.find(&closing)?;
+    let contents_at = block.find('\n')? + 1;
+    Some(Block {
+        start,
+        end: fence + closing_at + closing.len(),
+        contents: fence + contents_at.min(closing_at)..fence + closing_at,
+    })
+}
+
+fn scan(mut reader: impl Read) -> io::Result<Scan> {
+    let mut buffer = vec![0u8; SCAN_BUFFER_SIZE];
+    let mut result = Scan { longest_backtick_run: 0, binary: false, ends_with_newline: true };
+    let mut run = 0;
+    let mut last = None;
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            match byte {
+                0 => {
+                    result.binary = true;
+                    return Ok(result);
+                }
+                b'`' => {
+                    run += 1;
+                    result.longest_backtick_run = result.longest_backtick_run.max(run);
+                }
+                _ => run = 0,
+            }
+        }
+        last = Some(buffer[read - 1]);
+    }
+
+    result.ends_with_newline = matches!(last, None | Some(b'\n'));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(files: &[(&str, &str)]) -> String {
+        let mut writer = ConcatWriter::new(Vec::new());
+        for (name, contents) in files {
+            writer.write_contents(contents.as_bytes(), name).unwrap();
+        }
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn find_block_skips_headings_inside_fences() {
+        let readme = "# Notes\n\n## src/a.rs\n\n```rust\nfn not_this() {}\n```\n";
+        let document = document(&[("README.md", readme), ("src/a.rs", "fn a() {}\n")]);
+        assert_eq!(block_contents(&document, "src/a.rs"), Some("fn a() {}"));
+        assert_eq!(block_contents(&document, "README.md"), Some(readme.trim_end()));
+
+        let removed = remove_block(&document, "src/a.rs").unwrap();
+        assert!(removed.contains("fn not_this"));
+        assert!(!removed.contains("fn a()"));
+    }
+
+    #[test]
+    fn remove_footer_strips_only_the_written_footer() {
+        let mut writer = ConcatWriter::new(Vec::new());
+        writer.write_contents(b"---\n\n*Generated by hand*\n", "notes.md").unwrap();
+        let body = String::from_utf8(writer.out.inner.clone()).unwrap();
+        writer.write_footer("2026-01-01", 1, "none").unwrap();
+        let document = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(remove_footer(&document), Some(body.as_str()));
+        assert_eq!(remove_footer(&body), None);
+    }
+
+    #[test]
+    fn remove_footer_of_an_empty_document() {
+        let mut writer = ConcatWriter::new(Vec::new());
+        writer.write_footer("2026-01-01", 0, "none").unwrap();
+        let document = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(remove_footer(&document), Some(""));
+    }
+
+    #[test]
+    fn find_block_finds_the_first_block() {
+        let document = document(&[("a.txt", "one\n"), ("b.txt", "two\n")]);
+        assert_eq!(block_contents(&document, "a.txt"), Some("one"));
+        assert_eq!(remove_block(&document, "a.txt").as_deref(), Some("## b.txt\n\n```txt\ntwo\n```\n\n"));
+        assert_eq!(block_contents(&document, "c.txt"), None);
+    }
+}
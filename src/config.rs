@@ -0,0 +1,798 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::classify::{self, Category};
+use crate::copy_mode::CopyMode;
+use crate::flatten::{self, FlattenScheme};
+use crate::pii::PiiPolicy;
+use crate::sampling::SampleStrata;
+use crate::secrets::SecretScan;
+use crate::terraform;
+use crate::transforms::{LongLines, TransformRule};
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct Config {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub allowed_extensions: Vec<String>,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: String,
+    #[serde(default = "default_subfolder")]
+    pub subfolder: String,
+    /// Name of the zip or concatenated output, with variables such as `{repo}` and `{date}`
+    /// filled in at run time, see `naming::render`.
+    #[serde(default)]
+    pub output_name_template: Option<String>,
+    #[serde(default)]
+    pub zip: bool,
+    #[serde(default)]
+    pub tree: bool,
+    #[serde(default)]
+    pub concat: bool,
+    /// End concatenated output with when, by which version and with which filters it was made.
+    #[serde(default = "default_footer")]
+    pub footer: bool,
+    /// Start the output with a project brief, see `brief`.
+    #[serde(default)]
+    pub brief: bool,
+    /// Add the build, test and lint commands `commands` finds as `commands.md`.
+    #[serde(default)]
+    pub commands: bool,
+    /// Outputs to write in one run; empty means the one `zip` and `concat` choose.
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+    /// Write `search_index.json` next to the outputs, for `lm search`.
+    #[serde(default)]
+    pub search_index: bool,
+    #[serde(default = "default_ignored_directories")]
+    pub ignored_directories: Vec<String>,
+    /// Gitignore-style patterns relative to the source; `!pattern` re-includes what an
+    /// earlier pattern excluded.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Regular expressions searched for anywhere in a file; files that match one are left
+    /// out, such as those marked `DO NOT SHARE` or generated by `protoc`.
+    #[serde(default)]
+    pub exclude_content_patterns: Vec<String>,
+    /// When set, only these files and directories, relative to the source: listed files are
+    /// included even if the filters would skip them, listed directories are walked as usual.
+    #[serde(default)]
+    pub include_paths: Vec<String>,
+    #[serde(default)]
+    pub only_categories: Vec<Category>,
+    #[serde(default)]
+    pub exclude_categories: Vec<Category>,
+    #[serde(default = "default_include_tests")]
+    pub include_tests: bool,
+    #[serde(default = "default_respect_gitignore")]
+    pub respect_gitignore: bool,
+    /// Only include files git tracks (`git ls-files`).
+    #[serde(default)]
+    pub tracked_only: bool,
+    /// Whether the files are read from disk or as committed, see `ContentSource`.
+    #[serde(default)]
+    pub content_source: ContentSource,
+    /// Let new files git does not track yet, but does not ignore, through git based selection.
+    #[serde(default)]
+    pub include_untracked: bool,
+    /// Only include files that differ from this git revision, and add the diff itself as
+    /// `changes.patch`.
+    #[serde(default)]
+    pub diff: Option<String>,
+    /// Add `history.txt` with the last this many commits that touched the selected files.
+    #[serde(default)]
+    pub history: Option<usize>,
+    #[serde(default)]
+    pub submodules: Submodules,
+    #[serde(default)]
+    pub lfs: LfsPointers,
+    #[serde(default)]
+    pub vendored: VendoredCode,
+    #[serde(default)]
+    pub skip_trivial: TrivialFiles,
+    /// Regular expressions for lines `skip_trivial: trivial` counts as boilerplate, besides
+    /// its own such as `"use strict";`.
+    #[serde(default)]
+    pub trivial_patterns: Vec<String>,
+    #[serde(default)]
+    pub standard_filters: bool,
+    #[serde(default)]
+    pub max_filesize: Option<u64>,
+    /// Lines longer than this many characters are cut or wrapped, see `long_lines`.
+    #[serde(default)]
+    pub max_line_length: Option<usize>,
+    #[serde(default)]
+    pub long_lines: LongLines,
+    /// In zip and concatenated output, files of at most this many bytes are merged into one
+    /// entry per directory, see `merge`.
+    #[serde(default)]
+    pub merge_small_files: Option<u64>,
+    /// Line endings as git checks the files out on Linux, see `line_endings`.
+    #[serde(default)]
+    pub normalize_line_endings: bool,
+    #[serde(default)]
+    pub max_files: Option<usize>,
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+    /// When the files would pass this many estimated tokens, cut the least important ones
+    /// down to their outlines, and drop them only if that is not enough.
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    /// Count tokens with this tokenizer instead of estimating them: `hf:<model-id>` or the
+    /// path of a `tokenizer.json`, or `approx` to keep the estimate.
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+    /// A `pricing` preset, such as `claude-sonnet-4`: print what sending the output to it
+    /// once would cost.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub sample: Option<usize>,
+    #[serde(default)]
+    pub sample_strata: SampleStrata,
+    #[serde(default)]
+    pub sample_seed: Option<u64>,
+    /// Only include the file that defines this symbol and the files that use it.
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// How far `symbol` reaches: each hop adds the files that use what the last one added.
+    #[serde(default = "default_symbol_hops")]
+    pub symbol_hops: usize,
+    /// Only include these files, relative to the source, and their neighbours in the import
+    /// graph.
+    #[serde(default)]
+    pub seeds: Vec<String>,
+    /// How many imports away from `seeds` files are still included.
+    #[serde(default = "default_seed_depth")]
+    pub seed_depth: usize,
+    /// Stop adding neighbours of `seeds` once the files would pass this many tokens.
+    #[serde(default)]
+    pub seed_token_budget: Option<usize>,
+    #[serde(default)]
+    pub threads: usize,
+    #[serde(default)]
+    pub layout: Layout,
+    #[serde(default)]
+    pub flatten_scheme: FlattenScheme,
+    #[serde(default = "default_max_filename_length")]
+    pub max_filename_length: usize,
+    #[serde(default)]
+    pub ascii_filenames: bool,
+    #[serde(default)]
+    pub copy_mode: CopyMode,
+    /// Fence languages for concatenated output, keyed by `.extension` or by file name.
+    #[serde(default)]
+    pub language_map: BTreeMap<String, String>,
+    #[serde(default)]
+    pub transforms: Vec<TransformRule>,
+    /// Named groups of files, each under its own heading in concatenated output.
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    #[serde(default)]
+    pub order: Order,
+    /// Priorities of files by glob, relative to the source: higher ones come first in the
+    /// output and are the last to be outlined or dropped by the limits. Files no glob matches
+    /// have priority 0.
+    #[serde(default)]
+    pub priorities: BTreeMap<String, i64>,
+    /// Files moved to the start of the output, in the order of these globs, since models
+    /// anchor on what they read first.
+    #[serde(default = "default_promote")]
+    pub promote: Vec<String>,
+    /// A list of recently opened files exported from an editor, see `editor_history`. Its
+    /// files rank above every other file, or are the only ones with `editor_history_only`.
+    #[serde(default)]
+    pub editor_history: Option<String>,
+    #[serde(default)]
+    pub editor_history_only: bool,
+    /// Keep API definitions in full and first, and the other files as outlines, see
+    /// `api_schema`.
+    #[serde(default)]
+    pub api_first: bool,
+    /// Replace SQL migrations with the schema they add up to, see `migrations`.
+    #[serde(default)]
+    pub consolidate_migrations: bool,
+    /// Select Terraform files with their secrets redacted, leave out `.terraform/` and state
+    /// files, and summarize the configuration, see `terraform`.
+    #[serde(default)]
+    pub terraform: bool,
+    #[serde(default)]
+    pub secret_scan: SecretScan,
+    /// Also write the findings of `secret_scan` as JSON to this file.
+    #[serde(default)]
+    pub secrets_report: Option<String>,
+    #[serde(default)]
+    pub pii: PiiPolicy,
+    /// Categories of files checked for personal data.
+    #[serde(default = "default_pii_categories")]
+    pub pii_categories: Vec<Category>,
+    /// Categories of files described in a few lines instead of included in full once they
+    /// reach `summarize_min_bytes`, see `assets`.
+    #[serde(default)]
+    pub summarize_categories: Vec<Category>,
+    #[serde(default = "default_summarize_min_bytes")]
+    pub summarize_min_bytes: u64,
+    /// Experimental: replace project names, `anonymize_terms`, strings and domains with
+    /// placeholders.
+    #[serde(default)]
+    pub anonymize: bool,
+    #[serde(default)]
+    pub anonymize_terms: Vec<String>,
+    /// Where the reverse map of `anonymize` is kept; defaults to a file per source in the
+    /// cache directory, outside the output.
+    #[serde(default)]
+    pub anonymize_map: Option<String>,
+    /// URLs of documentation pages to fetch and add as markdown, see `references`.
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+impl Config {
+    /// Whether `extension` (lowercase, `None` if the file has none) is in
+    /// `allowed_extensions`, or a Terraform extension with `terraform`; an empty list allows
+    /// everything.
+    pub fn allows_extension(&self, extension: Option<&str>) -> bool {
+        if self.allowed_extensions.is_empty() {
+            return true;
+        }
+        if self.terraform && extension.is_some_and(|ext| terraform::EXTENSIONS.contains(&ext)) {
+            return true;
+        }
+
+        match extension {
+            Some(ext) => self.allowed_extensions.iter().any(|e| e.to_lowercase() == ext),
+            None => false,
+        }
+    }
+
+    /// The outputs a run writes: `outputs`, or else a zip with `zip`, a concatenated document
+    /// with `concat` or an output folder.
+    pub fn outputs(&self) -> Vec<Output> {
+        if !self.outputs.is_empty() {
+            let mut outputs = Vec::new();
+            for output in &self.outputs {
+                if !outputs.contains(output) {
+                    outputs.push(*output);
+                }
+            }
+            return outputs;
+        }
+        vec![match (self.zip, self.concat) {
+            (true, _) => Output::Zip,
+            (false, true) => Output::Concat,
+            (false, false) => Output::Folder,
+        }]
+    }
+
+    /// Whether `path`'s category passes `only_categories` and `exclude_categories`.
+    pub fn allows_category(&self, path: &Path) -> bool {
+        if self.only_categories.is_empty() && self.exclude_categories.is_empty() {
+            return true;
+        }
+        let category = classify::category(path);
+        (self.only_categories.is_empty() || self.only_categories.contains(&category))
+            && !self.exclude_categories.contains(&category)
+    }
+}
+
+/// `ignored_directories` split into bare names, which match at any depth, and entries
+/// containing a `/` (`/build`, `src/generated/`), which only match that path relative to the
+/// source. Matching is case-insensitive.
+pub struct IgnoredDirectories {
+    names: Vec<String>,
+    anchored: Vec<String>,
+}
+
+impl IgnoredDirectories {
+    pub fn new(entries: &[String]) -> Self {
+        let mut names = Vec::new();
+        let mut anchored = Vec::new();
+        for entry in entries {
+            let entry = entry.to_lowercase();
+            let trimmed = entry.trim_matches('/');
+            if trimmed.contains('/') || entry.starts_with('/') {
+                anchored.push(trimmed.to_string());
+            } else {
+                names.push(trimmed.to_string());
+            }
+        }
+        Self { names, anchored }
+    }
+
+    /// Whether the entry at `relative` (to the source, `/`-separated) is ignored. Its parents
+    /// are not checked.
+    pub fn matches(&self, relative: &str) -> bool {
+        let relative = relative.to_lowercase();
+        let name = relative.rsplit('/').next().unwrap_or_default();
+        self.names.iter().any(|n| n == name) || self.anchored.contains(&relative)
+    }
+}
+
+/// One `sections` entry: a heading in concatenated output and the files that go under it.
+/// A file goes into the first section with a glob that matches it.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Section {
+    pub name: String,
+    /// Matched against the path relative to the source.
+    pub globs: Vec<String>,
+    /// Budget of the section's files in estimated tokens, applied like `max_tokens` and
+    /// before it.
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+}
+
+/// What a run writes into the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    /// The files themselves, flattened or mirrored
+    Folder,
+    /// `context.zip`
+    Zip,
+    /// `context.md`, every file in a fenced code block
+    Concat,
+    /// `context.html`, a page with a file tree and highlighted sources for reading in a browser
+    Html,
+    /// `context.db`, a SQLite database of the files and the symbols they define
+    Sqlite,
+}
+
+/// How collected files are arranged inside the output folder or zip.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Join path components with the delimiter into a single file name
+    #[default]
+    Flat,
+    /// Reproduce the original directory tree
+    Mirror,
+}
+
+/// The order of the files in the output, see `ordering`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Order {
+    /// By path
+    #[default]
+    Alpha,
+    /// Smallest first
+    Size,
+    /// Fewest estimated tokens first
+    Tokens,
+    /// Most recently committed first
+    GitRecency,
+    /// Most imported by the other files first
+    ImportRank,
+}
+
+/// Which version of the files goes into the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentSource {
+    /// The files as they are on disk, with uncommitted changes
+    #[default]
+    WorkingTree,
+    /// The files as committed at HEAD, read from the repository
+    Head,
+}
+
+/// What to do with git submodules and other nested repositories under the source.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Submodules {
+    /// Leave them out
+    Skip,
+    /// Walk them like any other directory, with their own ignore files
+    #[default]
+    Include,
+    /// Only the files at the top of each submodule (README, manifests), not its subdirectories
+    IncludeShallow,
+}
+
+/// What to do with Git LFS pointer files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LfsPointers {
+    /// Leave them out
+    #[default]
+    Skip,
+    /// Include the pointer, headed by a note that the real file is not included
+    Annotate,
+    /// Include the real content if it is text and within the size cap, otherwise annotate
+    Fetch,
+}
+
+/// What to do with vendored third-party code, see `vendored`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum VendoredCode {
+    /// Leave it out
+    #[default]
+    Exclude,
+    /// Leave it out and describe each vendored directory in `vendored.md`
+    Summarize,
+    /// Walk it like any other directory
+    Include,
+}
+
+/// Which files without content to leave out, see `skip_trivial`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TrivialFiles {
+    /// Keep every file
+    Keep,
+    /// Leave out files that are empty or only whitespace
+    #[default]
+    Empty,
+    /// Also leave out files of only comments and boilerplate lines
+    Trivial,
+}
+
+fn default_version() -> u32 { CONFIG_SCHEMA_VERSION }
+fn default_delimiter() -> String { "^".to_string() }
+fn default_subfolder() -> String { "context".to_string() }
+fn default_include_tests() -> bool { true }
+fn default_footer() -> bool { true }
+fn default_respect_gitignore() -> bool { true }
+fn default_pii_categories() -> Vec<Category> { vec![Category::Data, Category::Docs] }
+fn default_summarize_min_bytes() -> u64 { 8192 }
+fn default_max_filename_length() -> usize { flatten::DEFAULT_MAX_FILENAME_LENGTH }
+fn default_symbol_hops() -> usize { 1 }
+fn default_seed_depth() -> usize { 2 }
+
+fn default_promote() -> Vec<String> {
+    [
+        "README*", "readme*", "src/main.rs", "src/lib.rs", "main.go", "main.py", "app.py", "src/index.ts",
+        "src/index.js", "index.ts", "index.js", "Cargo.toml", "package.json", "pyproject.toml", "go.mod",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_ignored_directories() -> Vec<String> {
+    vec![
+        "node_modules".to_string(),
+        "venv".to_string(),
+        ".venv".to_string(),
+        "env".to_string(),
+        ".env".to_string(),
+        "target".to_string(),
+        "build".to_string(),
+        "dist".to_string(),
+        "__pycache__".to_string(),
+        ".git".to_string(),
+        ".idea".to_string(),
+        ".vs".to_string(),
+        ".vscode".to_string(),
+    ]
+}
+
+/// Version of the config file format understood by this build.
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+// Include default config at compile time
+pub const DEFAULT_CONFIG: &str = include_str!("../default_config.yml");
+
+impl Default for Config {
+    fn default() -> Self {
+        match parse_config(DEFAULT_CONFIG) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Warning: Error parsing default config: {}. Using hardcoded defaults.", e);
+                Self {
+                    version: CONFIG_SCHEMA_VERSION,
+                    allowed_extensions: vec![],
+                    delimiter: default_delimiter(),
+                    subfolder: default_subfolder(),
+                    output_name_template: None,
+                    zip: false,
+                    tree: false,
+                    concat: false,
+                    footer: default_footer(),
+                    brief: false,
+                    commands: false,
+                    outputs: vec![],
+                    search_index: false,
+                    ignored_directories: default_ignored_directories(),
+                    exclude: vec![],
+                    exclude_content_patterns: vec![],
+                    include_paths: vec![],
+                    only_categories: vec![],
+                    exclude_categories: vec![],
+                    include_tests: default_include_tests(),
+                    respect_gitignore: default_respect_gitignore(),
+                    tracked_only: false,
+                    content_source: ContentSource::default(),
+                    include_untracked: false,
+                    diff: None,
+                    history: None,
+                    submodules: Submodules::default(),
+                    skip_trivial: TrivialFiles::default(),
+                    trivial_patterns: vec![],
+                    lfs: LfsPointers::default(),
+                    vendored: VendoredCode::default(),
+                    standard_filters: false,
+                    max_filesize: None,
+                    max_line_length: None,
+                    long_lines: LongLines::default(),
+                    merge_small_files: None,
+                    normalize_line_endings: false,
+                    max_files: None,
+                    max_total_bytes: None,
+                    max_tokens: None,
+                    tokenizer: None,
+                    model: None,
+                    sample: None,
+                    sample_strata: SampleStrata::default(),
+                    sample_seed: None,
+                    symbol: None,
+                    symbol_hops: default_symbol_hops(),
+                    seeds: vec![],
+                    seed_depth: default_seed_depth(),
+                    seed_token_budget: None,
+                    threads: 0,
+                    layout: Layout::default(),
+                    flatten_scheme: FlattenScheme::default(),
+                    max_filename_length: default_max_filename_length(),
+                    ascii_filenames: false,
+                    copy_mode: CopyMode::default(),
+                    language_map: BTreeMap::new(),
+                    transforms: vec![],
+                    sections: vec![],
+                    order: Order::default(),
+                    priorities: BTreeMap::new(),
+                    promote: default_promote(),
+                    editor_history: None,
+                    editor_history_only: false,
+                    api_first: false,
+                    consolidate_migrations: false,
+                    terraform: false,
+                    secret_scan: SecretScan::default(),
+                    secrets_report: None,
+                    pii: PiiPolicy::default(),
+                    pii_categories: default_pii_categories(),
+                    summarize_categories: vec![],
+                    summarize_min_bytes: default_summarize_min_bytes(),
+                    anonymize: false,
+                    anonymize_terms: vec![],
+                    anonymize_map: None,
+                    references: vec![],
+                }
+            }
+        }
+    }
+}
+
+pub fn load_config(config_path: &Option<String>) -> Result<Config> {
+    if let Some(path) = config_path {
+        return load_config_from_path(path);
+    }
+
+    for ext in &[".yml", ".yaml"] {
+        let config_path = format!(".lmprep{}", ext);
+        if Path::new(&config_path).exists() {
+            return load_config_from_path(&config_path);
+        }
+    }
+
+    if let Ok(home) = env::var("HOME") {
+        let home_path = PathBuf::from(home);
+        for ext in &[".yml", ".yaml"] {
+            let config_path = home_path.join(format!(".lmprep{}", ext));
+            match fs::read_to_string(&config_path) {
+                Ok(contents) => {
+                    return parse_config(&contents)
+                        .with_context(|| format!("Error parsing config file {}", config_path.display()))
+                }
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    eprintln!("Warning: Could not read config from home directory (permission denied)");
+                    break;  // Don't try other extensions if we have permission issues
+                }
+                Err(_) => continue,  // Try next extension
+            }
+        }
+    }
+
+    if let Ok(home) = env::var("USERPROFILE") {
+        let home_path = PathBuf::from(home);
+        for ext in &[".yml", ".yaml"] {
+            let config_path = home_path.join(format!(".lmprep{}", ext));
+            match fs::read_to_string(&config_path) {
+                Ok(contents) => {
+                    return parse_config(&contents)
+                        .with_context(|| format!("Error parsing config file {}", config_path.display()))
+                }
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    eprintln!("Warning: Could not read config from home directory (permission denied)");
+                    break;  // Don't try other extensions if we have permission issues
+                }
+                Err(_) => continue,  // Try next extension
+            }
+        }
+    }
+
+    eprintln!("No config file found, using defaults");
+    Ok(Config::default())
+}
+
+/// Loads the config files at `paths` merged in order, later files overriding earlier ones:
+/// nested mappings such as `language_map` are merged key by key, anything else is replaced.
+/// Every file is read and parsed as strictly as by `load_config_from_path`, however many there
+/// are. Without any paths this is `load_config(&None)`.
+pub fn load_configs(paths: &[String]) -> Result<Config> {
+    if paths.is_empty() {
+        return load_config(&None);
+    }
+    let mut merged = Value::Mapping(Mapping::new());
+    for path in paths {
+        merge(&mut merged, read_document(path)?);
+    }
+    serde_yaml::from_value(merged).with_context(|| match paths {
+        [path] => format!("Error parsing config file {}", path),
+        paths => format!("Error parsing config files {}", paths.join(", ")),
+    })
+}
+
+/// Merges `overrides` into `base`, see `load_configs`.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Mapping(base), Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Loads the config file at `path`. A file that cannot be read or parsed, or that is for a
+/// newer schema, is an error rather than a reason to fall back to the defaults.
+pub fn load_config_from_path(path: &str) -> Result<Config> {
+    serde_yaml::from_value(read_document(path)?).with_context(|| format!("Error parsing config file {}", path))
+}
+
+/// The config file at `path` as a document in the current schema, see `parse_document`.
+fn read_document(path: &str) -> Result<Value> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Could not read config file {}", path))?;
+    parse_document(&contents).with_context(|| format!("Error parsing config file {}", path))
+}
+
+/// Parses a config file, upgrading it to the current schema first so that files written for
+/// older versions keep working.
+pub fn parse_config(contents: &str) -> Result<Config> {
+    Ok(serde_yaml::from_value(parse_document(contents)?)?)
+}
+
+/// A config file as a document in the current schema, before it becomes a `Config`.
+fn parse_document(contents: &str) -> Result<Value> {
+    let mut value: Value = serde_yaml::from_str(contents)?;
+    if value.is_null() {
+        value = Value::Mapping(Mapping::new());
+    }
+    migrate(&mut value)?;
+    Ok(value)
+}
+
+/// Schema upgrades, one per version: `MIGRATIONS[n]` turns a version `n` document into a
+/// version `n + 1` document.
+const MIGRATIONS: &[fn(&mut Mapping)] = &[migrate_v0_to_v1];
+
+/// Version 0 files predate the `version` key; their fields carry over unchanged.
+fn migrate_v0_to_v1(_config: &mut Mapping) {}
+
+/// Upgrades a config document in place to `CONFIG_SCHEMA_VERSION` and returns the version it
+/// started at. Files without a `version` key are treated as version 0.
+pub fn migrate(value: &mut Value) -> Result<u32> {
+    let mapping = value
+        .as_mapping_mut()
+        .ok_or_else(|| anyhow!("config file must be a mapping of options"))?;
+
+    let from = match mapping.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow!("`version` must be a whole number"))?,
+    };
+    if from > CONFIG_SCHEMA_VERSION {
+        bail!(
+            "config schema version {} is newer than this lmprep supports ({}), please upgrade lmprep",
+            from,
+            CONFIG_SCHEMA_VERSION
+        );
+    }
+
+    for step in &MIGRATIONS[from as usize..] {
+        step(mapping);
+    }
+
+    // keep `version` as the first key so it is easy to spot
+    mapping.remove("version");
+    let mut upgraded = Mapping::new();
+    upgraded.insert(Value::from("version"), Value::from(CONFIG_SCHEMA_VERSION));
+    upgraded.extend(std::mem::take(mapping));
+    *mapping = upgraded;
+
+    Ok(from)
+}
+
+/// Rewrites the config file at `path` in the current schema, keeping a `.bak` copy of the
+/// original. With `dry_run` the upgraded file is printed instead.
+pub fn migrate_file(path: &str, dry_run: bool) -> Result<()> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read config file {}: {}", path, e))?;
+    let mut value: Value = serde_yaml::from_str(&contents)?;
+    let from = migrate(&mut value)?;
+    let upgraded = serde_yaml::to_string(&value)?;
+
+    // make sure the result is still a valid config before touching the file
+    serde_yaml::from_value::<Config>(value)?;
+
+    if dry_run {
+        print!("{}", upgraded);
+        return Ok(());
+    }
+
+    if from == CONFIG_SCHEMA_VERSION {
+        println!("{} is already at schema version {}", path, CONFIG_SCHEMA_VERSION);
+        return Ok(());
+    }
+
+    let backup = format!("{}.bak", path);
+    fs::copy(path, &backup)?;
+    fs::write(path, upgraded)?;
+    println!(
+        "Migrated {} from schema version {} to {} (original saved as {}, comments are not preserved)",
+        path, from, CONFIG_SCHEMA_VERSION, backup
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_upgrades_files_without_a_version() {
+        let mut value: Value = serde_yaml::from_str("delimiter: \"+\"\ninclude_tests: false\n").unwrap();
+        assert_eq!(migrate(&mut value).unwrap(), 0);
+        let keys: Vec<&str> = value.as_mapping().unwrap().keys().filter_map(Value::as_str).collect();
+        assert_eq!(keys, ["version", "delimiter", "include_tests"]);
+        assert_eq!(value["version"].as_u64(), Some(u64::from(CONFIG_SCHEMA_VERSION)));
+
+        let config: Config = serde_yaml::from_value(value).unwrap();
+        assert_eq!(config.delimiter, "+");
+        assert!(!config.include_tests);
+    }
+
+    #[test]
+    fn migrate_keeps_current_files() {
+        let yaml = format!("exclude: [\"*.log\"]\nversion: {}\n", CONFIG_SCHEMA_VERSION);
+        let mut value: Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(migrate(&mut value).unwrap(), CONFIG_SCHEMA_VERSION);
+        assert_eq!(value.as_mapping().unwrap().keys().next().and_then(Value::as_str), Some("version"));
+    }
+
+    #[test]
+    fn migrate_rejects_newer_and_malformed_files() {
+        let mut newer: Value = serde_yaml::from_str(&format!("version: {}\n", CONFIG_SCHEMA_VERSION + 1)).unwrap();
+        assert!(migrate(&mut newer).unwrap_err().to_string().contains("newer than this lmprep supports"));
+
+        let mut fractional: Value = serde_yaml::from_str("version: 1.5\n").unwrap();
+        assert!(migrate(&mut fractional).unwrap_err().to_string().contains("whole number"));
+
+        let mut list: Value = serde_yaml::from_str("- exclude\n").unwrap();
+        assert!(migrate(&mut list).unwrap_err().to_string().contains("must be a mapping"));
+    }
+}
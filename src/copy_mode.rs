@@ -0,0 +1,73 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How files are placed into the output folder.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyMode {
+    /// Copy file contents
+    #[default]
+    Copy,
+    /// Hard link to the source file
+    Hardlink,
+    /// Copy-on-write clone (APFS, btrfs, XFS), falling back to a copy
+    Reflink,
+}
+
+/// Places `source` at `target` using `mode`. Hard links and reflinks fall back to a plain
+/// copy when the filesystem cannot provide them (e.g. across devices); the returned mode is
+/// the one that was actually used.
+pub fn place_file(source: &Path, target: &Path, mode: CopyMode) -> Result<CopyMode> {
+    let linked = match mode {
+        CopyMode::Copy => Err(io::Error::from(io::ErrorKind::Unsupported)),
+        CopyMode::Hardlink => fs::hard_link(source, target),
+        CopyMode::Reflink => reflink(source, target),
+    };
+
+    match linked {
+        Ok(()) => Ok(mode),
+        Err(_) => {
+            // a failed clone may leave an empty target behind
+            let _ = fs::remove_file(target);
+            fs::copy(source, target)?;
+            Ok(CopyMode::Copy)
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let src = fs::File::open(source)?;
+    let dst = fs::OpenOptions::new().write(true).create_new(true).open(target)?;
+    // SAFETY: both descriptors are valid for the duration of the call
+    let ret = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE as _, src.as_raw_fd()) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = CString::new(source.as_os_str().as_bytes())?;
+    let dst = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: both pointers are valid, NUL-terminated C strings
+    let ret = unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn reflink(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
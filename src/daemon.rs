@@ -0,0 +1,365 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::concat::ConcatWriter;
+use crate::file_filter::WalkResult;
+use crate::flatten;
+use crate::paths;
+use crate::processor::{ContentsKey, FileProcessor, Findings, CHANGES_PATCH};
+use crate::tree;
+
+/// Default socket for a source directory: one per repository, in the temp directory.
+pub fn default_socket_path(source: &str) -> PathBuf {
+    let source = Path::new(source).canonicalize().unwrap_or_else(|_| PathBuf::from(source));
+    env::temp_dir().join(format!("lmprep-{}.sock", flatten::short_hash(&source.to_string_lossy())))
+}
+
+/// A selected file as reported by `list`.
+#[derive(Serialize)]
+pub struct Listed {
+    pub path: String,
+    pub output_name: String,
+}
+
+/// The result of a pack request.
+#[derive(Serialize)]
+pub struct Packed {
+    pub files: usize,
+    pub tokens: usize,
+    pub context: String,
+}
+
+/// Walk result of the last request, reused until a directory or ignore file under the
+/// source changes.
+struct Index {
+    walked: WalkResult,
+    stamps: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+/// Contents and token estimate of a file, reused while its size, mtime and `ContentsKey` stay
+/// the same. Files without a key are read again every time.
+struct CachedFile {
+    modified: Option<SystemTime>,
+    len: u64,
+    key: Option<ContentsKey>,
+    contents: Vec<u8>,
+    language: Option<String>,
+    tokens: usize,
+}
+
+/// Keeps the file index and file contents of one source tree in memory between requests.
+pub struct Daemon<'a> {
+    processor: FileProcessor<'a>,
+    index: Option<Index>,
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// Least time between two `progress` notifications of a request, so large packs do not flood
+/// the client.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl<'a> Daemon<'a> {
+    pub fn new(processor: FileProcessor<'a>) -> Self {
+        Self { processor, index: None, files: HashMap::new() }
+    }
+
+    /// Drops every cached walk and file.
+    pub fn invalidate(&mut self) {
+        self.index = None;
+        self.files.clear();
+    }
+
+    /// Keeps the cached walk, walking again if a file was added, removed or renamed (which
+    /// changes its directory's mtime) or an ignore file was edited.
+    fn refresh_index(&mut self) -> Result<()> {
+        let stale = match &self.index {
+            Some(index) => index.stamps.iter().any(|(path, stamp)| modified(path) != *stamp),
+            None => true,
+        };
+
+        if stale {
+            let walked = self.processor.walk()?;
+            let mut stamps = Vec::new();
+            let source = self.processor.source_path().to_path_buf();
+            for dir in std::iter::once(&source).chain(&walked.directories) {
+                stamps.push((dir.clone(), modified(dir)));
+                for name in IGNORE_FILES {
+                    let ignore_file = dir.join(name);
+                    stamps.push((ignore_file.clone(), modified(&ignore_file)));
+                }
+            }
+            self.index = Some(Index { walked, stamps });
+        }
+
+        Ok(())
+    }
+
+    /// Reads and transforms `path`, or reuses the cached copy if neither it nor how it is
+    /// transformed changed since.
+    fn file<'f>(
+        processor: &FileProcessor,
+        files: &'f mut HashMap<PathBuf, CachedFile>,
+        path: &Path,
+    ) -> Result<&'f CachedFile> {
+        let metadata = fs::metadata(path)?;
+        let (modified, len) = (metadata.modified().ok(), metadata.len());
+
+        let key = processor.contents_key(path)?;
+        let fresh = files
+            .get(path)
+            .is_some_and(|cached| cached.modified == modified && cached.len == len && key.is_some() && cached.key == key);
+        if !fresh {
+            let (contents, language) = processor.read_contents(path)?;
+            let tokens = processor.count_tokens(&String::from_utf8_lossy(&contents));
+            files.insert(path.to_path_buf(), CachedFile { modified, len, key, contents, language, tokens });
+        }
+
+        Ok(&files[path])
+    }
+
+    /// Every selected file, relative to the source, with its name in the output.
+    pub fn list(&mut self) -> Result<Vec<Listed>> {
+        self.refresh_index()?;
+        let walked = &self.index.as_ref().expect("index was just built").walked;
+        let source = self.processor.source_path();
+        self.processor
+            .collect_files(walked)?
+            .into_iter()
+            .map(|file| {
+                Ok(Listed {
+                    path: self.processor.anonymize_names(paths::to_slash(file.path.strip_prefix(source)?)),
+                    output_name: file.output_name,
+                })
+            })
+            .collect()
+    }
+
+    /// The file tree of the source, as written to `filetree.txt`.
+    pub fn tree(&mut self) -> Result<String> {
+        self.refresh_index()?;
+        let walked = &self.index.as_ref().expect("index was just built").walked;
+        Ok(self
+            .processor
+            .anonymize_names(tree::render(self.processor.source_path(), &walked.directories, &walked.files)))
+    }
+
+    /// Builds the concatenated document the same way `lm --concat` does, from cached data
+    /// wherever possible.
+    pub fn pack(&mut self) -> Result<Packed> {
+        self.pack_with_progress(&mut |_, _| Ok(()))
+    }
+
+    /// `pack`, calling `progress` with the files done and the files selected before the first
+    /// file and after each one. An error from `progress` stops the pack and is returned.
+    pub fn pack_with_progress(&mut self, progress: &mut dyn FnMut(usize, usize) -> Result<()>) -> Result<Packed> {
+        self.refresh_index()?;
+        let walked = &self.index.as_ref().expect("index was just built").walked;
+        let selected = self.processor.collect_files(walked)?;
+        let config = self.processor.config();
+        let source = self.processor.source_path();
+
+        let mut writer = ConcatWriter::new(Vec::new()).with_tokenizer(self.processor.tokenizer()).with_language_map(&config.language_map);
+        let mut tokens = 0;
+        for (_, contents) in self.processor.front_matter(&selected)? {
+            tokens += self.processor.count_tokens(&contents);
+            writer.write_markdown(&contents)?;
+        }
+        if config.tree {
+            let tree = self.processor.anonymize_names(tree::render(source, &walked.directories, &walked.files));
+            tokens += self.processor.count_tokens(&tree);
+            writer.write_tree(&tree)?;
+        }
+
+        let mut findings = Findings::default();
+        for (name, contents) in self.processor.artifacts(&selected)? {
+            if self.processor.scans_output() {
+                self.processor.scan_contents(Path::new(name), name, contents.as_bytes(), &mut findings);
+            }
+            tokens += self.processor.count_tokens(&contents);
+            match &config.diff {
+                Some(base) if name == CHANGES_PATCH => writer.write_changes(base, &contents)?,
+                _ => {
+                    writer.write_contents(contents.as_bytes(), name)?;
+                }
+            }
+        }
+        let mut written = 0;
+        progress(0, selected.len())?;
+        for (done, file) in selected.iter().enumerate() {
+            let cached = Self::file(&self.processor, &mut self.files, &file.path)?;
+            let display_name = self.processor.anonymize_names(paths::to_slash(file.path.strip_prefix(source)?));
+            if self.processor.scans_output() {
+                let relative = file.path.strip_prefix(source)?;
+                self.processor.scan_contents(relative, &display_name, &cached.contents, &mut findings);
+            }
+            if writer.write_contents_as(&cached.contents, &display_name, cached.language.as_deref())? {
+                written += 1;
+                tokens += cached.tokens;
+            }
+            progress(done + 1, selected.len())?;
+        }
+
+        // forget files that are no longer selected
+        self.files.retain(|path, _| selected.iter().any(|f| &f.path == path));
+        self.processor.report_findings(&findings)?;
+        self.processor.save_anonymize_map()?;
+
+        Ok(Packed {
+            files: written,
+            tokens,
+            context: String::from_utf8_lossy(&writer.finish()?).into_owned(),
+        })
+    }
+}
+
+/// Serves `rpc` requests for `processor`'s source on the unix socket at `socket_path` until
+/// interrupted.
+#[cfg(unix)]
+pub fn run(processor: FileProcessor, socket_path: &Path, verbose: bool) -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    use crate::rpc;
+
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            bail!("Another lmprep daemon is already listening on {}", socket_path.display());
+        }
+        // left behind by a daemon that did not shut down cleanly
+        fs::remove_file(socket_path)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    let cleanup = socket_path.to_path_buf();
+    ctrlc::set_handler(move || {
+        let _ = fs::remove_file(&cleanup);
+        std::process::exit(0);
+    })?;
+    println!("lmprep daemon listening on {}", socket_path.display());
+
+    let mut daemon = Daemon::new(processor);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Warning: failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            let started = std::time::Instant::now();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = rpc::handle_line(&mut daemon, &line)?;
+            if verbose {
+                eprintln!("{} answered in {:?}", line.trim(), started.elapsed());
+            }
+            if let Some(reply) = reply {
+                if writeln!(writer, "{}", reply).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Serves `rpc` requests for `processor`'s source on stdin and stdout until stdin closes, for
+/// editor extensions that start lmprep as a child process. Stdout carries nothing but JSON:
+/// a `ready` notification first, then the responses, with `progress` notifications while a
+/// `pack` runs. Requests are answered one at a time; a `cancel` notification stops the one
+/// it names, or drops it if it has not started.
+pub fn run_stdio(processor: FileProcessor, verbose: bool) -> Result<()> {
+    use std::collections::HashSet;
+    use std::io::{self, BufRead, Write};
+    use std::sync::{mpsc, Arc, Mutex, PoisonError};
+    use std::thread;
+    use std::time::Instant;
+
+    use crate::rpc;
+
+    fn send(line: &str) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    // cancellations are read while a request runs, so stdin is read on its own thread
+    let cancelled: Arc<Mutex<HashSet<String>>> = Arc::default();
+    let (requests, incoming) = mpsc::channel();
+    let reader_cancelled = Arc::clone(&cancelled);
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(id) = rpc::cancelled_id(&line) {
+                reader_cancelled.lock().unwrap_or_else(PoisonError::into_inner).insert(id);
+            } else if requests.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    send(&rpc::ready(processor.source_path())?)?;
+    let mut daemon = Daemon::new(processor);
+    for line in incoming {
+        let started = Instant::now();
+        let id = rpc::request_id(&line);
+        let is_cancelled = || {
+            let key = id.as_ref().map(rpc::id_key).unwrap_or_default();
+            cancelled.lock().unwrap_or_else(PoisonError::into_inner).remove(&key)
+        };
+        let reply = match &id {
+            Some(id) if is_cancelled() => Some(rpc::cancelled(id.clone())?),
+            _ => {
+                let mut last_sent: Option<Instant> = None;
+                let mut progress = |done: usize, total: usize| -> Result<()> {
+                    if is_cancelled() {
+                        return Err(rpc::Cancelled.into());
+                    }
+                    let due = last_sent.is_none_or(|sent| sent.elapsed() >= PROGRESS_INTERVAL) || done == total;
+                    if let (true, Some(id)) = (due, &id) {
+                        send(&rpc::progress(id, done, total)?)?;
+                        last_sent = Some(Instant::now());
+                    }
+                    Ok(())
+                };
+                rpc::handle_line_with_progress(&mut daemon, &line, &mut progress)?
+            }
+        };
+        // a cancel that came too late must not stop a later request with the same id
+        is_cancelled();
+        if verbose {
+            eprintln!("{} answered in {:?}", line.trim(), started.elapsed());
+        }
+        if let Some(reply) = reply {
+            send(&reply)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run(_processor: FileProcessor, _socket_path: &Path, _verbose: bool) -> Result<()> {
+    bail!("lm daemon needs unix domain sockets and is not available on this platform")
+}
@@ -0,0 +1,42 @@
+//! Free space on the volume an output is written to, checked before writing so that a run
+//! that cannot fit fails at once rather than partway through.
+
+use std::path::Path;
+
+/// Free space on a volume.
+#[derive(Debug, Clone, Copy)]
+pub struct Space {
+    /// Bytes this user can still write.
+    pub available: u64,
+    /// Every file takes up a multiple of this many bytes.
+    pub block_size: u64,
+}
+
+/// The free space of the volume holding `path`, if the platform tells.
+#[cfg(unix)]
+pub fn space(path: &Path) -> Option<Space> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid, NUL-terminated C string and `stats` is large enough
+    if unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: statvfs succeeded, so it filled in `stats`
+    let stats = unsafe { stats.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    let (blocks, block_size) = (stats.f_bavail as u64, stats.f_frsize as u64);
+    Some(Space { available: blocks * block_size, block_size: block_size.max(1) })
+}
+
+#[cfg(not(unix))]
+pub fn space(_path: &Path) -> Option<Space> {
+    None
+}
+
+/// `bytes` in megabytes, for messages.
+pub fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
@@ -0,0 +1,72 @@
+//! `--from-docker <image>[:path]`: a directory from a container or image filesystem, copied
+//! out with `docker cp` and extracted to a temporary directory.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::archive::{self, Extracted};
+
+/// A directory inside a container, or inside a container created from an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DockerSource {
+    /// Container name or id, or image reference such as `app:1.4`.
+    pub target: String,
+    pub path: String,
+}
+
+impl DockerSource {
+    /// Parses `target[:path]`; the path is told apart from an image tag by its leading `/`.
+    pub fn parse(spec: &str) -> Self {
+        match spec.split_once(":/") {
+            Some((target, path)) => Self { target: target.to_string(), path: format!("/{}", path) },
+            None => Self { target: spec.to_string(), path: "/".to_string() },
+        }
+    }
+}
+
+/// A container `fetch` created from an image, removed when dropped.
+struct CreatedContainer(String);
+
+impl Drop for CreatedContainer {
+    fn drop(&mut self) {
+        let _ = docker(&["rm", "--force", &self.0]);
+    }
+}
+
+/// Copies the files under `source.path` into a temporary directory. A running or stopped
+/// container is read as it is; for an image, a container is created and removed again
+/// without ever being started.
+pub fn fetch(source: &DockerSource) -> Result<Extracted> {
+    let _created;
+    let container = if docker(&["container", "inspect", &source.target]).is_ok() {
+        source.target.clone()
+    } else {
+        // the command is never run; giving one lets images without a CMD through
+        let id = docker(&["create", &source.target, "true"])
+            .with_context(|| format!("{} is neither a container nor an image docker can create", source.target))?;
+        _created = CreatedContainer(id.clone());
+        id
+    };
+
+    // `docker cp` puts the copied directory itself at the top of the archive
+    let root = Path::new(&source.path).file_name().map_or(Path::new(""), Path::new);
+    archive::extract_output(
+        Command::new("docker").args(["cp", &format!("{}:{}", container, source.path), "-"]),
+        root,
+    )
+    .with_context(|| format!("Could not copy {} out of {}", source.path, source.target))
+}
+
+/// Runs `docker` with `args` and returns its trimmed output.
+fn docker(args: &[&str]) -> Result<String> {
+    let output = Command::new("docker")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .context("Could not run docker")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,76 @@
+//! `editor_history`: the files an editor opened recently, as a list exported from it, so a
+//! run can favour or keep to the files someone is working on. Plain lists of paths, VS Code's
+//! `file://` URIs and JetBrains' `$PROJECT_DIR$` paths are understood, in any text around them.
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+const FILE_URI: &str = "file://";
+const PROJECT_DIR: &str = "$PROJECT_DIR$/";
+
+/// The files in `history` that are under `source`, relative to it, most recent first as the
+/// editors list them. Absolute paths are compared with `source` as given, so it should be
+/// canonical.
+pub fn parse(history: &str, source: &Path) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    for line in history.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let found: Vec<PathBuf> = if line.contains(FILE_URI) {
+            tokens(line, FILE_URI).map(|uri| PathBuf::from(from_uri(uri))).collect()
+        } else if line.contains(PROJECT_DIR) {
+            tokens(line, PROJECT_DIR).map(PathBuf::from).collect()
+        } else {
+            vec![PathBuf::from(line)]
+        };
+        for path in found {
+            let relative = match path.strip_prefix(source) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) if path.is_relative() => path,
+                Err(_) => continue,
+            };
+            let relative: PathBuf = relative.components().filter(|c| *c != Component::CurDir).collect();
+            if !relative.as_os_str().is_empty() && seen.insert(relative.clone()) {
+                files.push(relative);
+            }
+        }
+    }
+    files
+}
+
+/// What follows each `prefix` in `line`, up to a quote, bracket or whitespace.
+fn tokens<'a>(line: &'a str, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+    line.split(prefix).skip(1).map(|rest| {
+        let end = rest.find(|c: char| c == '"' || c == '\'' || c == '<' || c == '>' || c.is_whitespace());
+        &rest[..end.unwrap_or(rest.len())]
+    })
+}
+
+/// The path of a `file://` URI without its scheme: `/c%3A/src/a.rs` becomes `c:/src/a.rs`.
+fn from_uri(uri: &str) -> String {
+    // `file://host/path` is rare; the host is dropped
+    let path = uri.find('/').map_or(uri, |at| &uri[at..]);
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8_lossy(&bytes).into_owned();
+    // a Windows drive after the slash: `/c:/src`
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+        _ => path,
+    }
+}
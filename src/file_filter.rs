@@ -1,91 +1,229 @@
 use anyhow::Result;
-use ignore::gitignore::{GitignoreBuilder, Gitignore};
-use std::path::Path;
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+
+use crate::config::{Submodules, VendoredCode};
+use crate::git;
+use crate::language;
+use crate::manifest;
+use crate::paths;
+use crate::selection::Selection;
+use crate::vendored;
 
 pub struct FileFilter<'a> {
     source_path: &'a Path,
-    allowed_extensions: &'a [String],
-    ignored_directories: &'a [String],
-    gitignore: Option<Gitignore>,
+    config: &'a crate::Config,
 }
 
-impl<'a> FileFilter<'a> {
-    pub fn new(source_path: &'a Path, config: &'a crate::Config) -> Result<Self> {
-        let gitignore = if config.respect_gitignore {
-            let mut builder = GitignoreBuilder::new(source_path);
-            let gitignore_path = source_path.join(".gitignore");
-            if gitignore_path.exists() {
-                builder.add(gitignore_path);
-            }
-            Some(builder.build()?)
-        } else {
-            None
-        };
+/// Everything the walker kept, sorted by path.
+pub struct WalkResult {
+    pub files: Vec<PathBuf>,
+    pub directories: Vec<PathBuf>,
+    /// Directories left out as vendored code, see `vendored`.
+    pub vendored: Vec<PathBuf>,
+}
 
-        Ok(Self {
-            source_path,
-            allowed_extensions: &config.allowed_extensions,
-            ignored_directories: &config.ignored_directories,
-            gitignore,
-        })
+impl WalkResult {
+    /// The walk with only the `kept` files, and only the directories they are in.
+    pub fn only(self, kept: &HashSet<PathBuf>) -> Self {
+        let files: Vec<PathBuf> = self.files.into_iter().filter(|file| kept.contains(file)).collect();
+        let parents: HashSet<&Path> = files.iter().flat_map(|file| file.ancestors().skip(1)).collect();
+        let directories = self.directories.into_iter().filter(|dir| parents.contains(dir.as_path())).collect();
+        Self { files, directories, vendored: self.vendored }
     }
+}
 
-    pub fn should_process_file(&self, path: &Path) -> Result<bool> {
-        if !Self::should_process_path(
-            path,
-            self.source_path,
-            self.allowed_extensions,
-            self.ignored_directories,
-            self.gitignore.as_ref(),
-        )? {
-            return Ok(false);
-        }
+/// What identifies a directory however its path is written: its device and inode.
+#[cfg(unix)]
+type DirId = (u64, u64);
+/// What identifies a directory however its path is written: its canonical path.
+#[cfg(not(unix))]
+type DirId = PathBuf;
 
-        if !self.allowed_extensions.is_empty() {
-            if let Some(ext) = path.extension() {
-                let ext_str = ext.to_string_lossy().to_lowercase();
-                if !self.allowed_extensions.iter().any(|e| e.to_lowercase() == ext_str) {
-                    return Ok(false);
-                }
-            } else {
-                return Ok(false);
-            }
+#[cfg(unix)]
+fn dir_id(path: &Path) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path) -> Option<DirId> {
+    path.canonicalize().ok()
+}
+
+/// Whether the walker may enter the directory at `path` (`depth` below the source): nested
+/// repositories are recognised by their `.git` file or directory.
+fn submodules_allow(submodules: Submodules, path: &Path, depth: usize) -> bool {
+    match submodules {
+        Submodules::Include => true,
+        Submodules::Skip => !path.join(".git").exists(),
+        Submodules::IncludeShallow => {
+            // the source's own `.git` does not make it a submodule
+            depth < 2 || !path.parent().is_some_and(|parent| parent.join(".git").exists())
         }
+    }
+}
 
-        Ok(true)
+impl<'a> FileFilter<'a> {
+    pub fn new(source_path: &'a Path, config: &'a crate::Config) -> Self {
+        Self { source_path, config }
     }
 
-    pub fn should_process_path(
-        path: &Path,
-        source_path: &Path,
-        _allowed_extensions: &[String],
-        ignored_directories: &[String],
-        gitignore: Option<&Gitignore>,
-    ) -> Result<bool> {
-        // check gitignore if enabled
-        if let Some(gitignore) = gitignore {
-            let relative_path = path.strip_prefix(source_path)?;
-            if gitignore.matched(relative_path, path.is_dir()).is_ignore() {
-                return Ok(false);
-            }
+    /// Walks the source tree with `ignore`'s parallel walker, which applies `.gitignore` files
+    /// at every level and global excludes, and keeps what `Selection` keeps. Vendored code is
+    /// left out unless `vendored` is `include`. `output_dir` is never entered.
+    pub fn walk(&self, output_dir: &Path) -> Result<WalkResult> {
+        let selection = Arc::new(Selection::new(self.config)?);
+        let keeps = Arc::clone(&selection);
+        let source_path = self.source_path.to_path_buf();
+        // by identity, as `.` and an absolute path or a symlink name the same output differently
+        let output_name = output_dir.file_name().map(|name| name.to_os_string());
+        let output_id = dir_id(output_dir);
+        let respect_gitignore = self.config.respect_gitignore;
+        let submodules = self.config.submodules;
+        let vendored_code = self.config.vendored;
+        let source_license = match vendored_code {
+            VendoredCode::Include => None,
+            _ => vendored::license(self.source_path),
+        };
+        let vendored_dirs = Arc::new(Mutex::new(Vec::new()));
+        let found_vendored = Arc::clone(&vendored_dirs);
+        if submodules != Submodules::Skip {
+            self.warn_uninitialized_submodules();
         }
 
-        let mut current = Some(path);
-        while let Some(p) = current {
-            if let Some(name) = p.file_name().and_then(|n| n.to_str()) {
-                if ignored_directories.iter().any(|ignored| {
-                    name.to_lowercase() == ignored.to_lowercase()
-                }) {
-                    return Ok(false);
+        let mut builder = WalkBuilder::new(self.source_path);
+        builder
+            .standard_filters(self.config.standard_filters)
+            .parents(self.config.standard_filters || respect_gitignore)
+            .git_ignore(respect_gitignore)
+            .git_global(respect_gitignore)
+            .git_exclude(respect_gitignore)
+            .require_git(false)
+            .follow_links(false)
+            .max_filesize(self.config.max_filesize)
+            .threads(self.config.threads)
+            .filter_entry(move |entry| {
+                if entry.depth() == 0 {
+                    return true;
                 }
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                if is_dir
+                    && output_id.is_some()
+                    && entry.file_name() == output_name.as_deref().unwrap_or_default()
+                    && dir_id(entry.path()) == output_id
+                {
+                    return false;
+                }
+                // the outputs of other runs, such as those of sibling packages
+                if is_dir && manifest::is_output_dir(entry.path()) {
+                    return false;
+                }
+                if is_dir && !submodules_allow(submodules, entry.path(), entry.depth()) {
+                    return false;
+                }
+                let kept = match entry.path().strip_prefix(&source_path) {
+                    Ok(relative) => keeps.keeps(&paths::to_slash(relative), is_dir),
+                    Err(_) => true,
+                };
+                let vendored = kept
+                    && is_dir
+                    && vendored_code != VendoredCode::Include
+                    && vendored::is_vendored(entry.path(), source_license.as_deref());
+                if vendored {
+                    found_vendored.lock().unwrap_or_else(PoisonError::into_inner).push(entry.path().to_path_buf());
+                    return false;
+                }
+                kept
+            });
+
+        let (tx, rx) = mpsc::channel::<Result<DirEntry, ignore::Error>>();
+        builder.build_parallel().run(|| {
+            let tx = tx.clone();
+            Box::new(move |entry| {
+                let _ = tx.send(entry);
+                WalkState::Continue
+            })
+        });
+        drop(tx);
+
+        let vendored = std::mem::take(&mut *vendored_dirs.lock().unwrap_or_else(PoisonError::into_inner));
+        let mut result = WalkResult { files: Vec::new(), directories: Vec::new(), vendored };
+        for entry in rx {
+            let entry = entry?;
+            if entry.depth() == 0 {
+                continue;
+            }
+            match entry.file_type() {
+                Some(ft) if ft.is_file() && self.should_process_file(entry.path()) => {
+                    let len = entry.metadata().map_or(0, |m| m.len());
+                    // files that cannot be read are kept for the run to report
+                    let left_out = !selection.keeps_size(len)
+                        || (selection.reads_contents(len)
+                            && std::fs::read(entry.path()).is_ok_and(|contents| !selection.keeps_contents(&contents)));
+                    if left_out {
+                        continue;
+                    }
+                    result.files.push(entry.into_path());
+                }
+                Some(ft) if ft.is_dir() => result.directories.push(entry.into_path()),
+                _ => {}
             }
-            current = p.parent();
         }
 
-        Ok(true)
+        let from_git = match &self.config.diff {
+            Some(base) => Some(git::changed_files(self.source_path, base)?),
+            None if self.config.tracked_only => Some(git::tracked_files(self.source_path)?),
+            None => None,
+        };
+        if let Some(mut from_git) = from_git {
+            if self.config.include_untracked {
+                from_git.extend(git::untracked_files(self.source_path)?);
+            }
+            result.files.retain(|file| from_git.contains(file));
+            // keep only directories that still lead to a file
+            let parents: HashSet<&Path> = result.files.iter().flat_map(|file| file.ancestors().skip(1)).collect();
+            result.directories.retain(|dir| parents.contains(dir.as_path()));
+        }
+
+        // the parallel walker yields entries in no particular order
+        result.files.sort();
+        result.directories.sort();
+        result.vendored.sort();
+        Ok(result)
+    }
+
+    /// Warns about submodules listed in `.gitmodules` whose directory is empty, as they have
+    /// nothing to include until they are checked out.
+    fn warn_uninitialized_submodules(&self) {
+        let Ok(gitmodules) = std::fs::read_to_string(self.source_path.join(".gitmodules")) else {
+            return;
+        };
+        for line in gitmodules.lines() {
+            let Some(path) = line.trim().strip_prefix("path").map(|rest| rest.trim_start()) else {
+                continue;
+            };
+            let Some(path) = path.strip_prefix('=').map(str::trim) else {
+                continue;
+            };
+            let dir = self.source_path.join(path);
+            let empty = std::fs::read_dir(&dir).map_or(true, |mut entries| entries.next().is_none());
+            if empty {
+                eprintln!(
+                    "Warning: submodule {} is not checked out, run `git submodule update --init` to include it",
+                    path
+                );
+            }
+        }
     }
 
-    pub fn gitignore(&self) -> Option<&Gitignore> {
-        self.gitignore.as_ref()
+    pub fn should_process_file(&self, path: &Path) -> bool {
+        // extensionless scripts are only opened when an extension list has to be checked
+        let extension_allowed = self.config.allowed_extensions.is_empty()
+            || self.config.allows_extension(language::effective_extension(path).as_deref());
+        extension_allowed && self.config.allows_category(path)
     }
 }
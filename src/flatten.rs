@@ -0,0 +1,521 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::{Config, Layout};
+
+/// Most filesystems cap a single file name at 255 bytes.
+pub const DEFAULT_MAX_FILENAME_LENGTH: usize = 255;
+
+const HASH_LEN: usize = 8;
+
+/// How a relative path is turned into a single output file name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FlattenScheme {
+    /// Join every path component with the delimiter
+    #[default]
+    Join,
+    /// Short hash of the parent directories followed by the file name
+    Hashed,
+    /// Join, then drop leading components until the name fits
+    Truncated,
+}
+
+pub struct Flattener<'a> {
+    delimiter: &'a str,
+    scheme: FlattenScheme,
+    max_len: usize,
+}
+
+impl<'a> Flattener<'a> {
+    pub fn new(delimiter: &'a str, scheme: FlattenScheme, max_len: usize) -> Self {
+        Self {
+            delimiter,
+            scheme,
+            // leave room for the hash prefix and at least one character of the name
+            max_len: max_len.max(HASH_LEN + delimiter.len() + 1),
+        }
+    }
+
+    pub fn flatten(&self, components: &[String]) -> String {
+        let escaped: Vec<String> = components.iter().map(|c| escape_delimiter(c, self.delimiter).into_owned()).collect();
+        let components = escaped.as_slice();
+        match self.scheme {
+            FlattenScheme::Join => {
+                let joined = components.join(self.delimiter);
+                if joined.len() <= self.max_len {
+                    joined
+                } else {
+                    // the joined name cannot be created on disk, fall back to truncation
+                    self.truncated(components)
+                }
+            }
+            FlattenScheme::Hashed => self.hashed(components),
+            FlattenScheme::Truncated => self.truncated(components),
+        }
+    }
+
+    fn hashed(&self, components: &[String]) -> String {
+        let (file_name, parents) = match components.split_last() {
+            Some(split) => split,
+            None => return String::new(),
+        };
+        if parents.is_empty() {
+            return self.fit(file_name);
+        }
+        let prefix = short_hash(&parents.join("/"));
+        format!("{}{}{}", prefix, self.delimiter, self.fit_with_prefix(file_name, prefix.len()))
+    }
+
+    fn truncated(&self, components: &[String]) -> String {
+        let joined = components.join(self.delimiter);
+        if joined.len() <= self.max_len {
+            return joined;
+        }
+
+        // the hash of the full path keeps names unique once leading components are dropped
+        let prefix = short_hash(&components.join("/"));
+        let budget = self.max_len - prefix.len() - self.delimiter.len();
+
+        let mut tail: Vec<&str> = Vec::new();
+        let mut tail_len = 0;
+        for component in components.iter().rev() {
+            let extra = if tail.is_empty() { component.len() } else { component.len() + self.delimiter.len() };
+            if tail_len + extra > budget {
+                break;
+            }
+            tail_len += extra;
+            tail.push(component);
+        }
+
+        let tail = if tail.is_empty() {
+            // even the file name alone is too long
+            self.fit_with_prefix(components.last().map(String::as_str).unwrap_or(""), prefix.len())
+        } else {
+            tail.reverse();
+            tail.join(self.delimiter)
+        };
+
+        format!("{}{}{}", prefix, self.delimiter, tail)
+    }
+
+    /// `name` with `hash` before the extension of its file name, shortened if that makes it
+    /// too long.
+    pub fn disambiguate(&self, name: &str, hash: &str) -> String {
+        let (dir, file_name) = match name.rfind('/') {
+            Some(idx) => name.split_at(idx + 1),
+            None => ("", name),
+        };
+        let (stem, ext) = match file_name.rfind('.') {
+            Some(idx) if idx > 0 => file_name.split_at(idx),
+            _ => (file_name, ""),
+        };
+        let marker = escape_delimiter("~", self.delimiter);
+        format!("{}{}", dir, self.fit(&format!("{}{}{}{}", stem, marker, hash, ext)))
+    }
+
+    fn fit(&self, name: &str) -> String {
+        if name.len() <= self.max_len {
+            return name.to_string();
+        }
+        let prefix = short_hash(name);
+        format!("{}{}{}", prefix, self.delimiter, self.fit_with_prefix(name, prefix.len()))
+    }
+
+    /// Shortens `name` so that it fits after a prefix of `prefix_len` bytes and the delimiter,
+    /// keeping the extension and the end of the stem.
+    fn fit_with_prefix(&self, name: &str, prefix_len: usize) -> String {
+        let budget = self.max_len - prefix_len - self.delimiter.len();
+        if name.len() <= budget {
+            return name.to_string();
+        }
+        let (stem, ext) = match name.rfind('.') {
+            Some(idx) if idx > 0 && name.len() - idx < budget => name.split_at(idx),
+            _ => (name, ""),
+        };
+        let keep = budget - ext.len();
+        let mut start = stem.len() - keep;
+        while !stem.is_char_boundary(start) {
+            start += 1;
+        }
+        format!("{}{}", &stem[start..], ext)
+    }
+}
+
+/// Checks that `delimiter` can separate path components in a file name on this platform:
+/// it must not be empty or contain characters file names cannot hold, nor `%` and hex digits,
+/// which `escape_delimiter` writes.
+pub fn validate_delimiter(delimiter: &str) -> Result<()> {
+    if delimiter.is_empty() {
+        bail!("delimiter must not be empty");
+    }
+    let invalid: &[char] = if cfg!(windows) { &['<', '>', ':', '"', '/', '\\', '|', '?', '*'] } else { &['/'] };
+    if let Some(c) = delimiter.chars().find(|c| invalid.contains(c) || c.is_control()) {
+        bail!("delimiter {:?} contains {:?}, which is not allowed in file names", delimiter, c);
+    }
+    if delimiter.chars().any(|c| c == '%' || c.is_ascii_hexdigit()) {
+        bail!("delimiter {:?} must not contain `%` or hex digits, they are used to escape it", delimiter);
+    }
+    Ok(())
+}
+
+/// Percent-escapes the occurrences of `delimiter` in a component, the way `escape_non_utf8`
+/// escapes bytes, so that `unflatten` can split the joined name again. A component that starts
+/// or ends with part of a longer delimiter has that character escaped as well, as it would
+/// otherwise form a delimiter with its neighbour, and so does a `%` that would be read back
+/// as an escape.
+pub fn escape_delimiter<'c>(component: &'c str, delimiter: &str) -> Cow<'c, str> {
+    if delimiter.is_empty() {
+        return Cow::Borrowed(component);
+    }
+    let mut escaped = Cow::Borrowed(component);
+    if component.match_indices('%').any(|(at, _)| reads_as_escape(&component[at..], delimiter)) {
+        let mut name = String::with_capacity(component.len() + 2);
+        for (at, c) in component.char_indices() {
+            match c == '%' && reads_as_escape(&component[at..], delimiter) {
+                true => name.push_str("%25"),
+                false => name.push(c),
+            }
+        }
+        escaped = Cow::Owned(name);
+    }
+    if escaped.contains(delimiter) {
+        escaped = Cow::Owned(escaped.replace(delimiter, &percent_encode(delimiter)));
+    }
+
+    let partial = |n: &usize| delimiter.is_char_boundary(*n);
+    if (1..delimiter.len()).filter(partial).any(|n| escaped.ends_with(&delimiter[..n])) {
+        let name = escaped.to_mut();
+        if let Some(last) = name.pop() {
+            name.push_str(&percent_encode(&last.to_string()));
+        }
+    }
+    if (1..delimiter.len()).filter(partial).any(|n| escaped.starts_with(&delimiter[n..])) {
+        let name = escaped.to_mut();
+        let first = name.remove(0);
+        name.insert_str(0, &percent_encode(&first.to_string()));
+    }
+    escaped
+}
+
+/// Splits a name flattened with the `join` scheme back into its components, undoing
+/// `escape_delimiter`. Names shortened by hashing or truncation lose their leading components,
+/// so only the ones that are left come back.
+pub fn unflatten(name: &str, delimiter: &str) -> Vec<String> {
+    if delimiter.is_empty() {
+        return vec![name.to_string()];
+    }
+    name.split(delimiter).map(|component| unescape_delimiter(component, delimiter)).collect()
+}
+
+/// Whether `unescape_delimiter` decodes the `%` at the start of `text`: it spells a character
+/// of `delimiter`, or is an escaped `%` before two hex digits.
+fn reads_as_escape(text: &str, delimiter: &str) -> bool {
+    let escaped_percent = text
+        .strip_prefix("%25")
+        .is_some_and(|rest| rest.len() >= 2 && rest.as_bytes()[..2].iter().all(u8::is_ascii_hexdigit));
+    escaped_percent || percent_decode_char(text).is_some_and(|(c, _)| delimiter.contains(c))
+}
+
+/// Decodes the `%XX` sequences in `component` that `escape_delimiter` wrote; every other
+/// escape belongs to `escape_non_utf8` and is kept.
+fn unescape_delimiter(component: &str, delimiter: &str) -> String {
+    let mut out = String::with_capacity(component.len());
+    let mut rest = component;
+    while let Some(at) = rest.find('%') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        match percent_decode_char(rest) {
+            Some((c, len)) if reads_as_escape(rest, delimiter) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            _ => {
+                out.push('%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The character the `%XX` sequences at the start of `text` encode, and their length.
+fn percent_decode_char(text: &str) -> Option<(char, usize)> {
+    let mut bytes = Vec::new();
+    while bytes.len() < 4 {
+        let hex = text.get(bytes.len() * 3..bytes.len() * 3 + 3)?.strip_prefix('%')?;
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        if let Ok(decoded) = std::str::from_utf8(&bytes) {
+            return decoded.chars().next().map(|c| (c, bytes.len() * 3));
+        }
+    }
+    None
+}
+
+/// Delimiters to suggest instead of one that file names use.
+const SPARE_DELIMITERS: &[&str] = &["^", "+", "~", "@", "=", "!", "#"];
+
+/// The first of a few common delimiters that none of `paths` contain, to suggest when the
+/// configured one has to be escaped in their names.
+pub fn spare_delimiter(paths: &[&Path]) -> Option<&'static str> {
+    SPARE_DELIMITERS.iter().copied().find(|spare| paths.iter().all(|path| !path.to_string_lossy().contains(spare)))
+}
+
+pub fn percent_encode(text: &str) -> String {
+    text.bytes().map(|byte| format!("%{:02X}", byte)).collect()
+}
+
+/// Name of the file at `relative` (to the source root) inside the output, following the
+/// configured layout.
+pub fn output_name(relative: &Path, config: &Config, flattener: &Flattener) -> String {
+    let components = relative
+        .components()
+        .map(|c| component_name(c.as_os_str(), config.ascii_filenames))
+        .collect::<Vec<_>>();
+    match config.layout {
+        Layout::Flat => flattener.flatten(&components),
+        Layout::Mirror => components.join("/"),
+    }
+}
+
+/// Output names of the files at `relatives`, see `output_name`, with none used twice.
+/// Sanitizing, NFC normalization and `ascii_filenames` can give distinct paths one name, such
+/// as `a:b.rs` and `a_b.rs`, or `café.rs` and `cafe.rs`: the file whose name is its path as it
+/// is keeps it, and the others get the short hash of their path before
+/// the extension. `taken` are the names other files of the output already have.
+pub fn output_names(relatives: &[&Path], config: &Config, flattener: &Flattener, taken: HashSet<String>) -> Vec<String> {
+    let mut names: Vec<String> = relatives.iter().map(|relative| output_name(relative, config, flattener)).collect();
+    let unchanged = |relative: &Path| {
+        relative.components().all(|c| c.as_os_str().to_str() == Some(&component_name(c.as_os_str(), config.ascii_filenames)))
+    };
+    let mut order: Vec<usize> = (0..relatives.len()).collect();
+    // stable, so among the changed names the first in path order keeps its name
+    order.sort_by_key(|&i| !unchanged(relatives[i]));
+
+    let mut used = taken;
+    for i in order {
+        let path: Vec<String> = relatives[i].components().map(|c| escape_non_utf8(c.as_os_str())).collect();
+        let mut attempt = 0;
+        while !used.insert(names[i].clone()) {
+            let key = match attempt {
+                0 => path.join("/"),
+                n => format!("{}#{}", path.join("/"), n),
+            };
+            names[i] = flattener.disambiguate(&output_name(relatives[i], config, flattener), &short_hash(&key));
+            attempt += 1;
+        }
+    }
+    names
+}
+
+/// FNV-1a, truncated to 32 bits. Stable across runs and platforms, unlike `DefaultHasher`.
+pub fn short_hash(input: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in input.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:0width$x}", hash as u32, width = HASH_LEN)
+}
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Makes a single path component safe to create on Windows: replaces characters that are
+/// invalid there, avoids trailing dots/spaces and renames reserved device names.
+/// Applied on every platform so that bundles are portable.
+pub fn sanitize_component(component: &str) -> String {
+    let mut name: String = component
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    if name.ends_with('.') || name.ends_with(' ') {
+        name.pop();
+        name.push('_');
+    }
+
+    let stem = name.split('.').next().unwrap_or("").trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        name.insert(0, '_');
+    }
+
+    name
+}
+
+/// Converts a raw path component into the string used for output names: bytes that are not
+/// valid UTF-8 are percent-escaped, the result is NFC-normalized and, if requested,
+/// transliterated to ASCII before being sanitized.
+pub fn component_name(component: &OsStr, ascii_only: bool) -> String {
+    let decoded = escape_non_utf8(component);
+    let normalized: String = decoded.nfc().collect();
+    let name = if ascii_only {
+        deunicode::deunicode(&normalized)
+    } else {
+        normalized
+    };
+    sanitize_component(&name)
+}
+
+/// Lossless alternative to `to_string_lossy`: every byte that is not part of a valid UTF-8
+/// sequence becomes `%XX`, and a literal `%` that would read as such an escape becomes `%25`,
+/// so two distinct components can never produce the same string.
+pub fn escape_non_utf8(component: &OsStr) -> String {
+    let mut bytes = component.as_encoded_bytes();
+    let mut escaped = String::with_capacity(bytes.len());
+
+    loop {
+        let (valid, rest) = match std::str::from_utf8(bytes) {
+            Ok(valid) => (valid, &[][..]),
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                (std::str::from_utf8(valid).unwrap_or_default(), rest)
+            }
+        };
+
+        for (idx, c) in valid.char_indices() {
+            let looks_escaped = c == '%'
+                && valid[idx + 1..].chars().take(2).filter(char::is_ascii_hexdigit).count() == 2;
+            if looks_escaped {
+                escaped.push_str("%25");
+            } else {
+                escaped.push(c);
+            }
+        }
+
+        match rest.split_first() {
+            Some((invalid, remaining)) => {
+                escaped.push_str(&format!("%{:02X}", invalid));
+                bytes = remaining;
+            }
+            None => break,
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(path: &str) -> Vec<String> {
+        path.split('/').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn unflatten_undoes_flatten() {
+        for delimiter in ["^", "__", "-+"] {
+            let flattener = Flattener::new(delimiter, FlattenScheme::Join, DEFAULT_MAX_FILENAME_LENGTH);
+            for path in ["src/main.rs", "a^b/c^.rs", "my__dir/_lead/trail_/x.rs", "a-/+b/-+/c.rs", "%5E/%255E/100%.md"] {
+                let flattened = flattener.flatten(&components(path));
+                assert_eq!(unflatten(&flattened, delimiter), components(path), "{} with {:?}", path, delimiter);
+            }
+        }
+    }
+
+    #[test]
+    fn escape_delimiter_escapes_only_what_would_split() {
+        assert_eq!(escape_delimiter("plain", "^"), "plain");
+        assert_eq!(escape_delimiter("a^b", "^"), "a%5Eb");
+        assert_eq!(escape_delimiter("trail_", "__"), "trail%5F");
+        assert_eq!(escape_delimiter("+lead", "-+"), "%2Blead");
+        assert_eq!(escape_delimiter("%5E%41%FF", "^"), "%255E%41%FF");
+        assert_eq!(unflatten("name.rs", ""), ["name.rs"]);
+    }
+
+    #[test]
+    fn sanitize_component_replaces_characters_windows_forbids() {
+        assert_eq!(sanitize_component("a:b<c>d.rs"), "a_b_c_d.rs");
+        assert_eq!(sanitize_component("what?.md"), "what_.md");
+        assert_eq!(sanitize_component("tab\there"), "tab_here");
+        assert_eq!(sanitize_component("a|b*c\"d\\e/f.rs"), "a_b_c_d_e_f.rs");
+        assert_eq!(sanitize_component("bell\u{7}.txt"), "bell_.txt");
+        assert_eq!(sanitize_component("plain.rs"), "plain.rs");
+    }
+
+    #[test]
+    fn sanitize_component_avoids_trailing_dots_and_spaces() {
+        assert_eq!(sanitize_component("notes."), "notes_");
+        assert_eq!(sanitize_component("notes "), "notes_");
+    }
+
+    #[test]
+    fn sanitize_component_renames_reserved_device_names() {
+        assert_eq!(sanitize_component("con"), "_con");
+        assert_eq!(sanitize_component("NUL.txt"), "_NUL.txt");
+        assert_eq!(sanitize_component("lpt1.tar.gz"), "_lpt1.tar.gz");
+        assert_eq!(sanitize_component("aux .c"), "_aux .c");
+        assert_eq!(sanitize_component("console.rs"), "console.rs");
+        assert_eq!(sanitize_component("com10"), "com10");
+    }
+
+    #[test]
+    fn sanitize_component_renames_reserved_names_in_any_case_and_with_extensions() {
+        for reserved in ["CON", "PRN", "AUX", "NUL", "COM1", "COM9", "LPT1", "LPT9"] {
+            assert_eq!(sanitize_component(reserved), format!("_{}", reserved));
+            assert_eq!(sanitize_component(&reserved.to_lowercase()), format!("_{}", reserved.to_lowercase()));
+            assert_eq!(sanitize_component(&format!("{}.rs", reserved)), format!("_{}.rs", reserved));
+        }
+        assert_eq!(sanitize_component("Aux.md"), "_Aux.md");
+        // with its trailing dot replaced the name is no longer reserved
+        assert_eq!(sanitize_component("nul."), "nul_");
+        assert_eq!(sanitize_component("nullable.rs"), "nullable.rs");
+        assert_eq!(sanitize_component("my.con"), "my.con");
+    }
+
+    #[test]
+    fn output_names_disambiguate_sanitized_clashes() {
+        let config = Config::default();
+        let flattener = Flattener::new(&config.delimiter, config.flatten_scheme, config.max_filename_length);
+        let relative = [Path::new("src/a:b.rs"), Path::new("src/a_b.rs")];
+        let names = output_names(&relative, &config, &flattener, HashSet::new());
+        assert_eq!(names[1], output_name(relative[1], &config, &flattener));
+        assert_ne!(names[0], names[1]);
+        assert!(names[0].ends_with(".rs"));
+        assert!(names[0].contains(&short_hash("src/a:b.rs")));
+    }
+
+    #[test]
+    fn output_names_disambiguate_normalization_clashes() {
+        let config = Config::default();
+        let flattener = Flattener::new(&config.delimiter, config.flatten_scheme, config.max_filename_length);
+        // decomposed and precomposed `é`
+        let relative = [Path::new("cafe\u{301}.rs"), Path::new("caf\u{e9}.rs")];
+        let names = output_names(&relative, &config, &flattener, HashSet::new());
+        assert_eq!(names[1], "caf\u{e9}.rs");
+        assert_eq!(names[0], format!("caf\u{e9}~{}.rs", short_hash("cafe\u{301}.rs")));
+    }
+
+    #[test]
+    fn output_names_disambiguate_transliteration_clashes() {
+        let config = Config { ascii_filenames: true, ..Config::default() };
+        let flattener = Flattener::new(&config.delimiter, config.flatten_scheme, config.max_filename_length);
+        let relative = [Path::new("caf\u{e9}.rs"), Path::new("cafe.rs")];
+        let names = output_names(&relative, &config, &flattener, HashSet::new());
+        assert_eq!(names[1], "cafe.rs");
+        assert_eq!(names[0], format!("cafe~{}.rs", short_hash("caf\u{e9}.rs")));
+    }
+
+    #[test]
+    fn output_names_avoid_taken_names() {
+        let config = Config::default();
+        let flattener = Flattener::new(&config.delimiter, config.flatten_scheme, config.max_filename_length);
+        let relative = [Path::new("lib.rs")];
+        let names = output_names(&relative, &config, &flattener, HashSet::from(["lib.rs".to_string()]));
+        assert_eq!(names, [format!("lib~{}.rs", short_hash("lib.rs"))]);
+    }
+}
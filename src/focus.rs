@@ -0,0 +1,161 @@
+//! Narrowing a run down to the code around one thing instead of the whole source: `--symbol`
+//! keeps the file that defines a symbol and the files that use it, `--seed` keeps files and
+//! their neighbours in the import graph.
+
+use anyhow::{bail, Result};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::file_filter::WalkResult;
+use crate::symbols;
+use crate::tokens::{self, Tokenize};
+
+/// Lines that pull in other files start with one of these, after indentation.
+const IMPORT_KEYWORDS: &[&str] = &["import ", "from ", "use ", "pub use ", "mod ", "#include", "require", "export "];
+
+/// File stems that name their directory's module rather than themselves.
+const MODULE_STEMS: &[&str] = &["mod", "index", "__init__", "lib", "main"];
+
+/// The files of `walked` that define `symbol`, and those within `hops` of them: each hop adds
+/// the files that use a symbol defined in the files the hop before added. `hops: 0` keeps
+/// only the definitions.
+pub fn around_symbol(walked: WalkResult, symbol: &str, hops: usize) -> Result<WalkResult> {
+    let texts: Vec<(&PathBuf, String)> = walked.files.iter().filter_map(|file| Some((file, read_text(file)?))).collect();
+
+    let definitions: HashSet<&PathBuf> = texts
+        .iter()
+        .filter(|(file, text)| symbols::extract(text, &language(file)).iter().any(|found| found.name == symbol))
+        .map(|(file, _)| *file)
+        .collect();
+    if definitions.is_empty() {
+        bail!("No selected file defines {}", symbol);
+    }
+
+    let mut selected = definitions.clone();
+    let mut wanted: HashSet<String> = HashSet::from([symbol.to_string()]);
+    for _ in 0..hops {
+        let added: Vec<&PathBuf> = texts
+            .iter()
+            .filter(|(file, text)| !selected.contains(file) && identifiers(text).any(|word| wanted.contains(word)))
+            .map(|(file, _)| *file)
+            .collect();
+        if added.is_empty() {
+            break;
+        }
+        wanted = texts
+            .iter()
+            .filter(|(file, _)| added.contains(file))
+            .flat_map(|(file, text)| symbols::extract(text, &language(file)))
+            .map(|found| found.name)
+            .collect();
+        selected.extend(added);
+    }
+
+    let selected: HashSet<PathBuf> = selected.into_iter().cloned().collect();
+    Ok(walked.only(&selected))
+}
+
+/// The `seeds`, paths relative to `source`, and the files around them in the import graph of
+/// `walked`: the files they import and the files that import them, then the same for those,
+/// up to `depth` steps away. With `token_budget`, files further out are only added while the
+/// tokens of everything kept, counted with `tokenizer` or else estimated, stay within it; the
+/// seeds are always kept.
+pub fn around_seeds(
+    walked: WalkResult,
+    source: &Path,
+    seeds: &[String],
+    depth: usize,
+    token_budget: Option<usize>,
+    tokenizer: Option<&dyn Tokenize>,
+) -> Result<WalkResult> {
+    let files: Vec<(&PathBuf, String)> = walked.files.iter().filter_map(|file| Some((file, read_text(file)?))).collect();
+    let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, (file, _)) in files.iter().enumerate() {
+        if let Some(name) = module_name(file) {
+            by_name.entry(name).or_default().push(i);
+        }
+    }
+    // both ways: what a file imports, and what imports it
+    let mut neighbours = vec![BTreeSet::new(); files.len()];
+    for (i, (_, text)) in files.iter().enumerate() {
+        for name in imported_names(text) {
+            for &j in by_name.get(name).into_iter().flatten().filter(|&&j| j != i) {
+                neighbours[i].insert(j);
+                neighbours[j].insert(i);
+            }
+        }
+    }
+
+    let mut kept = HashSet::new();
+    let mut frontier = Vec::new();
+    let mut tokens = 0;
+    for seed in seeds {
+        let seed_path: PathBuf = Path::new(seed).components().filter(|c| !matches!(c, Component::CurDir)).collect();
+        let Some(i) = files.iter().position(|(file, _)| file.strip_prefix(source).is_ok_and(|file| file == seed_path)) else {
+            bail!("Seed {} is not a text file the filters select", seed);
+        };
+        if kept.insert(i) {
+            tokens += tokens::count(tokenizer, &files[i].1);
+            frontier.push(i);
+        }
+    }
+
+    for _ in 0..depth {
+        let mut next = Vec::new();
+        for &i in &frontier {
+            for &j in &neighbours[i] {
+                if kept.contains(&j) {
+                    continue;
+                }
+                let size = tokens::count(tokenizer, &files[j].1);
+                if token_budget.is_some_and(|budget| tokens + size > budget) {
+                    continue;
+                }
+                kept.insert(j);
+                tokens += size;
+                next.push(j);
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+
+    let kept: HashSet<PathBuf> = kept.into_iter().map(|i| files[i].0.clone()).collect();
+    Ok(walked.only(&kept))
+}
+
+/// The name other files import `path` by: its stem, or its directory's name for files such as
+/// `mod.rs` and `index.js`.
+pub fn module_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_string_lossy();
+    if MODULE_STEMS.contains(&stem.as_ref()) {
+        return Some(path.parent()?.file_name()?.to_string_lossy().into_owned());
+    }
+    Some(stem.into_owned())
+}
+
+/// Identifiers and path segments in the import lines of `text`.
+pub fn imported_names(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim_start)
+        .filter(|line| IMPORT_KEYWORDS.iter().any(|keyword| line.starts_with(keyword)) || line.contains("require("))
+        .flat_map(|line| line.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')))
+        .filter(|word| !word.is_empty())
+}
+
+/// The contents of `path` if it is text.
+fn read_text(path: &Path) -> Option<String> {
+    let contents = fs::read(path).ok()?;
+    (!contents.contains(&0)).then(|| String::from_utf8_lossy(&contents).into_owned())
+}
+
+fn language(path: &Path) -> String {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).filter(|word| !word.is_empty())
+}
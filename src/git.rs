@@ -0,0 +1,351 @@
+//! Asking git which files it knows about, for selections that follow the repository rather
+//! than ignore files.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Pathspecs passed to one `git diff`, to stay well within command line length limits.
+const PATHSPECS_PER_CALL: usize = 256;
+
+/// Paths passed to one `git check-attr`, few enough that its answers fit in the pipe while
+/// the paths are still being written.
+const ATTRIBUTE_PATHS_PER_CALL: usize = 128;
+
+/// Files git tracks under `source`, joined onto `source` so they compare equal to the
+/// walker's paths.
+pub fn tracked_files(source: &Path) -> Result<HashSet<PathBuf>> {
+    Ok(paths(source, &git(source, ["ls-files", "-z", "--cached"], &[0], None)?))
+}
+
+/// Files under `source` git does not track yet but would not ignore either: new files that
+/// have not been added.
+pub fn untracked_files(source: &Path) -> Result<HashSet<PathBuf>> {
+    Ok(paths(source, &git(source, ["ls-files", "-z", "--others", "--exclude-standard"], &[0], None)?))
+}
+
+/// Tracked files under `source` whose working tree version, staged or not, differs from
+/// `base`.
+pub fn changed_files(source: &Path, base: &str) -> Result<HashSet<PathBuf>> {
+    Ok(paths(source, &git(source, ["diff", "--name-only", "-z", "--relative", base, "--"], &[0], None)?))
+}
+
+/// `git diff` of the working tree against `base` for `files`, relative to `source`, with
+/// `new_files` (untracked, so unknown to `git diff`) shown as added.
+pub fn diff(source: &Path, base: &str, files: &[&Path], new_files: &[&Path]) -> Result<String> {
+    let mut patch = Vec::new();
+    for chunk in files.chunks(PATHSPECS_PER_CALL) {
+        let args = [OsStr::new("diff"), "--relative".as_ref(), base.as_ref(), "--".as_ref()];
+        patch.extend(git(source, args.into_iter().chain(chunk.iter().map(|file| file.as_os_str())), &[0], None)?);
+    }
+    for file in new_files {
+        // exits with 1 when the files differ, which they always do
+        let args = [OsStr::new("diff"), "--no-index".as_ref(), "--".as_ref(), "/dev/null".as_ref(), file.as_os_str()];
+        patch.extend(git(source, args, &[0, 1], None)?);
+    }
+    Ok(String::from_utf8_lossy(&patch).into_owned())
+}
+
+/// The commits in `revisions` (such as `HEAD` or `main..HEAD`) that touched any of `files`,
+/// relative to `source`, newest first and at most `count` of them: hash, date, author and
+/// subject, and the files each one changed with `--stat`.
+pub fn history(source: &Path, revisions: &str, count: Option<usize>, files: &[&Path]) -> Result<String> {
+    if files.is_empty() {
+        return Ok(String::new());
+    }
+    // the paths go in on stdin, as there can be too many for a command line
+    let mut input = b"--\n".to_vec();
+    for file in files {
+        input.extend_from_slice(file.to_string_lossy().as_bytes());
+        input.push(b'\n');
+    }
+    let max_count = format!("--max-count={}", count.map_or(-1, |count| count as i64));
+    let args = ["log", &max_count, "--date=short", "--format=%n%h %ad %an%n    %s", "--stat=120", "--relative", "--stdin", revisions];
+    let log = git(source, args, &[0], Some(&input))?;
+    // `%n` first separates each commit from the files listed for the one before
+    Ok(String::from_utf8_lossy(&log).trim_start().to_string())
+}
+
+/// When each file under `source` last changed in a commit, as seconds since the epoch, keyed
+/// by the path joined onto `source`.
+pub fn last_changed(source: &Path) -> Result<HashMap<PathBuf, i64>> {
+    let log = git(source, ["-c", "core.quotePath=false", "log", "--format=>%ct", "--name-only", "--relative"], &[0], None)?;
+    let mut changed = HashMap::new();
+    let mut time = 0;
+    // newest first, so the first time a path shows up is its last change
+    for line in String::from_utf8_lossy(&log).lines() {
+        match line.strip_prefix('>') {
+            Some(seconds) => time = seconds.parse().unwrap_or_default(),
+            None if !line.is_empty() => {
+                changed.entry(source.join(line)).or_insert(time);
+            }
+            None => {}
+        }
+    }
+    Ok(changed)
+}
+
+/// The `text` and `eol` attributes of `files`, relative to `source`, as `git check-attr`
+/// reports them: `set`, `unset`, `unspecified` or the value, such as `auto` or `crlf`. Keyed
+/// by the path joined onto `source`.
+pub fn line_ending_attributes(source: &Path, files: &[&Path]) -> Result<HashMap<PathBuf, (String, String)>> {
+    let mut attributes: HashMap<PathBuf, (String, String)> = HashMap::new();
+    for chunk in files.chunks(ATTRIBUTE_PATHS_PER_CALL) {
+        let mut input = Vec::new();
+        for file in chunk {
+            input.extend_from_slice(file.to_string_lossy().as_bytes());
+            input.push(0);
+        }
+        let output = git(source, ["check-attr", "-z", "--stdin", "text", "eol"], &[0], Some(&input))?;
+        // path, attribute and value, each ended by a NUL
+        let fields: Vec<String> = output.split(|byte| *byte == 0).map(|field| String::from_utf8_lossy(field).into_owned()).collect();
+        for record in fields.chunks_exact(3) {
+            let (text, eol) = attributes.entry(source.join(&record[0])).or_default();
+            match record[1].as_str() {
+                "text" => *text = record[2].clone(),
+                _ => *eol = record[2].clone(),
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+/// `core.autocrlf` of the repository at `source`, if it is set.
+pub fn autocrlf(source: &Path) -> Option<String> {
+    config_value(source, "core.autocrlf")
+}
+
+/// `core.eol` of the repository at `source`, if it is set.
+pub fn core_eol(source: &Path) -> Option<String> {
+    config_value(source, "core.eol")
+}
+
+/// The value of the config `key` of the repository at `source`, lowercased, if it is set.
+fn config_value(source: &Path, key: &str) -> Option<String> {
+    // exits with 1 when the key is not set
+    let output = git(source, ["config", "--get", key], &[0, 1], None).ok()?;
+    let value = String::from_utf8_lossy(&output).trim().to_lowercase();
+    (!value.is_empty()).then_some(value)
+}
+
+/// The path of `source` inside its repository, empty at the top or with a trailing `/`.
+pub fn prefix(source: &Path) -> Result<String> {
+    let output = git(source, ["rev-parse", "--show-prefix"], &[0], None)?;
+    Ok(String::from_utf8_lossy(&output).trim_end_matches('\n').to_string())
+}
+
+/// The output of `git rev-parse` with `args` in `source`, such as `--abbrev-ref HEAD` for
+/// the current branch.
+pub fn rev_parse(source: &Path, args: &[&str]) -> Result<String> {
+    let output = git(source, ["rev-parse"].iter().chain(args), &[0], None)?;
+    Ok(String::from_utf8_lossy(&output).trim_end_matches('\n').to_string())
+}
+
+/// Checks out `revision` into a new worktree at `path`, detached and next to the repository
+/// `source` is in.
+pub fn add_worktree(source: &Path, revision: &str, path: &Path) -> Result<()> {
+    let args = [OsStr::new("worktree"), "add".as_ref(), "--quiet".as_ref(), "--detach".as_ref(), path.as_os_str(), revision.as_ref()];
+    git(source, args, &[0], None)?;
+    Ok(())
+}
+
+/// Deletes a worktree `add_worktree` created.
+pub fn remove_worktree(source: &Path, path: &Path) -> Result<()> {
+    let args = [OsStr::new("worktree"), "remove".as_ref(), "--force".as_ref(), path.as_os_str()];
+    git(source, args, &[0], None)?;
+    Ok(())
+}
+
+/// A worktree checked out at a revision in the temporary directory, removed when dropped.
+pub struct Worktree {
+    repo: PathBuf,
+    path: PathBuf,
+}
+
+impl Worktree {
+    /// Checks out `revision` of the repository `source` is in, into a directory named after
+    /// `purpose`, such as `review`.
+    pub fn checkout(source: &Path, revision: &str, purpose: &str) -> Result<Self> {
+        let worktree = Self {
+            repo: source.to_path_buf(),
+            path: env::temp_dir().join(format!("lmprep-{}-{}", purpose, std::process::id())),
+        };
+        add_worktree(source, revision, &worktree.path).with_context(|| format!("Could not check out {}", revision))?;
+        Ok(worktree)
+    }
+
+    /// Where `source`, the directory the checkout was made from, is in the checkout.
+    pub fn source(&self) -> Result<PathBuf> {
+        Ok(self.path.join(prefix(&self.repo)?))
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        if let Err(e) = remove_worktree(&self.repo, &self.path) {
+            eprintln!("Warning: could not remove worktree {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// The files of a revision under `source`, written to a directory in the temporary directory
+/// straight from the object database with `ls-tree` and `cat-file`, and removed when dropped.
+/// Unlike a `Worktree` nothing in the repository changes and no hooks or filters run. Git
+/// commands such as `diff` still work in it: its `.git` file points at a git directory of its
+/// own, with the revision as `HEAD` and its own index, that shares the repository's objects,
+/// refs and config through `commondir`.
+pub struct Snapshot {
+    dir: PathBuf,
+    source: PathBuf,
+}
+
+impl Snapshot {
+    /// Writes the files of `revision` under `source` into a directory named after `purpose`,
+    /// such as `head`.
+    pub fn read(source: &Path, revision: &str, purpose: &str) -> Result<Self> {
+        let dir = env::temp_dir().join(format!("lmprep-{}-{}", purpose, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        let (tree, git_dir) = (dir.join("tree"), dir.join("git"));
+        // dropped on errors below, which removes the directory again
+        let snapshot = Self { source: tree.join(prefix(source)?), dir };
+        fs::create_dir_all(&snapshot.source)?;
+        fs::create_dir_all(&git_dir)?;
+        let commit = rev_parse(source, &["--verify", &format!("{}^{{commit}}", revision)])?;
+        // relative to `source`, or absolute
+        let common_dir = source.join(rev_parse(source, &["--git-common-dir"])?).canonicalize()?;
+        fs::write(git_dir.join("commondir"), format!("{}\n", common_dir.display()))?;
+        fs::write(git_dir.join("HEAD"), format!("{}\n", commit))?;
+        fs::write(tree.join(".git"), format!("gitdir: {}\n", git_dir.display()))?;
+
+        // mode, type and object, a tab and the path from the top of the repository
+        let listing = git(source, ["ls-tree", "-r", "-z", "--full-name", &commit], &[0], None)
+            .with_context(|| format!("Could not read {}", revision))?;
+        let mut blobs = Vec::new();
+        for entry in listing.split(|&b| b == 0).filter(|entry| !entry.is_empty()) {
+            let entry = String::from_utf8_lossy(entry);
+            let Some((info, path)) = entry.split_once('\t') else {
+                continue;
+            };
+            let mut info = info.split(' ');
+            let (Some(mode), Some("blob"), Some(object)) = (info.next(), info.next(), info.next()) else {
+                // submodules are commits of another repository
+                continue;
+            };
+            blobs.push((mode.to_string(), object.to_string(), tree.join(path)));
+        }
+        write_blobs(source, &blobs).with_context(|| format!("Could not read {}", revision))?;
+        // the index of the snapshot, so that git compares its files with the revision
+        git(&tree, ["read-tree", "HEAD"], &[0], None)?;
+        Ok(snapshot)
+    }
+
+    /// Where `source`, the directory the snapshot was read from, is in the snapshot.
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Writes the `blobs`, given as their mode, object and target path, with one
+/// `git cat-file --batch` that streams them in while their names are still being written.
+fn write_blobs(source: &Path, blobs: &[(String, String, PathBuf)]) -> Result<()> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(source)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Could not run git, which --head needs")?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let objects: Vec<String> = blobs.iter().map(|(_, object, _)| object.clone()).collect();
+    let writer = thread::spawn(move || -> io::Result<()> {
+        for object in objects {
+            writeln!(stdin, "{}", object)?;
+        }
+        Ok(())
+    });
+
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let mut header = String::new();
+    for (mode, object, path) in blobs {
+        header.clear();
+        stdout.read_line(&mut header)?;
+        // object, type and size
+        let Some(size) = header.split(' ').nth(2).and_then(|size| size.trim().parse::<u64>().ok()) else {
+            bail!("object {} is missing", object);
+        };
+        let mut contents = Vec::new();
+        (&mut stdout).take(size).read_to_end(&mut contents)?;
+        // the newline after the contents
+        stdout.read_exact(&mut [0])?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if mode == "120000" {
+            // a symbolic link, whose target is its contents
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(String::from_utf8_lossy(&contents).as_ref(), path)?;
+            continue;
+        }
+        fs::write(path, &contents)?;
+    }
+    drop(stdout);
+    writer.join().map_err(|_| anyhow::anyhow!("git cat-file input failed"))??;
+    child.wait()?;
+    Ok(())
+}
+
+/// Runs git with `args` in `source`, writing `input` to its stdin, and returns its output,
+/// failing unless it exits with one of `success_codes`.
+fn git<I, S>(source: &Path, args: I, success_codes: &[i32], input: Option<&[u8]>) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new("git");
+    // paths are passed as they are, never as glob patterns
+    command.arg("-C").arg(source).args(args).env("GIT_LITERAL_PATHSPECS", "1");
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Could not run git, which --tracked-only, --diff, --history and review need")?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.code().is_some_and(|code| success_codes.contains(&code)) {
+        bail!(
+            "git {} failed in {}: {}",
+            command.get_args().nth(2).unwrap_or_default().to_string_lossy(),
+            source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// NUL separated paths relative to `source`, joined onto it.
+fn paths(source: &Path, output: &[u8]) -> HashSet<PathBuf> {
+    output
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| source.join(String::from_utf8_lossy(path).as_ref()))
+        .collect()
+}
@@ -0,0 +1,248 @@
+//! The `html` output: one self-contained page with the shared files in a sidebar tree and
+//! their sources syntax-highlighted, for a person to review exactly what the model gets.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::flatten;
+
+const STYLE: &str = "\
+body{margin:0;display:grid;grid-template-columns:18rem 1fr;height:100vh;font:14px/1.5 system-ui,sans-serif;color:#24292f}
+nav{overflow:auto;padding:1rem;border-right:1px solid #d0d7de;background:#f6f8fa}
+nav ul{list-style:none;margin:0;padding-left:1rem}nav>ul{padding:0}
+nav a{color:inherit;text-decoration:none}nav a:hover{text-decoration:underline}
+summary{cursor:pointer}
+main{overflow:auto;padding:0 1.5rem}
+h2{font-size:1rem;margin:1.5rem 0 .5rem;padding-top:.5rem;border-top:1px solid #d0d7de}
+pre{margin:0;padding:.75rem;background:#f6f8fa;border-radius:6px;overflow:auto;font:12px/1.45 ui-monospace,monospace}
+.k{color:#cf222e}.s{color:#0a3069}.c{color:#6e7781;font-style:italic}.n{color:#0550ae}
+.add{background:#dafbe1}.del{background:#ffebe9}.hunk{color:#8250df}
+.binary{color:#6e7781}";
+
+/// Words shown as keywords in every language that gets highlighted. One list for all of them
+/// keeps this small; a word that is not a keyword in some language is rare enough.
+const KEYWORDS: &[&str] = &[
+    "abstract", "and", "as", "assert", "async", "await", "bool", "break", "case", "catch", "char", "class",
+    "const", "continue", "crate", "def", "default", "defer", "del", "do", "double", "elif", "else", "end",
+    "enum", "except", "export", "extends", "extern", "false", "final", "finally", "float", "fn", "for",
+    "from", "func", "function", "go", "if", "impl", "implements", "import", "in", "int", "interface", "is",
+    "lambda", "let", "local", "long", "loop", "match", "mod", "module", "mut", "namespace", "new", "nil",
+    "none", "None", "not", "null", "or", "override", "package", "pass", "private", "protected", "pub",
+    "public", "raise", "ref", "return", "self", "Self", "static", "struct", "super", "switch", "then",
+    "this", "throw", "throws", "trait", "true", "True", "False", "try", "type", "typeof", "unsafe", "use",
+    "using", "var", "virtual", "void", "where", "while", "with", "yield",
+];
+
+/// How comments and strings look in a family of languages.
+pub(crate) struct Syntax {
+    pub(crate) line_comments: &'static [&'static str],
+    pub(crate) block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const C_LIKE: Syntax = Syntax { line_comments: &["//"], block_comment: Some(("/*", "*/")), quotes: &['"', '\'', '`'] };
+const HASH: Syntax = Syntax { line_comments: &["#"], block_comment: None, quotes: &['"', '\''] };
+const DASH: Syntax = Syntax { line_comments: &["--"], block_comment: None, quotes: &['"', '\''] };
+const MARKUP: Syntax = Syntax { line_comments: &[], block_comment: Some(("<!--", "-->")), quotes: &['"'] };
+
+pub(crate) fn syntax(language: &str) -> Option<&'static Syntax> {
+    match language {
+        "rs" | "rust" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "java" | "js" | "jsx" | "mjs" | "ts" | "tsx"
+        | "go" | "swift" | "kt" | "kts" | "scala" | "php" | "dart" | "css" | "scss" | "json" | "proto" => {
+            Some(&C_LIKE)
+        }
+        "py" | "python" | "sh" | "bash" | "zsh" | "rb" | "yaml" | "yml" | "toml" | "r" | "pl" | "ps1"
+        | "dockerfile" | "makefile" | "cmake" | "nix" | "ini" | "cfg" | "conf" => Some(&HASH),
+        "sql" | "lua" | "hs" => Some(&DASH),
+        "html" | "htm" | "xml" | "svg" | "vue" => Some(&MARKUP),
+        _ => None,
+    }
+}
+
+/// Writes the page. Every section needs the full list of names up front for the sidebar.
+pub struct HtmlWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> HtmlWriter<W> {
+    /// Starts the page titled `title`, with a sidebar tree linking to the sections of `names`,
+    /// paths with `/` separators.
+    pub fn new(mut out: W, title: &str, names: &[String]) -> Result<Self> {
+        write!(
+            out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<nav>\n",
+            escape(title),
+            STYLE
+        )?;
+        let mut root = Directory::default();
+        for name in names {
+            root.insert(name, name);
+        }
+        root.render(&mut out)?;
+        writeln!(out, "</nav>\n<main>")?;
+        Ok(Self { out })
+    }
+
+    /// Adds the section of `name` with `contents` highlighted as `language`, an extension such
+    /// as `rs`. Binary contents are only noted as left out; returns `false` for them.
+    pub fn write_file(&mut self, name: &str, contents: &[u8], language: &str) -> Result<bool> {
+        writeln!(self.out, "<h2 id=\"{}\">{}</h2>", anchor(name), escape(name))?;
+        if contents.contains(&0) {
+            writeln!(self.out, "<p class=\"binary\">Binary file, not included.</p>")?;
+            return Ok(false);
+        }
+        let code = String::from_utf8_lossy(contents);
+        writeln!(self.out, "<pre><code>{}</code></pre>", highlight(&code, &language.to_lowercase()))?;
+        Ok(true)
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        writeln!(self.out, "</main>\n</body>\n</html>")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// A directory of the sidebar tree.
+#[derive(Default)]
+struct Directory {
+    directories: BTreeMap<String, Directory>,
+    /// File names and the full names they link to.
+    files: Vec<(String, String)>,
+}
+
+impl Directory {
+    fn insert(&mut self, rest: &str, full: &str) {
+        match rest.split_once('/') {
+            Some((dir, rest)) => self.directories.entry(dir.to_string()).or_default().insert(rest, full),
+            None => self.files.push((rest.to_string(), full.to_string())),
+        }
+    }
+
+    fn render(&self, out: &mut impl Write) -> Result<()> {
+        writeln!(out, "<ul>")?;
+        for (name, directory) in &self.directories {
+            writeln!(out, "<li><details open><summary>{}/</summary>", escape(name))?;
+            directory.render(out)?;
+            writeln!(out, "</details></li>")?;
+        }
+        let mut files: Vec<&(String, String)> = self.files.iter().collect();
+        files.sort();
+        for (name, full) in files {
+            writeln!(out, "<li><a href=\"#{}\">{}</a></li>", anchor(full), escape(name))?;
+        }
+        writeln!(out, "</ul>")?;
+        Ok(())
+    }
+}
+
+/// Id of the section of `name`; names can hold any character, hashes cannot.
+fn anchor(name: &str) -> String {
+    format!("f-{}", flatten::short_hash(name))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn span(out: &mut String, class: &str, text: &str) {
+    out.push_str(&format!("<span class=\"{}\">{}</span>", class, escape(text)));
+}
+
+/// `code` as escaped HTML with keywords, strings, comments and numbers in classed spans, or
+/// added and removed lines for diffs. Languages it does not know are only escaped.
+pub fn highlight(code: &str, language: &str) -> String {
+    if matches!(language, "diff" | "patch") {
+        return highlight_diff(code);
+    }
+    let Some(syntax) = syntax(language) else {
+        return escape(code);
+    };
+
+    let mut out = String::with_capacity(code.len() * 2);
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let token_len = if syntax.line_comments.iter().any(|marker| rest.starts_with(marker)) {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            span(&mut out, "c", &rest[..end]);
+            end
+        } else if let Some((open, close)) = syntax.block_comment.filter(|(open, _)| rest.starts_with(open)) {
+            let end = rest[open.len()..].find(close).map_or(rest.len(), |end| open.len() + end + close.len());
+            span(&mut out, "c", &rest[..end]);
+            end
+        } else if syntax.quotes.contains(&c) && !(c == '\'' && is_lifetime(rest, language)) {
+            let end = string_end(rest, c);
+            span(&mut out, "s", &rest[..end]);
+            end
+        } else if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_')).unwrap_or(rest.len());
+            span(&mut out, "n", &rest[..end]);
+            end
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            match KEYWORDS.contains(&&rest[..end]) {
+                true => span(&mut out, "k", &rest[..end]),
+                false => out.push_str(&rest[..end]),
+            }
+            end
+        } else {
+            out.push_str(&escape(&rest[..c.len_utf8()]));
+            c.len_utf8()
+        };
+        rest = &rest[token_len..];
+    }
+    out
+}
+
+/// Length of the string literal at the start of `text`, quoted with `quote`. Only backtick
+/// strings continue past the end of the line.
+fn string_end(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Whether the `'` at the start of `text` begins a Rust lifetime such as `'a` rather than a
+/// character literal.
+fn is_lifetime(text: &str, language: &str) -> bool {
+    let mut chars = text.chars().skip(1);
+    matches!(language, "rs" | "rust")
+        && chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.next() != Some('\'')
+}
+
+fn highlight_diff(patch: &str) -> String {
+    let mut out = String::with_capacity(patch.len() * 2);
+    for line in patch.split_inclusive('\n') {
+        let class = match line.as_bytes().first() {
+            Some(b'+') if !line.starts_with("+++") => Some("add"),
+            Some(b'-') if !line.starts_with("---") => Some("del"),
+            Some(b'@') if line.starts_with("@@") => Some("hunk"),
+            _ => None,
+        };
+        match class {
+            Some(class) => span(&mut out, class, line),
+            None => out.push_str(&escape(line)),
+        }
+    }
+    out
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Returned by `check` once Ctrl-C was pressed, carrying how far the run got.
+#[derive(Debug)]
+pub struct Interrupted {
+    pub completed: usize,
+    pub total: usize,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted after {} of {} files", self.completed, self.total)
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Installs a Ctrl-C handler that asks the run to stop after the current file. A second
+/// Ctrl-C exits immediately.
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("\nInterrupted, finishing the current file...");
+    })?;
+    Ok(())
+}
+
+/// Fails with `Interrupted` if Ctrl-C was pressed. Called between files.
+pub fn check(completed: usize, total: usize) -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(Interrupted { completed, total }.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! Language detection for files without an extension, from the shebang line or the first
+//! bytes of content.
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from the start of an extensionless file to detect its language.
+const HEADER_SIZE: usize = 256;
+
+/// Interpreter names (without version suffix) and the extension their scripts would have.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("sh", "sh"),
+    ("bash", "sh"),
+    ("zsh", "sh"),
+    ("dash", "sh"),
+    ("ksh", "sh"),
+    ("ash", "sh"),
+    ("fish", "fish"),
+    ("python", "py"),
+    ("pypy", "py"),
+    ("node", "js"),
+    ("nodejs", "js"),
+    ("deno", "ts"),
+    ("bun", "js"),
+    ("ts-node", "ts"),
+    ("ruby", "rb"),
+    ("perl", "pl"),
+    ("php", "php"),
+    ("lua", "lua"),
+    ("rscript", "r"),
+    ("tclsh", "tcl"),
+    ("awk", "awk"),
+    ("gawk", "awk"),
+    ("groovy", "groovy"),
+    ("pwsh", "ps1"),
+    ("make", "mk"),
+];
+
+/// The extension `path` has, or for extensionless files the one its content implies. Returns
+/// `None` if neither says anything; the result is lowercase.
+pub fn effective_extension(path: &Path) -> Option<String> {
+    if let Some(ext) = path.extension() {
+        return Some(ext.to_string_lossy().to_lowercase());
+    }
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    fs::File::open(path)
+        .and_then(|file| file.take(HEADER_SIZE as u64).read_to_end(&mut header))
+        .ok()?;
+    detect(&header).map(str::to_string)
+}
+
+/// Extension matching the language of `contents`: the interpreter named by a `#!` line, or
+/// a recognisable opening such as `<?php`.
+pub fn detect(contents: &[u8]) -> Option<&'static str> {
+    let first_line = contents.split(|&b| b == b'\n').next()?;
+    let first_line = String::from_utf8_lossy(first_line);
+    let first_line = first_line.trim();
+
+    if let Some(shebang) = first_line.strip_prefix("#!") {
+        return interpreter_extension(shebang);
+    }
+    if first_line.starts_with("<?php") {
+        return Some("php");
+    }
+    None
+}
+
+/// `/usr/bin/env -S python3 -u` -> `py`
+fn interpreter_extension(shebang: &str) -> Option<&'static str> {
+    let mut words = shebang.split_whitespace();
+    let mut program = basename(words.next()?);
+    if program == "env" {
+        // skip env's own flags and VAR=value assignments
+        program = basename(words.find(|w| !w.starts_with('-') && !w.contains('='))?);
+    }
+
+    let program = program.to_lowercase();
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(interpreter, _)| *interpreter == name)
+        .map(|(_, extension)| *extension)
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
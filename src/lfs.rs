@@ -0,0 +1,68 @@
+//! Git LFS pointer files: the three-line stubs git keeps in place of large files whose
+//! content lives on an LFS server.
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Pointer files are tiny; anything larger is not read to check.
+pub const MAX_POINTER_SIZE: u64 = 1024;
+
+/// Largest file `fetch` pulls when `max_filesize` is not set.
+pub const DEFAULT_FETCH_LIMIT: u64 = 1024 * 1024;
+
+const SPEC_LINE: &str = "version https://git-lfs.github.com/spec/v1";
+
+/// What a pointer says about the file it stands for.
+pub struct Pointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+impl Pointer {
+    /// Parses `contents` if it is a pointer file.
+    pub fn parse(contents: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(contents).ok()?;
+        let mut lines = text.lines();
+        if lines.next()? != SPEC_LINE {
+            return None;
+        }
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            if let Some(value) = line.strip_prefix("oid ") {
+                oid = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("size ") {
+                size = value.parse().ok();
+            }
+        }
+        Some(Self { oid: oid?, size: size? })
+    }
+
+    /// The pointer, headed by a line saying the real content is not included and why.
+    pub fn annotated(&self, contents: &[u8], reason: &str) -> Vec<u8> {
+        let mut annotated = format!("[Git LFS pointer to a {} byte file, not included: {}]\n", self.size, reason).into_bytes();
+        annotated.extend_from_slice(contents);
+        annotated
+    }
+}
+
+/// Downloads the content `pointer` stands for with `git lfs smudge`, run in `repo`.
+pub fn fetch(repo: &Path, relative: &Path, pointer: &[u8]) -> Result<Vec<u8>> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["lfs", "smudge", "--"])
+        .arg(relative)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Could not run git lfs")?;
+    child.stdin.take().expect("stdin is piped").write_all(pointer)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
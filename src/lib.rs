@@ -0,0 +1,92 @@
+//! Library side of lmprep: configuration, file selection and the output writers used by
+//! the `lm` binary. Everything that touches the real filesystem, the network or signals sits
+//! behind the default `native` feature; without it the crate builds for WebAssembly and works
+//! on in-memory files through `virtual_fs`.
+
+#[cfg(feature = "native")]
+pub mod anonymize;
+pub mod api_schema;
+#[cfg(feature = "native")]
+pub mod archive;
+pub mod assets;
+#[cfg(feature = "native")]
+pub mod blame;
+#[cfg(feature = "native")]
+pub mod cache;
+pub mod capabilities;
+pub mod brief;
+pub mod classify;
+pub mod commands;
+pub mod concat;
+pub mod config;
+pub mod copy_mode;
+#[cfg(feature = "native")]
+pub mod daemon;
+#[cfg(feature = "native")]
+pub mod disk;
+#[cfg(feature = "native")]
+pub mod docker;
+pub mod editor_history;
+#[cfg(feature = "native")]
+pub mod file_filter;
+pub mod flatten;
+#[cfg(feature = "native")]
+pub mod focus;
+#[cfg(feature = "native")]
+pub mod git;
+pub mod html;
+#[cfg(feature = "native")]
+pub mod interrupt;
+pub mod language;
+pub mod line_endings;
+pub mod lfs;
+pub mod manifest;
+pub mod merge;
+pub mod migrations;
+#[cfg(feature = "native")]
+pub mod naming;
+#[cfg(feature = "native")]
+pub mod pick;
+pub mod pii;
+pub mod pricing;
+#[cfg(feature = "native")]
+pub mod lock;
+#[cfg(feature = "native")]
+pub mod ordering;
+#[cfg(feature = "native")]
+mod paths;
+#[cfg(feature = "native")]
+pub mod processor;
+pub mod ranking;
+pub mod report;
+#[cfg(feature = "native")]
+pub mod references;
+#[cfg(feature = "native")]
+pub mod remote;
+#[cfg(feature = "native")]
+pub mod review;
+#[cfg(feature = "native")]
+pub mod rpc;
+pub mod sampling;
+pub mod search;
+pub mod secrets;
+pub mod selection;
+#[cfg(feature = "native")]
+pub mod self_update;
+pub mod sqlite;
+pub mod symbols;
+pub mod terraform;
+#[cfg(feature = "native")]
+pub mod tokenizer;
+pub mod tokens;
+pub mod transforms;
+mod tree;
+pub mod trivial;
+pub mod vendored;
+pub mod virtual_fs;
+
+pub use config::Config;
+#[cfg(feature = "native")]
+pub use ordering::FileOrderer;
+#[cfg(feature = "native")]
+pub use processor::{FileProcessor, SelectedFile};
@@ -0,0 +1,112 @@
+//! `normalize_line_endings`: contents with the line endings git would check them out with on
+//! Linux, whatever the working tree has, so that diffs written against the output apply to
+//! the repository.
+
+/// What the line endings of a file become.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eol {
+    /// Left as they are, for files git does not convert
+    Keep,
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+/// The line endings git checks a file out with on Linux, given its `text` and `eol`
+/// attributes as `git check-attr` reports them (`set`, `unset`, `unspecified` or a value),
+/// `core.autocrlf` and `core.eol`. A file is converted if `text` is set or `auto`, if it has
+/// an `eol` attribute, or if `core.autocrlf` is `true` or `input`, which take files without
+/// attributes as `text=auto`; other files are checked out as they are.
+pub fn eol(text: &str, eol: &str, autocrlf: Option<&str>, core_eol: Option<&str>) -> Eol {
+    let autocrlf = match autocrlf {
+        Some("true" | "yes" | "on" | "1") => Some(true),
+        Some("input") => Some(false),
+        _ => None,
+    };
+    let converted = match text {
+        "set" | "auto" => true,
+        // `-text`, and `binary`, which unsets it
+        "unset" => false,
+        _ => matches!(eol, "crlf" | "lf") || autocrlf.is_some(),
+    };
+    if !converted {
+        return Eol::Keep;
+    }
+    match (eol, autocrlf) {
+        ("crlf", _) => Eol::Crlf,
+        ("lf", _) => Eol::Lf,
+        (_, Some(true)) => Eol::Crlf,
+        // `input` only converts when committing, so files come out as the repository has them
+        (_, Some(false)) => Eol::Lf,
+        // `native` is LF on Linux
+        (_, None) if core_eol == Some("crlf") => Eol::Crlf,
+        (_, None) => Eol::Lf,
+    }
+}
+
+/// `contents` with every line ending in `eol`, or `None` if they are binary or already do.
+/// A lone `\r` is not a line ending and stays.
+pub fn convert(contents: &[u8], eol: Eol) -> Option<Vec<u8>> {
+    let ending: &[u8] = match eol {
+        Eol::Keep => return None,
+        Eol::Lf => b"\n",
+        Eol::Crlf => b"\r\n",
+    };
+    if contents.contains(&0) {
+        return None;
+    }
+    let mut converted = Vec::with_capacity(contents.len());
+    for line in contents.split_inclusive(|byte| *byte == b'\n') {
+        match line.strip_suffix(b"\n") {
+            Some(line) => {
+                converted.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+                converted.extend_from_slice(ending);
+            }
+            None => converted.extend_from_slice(line),
+        }
+    }
+    (converted != contents).then_some(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eol_keeps_files_nothing_asks_to_convert() {
+        assert_eq!(eol("unspecified", "unspecified", None, None), Eol::Keep);
+        assert_eq!(eol("unspecified", "unspecified", Some("false"), Some("crlf")), Eol::Keep);
+        assert_eq!(eol("unset", "crlf", Some("true"), None), Eol::Keep);
+    }
+
+    #[test]
+    fn eol_follows_the_eol_attribute() {
+        assert_eq!(eol("unspecified", "crlf", None, None), Eol::Crlf);
+        assert_eq!(eol("set", "lf", Some("true"), Some("crlf")), Eol::Lf);
+        assert_eq!(eol("auto", "crlf", Some("input"), None), Eol::Crlf);
+    }
+
+    #[test]
+    fn eol_of_text_files_follows_core_eol_without_autocrlf() {
+        assert_eq!(eol("set", "unspecified", None, None), Eol::Lf);
+        assert_eq!(eol("auto", "unspecified", None, Some("crlf")), Eol::Crlf);
+        assert_eq!(eol("set", "unspecified", Some("false"), Some("native")), Eol::Lf);
+    }
+
+    #[test]
+    fn eol_follows_autocrlf_over_core_eol() {
+        assert_eq!(eol("unspecified", "unspecified", Some("true"), Some("lf")), Eol::Crlf);
+        assert_eq!(eol("set", "unspecified", Some("input"), Some("crlf")), Eol::Lf);
+        assert_eq!(eol("unspecified", "unspecified", Some("input"), None), Eol::Lf);
+    }
+
+    #[test]
+    fn convert_changes_only_line_endings() {
+        assert_eq!(convert(b"a\r\nb\n", Eol::Lf), Some(b"a\nb\n".to_vec()));
+        assert_eq!(convert(b"a\nb\r\nc", Eol::Crlf), Some(b"a\r\nb\r\nc".to_vec()));
+        assert_eq!(convert(b"a\rb\n", Eol::Lf), None);
+        assert_eq!(convert(b"a\0\r\n", Eol::Lf), None);
+        assert_eq!(convert(b"a\r\n", Eol::Keep), None);
+    }
+}
@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+
+use crate::flatten;
+
+/// Advisory lock on an output directory, held until dropped. The lock file lives in the temp
+/// directory so that it never ends up in the source tree or in the bundle.
+pub struct OutputLock {
+    _file: File,
+}
+
+impl OutputLock {
+    /// Locks `output_dir`, waiting for any other lmprep run that currently holds it.
+    pub fn acquire(output_dir: &Path) -> Result<Self> {
+        let key = match output_dir.parent().map(|p| p.canonicalize()) {
+            Some(Ok(parent)) => parent.join(output_dir.file_name().unwrap_or_default()),
+            _ => output_dir.to_path_buf(),
+        };
+        let lock_path = env::temp_dir()
+            .join(format!("lmprep-{}.lock", flatten::short_hash(&key.to_string_lossy())));
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Could not open lock file {}", lock_path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                eprintln!("Waiting for another lmprep run writing to {}...", output_dir.display());
+                file.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        Ok(Self { _file: file })
+    }
+}
@@ -1,16 +1,22 @@
-use anyhow::Result;
-use clap::Parser;
-use ignore::gitignore::Gitignore;
-use std::collections::BTreeMap;
-use std::env;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use lmprep::classify::Category;
+use lmprep::config::{self, ContentSource, Layout, LfsPointers, Order, Output, Submodules, TrivialFiles, VendoredCode, DEFAULT_CONFIG};
+use lmprep::copy_mode::CopyMode;
+use lmprep::pii::PiiPolicy;
+use lmprep::sampling::SampleStrata;
+use lmprep::search;
+use lmprep::secrets::SecretScan;
+use lmprep::transforms::LongLines;
+use lmprep::capabilities::Capabilities;
+use lmprep::docker::{self, DockerSource};
+use lmprep::remote::{self, Remote};
+use lmprep::{archive, cache, daemon, git, interrupt, lock, manifest, pick, review, self_update, FileProcessor};
 use std::fs;
-use std::io::BufWriter;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
-use zip::ZipWriter;
 
-mod file_filter;
-use file_filter::FileFilter;
+/// Exit code of a run that wrote its output but had to leave out files it could not read.
+const PARTIAL_SUCCESS: i32 = 3;
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about = "A tool for preparing your codebase for use with LLMs")]
@@ -18,10 +24,10 @@ struct Args {
     #[arg(default_value = ".")]
     source: String,
 
-    #[arg(short, long)]
-    config: Option<String>,
+    #[arg(short, long, global = true)]
+    config: Vec<String>,
 
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     subfolder: Option<String>,
 
     #[arg(short = 'z', long)]
@@ -30,271 +36,352 @@ struct Args {
     #[arg(short, long)]
     tree: bool,
 
-    #[arg(short, long)]
-    verbose: bool,
+    #[arg(long)]
+    concat: bool,
+
+    #[arg(long, alias = "format", value_enum, value_delimiter = ',')]
+    outputs: Vec<Output>,
+
+    #[arg(short, long, global = true, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "summary")]
+    verbose: Option<Verbosity>,
+
+    #[arg(long, value_enum)]
+    layout: Option<Layout>,
+
+    #[arg(long, value_enum)]
+    copy_mode: Option<CopyMode>,
+
+    #[arg(long, value_enum, value_delimiter = ',')]
+    only: Vec<Category>,
+
+    #[arg(long, value_enum, value_delimiter = ',')]
+    exclude_category: Vec<Category>,
+
+    #[arg(long, value_enum, value_delimiter = ',')]
+    summarize: Vec<Category>,
 
     #[arg(long)]
-    init_config: bool,
-}
+    no_tests: bool,
 
-#[derive(Debug, serde::Deserialize, Clone)]
-struct Config {
-    #[serde(default)]
-    allowed_extensions: Vec<String>,
-    #[serde(default = "default_delimiter")]
-    delimiter: String,
-    #[serde(default = "default_subfolder")]
-    subfolder: String,
-    #[serde(default)]
-    zip: bool,
-    #[serde(default)]
-    tree: bool,
-    #[serde(default = "default_ignored_directories")]
-    ignored_directories: Vec<String>,
-    #[serde(default = "default_respect_gitignore")]
-    respect_gitignore: bool,
-}
+    #[arg(long)]
+    no_promote: bool,
 
-fn default_delimiter() -> String { "^".to_string() }
-fn default_subfolder() -> String { "context".to_string() }
-fn default_respect_gitignore() -> bool { true }
-
-fn default_ignored_directories() -> Vec<String> {
-    vec![
-        "node_modules".to_string(),
-        "venv".to_string(),
-        ".venv".to_string(),
-        "env".to_string(),
-        ".env".to_string(),
-        "target".to_string(),
-        "build".to_string(),
-        "dist".to_string(),
-        "__pycache__".to_string(),
-        ".git".to_string(),
-        ".idea".to_string(),
-        ".vs".to_string(),
-        ".vscode".to_string(),
-    ]
-}
+    #[arg(long, value_name = "FILE")]
+    from_editor_history: Option<String>,
 
-// Include default config at compile time
-const DEFAULT_CONFIG: &str = include_str!("../default_config.yml");
-
-impl Default for Config {
-    fn default() -> Self {
-        match serde_yaml::from_str(DEFAULT_CONFIG) {
-            Ok(config) => config,
-            Err(e) => {
-                eprintln!("Warning: Error parsing default config: {}. Using hardcoded defaults.", e);
-                Self {
-                    allowed_extensions: vec![],
-                    delimiter: default_delimiter(),
-                    subfolder: default_subfolder(),
-                    zip: false,
-                    tree: false,
-                    ignored_directories: default_ignored_directories(),
-                    respect_gitignore: default_respect_gitignore(),
-                }
-            }
-        }
-    }
-}
+    #[arg(long)]
+    editor_history_only: bool,
 
-struct FileProcessor<'a> {
-    source_path: &'a Path,
-    output_dir: PathBuf,
-    config: &'a Config,
-    filter: FileFilter<'a>,
-    verbose: bool,
-    args: &'a Args,
-}
+    #[arg(long)]
+    api_first: bool,
 
-impl<'a> FileProcessor<'a> {
-    fn new(source: &'a str, config: &'a Config, verbose: bool, args: &'a Args) -> Result<Self> {
-        let source_path = Path::new(source);
-        let output_dir = source_path.join(&config.subfolder);
-        let filter = FileFilter::new(source_path, config)?;
-
-        Ok(Self {
-            source_path,
-            output_dir,
-            config,
-            filter,
-            verbose,
-            args,
-        })
-    }
-
-    fn prepare_output_directory(&self) -> Result<()> {
-        if self.output_dir.exists() {
-            if self.verbose {
-                eprintln!("Cleaning existing output directory");
-            }
-            fs::remove_dir_all(&self.output_dir)?;
-        }
-        fs::create_dir_all(&self.output_dir)?;
-        Ok(())
-    }
+    #[arg(long)]
+    terraform: bool,
 
-    fn collect_files(&self) -> Result<Vec<(PathBuf, String)>> {
-        let mut files_to_process = Vec::new();
+    #[arg(long, value_enum)]
+    order: Option<Order>,
 
-        for entry in WalkDir::new(self.source_path).follow_links(false) {
-            let entry = entry?;
-            let path = entry.path();
+    #[arg(long)]
+    tracked_only: bool,
 
-            if path.starts_with(&self.output_dir) {
-                if self.verbose {
-                    eprintln!("Skipping output directory: {}", path.display());
-                }
-                continue;
-            }
+    #[arg(long, conflicts_with = "head")]
+    working_tree: bool,
 
-            if !self.filter.should_process_file(path)? {
-                continue;
-            }
+    #[arg(long)]
+    head: bool,
 
-            if !entry.file_type().is_file() {
-                continue;
-            }
+    #[arg(long)]
+    include_untracked: bool,
 
-            let new_name = self.generate_new_filename(path)?;
-            if self.verbose {
-                eprintln!("Adding file: {} -> {}", path.display(), new_name);
-            }
+    #[arg(long, num_args = 0..=1, default_missing_value = "HEAD")]
+    diff: Option<String>,
 
-            files_to_process.push((path.to_path_buf(), new_name));
-        }
+    #[arg(long)]
+    history: Option<usize>,
 
-        if self.verbose {
-            eprintln!("Total files to process: {}", files_to_process.len());
-        }
+    #[arg(long, value_enum)]
+    submodules: Option<Submodules>,
 
-        Ok(files_to_process)
-    }
+    #[arg(long, value_enum)]
+    lfs: Option<LfsPointers>,
 
-    fn generate_new_filename(&self, path: &Path) -> Result<String> {
-        let relative_path = path.strip_prefix(self.source_path)?;
-        Ok(relative_path
-            .components()
-            .map(|c| c.as_os_str().to_string_lossy())
-            .collect::<Vec<_>>()
-            .join(&self.config.delimiter))
-    }
+    #[arg(long, value_enum)]
+    vendored: Option<VendoredCode>,
 
-    fn process(&self) -> Result<()> {
-        let files = self.collect_files()?;
-        let file_count = files.len();
-        let mut tree_content = String::new();
+    #[arg(long, value_enum)]
+    skip_trivial: Option<TrivialFiles>,
 
-        if self.args.tree {
-            if self.verbose {
-                println!("Generating file tree...");
-            }
-            let mut seen_dirs = BTreeMap::new();
-            tree_content = generate_tree_string(
-                self.source_path,
-                "",
-                true,
-                &mut seen_dirs,
-                &self.config.allowed_extensions,
-                &self.config.ignored_directories,
-                self.filter.gitignore(),
-                self.source_path,
-            )?;
-        }
+    #[arg(long)]
+    max_files: Option<usize>,
 
-        if self.args.zip {
-            self.create_zip(files, if self.args.tree { Some(&tree_content) } else { None })?;
-            println!("Successfully zipped {} files to {:?}", file_count, self.output_dir);
-        } else {
-            self.copy_files(files)?;
-            if self.args.tree {
-                let tree_file_path = self.output_dir.join("filetree.txt");
-                fs::write(&tree_file_path, tree_content)?;
-                if self.verbose {
-                    println!("Tree written to {:?}", tree_file_path);
-                }
-            }
-            println!("Successfully processed {} files to {:?}", file_count, self.output_dir);
-        }
+    #[arg(long)]
+    max_line_length: Option<usize>,
 
-        Ok(())
-    }
+    #[arg(long, value_enum)]
+    long_lines: Option<LongLines>,
 
-    fn copy_files(&self, files: Vec<(PathBuf, String)>) -> Result<()> {
-        if self.verbose {
-            println!("Copying {} files to {:?}", files.len(), self.output_dir);
-        }
+    #[arg(long)]
+    merge_small_files: Option<u64>,
 
-        for (source_path, new_name) in files {
-            if !self.filter.should_process_file(&source_path)? {
-                if self.verbose {
-                    println!("Skipping ignored file: {:?}", source_path);
-                }
-                continue;
-            }
+    #[arg(long)]
+    normalize_line_endings: bool,
 
-            let target_path = self.output_dir.join(&new_name);
+    #[arg(long)]
+    max_total_bytes: Option<u64>,
 
-            if let Some(parent) = target_path.parent() {
-                fs::create_dir_all(parent)?;
-            }
+    #[arg(long)]
+    max_tokens: Option<usize>,
 
-            fs::copy(&source_path, &target_path)?;
+    #[arg(long)]
+    tokenizer: Option<String>,
 
-            if self.verbose {
-                println!("Copied {:?} to {:?}", source_path, target_path);
-            }
-        }
+    #[arg(long)]
+    model: Option<String>,
 
-        Ok(())
-    }
+    #[arg(long)]
+    sample: Option<usize>,
 
-    fn create_zip(&self, files: Vec<(PathBuf, String)>, tree_content: Option<&str>) -> Result<()> {
-        if self.verbose {
-            println!("Starting to create zip archive");
-        }
+    #[arg(long, value_enum)]
+    sample_strata: Option<SampleStrata>,
 
-        let zip_path = self.output_dir.join("context.zip");
+    #[arg(long)]
+    sample_seed: Option<u64>,
 
-        let zip_file = fs::File::create(&zip_path)?;
-        let mut zip = ZipWriter::new(BufWriter::new(zip_file));
+    #[arg(long)]
+    symbol: Option<String>,
 
-        for (source_path, new_name) in files {
-            // Skip if this path should be ignored
-            if !self.filter.should_process_file(&source_path)? {
-                if self.verbose {
-                    println!("Skipping ignored file: {:?}", source_path);
-                }
-                continue;
-            }
+    #[arg(long)]
+    symbol_hops: Option<usize>,
 
-            zip.start_file(&new_name, Default::default())?;
-            let mut file = fs::File::open(&source_path)?;
-            std::io::copy(&mut file, &mut zip)?;
-        }
+    #[arg(long = "seed")]
+    seeds: Vec<String>,
 
-        // Add the tree file to the zip if it was generated
-        if let Some(content) = tree_content {
-            zip.start_file("filetree.txt", Default::default())?;
-            use std::io::Write;
-            zip.write_all(content.as_bytes())?;
-        }
+    #[arg(long)]
+    seed_depth: Option<usize>,
 
-        zip.finish()?;
+    #[arg(long)]
+    seed_token_budget: Option<usize>,
 
-        if self.verbose {
-            println!("Created zip archive at {:?}", zip_path);
-        }
+    #[arg(long)]
+    scan_secrets: bool,
 
-        Ok(())
-    }
+    #[arg(long)]
+    fail_if_secrets: bool,
+
+    #[arg(long)]
+    secrets_report: Option<String>,
+
+    #[arg(long, value_enum)]
+    pii: Option<PiiPolicy>,
+
+    #[arg(long)]
+    anonymize: bool,
+
+    #[arg(long)]
+    from_docker: Option<String>,
+
+    #[arg(long)]
+    init_config: bool,
+
+    #[arg(long)]
+    capabilities: bool,
+
+    #[arg(long)]
+    report: bool,
+
+    #[arg(long)]
+    resume: bool,
+
+    #[arg(long)]
+    force: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Add files, or the files of directories, to the existing output without rebuilding it
+    Add {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Source the output was made from
+        #[arg(long, default_value = ".")]
+        source: String,
+    },
+    /// Show or clear the per-user cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Manage lmprep config files
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Keep the index of SOURCE warm and answer JSON-RPC requests over a unix socket
+    Daemon {
+        #[arg(default_value = ".")]
+        source: String,
+        /// Socket to listen on [default: lmprep-<hash>.sock in the temp directory]
+        #[arg(long)]
+        socket: Option<String>,
+        /// Speak the protocol on stdin and stdout instead of a socket, for editor extensions
+        #[arg(long, conflicts_with = "socket")]
+        stdio: bool,
+    },
+    /// Choose the files of a run from the filtered list, with fzf or a built-in fuzzy prompt
+    Pick {
+        #[arg(default_value = ".")]
+        source: String,
+    },
+    /// Remove the files whose path matches one of the globs from the existing output
+    Remove {
+        #[arg(required = true)]
+        globs: Vec<String>,
+        /// Source the output was made from
+        #[arg(long, default_value = ".")]
+        source: String,
+    },
+    /// Bundle the changes between two revisions for code review
+    Review {
+        /// Revisions to compare: BASE..HEAD, or BASE alone to review up to HEAD
+        range: String,
+        #[arg(default_value = ".")]
+        source: String,
+    },
+    /// Print the JSON Schema of manifest.json
+    Schema,
+    /// Print the files of the existing output that match QUERY, and the lines that do
+    Search {
+        query: String,
+        /// Source the output was made from
+        #[arg(long, default_value = ".")]
+        source: String,
+        /// Number of files to print
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Download the latest release from GitHub and replace this executable
+    SelfUpdate {
+        /// Only report whether a newer version is available
+        #[arg(long)]
+        check: bool,
+        /// Reinstall even if the installed version is current
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// How much `--verbose` prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Verbosity {
+    /// The first lines of each kind about single files, the rest counted
+    Summary,
+    /// Every line about single files
+    Full,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum CacheCommand {
+    /// Print the cache directory
+    Dir,
+    /// Delete everything in the cache
+    Clear,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommand {
+    /// Rewrite a config file in the current schema version
+    Migrate {
+        /// Config file to migrate [default: .lmprep.yml]
+        path: Option<String>,
+        /// Print the migrated config instead of writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    if let Some(command) = &args.command {
+        return match command {
+            Command::Add { paths, source } => {
+                let config = existing_output_config(&args)?;
+                let processor = file_processor(source, &config, args.verbose)?;
+                let _lock = lock::OutputLock::acquire(processor.output_dir())?;
+                let added = processor.add(paths)?;
+                println!("Added {} files to {:?}", added, processor.output_dir());
+                if processor.any_failed() {
+                    std::process::exit(PARTIAL_SUCCESS);
+                }
+                Ok(())
+            }
+            Command::Cache { action: CacheCommand::Dir } => {
+                let dir = cache::dir().context("Could not find a cache directory, set LMPREP_CACHE_DIR")?;
+                println!("{}", dir.display());
+                Ok(())
+            }
+            Command::Cache { action: CacheCommand::Clear } => {
+                let freed = cache::clear()?;
+                println!("Cleared the cache, freeing {} bytes", freed);
+                Ok(())
+            }
+            Command::Config { action: ConfigCommand::Migrate { path, dry_run } } => {
+                let path = path.clone().or_else(|| args.config.last().cloned()).unwrap_or_else(|| ".lmprep.yml".to_string());
+                config::migrate_file(&path, *dry_run)
+            }
+            Command::Daemon { source, socket, stdio } => {
+                let config = config::load_configs(&args.config)?;
+                if *stdio {
+                    return daemon::run_stdio(file_processor(source, &config, args.verbose)?, args.verbose.is_some());
+                }
+                let socket = socket
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| daemon::default_socket_path(source));
+                daemon::run(file_processor(source, &config, args.verbose)?, &socket, args.verbose.is_some())
+            }
+            Command::Pick { source } => {
+                let config = config::load_configs(&args.config)?;
+                pick::run(source, &config, args.verbose.is_some())
+            }
+            Command::Remove { globs, source } => {
+                let config = existing_output_config(&args)?;
+                let processor = file_processor(source, &config, args.verbose)?;
+                let _lock = lock::OutputLock::acquire(processor.output_dir())?;
+                let removed = processor.remove(globs)?;
+                println!("Removed {} files from {:?}", removed, processor.output_dir());
+                Ok(())
+            }
+            Command::Review { range, source } => {
+                let config = config::load_configs(&args.config)?;
+                review::run(source, range, &config, args.verbose.is_some())
+            }
+            Command::Schema => {
+                print!("{}", manifest::MANIFEST_SCHEMA);
+                Ok(())
+            }
+            Command::Search { query, source, limit } => {
+                let config = existing_output_config(&args)?;
+                let processor = file_processor(source, &config, args.verbose)?;
+                let matches = processor.search(query, *limit)?;
+                if matches.is_empty() {
+                    println!("No files in {:?} match {:?}", processor.output_dir(), query);
+                }
+                print!("{}", search::render(&matches));
+                Ok(())
+            }
+            Command::SelfUpdate { check, force } => self_update::run(*check, *force),
+        };
+    }
+
+    if args.capabilities {
+        println!("{}", serde_json::to_string_pretty(&Capabilities::current())?);
+        return Ok(());
+    }
+
     if args.init_config {
         let config_path = Path::new(".lmprep.yml");
         if config_path.exists() {
@@ -306,7 +393,7 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let mut config = load_config(&args.config)?;
+    let mut config = config::load_configs(&args.config)?;
 
     if let Some(ref subfolder) = args.subfolder {
         config.subfolder = subfolder.clone();
@@ -320,166 +407,297 @@ fn main() -> Result<()> {
         config.tree = true;
     }
 
-    if args.verbose {
-        eprintln!("Final config after CLI overrides: {:#?}", config);
+    if args.concat {
+        config.concat = true;
     }
 
-    let processor = FileProcessor::new(&args.source, &config, args.verbose, &args)?;
-    processor.prepare_output_directory()?;
+    if !args.outputs.is_empty() {
+        config.outputs = args.outputs.clone();
+    }
 
-    processor.process()?;
+    if let Some(layout) = args.layout {
+        config.layout = layout;
+    }
 
-    Ok(())
-}
+    if let Some(copy_mode) = args.copy_mode {
+        config.copy_mode = copy_mode;
+    }
 
-fn load_config(config_path: &Option<String>) -> Result<Config> {
-    if let Some(path) = config_path {
-        return load_config_from_path(path);
+    if !args.only.is_empty() {
+        config.only_categories = args.only.clone();
     }
 
-    for ext in &[".yml", ".yaml"] {
-        let config_path = format!(".lmprep{}", ext);
-        if let Ok(config) = load_config_from_path(&config_path) {
-            return Ok(config);
-        }
+    if !args.exclude_category.is_empty() {
+        config.exclude_categories = args.exclude_category.clone();
     }
 
-    if let Ok(home) = env::var("HOME") {
-        let home_path = PathBuf::from(home);
-        for ext in &[".yml", ".yaml"] {
-            let config_path = home_path.join(format!(".lmprep{}", ext));
-            match load_config_from_path(&config_path.to_string_lossy()) {
-                Ok(config) => return Ok(config),
-                Err(e) if e.to_string().contains("Permission denied") => {
-                    eprintln!("Warning: Could not read config from home directory (permission denied)");
-                    break;  // Don't try other extensions if we have permission issues
-                }
-                Err(_) => continue,  // Try next extension
-            }
-        }
+    if !args.summarize.is_empty() {
+        config.summarize_categories = args.summarize.clone();
     }
 
-    if let Ok(home) = env::var("USERPROFILE") {
-        let home_path = PathBuf::from(home);
-        for ext in &[".yml", ".yaml"] {
-            let config_path = home_path.join(format!(".lmprep{}", ext));
-            match load_config_from_path(&config_path.to_string_lossy()) {
-                Ok(config) => return Ok(config),
-                Err(e) if e.to_string().contains("Permission denied") => {
-                    eprintln!("Warning: Could not read config from home directory (permission denied)");
-                    break;  // Don't try other extensions if we have permission issues
-                }
-                Err(_) => continue,  // Try next extension
-            }
-        }
+    if args.no_tests {
+        config.include_tests = false;
     }
 
-    eprintln!("No config file found, using defaults");
-    Ok(Config::default())
-}
+    if let Some(order) = args.order {
+        config.order = order;
+    }
 
-fn load_config_from_path(path: &str) -> Result<Config> {
-    let path_buf = PathBuf::from(path);
-    if path_buf.exists() {
-        let contents = fs::read_to_string(&path_buf)?;
-        match serde_yaml::from_str(&contents) {
-            Ok(config) => return Ok(config),
-            Err(e) => eprintln!("Warning: Error parsing config file {}: {}. Using defaults.", path, e),
-        }
+    if args.no_promote {
+        config.promote.clear();
     }
-    Ok(Config::default())
-}
 
-fn generate_tree_string(
-    path: &Path,
-    prefix: &str,
-    is_last: bool,
-    seen_dirs: &mut BTreeMap<PathBuf, bool>,
-    allowed_extensions: &[String],
-    ignored_directories: &[String],
-    gitignore: Option<&Gitignore>,
-    source_path: &Path,
-) -> Result<String> {
-    let mut result = String::new();
-
-    if path.eq(source_path) {
-        result.push_str(&format!(".\n"));
-    } else {
-        if !FileFilter::should_process_path(path, source_path, allowed_extensions, ignored_directories, gitignore)? {
-            return Ok(result);
-        }
+    if args.from_editor_history.is_some() {
+        config.editor_history = args.from_editor_history.clone();
+    }
 
-        if path.is_file() {
-            if !allowed_extensions.is_empty() {
-                if let Some(ext) = path.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    if !allowed_extensions.iter().any(|e| e.to_lowercase() == ext_str) {
-                        return Ok(result);
-                    }
-                } else {
-                    return Ok(result);
-                }
-            }
-        }
+    if args.editor_history_only {
+        config.editor_history_only = true;
+    }
 
-        let file_name = path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
+    if args.api_first {
+        config.api_first = true;
+    }
 
-        if file_name.is_empty() {
-            return Ok(result);
-        }
+    if args.terraform {
+        config.terraform = true;
+    }
+
+    if args.tracked_only {
+        config.tracked_only = true;
+    }
+
+    if args.working_tree {
+        config.content_source = ContentSource::WorkingTree;
+    }
+
+    if args.head {
+        config.content_source = ContentSource::Head;
+    }
+
+    if args.include_untracked {
+        config.include_untracked = true;
+    }
+
+    if args.diff.is_some() {
+        config.diff = args.diff;
+    }
+
+    if args.history.is_some() {
+        config.history = args.history;
+    }
+
+    if let Some(submodules) = args.submodules {
+        config.submodules = submodules;
+    }
+
+    if let Some(lfs) = args.lfs {
+        config.lfs = lfs;
+    }
+
+    if let Some(vendored) = args.vendored {
+        config.vendored = vendored;
+    }
+
+    if let Some(skip_trivial) = args.skip_trivial {
+        config.skip_trivial = skip_trivial;
+    }
+
+    if let Some(max_files) = args.max_files {
+        config.max_files = Some(max_files);
+    }
+
+    if let Some(max_line_length) = args.max_line_length {
+        config.max_line_length = Some(max_line_length);
+    }
+
+    if let Some(long_lines) = args.long_lines {
+        config.long_lines = long_lines;
+    }
+
+    if let Some(merge_small_files) = args.merge_small_files {
+        config.merge_small_files = Some(merge_small_files);
+    }
+
+    if args.normalize_line_endings {
+        config.normalize_line_endings = true;
+    }
 
-        if path.is_dir() {
-            seen_dirs.insert(path.to_path_buf(), true);
+    if let Some(max_total_bytes) = args.max_total_bytes {
+        config.max_total_bytes = Some(max_total_bytes);
+    }
+
+    if let Some(max_tokens) = args.max_tokens {
+        config.max_tokens = Some(max_tokens);
+    }
+
+    if args.tokenizer.is_some() {
+        config.tokenizer = args.tokenizer.clone();
+    }
+
+    if args.model.is_some() {
+        config.model = args.model.clone();
+    }
+
+    if let Some(sample) = args.sample {
+        config.sample = Some(sample);
+    }
+
+    if let Some(sample_strata) = args.sample_strata {
+        config.sample_strata = sample_strata;
+    }
+
+    if let Some(sample_seed) = args.sample_seed {
+        config.sample_seed = Some(sample_seed);
+    }
+
+    if args.symbol.is_some() {
+        config.symbol = args.symbol.clone();
+    }
+
+    if let Some(symbol_hops) = args.symbol_hops {
+        config.symbol_hops = symbol_hops;
+    }
+
+    if !args.seeds.is_empty() {
+        config.seeds = args.seeds.clone();
+    }
+
+    if let Some(seed_depth) = args.seed_depth {
+        config.seed_depth = seed_depth;
+    }
+
+    if args.seed_token_budget.is_some() {
+        config.seed_token_budget = args.seed_token_budget;
+    }
+
+    if args.scan_secrets {
+        config.secret_scan = SecretScan::Warn;
+    }
+
+    if args.fail_if_secrets {
+        config.secret_scan = SecretScan::Block;
+    }
+
+    if let Some(ref secrets_report) = args.secrets_report {
+        config.secrets_report = Some(secrets_report.clone());
+        if config.secret_scan == SecretScan::Off {
+            config.secret_scan = SecretScan::Warn;
         }
+    }
 
-        result.push_str(&format!("{}{}{}\n",
-            prefix,
-            if is_last { "└── " } else { "├── " },
-            if path.is_dir() { format!("{}/", file_name) } else { file_name.to_string() }
-        ));
-    }
-
-    if path.is_dir() {
-        let mut entries: Vec<_> = fs::read_dir(path)?
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                if let Ok(ft) = e.file_type() {
-                    ft.is_file() || ft.is_dir()
-                } else {
-                    false
-                }
-            })
-            .collect();
-
-        entries.sort_by_key(|e| {
-            let p = e.path();
-            (p.is_file(), e.file_name())
-        });
-
-        let num_entries = entries.len();
-        for (i, entry) in entries.into_iter().enumerate() {
-            let is_last_entry = i == num_entries - 1;
-            let new_prefix = format!("{}{}",
-                prefix,
-                if is_last { "    " } else { "│   " }
+    if let Some(pii) = args.pii {
+        config.pii = pii;
+    }
+
+    if args.anonymize {
+        config.anonymize = true;
+    }
+
+    if args.verbose.is_some() {
+        eprintln!("Final config after CLI overrides: {:#?}", config);
+    }
+
+    interrupt::install()?;
+
+    // archives, remote directories and containers are read from a temporary copy, with the
+    // output next to the archive or in the current directory
+    let source_path = Path::new(&args.source);
+    let (extracted, output_parent) = if let Some(spec) = &args.from_docker {
+        (Some(docker::fetch(&DockerSource::parse(spec))?), Path::new(""))
+    } else if let Some(remote) = Remote::parse(&args.source) {
+        (Some(remote::fetch(&remote, &config)?), Path::new(""))
+    } else if archive::is_archive(source_path) {
+        (Some(archive::extract(source_path)?), source_path.parent().unwrap_or(Path::new("")))
+    } else {
+        (None, source_path)
+    };
+    // `--head` reads the files of HEAD from the repository, with the output in the source as usual
+    let snapshot = match config.content_source {
+        ContentSource::Head if extracted.is_some() => {
+            anyhow::bail!("--head needs a git repository as the source, not an archive, remote directory or container")
+        }
+        ContentSource::Head => Some(git::Snapshot::read(source_path, "HEAD", "head")?),
+        ContentSource::WorkingTree => None,
+    };
+    let source = match (&extracted, &snapshot) {
+        (Some(extracted), _) => extracted.root().to_string_lossy().into_owned(),
+        (None, Some(snapshot)) => snapshot.source().to_string_lossy().into_owned(),
+        (None, None) => args.source.clone(),
+    };
+    let mut processor = file_processor(&source, &config, args.verbose)?;
+    if extracted.is_some() || snapshot.is_some() {
+        processor = processor.with_output_dir(output_parent.join(&config.subfolder));
+    }
+    if args.report {
+        print!("{}", processor.token_report()?.render());
+        return Ok(());
+    }
+    let _lock = lock::OutputLock::acquire(processor.output_dir())?;
+    if !args.resume && !args.force && processor.up_to_date()? {
+        println!("{:?} is up to date, nothing changed since the last run", processor.output_dir());
+        return Ok(());
+    }
+    if args.resume && processor.resumable()? {
+        processor = processor.with_resume();
+    } else {
+        if args.resume {
+            eprintln!(
+                "Warning: {:?} holds no stopped run with the same settings and sources to resume, starting over",
+                processor.output_dir()
             );
+        }
+        processor.prepare_output_directory()?;
+    }
 
-            let child_output = generate_tree_string(
-                &entry.path(),
-                &new_prefix,
-                is_last_entry,
-                seen_dirs,
-                allowed_extensions,
-                ignored_directories,
-                gitignore,
-                source_path,
-            )?;
-
-            result.push_str(&child_output);
+    if let Err(e) = processor.process() {
+        if processor.resumable().unwrap_or(false) {
+            if let Some(interrupted) = e.downcast_ref::<interrupt::Interrupted>() {
+                eprintln!(
+                    "Interrupted: processed {} of {} files, run again with --resume to continue",
+                    interrupted.completed, interrupted.total
+                );
+                drop(extracted);
+                drop(snapshot);
+                std::process::exit(130);
+            }
+            return Err(e.context("Stopped partway, run again with --resume to continue"));
+        }
+        if let Some(interrupted) = e.downcast_ref::<interrupt::Interrupted>() {
+            processor.remove_output_directory()?;
+            eprintln!(
+                "Interrupted: processed {} of {} files, removed partial output {:?}",
+                interrupted.completed, interrupted.total, processor.output_dir()
+            );
+            drop(extracted);
+            drop(snapshot);
+            std::process::exit(130);
         }
+        return Err(e);
+    }
+    if processor.any_failed() {
+        drop(extracted);
+        drop(snapshot);
+        std::process::exit(PARTIAL_SUCCESS);
     }
 
-    Ok(result)
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// A processor of `source` that prints as much as `--verbose` asks for.
+fn file_processor<'a>(source: &'a str, config: &'a config::Config, verbose: Option<Verbosity>) -> Result<FileProcessor<'a>> {
+    let processor = FileProcessor::new(source, config, verbose.is_some())?;
+    Ok(match verbose {
+        Some(Verbosity::Full) => processor.with_verbose_full(),
+        _ => processor,
+    })
+}
+
+/// Config of `add` and `remove`, which find the output by the subfolder like a normal run.
+fn existing_output_config(args: &Args) -> Result<config::Config> {
+    let mut config = config::load_configs(&args.config)?;
+    if let Some(ref subfolder) = args.subfolder {
+        config.subfolder = subfolder.clone();
+    }
+    Ok(config)
+}
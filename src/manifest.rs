@@ -0,0 +1,180 @@
+//! `manifest.json`, written next to the output: what went into it, so that `lm add` can
+//! change an existing output without walking and writing everything again, and so that other
+//! tools can read it. `lm schema` prints its JSON Schema.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_JSON: &str = "manifest.json";
+
+/// Version of the manifest format. Fields may be added within a version; removing or
+/// changing one needs a new version and a new `MANIFEST_SCHEMA`.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+/// Written into every output folder, so that runs over a source that holds it, such as a
+/// monorepo with an output in each package, leave it out.
+pub const OUTPUT_MARKER: &str = ".lmprep-output";
+
+/// JSON Schema of `manifest.json`.
+pub const MANIFEST_SCHEMA: &str = include_str!("../manifest.schema.json");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub schema_version: u32,
+    /// Version of the lmprep that wrote the manifest.
+    pub lmprep_version: String,
+    /// Whether the files themselves are in the output folder.
+    #[serde(default)]
+    pub folder: bool,
+    /// The zip and concatenated documents the files were written to, by name in the output
+    /// folder.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output_files: Vec<String>,
+    /// Every file in the output, in the order it was written.
+    pub files: Vec<ManifestFile>,
+    /// What the file tree shows, if the output has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<ManifestTree>,
+    /// Whether the run stopped before it finished. `files` are then the ones it was copying to
+    /// the output folder, which `--resume` does not copy again where they are there in full.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    /// Hash of the config and the files of the run that wrote the output, which a run with
+    /// the same ones leaves as it is, or resumes if it is `partial`. Left out once `lm add` or
+    /// `lm remove` changed the output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Path relative to the source with `/` separators, as the output shows it.
+    pub path: String,
+    /// Name of the file in an output folder or zip.
+    pub output_name: String,
+    /// Whether only the outline of the file is in the output, to stay within `max_tokens`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub outline: bool,
+}
+
+/// Directories and files of the file tree, relative to the source with `/` separators.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ManifestTree {
+    pub directories: Vec<String>,
+    pub files: Vec<String>,
+}
+
+/// Whether `dir` holds an lmprep output: it has `OUTPUT_MARKER`, or a manifest lmprep wrote,
+/// as the outputs of the versions between the manifest and the marker have. Outputs of the
+/// releases before the manifest have neither.
+pub fn is_output_dir(dir: &Path) -> bool {
+    if dir.join(OUTPUT_MARKER).is_file() {
+        return true;
+    }
+    fs::read_to_string(dir.join(MANIFEST_JSON))
+        .is_ok_and(|contents| serde_json::from_str::<Manifest>(&contents).is_ok())
+}
+
+impl Manifest {
+    pub fn new(files: Vec<ManifestFile>, tree: Option<ManifestTree>) -> Self {
+        Self {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            lmprep_version: env!("CARGO_PKG_VERSION").to_string(),
+            folder: false,
+            output_files: Vec::new(),
+            files,
+            tree,
+            partial: false,
+            run_key: None,
+        }
+    }
+
+    /// The manifest of the output in `output_dir`.
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join(MANIFEST_JSON);
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read {}, run lm once to create the output", path.display()))?;
+        let mut manifest: Self =
+            serde_json::from_str(&contents).with_context(|| format!("Could not parse {}", path.display()))?;
+        if manifest.schema_version > MANIFEST_SCHEMA_VERSION {
+            bail!(
+                "{} was written by lmprep {} in manifest schema version {}, newer than this lmprep supports ({})",
+                path.display(),
+                manifest.lmprep_version,
+                manifest.schema_version,
+                MANIFEST_SCHEMA_VERSION
+            );
+        }
+        // manifests from before `folder` only name their outputs if they are not a folder
+        if manifest.output_files.is_empty() {
+            manifest.folder = true;
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        fs::write(output_dir.join(MANIFEST_JSON), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Adds `file`, replacing the entry for the same path if there is one, and lists it and
+    /// its directories in the tree.
+    pub fn insert(&mut self, file: ManifestFile) {
+        if let Some(tree) = &mut self.tree {
+            tree.insert(&file.path);
+        }
+        match self.files.iter_mut().find(|existing| existing.path == file.path) {
+            Some(existing) => *existing = file,
+            None => self.files.push(file),
+        }
+    }
+
+    /// Takes the files whose path `matches` out of the manifest and its tree, together with
+    /// the directories that were only in the tree for them, and returns them.
+    pub fn remove(&mut self, matches: impl Fn(&str) -> bool) -> Vec<ManifestFile> {
+        let (removed, kept) = std::mem::take(&mut self.files).into_iter().partition(|file| matches(&file.path));
+        self.files = kept;
+        if let Some(tree) = &mut self.tree {
+            tree.remove(&removed);
+        }
+        removed
+    }
+}
+
+impl ManifestTree {
+    /// Lists `path` and its directories.
+    fn insert(&mut self, path: &str) {
+        if !self.files.iter().any(|file| file == path) {
+            self.files.push(path.to_string());
+            self.files.sort();
+        }
+        for (at, _) in path.match_indices('/') {
+            if !self.directories.iter().any(|dir| *dir == path[..at]) {
+                self.directories.push(path[..at].to_string());
+            }
+        }
+        self.directories.sort();
+    }
+
+    fn remove(&mut self, removed: &[ManifestFile]) {
+        self.files.retain(|file| !removed.iter().any(|entry| entry.path == *file));
+        let emptied: Vec<&str> = removed
+            .iter()
+            .flat_map(|entry| entry.path.match_indices('/').map(|(at, _)| &entry.path[..at]))
+            .collect();
+        let files = &self.files;
+        self.directories.retain(|dir| {
+            !emptied.contains(&dir.as_str()) || files.iter().any(|file| file.starts_with(&format!("{}/", dir)))
+        });
+    }
+
+    /// The directories and files as `tree::render` takes them, below `Path::new("")`.
+    pub fn entries(&self) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        (
+            self.directories.iter().map(PathBuf::from).collect(),
+            self.files.iter().map(PathBuf::from).collect(),
+        )
+    }
+}
@@ -0,0 +1,43 @@
+//! `merge_small_files`: many tiny files of one directory, such as `__init__.py`s and config
+//! stubs of a few lines, written to the zip or concatenated output as one entry with a header
+//! before each file, rather than with an entry and a heading each.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Name of a merged entry in the zip, inside the directory whose files it holds.
+pub const MERGED_NAME: &str = "small-files.txt";
+
+/// Fewest small files of a directory that are merged; fewer are left as they are.
+pub const MIN_FILES: usize = 3;
+
+/// Indices of `files`, given as their paths and sizes, grouped into entries: the files of at
+/// most `max_bytes` in a directory together if there are at least `MIN_FILES` of them, the
+/// other files alone. Entries are in the order of their first file.
+pub fn group(files: &[(&Path, u64)], max_bytes: u64) -> Vec<Vec<usize>> {
+    let mut small: HashMap<&Path, Vec<usize>> = HashMap::new();
+    for (i, (path, size)) in files.iter().enumerate() {
+        if *size <= max_bytes {
+            small.entry(path.parent().unwrap_or(Path::new(""))).or_default().push(i);
+        }
+    }
+    let mut entries = Vec::new();
+    for (i, (path, size)) in files.iter().enumerate() {
+        let merged = match small.get(path.parent().unwrap_or(Path::new(""))) {
+            Some(members) if *size <= max_bytes && members.len() >= MIN_FILES => members,
+            _ => {
+                entries.push(vec![i]);
+                continue;
+            }
+        };
+        if merged[0] == i {
+            entries.push(merged.clone());
+        }
+    }
+    entries
+}
+
+/// The line before the contents of the file shown as `name` in a merged entry.
+pub fn header(name: &str) -> String {
+    format!("==> {} <==\n", name)
+}
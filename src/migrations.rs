@@ -0,0 +1,459 @@
+//! `consolidate_migrations`: the SQL migrations of a project replayed into the schema they
+//! leave behind, written as one `schema.sql` instead of every migration since the first.
+//! Replaying follows `CREATE`, `ALTER TABLE`, `DROP` and index statements well enough for a
+//! model to see the current tables; statements it cannot follow are kept as they are, and
+//! data changes such as `INSERT` are left out.
+
+use std::cmp::Ordering;
+use std::path::{Component, Path};
+
+/// Output name of what `consolidate` writes.
+pub const SCHEMA_SQL: &str = "schema.sql";
+
+/// Directories that hold migrations, by name.
+const MIGRATION_DIRS: &[&str] = &["migrations", "migration", "migrate", "versions", "changelog"];
+
+/// Words that start a table constraint rather than a column in `CREATE TABLE`.
+const CONSTRAINT_WORDS: &[&str] = &["constraint", "primary", "unique", "foreign", "check", "index", "key", "exclude", "fulltext", "spatial"];
+
+/// Whether the file at `relative` is an SQL migration: a `.sql` file in a directory named like
+/// `migrations` or `db/migrate`, other than the down half of a pair of files.
+pub fn is_migration(relative: &Path) -> bool {
+    let name = relative.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if !name.ends_with(".sql") || name.ends_with(".down.sql") || name == "down.sql" {
+        return false;
+    }
+    relative.parent().is_some_and(|dir| {
+        dir.components().any(|c| matches!(c, Component::Normal(name) if MIGRATION_DIRS.contains(&name.to_string_lossy().to_lowercase().as_str())))
+    })
+}
+
+/// Orders migration paths the way their tools apply them: by path, with runs of digits
+/// compared as numbers, so `V10__` comes after `V9__`.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    fn chunks(text: &str) -> Vec<(bool, &str)> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for (at, c) in text.char_indices().skip(1) {
+            let previous = text[..at].chars().next_back().unwrap_or(c);
+            if previous.is_ascii_digit() != c.is_ascii_digit() {
+                chunks.push((text[start..at].starts_with(|c: char| c.is_ascii_digit()), &text[start..at]));
+                start = at;
+            }
+        }
+        chunks.push((text[start..].starts_with(|c: char| c.is_ascii_digit()), &text[start..]));
+        chunks
+    }
+    for (x, y) in chunks(a).into_iter().zip(chunks(b)) {
+        let order = match (x, y) {
+            ((true, x), (true, y)) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then(x.cmp(y))
+            }
+            ((_, x), (_, y)) => x.cmp(y),
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    a.len().cmp(&b.len()).then(a.cmp(b))
+}
+
+#[derive(Default)]
+struct Table {
+    name: String,
+    columns: Vec<(String, String)>,
+    constraints: Vec<String>,
+    /// `ALTER TABLE` statements that were not followed, in order.
+    alterations: Vec<String>,
+}
+
+/// Everything the migrations created and have not dropped, in the order it was created.
+#[derive(Default)]
+struct Schema {
+    tables: Vec<Table>,
+    /// `(index name, table, statement)`.
+    indexes: Vec<(String, String, String)>,
+    /// Views, types, functions and the like: `(kind, name, statement)`; statements without a
+    /// name are kept as they are.
+    others: Vec<(String, String, String)>,
+}
+
+/// `schema.sql` for `migrations`, `(path, contents)` pairs in the order they are applied.
+pub fn consolidate(migrations: &[(String, String)]) -> String {
+    let mut schema = Schema::default();
+    for (_, sql) in migrations {
+        for statement in statements(up_part(sql)) {
+            schema.apply(&statement);
+        }
+    }
+
+    let mut document = format!(
+        "-- The schema left by replaying {} migrations in order, consolidated by lmprep.\n\
+         -- Statements it could not follow are kept as written; data changes are left out.\n",
+        migrations.len()
+    );
+    if let Some((first, _)) = migrations.first() {
+        let last = &migrations[migrations.len() - 1].0;
+        document.push_str(&format!("-- First migration: {}\n-- Last migration: {}\n", first, last));
+    }
+    for table in &schema.tables {
+        let mut lines: Vec<String> = table.columns.iter().map(|(name, definition)| format!("  {} {}", name, definition).trim_end().to_string()).collect();
+        lines.extend(table.constraints.iter().map(|constraint| format!("  {}", constraint)));
+        document.push_str(&format!("\nCREATE TABLE {} (\n{}\n);\n", table.name, lines.join(",\n")));
+        for alteration in &table.alterations {
+            document.push_str(&format!("{};\n", alteration));
+        }
+    }
+    if !schema.indexes.is_empty() {
+        document.push('\n');
+        for (_, _, statement) in &schema.indexes {
+            document.push_str(&format!("{};\n", statement));
+        }
+    }
+    for (_, _, statement) in &schema.others {
+        document.push_str(&format!("\n{};\n", statement));
+    }
+    document
+}
+
+/// The part of a migration that applies it: everything before the markers of the `down`
+/// half that goose, dbmate and sql-migrate use.
+fn up_part(sql: &str) -> &str {
+    let lower = sql.to_lowercase();
+    ["-- +goose down", "-- migrate:down", "-- +migrate down"]
+        .iter()
+        .filter_map(|marker| lower.find(marker))
+        .min()
+        .map_or(sql, |at| &sql[..at])
+}
+
+/// The statements of `sql`, split at semicolons outside strings, quoted names, comments and
+/// dollar-quoted bodies, with comments removed and whitespace trimmed.
+fn statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let chars: Vec<char> = sql.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '-' if next == Some('-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                current.push(' ');
+                continue;
+            }
+            '\'' | '"' | '`' => {
+                current.push(c);
+                i += 1;
+                while i < chars.len() {
+                    current.push(chars[i]);
+                    i += 1;
+                    if chars[i - 1] == c {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '$' => {
+                // `$$ ... $$` or `$body$ ... $body$`
+                let tag_end = chars[i + 1..].iter().position(|&t| t == '$').map(|at| i + 1 + at);
+                let tag: Option<String> = tag_end
+                    .map(|end| chars[i..=end].iter().collect())
+                    .filter(|tag: &String| tag[1..tag.len() - 1].chars().all(|t| t.is_alphanumeric() || t == '_'));
+                if let Some(tag) = tag {
+                    let body: String = chars[i + tag.len()..].iter().collect();
+                    let length = body.find(&tag).map_or(body.len(), |end| end + tag.len());
+                    current.push_str(&tag);
+                    current.push_str(&body[..length]);
+                    i += tag.len() + body[..length].chars().count();
+                    continue;
+                }
+            }
+            ';' => {
+                statements.push(std::mem::take(&mut current));
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+        i += 1;
+    }
+    statements.push(current);
+    statements.into_iter().map(|statement| statement.trim().to_string()).filter(|statement| !statement.is_empty()).collect()
+}
+
+/// `name` without the quotes around it or its parts, lowercased, to compare names written
+/// differently.
+fn key(name: &str) -> String {
+    name.chars().filter(|c| !matches!(c, '"' | '`' | '[' | ']')).collect::<String>().to_lowercase()
+}
+
+/// The first word of `text` and the rest after it.
+fn word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(|c: char| c.is_whitespace() || c == '(').unwrap_or(text.len());
+    (&text[..end], &text[end..])
+}
+
+/// `text` after the words of `prefix`, ignoring case and extra whitespace.
+fn after<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let mut rest = text;
+    for expected in prefix.split_whitespace() {
+        let (found, tail) = word(rest);
+        if !found.eq_ignore_ascii_case(expected) {
+            return None;
+        }
+        rest = tail;
+    }
+    Some(rest.trim_start())
+}
+
+/// `rest` without a leading `IF EXISTS` or `IF NOT EXISTS`.
+fn skip_if_exists(rest: &str) -> &str {
+    after(rest, "if not exists").or_else(|| after(rest, "if exists")).unwrap_or(rest)
+}
+
+/// The parts of a parenthesised list split at top-level commas.
+fn split_top_level(list: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    let mut quote = None;
+    for c in list.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(std::mem::take(&mut current).trim().to_string());
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current.trim().to_string());
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+impl Schema {
+    fn table(&mut self, name: &str) -> Option<&mut Table> {
+        let name = key(name);
+        self.tables.iter_mut().find(|table| key(&table.name) == name)
+    }
+
+    fn apply(&mut self, statement: &str) {
+        let (verb, _) = word(statement);
+        match verb.to_lowercase().as_str() {
+            "create" => self.create(statement),
+            "alter" => self.alter(statement),
+            "drop" => self.drop(statement),
+            // data changes, transactions and session settings say nothing about the schema
+            "insert" | "update" | "delete" | "begin" | "commit" | "start" | "set" | "select" | "comment" | "grant"
+            | "revoke" | "analyze" | "vacuum" | "pragma" | "use" => {}
+            _ => self.others.push((String::new(), String::new(), statement.to_string())),
+        }
+    }
+
+    fn create(&mut self, statement: &str) {
+        let rest = after(statement, "create").unwrap_or_default();
+        let rest = after(rest, "or replace").unwrap_or(rest);
+        let temporary = ["temporary", "temp", "unlogged"].iter().find_map(|word| after(rest, word));
+        let rest = temporary.unwrap_or(rest);
+
+        if let Some(rest) = after(rest, "table") {
+            let (name, body) = word(skip_if_exists(rest));
+            let (Some(open), Some(close)) = (body.find('('), body.rfind(')')) else {
+                // `CREATE TABLE a AS SELECT ...`
+                self.others.push(("table".to_string(), key(name), statement.to_string()));
+                return;
+            };
+            let mut table = Table { name: name.to_string(), ..Table::default() };
+            for part in split_top_level(&body[open + 1..close]) {
+                let (first, definition) = word(&part);
+                if CONSTRAINT_WORDS.contains(&first.to_lowercase().as_str()) {
+                    table.constraints.push(part.clone());
+                } else {
+                    table.columns.push((first.to_string(), definition.trim().to_string()));
+                }
+            }
+            self.tables.retain(|existing| key(&existing.name) != key(name));
+            self.tables.push(table);
+            return;
+        }
+
+        let index = after(rest, "unique index").or_else(|| after(rest, "index"));
+        if let Some(index) = index {
+            let index = after(index, "concurrently").unwrap_or(index);
+            let (name, on) = word(skip_if_exists(index));
+            let table = after(on, "on").map(|on| word(after(on, "only").unwrap_or(on)).0.to_string()).unwrap_or_default();
+            self.indexes.retain(|(existing, _, _)| key(existing) != key(name));
+            self.indexes.push((name.to_string(), key(&table), statement.to_string()));
+            return;
+        }
+
+        let (kind, rest) = word(rest);
+        let kind = match kind.to_lowercase().as_str() {
+            "materialized" => "view".to_string(),
+            other => other.to_string(),
+        };
+        let rest = after(rest, "view").unwrap_or(rest);
+        let (name, _) = word(skip_if_exists(rest));
+        let name = key(name);
+        self.others.retain(|(existing_kind, existing, _)| !(*existing_kind == kind && *existing == name));
+        self.others.push((kind, name, statement.to_string()));
+    }
+
+    fn alter(&mut self, statement: &str) {
+        let Some(rest) = after(statement, "alter table") else {
+            self.others.push((String::new(), String::new(), statement.to_string()));
+            return;
+        };
+        let rest = skip_if_exists(rest);
+        let rest = after(rest, "only").unwrap_or(rest);
+        let (name, actions) = word(rest);
+        if self.table(name).is_none() {
+            self.others.push((String::new(), String::new(), statement.to_string()));
+            return;
+        }
+        let mut unfollowed = Vec::new();
+        for action in split_top_level(actions) {
+            if !self.alter_action(name, &action) {
+                unfollowed.push(action);
+            }
+        }
+        if let Some(table) = self.table(name) {
+            if !unfollowed.is_empty() {
+                table.alterations.push(format!("ALTER TABLE {} {}", table.name, unfollowed.join(", ")));
+            }
+        }
+    }
+
+    /// Applies one action of `ALTER TABLE name`; `false` if it cannot be followed.
+    fn alter_action(&mut self, name: &str, action: &str) -> bool {
+        if let Some(new_name) = after(action, "rename to") {
+            let new_name = word(new_name).0.to_string();
+            let old = key(name);
+            for index in self.indexes.iter_mut().filter(|(_, table, _)| *table == old) {
+                index.1 = key(&new_name);
+            }
+            if let Some(table) = self.table(name) {
+                table.name = new_name;
+            }
+            return true;
+        }
+        let Some(table) = self.table(name) else {
+            return false;
+        };
+        if let Some(constraint) = after(action, "add constraint") {
+            table.constraints.push(format!("CONSTRAINT {}", constraint));
+            return true;
+        }
+        let added = after(action, "add column").or_else(|| after(action, "add"));
+        if let Some(added) = added {
+            let (first, definition) = word(skip_if_exists(added));
+            if CONSTRAINT_WORDS.contains(&first.to_lowercase().as_str()) {
+                table.constraints.push(added.to_string());
+            } else {
+                table.columns.retain(|(existing, _)| key(existing) != key(first));
+                table.columns.push((first.to_string(), definition.trim().to_string()));
+            }
+            return true;
+        }
+        if let Some(constraint) = after(action, "drop constraint") {
+            let (constraint, _) = word(skip_if_exists(constraint));
+            let before = table.constraints.len();
+            table.constraints.retain(|existing| {
+                after(existing, "constraint").is_none_or(|rest| key(word(rest).0) != key(constraint))
+            });
+            return table.constraints.len() < before;
+        }
+        let dropped = after(action, "drop column").or_else(|| after(action, "drop"));
+        if let Some(dropped) = dropped {
+            let (column, _) = word(skip_if_exists(dropped));
+            let before = table.columns.len();
+            table.columns.retain(|(existing, _)| key(existing) != key(column));
+            return table.columns.len() < before;
+        }
+        if let Some(renamed) = after(action, "rename column").or_else(|| after(action, "rename")) {
+            let (old, rest) = word(renamed);
+            let Some(new) = after(rest, "to") else {
+                return false;
+            };
+            let new = word(new).0.to_string();
+            match table.columns.iter_mut().find(|(existing, _)| key(existing) == key(old)) {
+                Some(column) => column.0 = new,
+                None => return false,
+            }
+            return true;
+        }
+        let altered = after(action, "alter column").or_else(|| after(action, "alter"));
+        if let Some(altered) = altered {
+            let (column, change) = word(altered);
+            let new_type = after(change, "set data type").or_else(|| after(change, "type"));
+            if let (Some(new_type), Some(existing)) =
+                (new_type, table.columns.iter_mut().find(|(existing, _)| key(existing) == key(column)))
+            {
+                // the old type is the first word of the definition and its arguments, as in `varchar(40)`
+                let (_, constraints) = word(&existing.1);
+                let constraints = match constraints.strip_prefix('(') {
+                    Some(arguments) => arguments.split_once(')').map_or("", |(_, rest)| rest),
+                    None => constraints,
+                };
+                let using = new_type.to_lowercase().find(" using ").unwrap_or(new_type.len());
+                existing.1 = format!("{}{}", new_type[..using].trim(), constraints);
+                return true;
+            }
+        }
+        if let Some(modified) = after(action, "modify column").or_else(|| after(action, "modify")) {
+            let (column, definition) = word(modified);
+            if let Some(existing) = table.columns.iter_mut().find(|(existing, _)| key(existing) == key(column)) {
+                existing.1 = definition.trim().to_string();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn drop(&mut self, statement: &str) {
+        let rest = after(statement, "drop").unwrap_or_default();
+        let (kind, rest) = word(rest);
+        let kind = kind.to_lowercase();
+        let rest = match kind.as_str() {
+            "materialized" => after(rest, "view").unwrap_or(rest),
+            _ => rest,
+        };
+        let rest = after(rest, "concurrently").unwrap_or(rest);
+        let names: Vec<String> = skip_if_exists(rest)
+            .split(',')
+            .map(|name| key(word(name).0))
+            .filter(|name| !name.is_empty())
+            .collect();
+        match kind.as_str() {
+            "table" => {
+                self.tables.retain(|table| !names.contains(&key(&table.name)));
+                self.indexes.retain(|(_, table, _)| !names.contains(table));
+            }
+            "index" => self.indexes.retain(|(name, _, _)| !names.contains(&key(name))),
+            _ => {
+                let kind = if kind == "materialized" { "view".to_string() } else { kind };
+                self.others.retain(|(existing_kind, name, _)| !(*existing_kind == kind && names.contains(name)));
+            }
+        }
+    }
+}
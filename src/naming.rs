@@ -0,0 +1,108 @@
+//! `output_name_template`: self-describing names such as `{repo}-{branch}-{date}.zip` for the
+//! zip or concatenated output, filled in when lmprep runs.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::flatten;
+use crate::git;
+
+/// Variables a template can use.
+const VARIABLES: &[&str] = &["repo", "branch", "commit", "date"];
+
+/// Stands in for a variable git cannot tell, outside of a repository.
+const UNKNOWN: &str = "unknown";
+
+/// Fills in the variables of `template` for the output of `source`:
+///
+/// - `{repo}`: name of the repository's top directory, or of `source` outside of git
+/// - `{branch}`: current branch, or the commit when detached
+/// - `{commit}`: abbreviated commit hash
+/// - `{date}`: today's date, `YYYY-MM-DD` in UTC
+///
+/// Values are made safe for file names the way path components are.
+pub fn render(template: &str, source: &Path) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        name.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}') else {
+            bail!("Unclosed `{{` in output_name_template {:?}", template);
+        };
+        let variable = &rest[open + 1..open + close];
+        let value = match variable {
+            "repo" => repo(source),
+            "branch" => branch(source),
+            "commit" => git::rev_parse(source, &["--short", "HEAD"]).unwrap_or_else(|_| UNKNOWN.to_string()),
+            "date" => today(),
+            _ => bail!(
+                "Unknown variable {{{}}} in output_name_template, use one of {}",
+                variable,
+                VARIABLES.iter().map(|v| format!("{{{}}}", v)).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        name.push_str(&flatten::sanitize_component(&value));
+        rest = &rest[open + close + 1..];
+    }
+    name.push_str(rest);
+    Ok(flatten::sanitize_component(&name))
+}
+
+/// `name` with the extension of an output, replacing the extension of another output if it
+/// ends with one.
+pub fn with_extension(name: &str, extension: &str) -> String {
+    let stem = [".zip", ".md", ".html", ".db"]
+        .iter()
+        .find_map(|known| name.len().checked_sub(known.len()).filter(|&at| name[at..].eq_ignore_ascii_case(known)))
+        .map_or(name, |at| &name[..at]);
+    format!("{}.{}", stem, extension)
+}
+
+fn repo(source: &Path) -> String {
+    let top = git::rev_parse(source, &["--show-toplevel"])
+        .map(Into::into)
+        .or_else(|_| source.canonicalize());
+    match top {
+        Ok(top) => top.file_name().map_or(UNKNOWN.to_string(), |name| name.to_string_lossy().into_owned()),
+        Err(_) => UNKNOWN.to_string(),
+    }
+}
+
+fn branch(source: &Path) -> String {
+    match git::rev_parse(source, &["--abbrev-ref", "HEAD"]) {
+        Ok(branch) if branch != "HEAD" => branch,
+        _ => git::rev_parse(source, &["--short", "HEAD"]).unwrap_or_else(|_| UNKNOWN.to_string()),
+    }
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    date(seconds_since_epoch() / 86_400)
+}
+
+/// The current time in UTC as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn timestamp() -> String {
+    let seconds = seconds_since_epoch();
+    let time = seconds % 86_400;
+    format!("{} {:02}:{:02}:{:02} UTC", date(seconds / 86_400), time / 3600, time / 60 % 60, time % 60)
+}
+
+fn seconds_since_epoch() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64
+}
+
+/// The date `days` after 1970-01-01 as `YYYY-MM-DD`.
+fn date(days: i64) -> String {
+    // after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,111 @@
+//! The order files take in the output. `order` picks one of the orderings here; embedders
+//! that need their own implement `FileOrderer` and hand it to `FileProcessor::with_orderer`.
+
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::Order;
+use crate::focus;
+use crate::git;
+use crate::processor::SelectedFile;
+use crate::tokens::{self, Tokenize};
+
+/// Decides the order of the selected files. They come in path order; sections and `promote`
+/// still apply on top of the order this gives them.
+pub trait FileOrderer: Send + Sync {
+    /// Puts `files`, selected under `source`, in the order the output should have them.
+    fn order(&self, source: &Path, files: &mut [SelectedFile]) -> Result<()>;
+}
+
+/// The built-in ordering for `order`.
+pub fn built_in(order: Order, tokenizer: Option<Arc<dyn Tokenize>>) -> Box<dyn FileOrderer> {
+    match order {
+        Order::Alpha => Box::new(Alpha),
+        Order::Size => Box::new(Size),
+        Order::Tokens => Box::new(Tokens(tokenizer)),
+        Order::GitRecency => Box::new(GitRecency),
+        Order::ImportRank => Box::new(ImportRank),
+    }
+}
+
+/// By path, the order files are selected in.
+pub struct Alpha;
+
+impl FileOrderer for Alpha {
+    fn order(&self, _source: &Path, files: &mut [SelectedFile]) -> Result<()> {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(())
+    }
+}
+
+/// Smallest first, in bytes.
+pub struct Size;
+
+impl FileOrderer for Size {
+    fn order(&self, _source: &Path, files: &mut [SelectedFile]) -> Result<()> {
+        files.sort_by_cached_key(|file| fs::metadata(&file.path).map_or(0, |metadata| metadata.len()));
+        Ok(())
+    }
+}
+
+/// Fewest tokens first, counted with the tokenizer if there is one.
+pub struct Tokens(pub Option<Arc<dyn Tokenize>>);
+
+impl FileOrderer for Tokens {
+    fn order(&self, _source: &Path, files: &mut [SelectedFile]) -> Result<()> {
+        files.sort_by_cached_key(|file| tokens::count(self.0.as_deref(), &read_text(&file.path)));
+        Ok(())
+    }
+}
+
+/// Most recently committed first. Files git does not know yet are the newest of all.
+pub struct GitRecency;
+
+impl FileOrderer for GitRecency {
+    fn order(&self, source: &Path, files: &mut [SelectedFile]) -> Result<()> {
+        let changed = git::last_changed(source)?;
+        files.sort_by_key(|file| Reverse(changed.get(&file.path).copied().unwrap_or(i64::MAX)));
+        Ok(())
+    }
+}
+
+/// The files the most other files import first, so what everything builds on comes before
+/// what uses it.
+pub struct ImportRank;
+
+impl FileOrderer for ImportRank {
+    fn order(&self, _source: &Path, files: &mut [SelectedFile]) -> Result<()> {
+        let texts: Vec<String> = files.iter().map(|file| read_text(&file.path)).collect();
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, file) in files.iter().enumerate() {
+            if let Some(name) = focus::module_name(&file.path) {
+                by_name.entry(name).or_default().push(i);
+            }
+        }
+        let mut importers = vec![HashSet::new(); files.len()];
+        for (i, text) in texts.iter().enumerate() {
+            for name in focus::imported_names(text) {
+                for &j in by_name.get(name).into_iter().flatten().filter(|&&j| j != i) {
+                    importers[j].insert(i);
+                }
+            }
+        }
+
+        let mut ranked: Vec<(usize, SelectedFile)> =
+            files.iter().cloned().enumerate().map(|(i, file)| (importers[i].len(), file)).collect();
+        ranked.sort_by_key(|(count, _)| Reverse(*count));
+        for (slot, (_, file)) in files.iter_mut().zip(ranked) {
+            *slot = file;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of `path` as text, empty if it cannot be read.
+fn read_text(path: &Path) -> String {
+    fs::read(path).map(|contents| String::from_utf8_lossy(&contents).into_owned()).unwrap_or_default()
+}
@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use crate::flatten;
+
+/// Renders a relative path with `/` separators on every platform, so names written to
+/// bundles do not depend on where they were generated.
+pub fn to_slash(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| flatten::escape_non_utf8(c.as_os_str()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns a path that is not subject to the 260 character `MAX_PATH` limit on Windows by
+/// converting it to an absolute `\\?\` (or `\\?\UNC\`) path. Other platforms get the path back
+/// unchanged.
+#[cfg(windows)]
+pub fn extended_length(path: &Path) -> PathBuf {
+    let absolute = match std::path::absolute(path) {
+        Ok(absolute) => absolute,
+        Err(_) => return path.to_path_buf(),
+    };
+    let raw = absolute.as_os_str().to_string_lossy();
+    if raw.starts_with(r"\\?\") {
+        absolute
+    } else if let Some(unc) = raw.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{}", unc))
+    } else {
+        PathBuf::from(format!(r"\\?\{}", raw))
+    }
+}
+
+#[cfg(not(windows))]
+pub fn extended_length(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(windows)]
+    #[test]
+    fn extended_length_adds_the_prefix_to_absolute_paths() {
+        assert_eq!(extended_length(Path::new(r"C:\src\main.rs")), PathBuf::from(r"\\?\C:\src\main.rs"));
+        assert_eq!(extended_length(Path::new(r"\\server\share\a.rs")), PathBuf::from(r"\\?\UNC\server\share\a.rs"));
+        assert_eq!(extended_length(Path::new(r"\\?\C:\already")), PathBuf::from(r"\\?\C:\already"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extended_length_makes_relative_paths_absolute() {
+        let extended = extended_length(Path::new(r"src\main.rs"));
+        let raw = extended.to_string_lossy();
+        assert!(raw.starts_with(r"\\?\"), "{}", raw);
+        assert!(raw.ends_with(r"\src\main.rs"), "{}", raw);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn extended_length_leaves_paths_alone() {
+        assert_eq!(extended_length(Path::new("src/main.rs")), PathBuf::from("src/main.rs"));
+    }
+
+    #[test]
+    fn to_slash_joins_components_with_slashes() {
+        assert_eq!(to_slash(&Path::new("src").join("a").join("b.rs")), "src/a/b.rs");
+    }
+}
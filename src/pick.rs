@@ -0,0 +1,184 @@
+//! `lm pick`: choosing the files of a run by hand from the list the filters select, with
+//! fzf when it is installed and a built-in fuzzy prompt when it is not.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeSet, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+use crate::interrupt;
+use crate::lock;
+use crate::processor::FileProcessor;
+
+/// Matches the built-in prompt lists at a time; narrowing the filter shows the rest.
+const MAX_SHOWN: usize = 40;
+
+/// What fzf exits with when Esc or Ctrl-C closes it.
+const FZF_CANCELLED: i32 = 130;
+
+/// Walks `source`, asks which of the files the filters select to keep and writes the usual
+/// output with only those. Nothing is written if none are picked.
+pub fn run(source: &str, config: &Config, verbose: bool) -> Result<()> {
+    let processor = FileProcessor::new(source, config, verbose)?;
+    let walked = processor.walk()?;
+    let names: Vec<String> = walked
+        .files
+        .iter()
+        .map(|file| file.strip_prefix(processor.source_path()).unwrap_or(file).to_string_lossy().replace('\\', "/"))
+        .collect();
+    if names.is_empty() {
+        bail!("No files in {} pass the filters", source);
+    }
+
+    let picked = pick(&names)?;
+    if picked.is_empty() {
+        println!("Nothing picked, the output is unchanged");
+        return Ok(());
+    }
+    if verbose {
+        println!("Picked {} of {} files", picked.len(), names.len());
+    }
+    let picked: HashSet<&str> = picked.iter().map(String::as_str).collect();
+    let kept: HashSet<PathBuf> =
+        walked.files.iter().zip(&names).filter(|(_, name)| picked.contains(name.as_str())).map(|(file, _)| file.clone()).collect();
+
+    interrupt::install()?;
+    let _lock = lock::OutputLock::acquire(processor.output_dir())?;
+    processor.prepare_output_directory()?;
+    processor.process_walked(&walked.only(&kept))
+}
+
+/// The entries of `files` the user picks, with `fzf --multi` if it is on the `PATH` and the
+/// built-in prompt otherwise.
+pub fn pick(files: &[String]) -> Result<Vec<String>> {
+    match fzf(files) {
+        Ok(picked) => Ok(picked),
+        Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::NotFound) => prompt(files),
+        Err(e) => Err(e),
+    }
+}
+
+/// Runs fzf over `files`, one per line; its interface goes to the terminal, so it works in a
+/// tmux pane or popup as it does anywhere else. `FZF_DEFAULT_OPTS` applies as usual.
+fn fzf(files: &[String]) -> Result<Vec<String>> {
+    let mut child = Command::new("fzf")
+        .args(["--multi", "--prompt", "lm pick> ", "--header", "Tab to pick, Enter to finish"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = child.stdin.take().context("Could not write to fzf")?;
+        for file in files {
+            // fzf may quit before reading everything
+            if writeln!(stdin, "{}", file).is_err() {
+                break;
+            }
+        }
+    }
+    let output = child.wait_with_output()?;
+    match output.status.code() {
+        Some(0) => {}
+        // 1 is no match
+        Some(1) | Some(FZF_CANCELLED) => return Ok(Vec::new()),
+        _ => bail!("fzf failed with {}", output.status),
+    }
+    let known: HashSet<&str> = files.iter().map(String::as_str).collect();
+    Ok(String::from_utf8_lossy(&output.stdout).lines().filter(|line| known.contains(line)).map(str::to_string).collect())
+}
+
+/// The built-in prompt: lists the files matching a filter, numbered, and picks by number
+/// until an empty line.
+fn prompt(files: &[String]) -> Result<Vec<String>> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut picked = BTreeSet::new();
+    let mut matches: Vec<&String> = files.iter().collect();
+    loop {
+        for (i, file) in matches.iter().enumerate().take(MAX_SHOWN) {
+            let mark = if picked.contains(*file) { '*' } else { ' ' };
+            println!("{} {:>4}  {}", mark, i + 1, file);
+        }
+        if matches.len() > MAX_SHOWN {
+            println!("       ... and {} more, type to narrow them down", matches.len() - MAX_SHOWN);
+        }
+        print!("{} picked. Filter, numbers to pick (1 3 5-7), a for all listed, or Enter to finish: ", picked.len());
+        io::stdout().flush()?;
+
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        match numbers(line, matches.len()) {
+            Some(chosen) => picked.extend(chosen.into_iter().map(|i| matches[i].clone())),
+            None => {
+                matches = filter(files, line);
+                if matches.is_empty() {
+                    println!("Nothing matches {:?}", line);
+                    matches = files.iter().collect();
+                }
+            }
+        }
+    }
+    println!();
+    Ok(files.iter().filter(|file| picked.contains(*file)).cloned().collect())
+}
+
+/// The 0-based indices `line` names among `count` matches, such as `1 3 5-7` or `a` for all;
+/// `None` if it is not a list of numbers, and so a filter.
+fn numbers(line: &str, count: usize) -> Option<Vec<usize>> {
+    if line == "a" {
+        return Some((0..count).collect());
+    }
+    let mut chosen = Vec::new();
+    for word in line.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty()) {
+        let (first, last) = match word.split_once('-') {
+            Some((first, last)) => (first.parse::<usize>().ok()?, last.parse::<usize>().ok()?),
+            None => {
+                let number = word.parse::<usize>().ok()?;
+                (number, number)
+            }
+        };
+        chosen.extend((first.max(1)..=last.min(count)).map(|number| number - 1));
+    }
+    Some(chosen)
+}
+
+/// The entries of `files` that have every word of `query` as a subsequence, ignoring case,
+/// the tightest matches first.
+fn filter<'a>(files: &'a [String], query: &str) -> Vec<&'a String> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let mut scored: Vec<(usize, &String)> = files
+        .iter()
+        .filter_map(|file| {
+            let lower = file.to_lowercase();
+            let spans: Option<Vec<usize>> = words.iter().map(|word| span(&lower, word)).collect();
+            Some((spans?.iter().sum(), file))
+        })
+        .collect();
+    scored.sort_by_key(|&(score, file)| (score, Path::new(file).components().count(), file));
+    scored.into_iter().map(|(_, file)| file).collect()
+}
+
+/// The length of the shortest stretch of `text` that holds the characters of `word` in order,
+/// if any does.
+fn span(text: &str, word: &str) -> Option<usize> {
+    let text: Vec<char> = text.chars().collect();
+    let word: Vec<char> = word.chars().collect();
+    let first = *word.first()?;
+    text.iter()
+        .enumerate()
+        .filter(|&(_, &c)| c == first)
+        .filter_map(|(start, _)| {
+            let mut at = start;
+            for &c in &word[1..] {
+                at += 1 + text[at + 1..].iter().position(|&t| t == c)?;
+            }
+            Some(at + 1 - start)
+        })
+        .min()
+}
@@ -0,0 +1,296 @@
+//! Detection of personal data in data and text files: email addresses, phone numbers and
+//! national ID numbers.
+
+use serde::Deserialize;
+use std::ops::Range;
+
+use crate::secrets::{Finding, ALLOW_MARKER};
+
+/// What to do with personal data in the files `pii_categories` covers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PiiPolicy {
+    /// Do not look for personal data
+    #[default]
+    Off,
+    /// Report it and write the output anyway
+    Warn,
+    /// Replace it with `[REDACTED:kind]` in the output
+    Redact,
+    /// Report it and write nothing if there is any
+    Fail,
+}
+
+/// A match in one line of text.
+struct Match {
+    kind: &'static str,
+    range: Range<usize>,
+}
+
+/// Everything in `text` that looks like personal data, reported against `path`.
+pub fn scan(path: &str, text: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.contains(ALLOW_MARKER)) {
+        findings.extend(find(line).into_iter().map(|m| Finding {
+            path: path.to_string(),
+            line: number + 1,
+            detector: m.kind,
+            preview: format!("{} chars", line[m.range].chars().count()),
+        }));
+    }
+    findings
+}
+
+/// `text` with every match replaced by `[REDACTED:kind]`.
+pub fn redact(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if line.contains(ALLOW_MARKER) {
+            out.push_str(line);
+            continue;
+        }
+        let mut last = 0;
+        for m in find(line) {
+            out.push_str(&line[last..m.range.start]);
+            out.push_str(&format!("[REDACTED:{}]", m.kind));
+            last = m.range.end;
+        }
+        out.push_str(&line[last..]);
+    }
+    out
+}
+
+/// Matches in `line`, in order and not overlapping.
+fn find(line: &str) -> Vec<Match> {
+    let mut matches = emails(line);
+    for m in national_ids(line).into_iter().chain(phones(line)) {
+        if !matches.iter().any(|e| e.range.start < m.range.end && m.range.start < e.range.end) {
+            matches.push(m);
+        }
+    }
+    matches.sort_by_key(|m| m.range.start);
+    matches
+}
+
+fn emails(line: &str) -> Vec<Match> {
+    let bytes = line.as_bytes();
+    let is_local = |b: u8| b.is_ascii_alphanumeric() || b"._%+-".contains(&b);
+    let is_domain = |b: u8| b.is_ascii_alphanumeric() || b == b'-' || b == b'.';
+
+    let mut matches = Vec::new();
+    for (at, _) in line.match_indices('@') {
+        let start = bytes[..at].iter().rposition(|&b| !is_local(b)).map_or(0, |i| i + 1);
+        let mut end = at + 1 + bytes[at + 1..].iter().position(|&b| !is_domain(b)).unwrap_or(bytes.len() - at - 1);
+        // a sentence may end right after the address
+        while end > at + 1 && bytes[end - 1] == b'.' {
+            end -= 1;
+        }
+        let domain = &line[at + 1..end];
+        let tld = domain.rsplit('.').next().unwrap_or_default();
+        if start < at && domain.contains('.') && tld.len() >= 2 && tld.bytes().all(|b| b.is_ascii_alphabetic()) {
+            matches.push(Match { kind: "email", range: start..end });
+        }
+    }
+    matches
+}
+
+/// Runs of digits and the separators phone numbers are written with, such as
+/// `+44 20 7946 0958` or `(555) 123-4567`.
+fn phones(line: &str) -> Vec<Match> {
+    let bytes = line.as_bytes();
+    let is_part = |b: u8| b.is_ascii_digit() || b" -.()".contains(&b);
+
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let starts = bytes[i] == b'+' || bytes[i] == b'(' || bytes[i].is_ascii_digit();
+        let after_word = i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        if !starts || after_word {
+            i += 1;
+            continue;
+        }
+
+        let mut end = i + 1 + bytes[i + 1..].iter().position(|&b| !is_part(b)).unwrap_or(bytes.len() - i - 1);
+        while end > i && !bytes[end - 1].is_ascii_digit() {
+            end -= 1;
+        }
+        let candidate = &line[i..end];
+        if is_phone(candidate) && !bytes.get(end).is_some_and(|b| b.is_ascii_alphanumeric()) {
+            matches.push(Match { kind: "phone", range: i..end });
+        }
+        i = end.max(i + 1);
+    }
+    matches
+}
+
+fn is_phone(candidate: &str) -> bool {
+    let digits = candidate.bytes().filter(u8::is_ascii_digit).count();
+    let groups: Vec<&str> = candidate
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|g| !g.is_empty())
+        .collect();
+    let dotted_only = !candidate.contains([' ', '-', '(', '+']);
+    // an IPv4 address or a version number rather than a phone number
+    let address_like = dotted_only && groups.iter().all(|g| g.len() <= 3);
+    // a date followed by the hour of a timestamp, as in `2024-01-15 10:30`
+    let dated = candidate.get(..10).is_some_and(|date| shaped(date, "9999-99-99"));
+
+    (10..=15).contains(&digits)
+        && (candidate.starts_with('+') || groups.len() >= 3)
+        && !address_like
+        && !dated
+        && !(dotted_only && groups.len() == 2)
+}
+
+/// US Social Security, Canadian Social Insurance and UK National Insurance numbers.
+fn national_ids(line: &str) -> Vec<Match> {
+    let bytes = line.as_bytes();
+    let boundary = |i: usize| i >= bytes.len() || !bytes[i].is_ascii_alphanumeric();
+
+    let mut matches = Vec::new();
+    for start in 0..bytes.len() {
+        if !line.is_char_boundary(start) || start > 0 && !boundary(start - 1) {
+            continue;
+        }
+        let rest = &line[start..];
+        let found = [(11, "us-ssn"), (11, "ca-sin"), (9, "uk-nino")].into_iter().find(|&(len, kind)| {
+            rest.len() >= len
+                && rest.is_char_boundary(len)
+                && boundary(start + len)
+                && match kind {
+                    "us-ssn" => is_ssn(&rest[..len]),
+                    "ca-sin" => is_sin(&rest[..len]),
+                    _ => is_nino(&rest[..len]),
+                }
+        });
+        if let Some((len, kind)) = found {
+            matches.push(Match { kind, range: start..start + len });
+        }
+    }
+    matches
+}
+
+/// Whether `pattern` (`9` for a digit, `A` for an uppercase letter, anything else literal)
+/// describes `text`.
+fn shaped(text: &str, pattern: &str) -> bool {
+    text.len() == pattern.len()
+        && text.bytes().zip(pattern.bytes()).all(|(t, p)| match p {
+            b'9' => t.is_ascii_digit(),
+            b'A' => t.is_ascii_uppercase(),
+            _ => t == p,
+        })
+}
+
+/// `123-45-6789`, without the area numbers that are never issued.
+fn is_ssn(text: &str) -> bool {
+    shaped(text, "999-99-9999")
+        && !text.starts_with("000")
+        && !text.starts_with("666")
+        && !text.starts_with('9')
+        && &text[4..6] != "00"
+        && &text[7..] != "0000"
+}
+
+/// `123-456-782`, which must pass the Luhn check.
+fn is_sin(text: &str) -> bool {
+    if !shaped(text, "999-999-999") {
+        return false;
+    }
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, &d)| match i % 2 {
+            1 if d * 2 > 9 => d * 2 - 9,
+            1 => d * 2,
+            _ => d,
+        })
+        .sum();
+    digits[0] != 0 && sum.is_multiple_of(10)
+}
+
+/// `AB123456C`
+fn is_nino(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    shaped(text, "AA999999A")
+        && !b"DFIQUV".contains(&bytes[0])
+        && !b"DFIQUVO".contains(&bytes[1])
+        && b"ABCD".contains(&bytes[8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(line: &str) -> Vec<&'static str> {
+        find(line).into_iter().map(|m| m.kind).collect()
+    }
+
+    #[test]
+    fn finds_emails() {
+        assert_eq!(kinds("contact: jane.doe+news@example.co.uk"), ["email"]);
+        assert_eq!(redact("Write to ops@example.com."), "Write to [REDACTED:email].");
+        // no domain, no dot in it, or a numeric top-level domain
+        assert!(kinds("@decorator").is_empty());
+        assert!(kinds("user@localhost").is_empty());
+        assert!(kinds("pkg@1.2.3").is_empty());
+        assert!(kinds("build@sha256.1234").is_empty());
+    }
+
+    #[test]
+    fn finds_phone_numbers() {
+        assert_eq!(kinds("+44 20 7946 0958"), ["phone"]);
+        assert_eq!(kinds("call (555) 123-4567 today"), ["phone"]);
+        assert_eq!(kinds("555.123.4567"), ["phone"]);
+        assert_eq!(kinds("+14155552671"), ["phone"]);
+    }
+
+    #[test]
+    fn ignores_numbers_that_are_not_phone_numbers() {
+        // version strings and addresses
+        assert!(kinds("version 10.2.3.4567").is_empty());
+        assert!(kinds("listen 192.168.100.200").is_empty());
+        assert!(kinds("requires >= 1.2.3").is_empty());
+        // timestamps and dates
+        assert!(kinds("2024-01-15 10:30:00").is_empty());
+        assert!(kinds("2024-01-15T10:30:00Z").is_empty());
+        assert!(kinds("created_at: 1705314600").is_empty());
+        assert!(kinds("1705314600.123456").is_empty());
+        // too short or too long, or part of a word
+        assert!(kinds("123-4567").is_empty());
+        assert!(kinds("1234 5678 9012 3456 7890").is_empty());
+        assert!(kinds("id_555-123-4567").is_empty());
+    }
+
+    #[test]
+    fn finds_national_ids() {
+        assert_eq!(kinds("ssn: 123-45-6789"), ["us-ssn"]);
+        assert_eq!(kinds("SIN 130-692-544"), ["ca-sin"]);
+        assert_eq!(kinds("NI number AB123456C"), ["uk-nino"]);
+        // never issued, failing the Luhn check, or with letters no NINO uses
+        assert!(kinds("000-12-3456 666-12-3456 912-34-5678 123-00-4567 123-45-0000").is_empty());
+        assert!(kinds("130-692-545").is_empty());
+        assert!(kinds("DA123456C AB123456E").is_empty());
+        // inside a longer token
+        assert!(kinds("X123-45-6789").is_empty());
+        assert!(kinds("ZAB123456C").is_empty());
+    }
+
+    #[test]
+    fn scan_reports_lines_and_skips_allowed_ones() {
+        let text = "name,email\nJane,jane@example.com\nBob,bob@example.com # lmprep:allow-secret\n";
+        let findings = scan("people.csv", text);
+        let found: Vec<(usize, &str, &str)> =
+            findings.iter().map(|f| (f.line, f.detector, f.preview.as_str())).collect();
+        assert_eq!(found, [(2, "email", "16 chars")]);
+    }
+
+    #[test]
+    fn redact_replaces_every_match_and_keeps_allowed_lines() {
+        let text = "a@example.com, +1 415 555 2671\nssn 123-45-6789 # lmprep:allow-secret\nno data\n";
+        assert_eq!(
+            redact(text),
+            "[REDACTED:email], [REDACTED:phone]\nssn 123-45-6789 # lmprep:allow-secret\nno data\n"
+        );
+    }
+}
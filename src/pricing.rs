@@ -0,0 +1,45 @@
+//! `model` presets: the published input price of common models, to estimate what sending the
+//! prepared context once costs before pasting it into a paid API. Prices change; these are
+//! the list prices in US dollars when this version was released, without batch or cache
+//! discounts.
+
+/// A model and its input price.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub provider: &'static str,
+    /// US dollars per million input tokens.
+    pub input_per_million: f64,
+}
+
+const fn preset(name: &'static str, provider: &'static str, input_per_million: f64) -> Preset {
+    Preset { name, provider, input_per_million }
+}
+
+pub const PRESETS: &[Preset] = &[
+    preset("claude-opus-4", "Anthropic", 15.0),
+    preset("claude-sonnet-4", "Anthropic", 3.0),
+    preset("claude-3-5-haiku", "Anthropic", 0.8),
+    preset("gpt-4.1", "OpenAI", 2.0),
+    preset("gpt-4.1-mini", "OpenAI", 0.4),
+    preset("gpt-4o", "OpenAI", 2.5),
+    preset("gpt-4o-mini", "OpenAI", 0.15),
+    preset("o3", "OpenAI", 2.0),
+    preset("o4-mini", "OpenAI", 1.1),
+    preset("gemini-2.5-pro", "Google", 1.25),
+    preset("gemini-2.5-flash", "Google", 0.3),
+    preset("mistral-large", "Mistral", 2.0),
+    preset("deepseek-chat", "DeepSeek", 0.27),
+];
+
+/// The preset called `name`, ignoring case.
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+impl Preset {
+    /// What sending `tokens` input tokens costs, in US dollars.
+    pub fn cost(&self, tokens: usize) -> f64 {
+        tokens as f64 * self.input_per_million / 1_000_000.0
+    }
+}
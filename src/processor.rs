@@ -0,0 +1,2736 @@
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock};
+use zip::write::FileOptions;
+use zip::{DateTime, ZipArchive, ZipWriter};
+
+use crate::anonymize::Anonymizer;
+use crate::api_schema;
+use crate::assets;
+use crate::blame;
+use crate::brief;
+use crate::cache;
+use crate::commands::{self, COMMANDS_MD};
+use crate::classify;
+use crate::concat::{self, ConcatWriter};
+use crate::config::{Config, Layout, LfsPointers, Output, TrivialFiles, VendoredCode};
+use crate::copy_mode::{self, CopyMode};
+use crate::disk;
+use crate::editor_history;
+use crate::file_filter::{FileFilter, WalkResult};
+use crate::flatten::{self, Flattener};
+use crate::focus;
+use crate::git;
+use crate::html::HtmlWriter;
+use crate::interrupt;
+use crate::lfs;
+use crate::line_endings::{self, Eol};
+use crate::migrations::{self, SCHEMA_SQL};
+use crate::naming;
+use crate::ordering::{self, FileOrderer};
+use crate::merge::{self, MERGED_NAME};
+use crate::manifest::{self, Manifest, ManifestFile, ManifestTree, OUTPUT_MARKER};
+use crate::paths;
+use crate::ranking;
+use crate::references;
+use crate::report::TokenReport;
+use crate::sampling;
+use crate::pii::{self, PiiPolicy};
+use crate::pricing::{self, Preset};
+use crate::search::{self, Match, SearchIndex, SEARCH_INDEX_JSON};
+use crate::secrets::{self, Finding, SecretScan};
+use crate::sqlite::{self, Table, Value};
+use crate::symbols;
+use crate::terraform::{self, TERRAFORM_MD};
+use crate::vendored::{self, VENDORED_MD};
+use crate::tokenizer;
+use crate::tokens::{self, Tokenize};
+use crate::transforms::{self, Transformed, Transforms};
+use crate::tree;
+use crate::trivial::{self, Boilerplate};
+
+/// Files listed individually when limits drop files, unless running verbose.
+const MAX_DROPPED_SHOWN: usize = 20;
+
+/// Per-file lines of each kind printed when running verbose before the rest are only
+/// counted, unless running `--verbose=full`.
+const MAX_VERBOSE_LINES: usize = 20;
+
+/// Roughly what each file adds to `manifest.json`, for `check_free_space`.
+const MANIFEST_BYTES_PER_FILE: u64 = 128;
+
+/// Output names of what `artifacts` adds.
+pub const CHANGES_PATCH: &str = "changes.patch";
+pub const HISTORY_TXT: &str = "history.txt";
+
+/// Output name of what `references` fetches, outside of `concat`.
+pub const REFERENCES_MD: &str = "references.md";
+
+/// Output name of the `brief`, outside of `concat`; `commands.md` is `commands::COMMANDS_MD`.
+pub const BRIEF_MD: &str = "brief.md";
+
+/// Names of the outputs in the output folder.
+pub const CONTEXT_ZIP: &str = "context.zip";
+pub const CONTEXT_MD: &str = "context.md";
+pub const CONTEXT_HTML: &str = "context.html";
+pub const CONTEXT_DB: &str = "context.db";
+pub const FILETREE_TXT: &str = "filetree.txt";
+
+/// Where `FileProcessor::search` reads the files of an existing output from: the folder if
+/// the output has one, else its zip or concatenated document.
+enum OutputContents {
+    Folder,
+    Zip(ZipArchive<BufReader<fs::File>>),
+    Concat(String),
+}
+
+impl OutputContents {
+    fn open(output_dir: &Path, manifest: &Manifest) -> Result<Self> {
+        if manifest.folder {
+            return Ok(Self::Folder);
+        }
+        for name in &manifest.output_files {
+            let path = output_dir.join(name);
+            if name.ends_with(".zip") {
+                return Ok(Self::Zip(ZipArchive::new(BufReader::new(fs::File::open(&path)?))?));
+            }
+            if name.ends_with(".md") {
+                return Ok(Self::Concat(fs::read_to_string(&path)?));
+            }
+        }
+        bail!("The output in {} has no folder, zip or concatenated document to search", output_dir.display())
+    }
+
+    /// The text of the file at `path`, named `output_name`, or `None` if it is binary or not
+    /// in the output.
+    fn read(&mut self, output_dir: &Path, path: &str, output_name: &str) -> Result<Option<String>> {
+        let contents = match self {
+            Self::Folder => match fs::read(paths::extended_length(&output_dir.join(output_name))) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            },
+            Self::Zip(zip) => {
+                let Ok(mut entry) = zip.by_name(output_name) else {
+                    return Ok(None);
+                };
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)?;
+                contents
+            }
+            Self::Concat(document) => return Ok(concat::block_contents(document, path).map(str::to_string)),
+        };
+        Ok((!contents.contains(&0)).then(|| String::from_utf8_lossy(&contents).into_owned()))
+    }
+}
+
+/// A selected file while `apply_token_budget` fits the files to the token budgets.
+struct Budgeted {
+    /// Position in path order.
+    index: usize,
+    file: SelectedFile,
+    full_tokens: usize,
+    /// Tokens of the outline, if the file has one.
+    outline_tokens: Option<usize>,
+    /// `ranking::sort_key`: files with smaller keys are kept first.
+    key: (Reverse<i64>, usize, u64),
+    /// Index into `sections` of the section the file is in.
+    section: Option<usize>,
+    /// Whether only the outline is kept.
+    outline: bool,
+}
+
+impl Budgeted {
+    fn tokens(&self) -> usize {
+        match self.outline_tokens {
+            Some(outline_tokens) if self.outline => outline_tokens,
+            _ => self.full_tokens,
+        }
+    }
+}
+
+/// Possible secrets and personal data in the output, see `FileProcessor::scan_contents`.
+#[derive(Default)]
+pub struct Findings {
+    pub secrets: Vec<Finding>,
+    pub pii: Vec<Finding>,
+}
+
+/// What decides how `FileProcessor::read_contents` transforms a file besides the file itself,
+/// see `FileProcessor::contents_key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentsKey {
+    outlined: bool,
+    summarized: bool,
+    eol: Eol,
+}
+
+/// A file that passed every filter, together with its name in the output.
+#[derive(Clone)]
+pub struct SelectedFile {
+    pub path: PathBuf,
+    pub output_name: String,
+}
+
+pub struct FileProcessor<'a> {
+    source_path: &'a Path,
+    output_dir: PathBuf,
+    config: &'a Config,
+    filter: FileFilter<'a>,
+    flattener: Flattener<'a>,
+    transforms: Transforms,
+    /// `sections`, with their globs compiled.
+    sections: Vec<(String, GlobSet)>,
+    /// `promote`, compiled.
+    promote: GlobSet,
+    /// The preset of `model`.
+    model: Option<&'static Preset>,
+    /// Counts tokens the way `tokenizer` says; `None` estimates them.
+    tokenizer: Option<Arc<dyn Tokenize>>,
+    /// The globs of `priorities`, compiled, and their priorities in the same order.
+    priorities: (GlobSet, Vec<i64>),
+    /// The files of `editor_history`, relative to the source, with how far above the highest
+    /// priority they rank: the most recent the furthest.
+    editor_history: HashMap<PathBuf, i64>,
+    /// Puts the files in `order`, or in the order an embedder chose, see `with_orderer`.
+    orderer: Box<dyn FileOrderer>,
+    anonymizer: Option<Anonymizer>,
+    /// Commits `history.txt` lists instead of the last `history` ones, see `with_history`.
+    history_range: Option<String>,
+    /// Whether files a stopped run already copied are kept, see `with_resume`.
+    resume: bool,
+    /// File names of the zip, concatenated, HTML and SQLite outputs, see `output_name_template`.
+    zip_name: String,
+    concat_name: String,
+    html_name: String,
+    db_name: String,
+    /// The `references` pages, fetched once for every output.
+    fetched_references: OnceLock<Option<String>>,
+    /// Files cut down to their outlines by `apply_token_budget`.
+    outlined: RwLock<HashSet<PathBuf>>,
+    /// Selected files that are API definitions, with `api_first`.
+    api_definitions: RwLock<HashSet<PathBuf>>,
+    /// SQL migrations set aside for `schema.sql`, with `consolidate_migrations`.
+    migrations: RwLock<Vec<PathBuf>>,
+    /// Directories the last `walk` left out as vendored code.
+    vendored: RwLock<Vec<PathBuf>>,
+    /// The line endings of the selected files with `normalize_line_endings`, see
+    /// `learn_line_endings`.
+    line_endings: RwLock<HashMap<PathBuf, Eol>>,
+    /// Selected files that could not be read, with why, left out of the output.
+    failed: Mutex<BTreeMap<PathBuf, String>>,
+    /// Hash of what the output of this run depends on, see `up_to_date`.
+    run_key: OnceLock<String>,
+    /// The walk `up_to_date` did, for `process` to go on with.
+    checked_walk: Mutex<Option<WalkResult>>,
+    verbose: bool,
+    /// Whether every per-file line is printed, see `with_verbose_full`.
+    verbose_full: bool,
+    /// Per-file lines printed when running verbose, by kind, see `verbose_file`.
+    verbose_lines: Mutex<BTreeMap<&'static str, usize>>,
+}
+
+impl<'a> FileProcessor<'a> {
+    pub fn new(source: &'a str, config: &'a Config, verbose: bool) -> Result<Self> {
+        let source_path = Path::new(source);
+        let subfolder = Path::new(&config.subfolder);
+        if config.subfolder.is_empty() || !subfolder.components().all(|c| matches!(c, Component::Normal(_))) {
+            bail!("subfolder {:?} must be a relative path inside the source", config.subfolder);
+        }
+        let output_dir = source_path.join(subfolder);
+        let filter = FileFilter::new(source_path, config);
+        if config.layout == Layout::Flat {
+            flatten::validate_delimiter(&config.delimiter)?;
+        }
+        let flattener = Flattener::new(&config.delimiter, config.flatten_scheme, config.max_filename_length);
+        let transforms = Transforms::new(&config.transforms)?;
+        let model = match &config.model {
+            Some(name) => Some(pricing::find(name).with_context(|| {
+                let names: Vec<&str> = pricing::PRESETS.iter().map(|preset| preset.name).collect();
+                format!("Unknown model {}, the presets are {}", name, names.join(", "))
+            })?),
+            None => None,
+        };
+        let tokenizer: Option<Arc<dyn Tokenize>> = match config.tokenizer.as_deref() {
+            None | Some(tokenizer::APPROX) => None,
+            Some(spec) => Some(Arc::new(tokenizer::load(spec)?)),
+        };
+        let sections = config
+            .sections
+            .iter()
+            .map(|section| {
+                let mut builder = GlobSetBuilder::new();
+                for glob in &section.globs {
+                    builder.add(Glob::new(glob).with_context(|| format!("Invalid glob {:?} in section {}", glob, section.name))?);
+                }
+                Ok((section.name.clone(), builder.build()?))
+            })
+            .collect::<Result<_>>()?;
+        let mut promote = GlobSetBuilder::new();
+        for glob in &config.promote {
+            promote.add(Glob::new(glob).with_context(|| format!("Invalid glob {:?} in promote", glob))?);
+        }
+        let promote = promote.build()?;
+        let mut priority_globs = GlobSetBuilder::new();
+        for glob in config.priorities.keys() {
+            priority_globs.add(Glob::new(glob).with_context(|| format!("Invalid glob {:?} in priorities", glob))?);
+        }
+        let priorities = (priority_globs.build()?, config.priorities.values().copied().collect());
+        let editor_history = match &config.editor_history {
+            Some(file) => {
+                let history = fs::read_to_string(file).with_context(|| format!("Could not read editor history {}", file))?;
+                let source = source_path.canonicalize().unwrap_or_else(|_| source_path.to_path_buf());
+                let recent = editor_history::parse(&history, &source);
+                let count = recent.len();
+                recent.into_iter().enumerate().map(|(i, file)| (file, (count - i) as i64)).collect()
+            }
+            None if config.editor_history_only => bail!("editor_history_only needs an editor_history file"),
+            None => HashMap::new(),
+        };
+        let (zip_name, concat_name, html_name, db_name) = match &config.output_name_template {
+            Some(template) => {
+                let name = naming::render(template, source_path)?;
+                let with_extension = |extension| naming::with_extension(&name, extension);
+                (with_extension("zip"), with_extension("md"), with_extension("html"), with_extension("db"))
+            }
+            None => (
+                CONTEXT_ZIP.to_string(),
+                CONTEXT_MD.to_string(),
+                CONTEXT_HTML.to_string(),
+                CONTEXT_DB.to_string(),
+            ),
+        };
+        let anonymizer = match config.anonymize {
+            true => {
+                let map_path = match &config.anonymize_map {
+                    Some(path) => PathBuf::from(path),
+                    None => default_anonymize_map(source_path)?,
+                };
+                Some(Anonymizer::load(&map_path, &config.anonymize_terms)?)
+            }
+            false => None,
+        };
+
+        Ok(Self {
+            source_path,
+            output_dir,
+            config,
+            filter,
+            flattener,
+            transforms,
+            sections,
+            promote,
+            model,
+            orderer: ordering::built_in(config.order, tokenizer.clone()),
+            tokenizer,
+            priorities,
+            editor_history,
+            anonymizer,
+            history_range: None,
+            resume: false,
+            zip_name,
+            concat_name,
+            html_name,
+            db_name,
+            fetched_references: OnceLock::new(),
+            outlined: RwLock::new(HashSet::new()),
+            api_definitions: RwLock::new(HashSet::new()),
+            migrations: RwLock::new(Vec::new()),
+            vendored: RwLock::new(Vec::new()),
+            line_endings: RwLock::new(HashMap::new()),
+            failed: Mutex::new(BTreeMap::new()),
+            run_key: OnceLock::new(),
+            checked_walk: Mutex::new(None),
+            verbose,
+            verbose_full: false,
+            verbose_lines: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Prints every per-file line when running verbose, rather than the first
+    /// `MAX_VERBOSE_LINES` of each kind.
+    pub fn with_verbose_full(mut self) -> Self {
+        self.verbose_full = true;
+        self
+    }
+
+    /// Writes the output to `output_dir` instead of `subfolder` inside the source.
+    pub fn with_output_dir(mut self, output_dir: PathBuf) -> Self {
+        self.output_dir = output_dir;
+        self
+    }
+
+    /// Lists every commit in `revisions`, such as `main..HEAD`, in `history.txt`, or the last
+    /// `history` of them if that is set.
+    pub fn with_history(mut self, revisions: &str) -> Self {
+        self.history_range = Some(revisions.to_string());
+        self
+    }
+
+    /// Continues the run that stopped while writing the output folder: the files its partial
+    /// manifest lists that are already there in full are not copied again. The other outputs
+    /// are written in full.
+    pub fn with_resume(mut self) -> Self {
+        self.resume = true;
+        self
+    }
+
+    /// Whether the output folder holds the files of a run that stopped partway with the same
+    /// `run_key`, which `with_resume` can continue. A run with other settings or sources has to
+    /// start over, as the files it left may not be what this one writes.
+    pub fn resumable(&self) -> Result<bool> {
+        let Some(stopped) = Manifest::load(&self.output_dir).ok().filter(|manifest| manifest.partial) else {
+            return Ok(false);
+        };
+        Ok(stopped.run_key.is_some() && stopped.run_key == Some(self.checked_run_key()?))
+    }
+
+    /// Orders the files with `orderer` instead of the built-in ordering `order` picks.
+    pub fn with_orderer(mut self, orderer: Box<dyn FileOrderer>) -> Self {
+        self.orderer = orderer;
+        self
+    }
+
+    pub fn source_path(&self) -> &Path {
+        self.source_path
+    }
+
+    pub fn config(&self) -> &Config {
+        self.config
+    }
+
+    /// The tokenizer of `tokenizer`, or `None` to estimate.
+    pub fn tokenizer(&self) -> Option<Arc<dyn Tokenize>> {
+        self.tokenizer.clone()
+    }
+
+    /// Number of tokens `text` will use, see `tokens::count`.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        tokens::count(self.tokenizer.as_deref(), text)
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Walks the source tree and applies every filter. Lists the vendored directories it
+    /// left out.
+    pub fn walk(&self) -> Result<WalkResult> {
+        let mut walked = self.filter.walk(&self.output_dir)?;
+        if !walked.vendored.is_empty() {
+            let names: Vec<String> = walked
+                .vendored
+                .iter()
+                .map(|dir| Ok(paths::to_slash(dir.strip_prefix(self.source_path)?)))
+                .collect::<Result<_>>()?;
+            let kept = match self.config.vendored {
+                VendoredCode::Summarize => format!("summarized in {}", VENDORED_MD),
+                _ => "set vendored: include to keep it".to_string(),
+            };
+            eprintln!("Left out vendored code in {}; {}", names.join(", "), kept);
+        }
+        *self.vendored.write().unwrap_or_else(|e| e.into_inner()) = walked.vendored.clone();
+        if !self.config.include_paths.is_empty() {
+            walked = self.include_only(walked)?;
+        }
+        if self.config.editor_history_only {
+            let recent: HashSet<PathBuf> = self.editor_history.keys().map(|file| self.source_path.join(file)).collect();
+            walked = walked.only(&recent);
+        }
+        Ok(walked)
+    }
+
+    /// `walked` cut down to `include_paths`: the files listed there, and the files in the
+    /// directories listed there.
+    fn include_only(&self, walked: WalkResult) -> Result<WalkResult> {
+        let mut files = Vec::new();
+        for listed in &self.config.include_paths {
+            let relative = Path::new(listed);
+            if !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+                bail!("include_paths entry {} must be a relative path inside the source", listed);
+            }
+            let relative: PathBuf = relative.components().filter(|c| *c != Component::CurDir).collect();
+            let path = self.source_path.join(relative);
+            if path.is_dir() {
+                files.extend(walked.files.iter().filter(|file| file.starts_with(&path)).cloned());
+            } else if path.is_file() {
+                files.push(path);
+            } else {
+                bail!("include_paths entry {} does not exist", listed);
+            }
+        }
+        files.sort();
+        files.dedup();
+        let mut directories: Vec<PathBuf> = files
+            .iter()
+            .flat_map(|file| file.ancestors().skip(1))
+            .filter(|dir| dir.starts_with(self.source_path) && *dir != self.source_path)
+            .map(Path::to_path_buf)
+            .collect();
+        directories.sort();
+        directories.dedup();
+        Ok(WalkResult { files, directories, vendored: walked.vendored })
+    }
+
+    /// Refuses to delete an output directory that is the source, holds the source, or was not
+    /// written by lmprep, such as `src` set as the subfolder by mistake.
+    fn check_output_directory(&self) -> Result<()> {
+        if !self.output_dir.exists() {
+            return Ok(());
+        }
+        let output_dir = self.output_dir.canonicalize()?;
+        if self.source_path.canonicalize().is_ok_and(|source| source.starts_with(&output_dir)) {
+            bail!("Output directory {} is or contains the source, refusing to delete it", self.output_dir.display());
+        }
+        let generated = manifest::is_output_dir(&output_dir)
+            || fs::read_dir(&output_dir)?.next().is_none()
+            || self.is_legacy_output(&output_dir)?;
+        if !generated {
+            bail!(
+                "{} was not written by lmprep, refusing to delete it; remove it or choose another subfolder",
+                self.output_dir.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether `dir` holds the output of a release from before `manifest.json`: nothing but
+    /// files, each of them `filetree.txt`, `context.zip` or a copy of a source file under its
+    /// flattened name.
+    fn is_legacy_output(&self, dir: &Path) -> Result<bool> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                return Ok(false);
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let flattened = !self.config.delimiter.is_empty() && name.contains(self.config.delimiter.as_str());
+            let known = name == FILETREE_TXT
+                || name == CONTEXT_ZIP
+                || flattened
+                || self.source_path.join(flatten::unflatten(&name, &self.config.delimiter).join("/")).is_file();
+            if !known {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn remove_output_directory(&self) -> Result<()> {
+        self.check_output_directory()?;
+        if self.output_dir.exists() {
+            fs::remove_dir_all(&self.output_dir)?;
+        }
+        Ok(())
+    }
+
+    pub fn prepare_output_directory(&self) -> Result<()> {
+        self.check_output_directory()?;
+        if self.output_dir.exists() {
+            if self.verbose {
+                eprintln!("Cleaning existing output directory");
+            }
+            fs::remove_dir_all(&self.output_dir)?;
+        }
+        fs::create_dir_all(&self.output_dir)?;
+        fs::write(self.output_dir.join(OUTPUT_MARKER), "Written by lmprep, which leaves this folder out of its runs.\n")?;
+        Ok(())
+    }
+
+    /// Walks the source tree once and returns every file that passes the filters. Later
+    /// stages trust this list and do not filter again.
+    pub fn collect_files(&self, walked: &WalkResult) -> Result<Vec<SelectedFile>> {
+        let mut files_to_process = Vec::new();
+        let boilerplate = match self.config.skip_trivial {
+            TrivialFiles::Trivial => Some(Boilerplate::new(&self.config.trivial_patterns)?),
+            _ => None,
+        };
+        let mut trivial = 0;
+
+        let mut selected = Vec::new();
+        for path in &walked.files {
+            let readable = fs::File::open(paths::extended_length(path)).map_err(Into::into);
+            if self.skip_failed(path, readable)?.is_none() {
+                continue;
+            }
+            if self.is_trivial(path, boilerplate.as_ref()) {
+                self.verbose_file("Skipping trivial file", format_args!("{}", path.display()));
+                trivial += 1;
+                continue;
+            }
+            selected.push(path);
+        }
+        for (path, output_name) in selected.iter().zip(self.output_names(&selected, HashSet::new())?) {
+            self.verbose_file("Adding file", format_args!("{} -> {}", path.display(), output_name));
+            files_to_process.push(SelectedFile { path: path.to_path_buf(), output_name });
+        }
+        if trivial > 0 {
+            let what = match boilerplate {
+                Some(_) => "empty or trivial",
+                None => "empty",
+            };
+            eprintln!("Skipped {} {} files; set skip_trivial: keep to include them", trivial, what);
+        }
+
+        let files_to_process = self.set_aside_migrations(files_to_process)?;
+        let files_to_process = self.apply_sample(files_to_process);
+        self.find_api_definitions(&files_to_process);
+        let files_to_process = self.apply_limits(files_to_process)?;
+        self.learn_headers(&files_to_process)?;
+        self.learn_line_endings(&files_to_process)?;
+        let files_to_process = self.learn_names(files_to_process)?;
+        let mut files_to_process = self.apply_token_budget(files_to_process)?;
+        self.orderer.order(self.source_path, &mut files_to_process)?;
+        let files_to_process = self.apply_priorities(files_to_process);
+        let files_to_process = self.apply_promote(files_to_process);
+        if self.config.layout == Layout::Flat {
+            self.warn_delimiter_clashes(&files_to_process)?;
+        }
+
+        self.report_verbose_lines();
+        if self.verbose {
+            eprintln!("Total files to process: {}", files_to_process.len());
+        }
+
+        Ok(files_to_process)
+    }
+
+    /// Whether `skip_trivial` leaves out the file at `path`: it is empty or only whitespace,
+    /// or with `boilerplate`, only comments and boilerplate lines.
+    fn is_trivial(&self, path: &Path, boilerplate: Option<&Boilerplate>) -> bool {
+        if self.config.skip_trivial == TrivialFiles::Keep {
+            return false;
+        }
+        let path = paths::extended_length(path);
+        if fs::metadata(&path).map_or(true, |metadata| metadata.len() > trivial::MAX_TRIVIAL_BYTES) {
+            return false;
+        }
+        let Ok(text) = fs::read_to_string(&path) else {
+            return false;
+        };
+        match boilerplate {
+            Some(boilerplate) => boilerplate.is_trivial(&text, &extension(&path).unwrap_or_default()),
+            None => trivial::is_empty(&text),
+        }
+    }
+
+    /// Prints `detail` of one file after `kind`, such as `Adding file`, when running verbose:
+    /// the first `MAX_VERBOSE_LINES` of each kind, or all of them with `with_verbose_full`.
+    fn verbose_file(&self, kind: &'static str, detail: std::fmt::Arguments) {
+        if !self.verbose {
+            return;
+        }
+        let mut lines = self.verbose_lines.lock().unwrap_or_else(PoisonError::into_inner);
+        let printed = lines.entry(kind).or_default();
+        *printed += 1;
+        if self.verbose_full || *printed <= MAX_VERBOSE_LINES {
+            eprintln!("{}: {}", kind, detail);
+        }
+    }
+
+    /// Prints how many lines of each kind `verbose_file` held back, and starts counting again.
+    fn report_verbose_lines(&self) {
+        let lines = std::mem::take(&mut *self.verbose_lines.lock().unwrap_or_else(PoisonError::into_inner));
+        if self.verbose_full {
+            return;
+        }
+        for (kind, printed) in lines.into_iter().filter(|(_, printed)| *printed > MAX_VERBOSE_LINES) {
+            eprintln!("{}: ... and {} more (use --verbose=full to list all)", kind, printed - MAX_VERBOSE_LINES);
+        }
+    }
+
+    /// `result` of reading the selected file at `path`, or `None` once an error with the file
+    /// itself is recorded in `failed`, so that one unreadable file does not end the run.
+    fn skip_failed<T>(&self, path: &Path, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if is_file_error(&e) => {
+                self.failed.lock().unwrap_or_else(PoisonError::into_inner).insert(path.to_path_buf(), format!("{:#}", e));
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether any selected file could not be read and was left out of the output.
+    pub fn any_failed(&self) -> bool {
+        !self.failed.lock().unwrap_or_else(PoisonError::into_inner).is_empty()
+    }
+
+    /// Lists the files `skip_failed` left out.
+    fn report_failed(&self) {
+        let failed = self.failed.lock().unwrap_or_else(PoisonError::into_inner);
+        if failed.is_empty() {
+            return;
+        }
+        eprintln!("Warning: {} files could not be read and were left out:", failed.len());
+        for (path, error) in failed.iter() {
+            eprintln!("  {}: {}", path.display(), error);
+        }
+    }
+
+    /// Warns about selected paths that contain the delimiter: their names escape it, which
+    /// keeps them reversible but harder to read.
+    fn warn_delimiter_clashes(&self, files: &[SelectedFile]) -> Result<()> {
+        let delimiter = self.config.delimiter.as_str();
+        let relative = files.iter().map(|file| file.path.strip_prefix(self.source_path)).collect::<Result<Vec<_>, _>>()?;
+        let clashing: Vec<&Path> = relative.iter().copied().filter(|path| path.to_string_lossy().contains(delimiter)).collect();
+        let Some(example) = clashing.first() else {
+            return Ok(());
+        };
+        let mut warning = format!(
+            "Warning: the delimiter {:?} is in {} of the selected paths, such as {}; their output names escape it as {}",
+            delimiter,
+            clashing.len(),
+            paths::to_slash(example),
+            flatten::percent_encode(delimiter)
+        );
+        if let Some(spare) = flatten::spare_delimiter(&relative) {
+            warning.push_str(&format!(". Set delimiter to {:?} for plainer names", spare));
+        }
+        eprintln!("{}", warning);
+        Ok(())
+    }
+
+    /// Shows `transforms` the start of every selected file they apply to, for rules that
+    /// depend on what the other files look like.
+    fn learn_headers(&self, files: &[SelectedFile]) -> Result<()> {
+        if !self.transforms.needs_headers() {
+            return Ok(());
+        }
+
+        let mut heads = Vec::new();
+        for file in files {
+            let relative = file.path.strip_prefix(self.source_path)?;
+            if !self.transforms.applies_to(relative) {
+                continue;
+            }
+            let mut head = Vec::new();
+            fs::File::open(paths::extended_length(&file.path))?
+                .take(transforms::HEADER_BYTES)
+                .read_to_end(&mut head)?;
+            heads.push((relative, head));
+        }
+        self.transforms
+            .learn_headers(heads.iter().map(|(relative, head)| (*relative, head.as_slice())));
+        Ok(())
+    }
+
+    /// With `normalize_line_endings`, finds the line endings git checks each of `files` out
+    /// with from `.gitattributes`, `core.autocrlf` and `core.eol`. Outside a repository files
+    /// are left as they are.
+    fn learn_line_endings(&self, files: &[SelectedFile]) -> Result<()> {
+        if !self.config.normalize_line_endings {
+            return Ok(());
+        }
+        let relative = files.iter().map(|file| file.path.strip_prefix(self.source_path)).collect::<Result<Vec<_>, _>>()?;
+        let attributes = git::line_ending_attributes(self.source_path, &relative).unwrap_or_default();
+        let (autocrlf, core_eol) = (git::autocrlf(self.source_path), git::core_eol(self.source_path));
+        let mut learned = self.line_endings.write().unwrap_or_else(|e| e.into_inner());
+        for (path, (text, eol)) in attributes {
+            learned.insert(path, line_endings::eol(&text, &eol, autocrlf.as_deref(), core_eol.as_deref()));
+        }
+        Ok(())
+    }
+
+    /// With `anonymize`, collects the names the selected files define and anonymizes the
+    /// output names, which only works once every file has been seen.
+    fn learn_names(&self, files: Vec<SelectedFile>) -> Result<Vec<SelectedFile>> {
+        let Some(anonymizer) = &self.anonymizer else {
+            return Ok(files);
+        };
+
+        for file in &files {
+            let contents = fs::read(paths::extended_length(&file.path))?;
+            anonymizer.learn(&String::from_utf8_lossy(&contents), extension(&file.path).as_deref());
+        }
+        Ok(files
+            .into_iter()
+            .map(|file| SelectedFile { output_name: anonymizer.names(&file.output_name), ..file })
+            .collect())
+    }
+
+    /// `text` (a path or the file tree) with names anonymized, or as it is without
+    /// `anonymize`.
+    pub fn anonymize_names(&self, text: String) -> String {
+        match &self.anonymizer {
+            Some(anonymizer) => anonymizer.names(&text),
+            None => text,
+        }
+    }
+
+    /// Writes the reverse map of `anonymize`, if enabled.
+    pub fn save_anonymize_map(&self) -> Result<()> {
+        if let Some(anonymizer) = &self.anonymizer {
+            anonymizer.save()?;
+            eprintln!("Anonymize map (keep it private) written to {}", anonymizer.map_path().display());
+        }
+        Ok(())
+    }
+
+    /// Keeps a random subset of `sample` files, spread according to `sample_strata`.
+    fn apply_sample(&self, files: Vec<SelectedFile>) -> Vec<SelectedFile> {
+        let count = match self.config.sample {
+            Some(count) if count < files.len() => count,
+            _ => return files,
+        };
+
+        let seed = self.config.sample_seed.unwrap_or_else(sampling::random_seed);
+        let relative_paths: Vec<&Path> = files
+            .iter()
+            .map(|f| f.path.strip_prefix(self.source_path).unwrap_or(&f.path))
+            .collect();
+        let picked = sampling::sample(&relative_paths, count, self.config.sample_strata, seed);
+        eprintln!("Sampled {} of {} files (seed {})", picked.len(), files.len(), seed);
+
+        let mut picked = picked.into_iter().peekable();
+        files
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| picked.next_if_eq(i).is_some())
+            .map(|(_, file)| file)
+            .collect()
+    }
+
+    /// Enforces `max_files` and `max_total_bytes` by keeping the highest ranked files that
+    /// still fit, and reports every file that was dropped.
+    fn apply_limits(&self, files: Vec<SelectedFile>) -> Result<Vec<SelectedFile>> {
+        let max_files = self.config.max_files.unwrap_or(usize::MAX);
+        let max_total_bytes = self.config.max_total_bytes.unwrap_or(u64::MAX);
+        if files.len() <= max_files && self.config.max_total_bytes.is_none() {
+            return Ok(files);
+        }
+
+        let mut ranked = Vec::with_capacity(files.len());
+        for (i, file) in files.into_iter().enumerate() {
+            // a file that vanished since the walk is left out like one that cannot be read
+            let size = fs::metadata(&file.path).map(|metadata| metadata.len()).map_err(anyhow::Error::from);
+            if let Some(size) = self.skip_failed(&file.path, size)? {
+                ranked.push((size, i, file));
+            }
+        }
+        ranked.sort_by_key(|(size, i, file)| {
+            let relative = file.path.strip_prefix(self.source_path).unwrap_or(&file.path);
+            (ranking::sort_key(relative, *size, self.priority(relative)), *i)
+        });
+
+        let mut kept = Vec::new();
+        let mut dropped = Vec::new();
+        let mut total_bytes = 0;
+        for (size, i, file) in ranked {
+            if kept.len() < max_files && total_bytes + size <= max_total_bytes {
+                total_bytes += size;
+                kept.push((i, file));
+            } else {
+                dropped.push(file.path);
+            }
+        }
+
+        if !dropped.is_empty() {
+            eprintln!(
+                "Limits reached: keeping {} files ({} bytes), dropped {}:",
+                kept.len(),
+                total_bytes,
+                dropped.len()
+            );
+            dropped.sort();
+            let shown = if self.verbose { dropped.len() } else { dropped.len().min(MAX_DROPPED_SHOWN) };
+            for path in &dropped[..shown] {
+                eprintln!("  {}", path.display());
+            }
+            if shown < dropped.len() {
+                eprintln!("  ... and {} more (use --verbose to list all)", dropped.len() - shown);
+            }
+        }
+
+        // back to path order
+        kept.sort_by_key(|(i, _)| *i);
+        Ok(kept.into_iter().map(|(_, file)| file).collect())
+    }
+
+    /// With `consolidate_migrations`, takes the SQL migrations out of `files` for `artifacts`
+    /// to replay into `schema.sql`.
+    fn set_aside_migrations(&self, files: Vec<SelectedFile>) -> Result<Vec<SelectedFile>> {
+        let mut set_aside = self.migrations.write().unwrap_or_else(|e| e.into_inner());
+        set_aside.clear();
+        if !self.config.consolidate_migrations {
+            return Ok(files);
+        }
+        let mut kept = Vec::with_capacity(files.len());
+        for file in files {
+            if migrations::is_migration(file.path.strip_prefix(self.source_path)?) {
+                set_aside.push(file.path);
+            } else {
+                kept.push(file);
+            }
+        }
+        if set_aside.is_empty() {
+            eprintln!("Warning: consolidate_migrations is set but none of the selected files is an SQL migration");
+        } else if self.verbose {
+            eprintln!("{} SQL migrations consolidated into {}", set_aside.len(), SCHEMA_SQL);
+        }
+        Ok(kept)
+    }
+
+    /// With `api_first`, notes which of `files` are API definitions, or warns that none is.
+    fn find_api_definitions(&self, files: &[SelectedFile]) {
+        let mut found = self.api_definitions.write().unwrap_or_else(|e| e.into_inner());
+        found.clear();
+        if !self.config.api_first {
+            return;
+        }
+        found.extend(files.iter().filter(|file| api_schema::is_api_definition(&file.path)).map(|file| file.path.clone()));
+        if found.is_empty() {
+            eprintln!("Warning: api_first is set but none of the selected files is an API definition, keeping them all in full");
+        } else if self.verbose {
+            eprintln!("{} API definitions kept in full, the other files as outlines", found.len());
+        }
+    }
+
+    fn is_api_definition(&self, path: &Path) -> bool {
+        self.api_definitions.read().unwrap_or_else(|e| e.into_inner()).contains(path)
+    }
+
+    /// With `max_tokens`, or `max_tokens` on `sections`, cuts files down to their outlines,
+    /// least important first, until the estimated tokens fit each budget; where that is not
+    /// enough, drops files the same way. Section budgets go first, so one kind of file cannot
+    /// crowd out the rest before the overall budget is applied. With `api_first`, every file
+    /// but the API definitions starts out as an outline. Reports what it outlined and
+    /// dropped.
+    fn apply_token_budget(&self, files: Vec<SelectedFile>) -> Result<Vec<SelectedFile>> {
+        self.outlined.write().unwrap_or_else(|e| e.into_inner()).clear();
+        let section_budgets: Vec<(usize, usize)> = self
+            .config
+            .sections
+            .iter()
+            .enumerate()
+            .filter_map(|(i, section)| Some((i, section.max_tokens?)))
+            .collect();
+        let api_first = !self.api_definitions.read().unwrap_or_else(|e| e.into_inner()).is_empty();
+        if self.config.max_tokens.is_none() && section_budgets.is_empty() && !api_first {
+            return Ok(files);
+        }
+
+        let mut ranked = Vec::new();
+        for (index, file) in files.into_iter().enumerate() {
+            let (contents, language) = self.read_contents(&file.path)?;
+            let text = String::from_utf8_lossy(&contents);
+            let language = language.or_else(|| extension(&file.path)).unwrap_or_default();
+            let relative = file.path.strip_prefix(self.source_path)?;
+            let full_tokens = self.count_tokens(&text);
+            let outline_tokens = symbols::outline(&text, &language).map(|outline| self.count_tokens(&outline));
+            let outline = api_first
+                && !self.is_api_definition(&file.path)
+                && outline_tokens.is_some_and(|outline_tokens| outline_tokens < full_tokens);
+            ranked.push(Budgeted {
+                index,
+                full_tokens,
+                outline_tokens,
+                key: ranking::sort_key(relative, contents.len() as u64, self.priority(relative)),
+                section: self.sections.iter().position(|(_, globs)| globs.is_match(relative)),
+                outline,
+                file,
+            });
+        }
+        ranked.sort_by_key(|budgeted| (budgeted.key, budgeted.index));
+
+        let mut dropped = Vec::new();
+        for (section, budget) in section_budgets {
+            fit_budget(&mut ranked, |budgeted| budgeted.section == Some(section), budget, &mut dropped);
+        }
+        if let Some(max_tokens) = self.config.max_tokens {
+            fit_budget(&mut ranked, |_| true, max_tokens, &mut dropped);
+        }
+        let mut outlined: Vec<PathBuf> =
+            ranked.iter().filter(|budgeted| budgeted.outline).map(|budgeted| budgeted.file.path.clone()).collect();
+        // `api_first` outlines are asked for, not forced by a budget
+        let over_budget = !dropped.is_empty() || (!api_first && !outlined.is_empty());
+
+        if over_budget {
+            eprintln!(
+                "Token budget reached: keeping {} files (~{} tokens), {} as outlines, dropped {}",
+                ranked.len(),
+                ranked.iter().map(Budgeted::tokens).sum::<usize>(),
+                outlined.len(),
+                dropped.len()
+            );
+        }
+        for (label, paths) in [("Outlined", &mut outlined), ("Dropped", &mut dropped)] {
+            if paths.is_empty() || !over_budget {
+                continue;
+            }
+            paths.sort();
+            eprintln!("{}:", label);
+            let shown = if self.verbose { paths.len() } else { paths.len().min(MAX_DROPPED_SHOWN) };
+            for path in &paths[..shown] {
+                eprintln!("  {}", path.display());
+            }
+            if shown < paths.len() {
+                eprintln!("  ... and {} more (use --verbose to list all)", paths.len() - shown);
+            }
+        }
+
+        *self.outlined.write().unwrap_or_else(|e| e.into_inner()) = outlined.into_iter().collect();
+        // back to path order
+        ranked.sort_by_key(|budgeted| budgeted.index);
+        Ok(ranked.into_iter().map(|budgeted| budgeted.file).collect())
+    }
+
+    /// The priority `priorities` gives the file at `relative`: the highest of the globs that
+    /// match it, or 0 if none does. Files in `editor_history` rank above them all, the most
+    /// recent first, and API definitions with `api_first` above those.
+    fn priority(&self, relative: &Path) -> i64 {
+        let (globs, priorities) = &self.priorities;
+        if self.is_api_definition(&self.source_path.join(relative)) {
+            return i64::MAX;
+        }
+        if let Some(boost) = self.editor_history.get(relative) {
+            return priorities.iter().copied().max().unwrap_or(0).max(0) + boost;
+        }
+        globs.matches(relative).into_iter().map(|i| priorities[i]).max().unwrap_or(0)
+    }
+
+    /// Orders the files by `priorities`, highest first, keeping the order they had among
+    /// files of the same priority.
+    fn apply_priorities(&self, mut files: Vec<SelectedFile>) -> Vec<SelectedFile> {
+        if self.config.priorities.is_empty() && self.editor_history.is_empty() && !self.config.api_first {
+            return files;
+        }
+        files.sort_by_cached_key(|file| {
+            let relative = file.path.strip_prefix(self.source_path).unwrap_or(&file.path);
+            Reverse(self.priority(relative))
+        });
+        files
+    }
+
+    /// Moves the files `promote` matches to the front, in the order of its globs, and leaves
+    /// the others in path order after them. API definitions with `api_first` go before them
+    /// all.
+    fn apply_promote(&self, mut files: Vec<SelectedFile>) -> Vec<SelectedFile> {
+        if self.promote.is_empty() && !self.config.api_first {
+            return files;
+        }
+        // stable, so files the same glob matches stay in path order
+        files.sort_by_key(|file| {
+            let relative = file.path.strip_prefix(self.source_path).unwrap_or(&file.path);
+            let promoted = self.promote.matches(relative).into_iter().min().unwrap_or(usize::MAX);
+            (!self.is_api_definition(&file.path), promoted)
+        });
+        files
+    }
+
+    fn is_outlined(&self, path: &Path) -> bool {
+        self.outlined.read().unwrap_or_else(|e| e.into_inner()).contains(path)
+    }
+
+    /// The output names of the files at `paths`, unique among them and `taken`, see
+    /// `flatten::output_names`.
+    fn output_names(&self, paths: &[&PathBuf], taken: HashSet<String>) -> Result<Vec<String>> {
+        let relative = paths.iter().map(|path| path.strip_prefix(self.source_path)).collect::<Result<Vec<_>, _>>()?;
+        Ok(flatten::output_names(&relative, self.config, &self.flattener, taken))
+    }
+
+    fn generate_new_filename(&self, path: &Path) -> Result<String> {
+        let relative_path = path.strip_prefix(self.source_path)?;
+        Ok(flatten::output_name(relative_path, self.config, &self.flattener))
+    }
+
+    /// Whether the output folder already holds what this run would write: a run that
+    /// finished wrote it with the same `run_key`, and its outputs are still there.
+    pub fn up_to_date(&self) -> Result<bool> {
+        let key = self.checked_run_key()?;
+        Ok(Manifest::load(&self.output_dir).is_ok_and(|manifest| {
+            !manifest.partial
+                && manifest.run_key.as_deref() == Some(key.as_str())
+                && manifest.output_files.iter().all(|name| self.output_dir.join(name).is_file())
+        }))
+    }
+
+    /// The `run_key` of this run, walking the source for it once; `process` goes on with
+    /// that walk.
+    fn checked_run_key(&self) -> Result<String> {
+        if let Some(key) = self.run_key.get() {
+            return Ok(key.clone());
+        }
+        let walked = self.walk()?;
+        let key = self.run_key(&walked)?;
+        *self.checked_walk.lock().unwrap_or_else(PoisonError::into_inner) = Some(walked);
+        self.run_key.set(key.clone()).ok();
+        Ok(key)
+    }
+
+    /// SHA-256 of the lmprep version, the config after the command line, the commits checked
+    /// out and compared against, the `references` pages and `tokenizer` file, and the path
+    /// and contents of every file of `walked`, and so of everything a run writes.
+    fn run_key(&self, walked: &WalkResult) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}\0{:?}\0{:?}\0", env!("CARGO_PKG_VERSION"), self.config, self.history_range));
+        hasher.update(git::rev_parse(self.source_path, &["HEAD"]).unwrap_or_default());
+        // a `diff` base such as `main` and a range of `history` move with their branches
+        for revisions in self.config.diff.iter().chain(&self.history_range) {
+            hasher.update(format!("\0{}", git::rev_parse(self.source_path, &[revisions.as_str()]).unwrap_or_default()));
+        }
+        // fetched here once for the whole run
+        hasher.update(format!("\0{}\0", self.references().unwrap_or_default()));
+        if let Some(Ok(contents)) = self.config.tokenizer.as_deref().map(fs::read) {
+            hasher.update(Sha256::digest(contents));
+        }
+        if self.config.normalize_line_endings {
+            hasher.update(format!("{:?}\0{:?}\0", git::autocrlf(self.source_path), git::core_eol(self.source_path)));
+            hasher.update(fs::read(self.source_path.join(".gitattributes")).unwrap_or_default());
+        }
+        let mut editor_history: Vec<_> = self.editor_history.iter().collect();
+        editor_history.sort();
+        hasher.update(format!("\0{:?}\0", editor_history));
+        for dir in &walked.directories {
+            hasher.update(format!("{}/\0", paths::to_slash(dir.strip_prefix(self.source_path)?)));
+        }
+        for file in &walked.files {
+            hasher.update(format!("{}\0", paths::to_slash(file.strip_prefix(self.source_path)?)));
+            // an unreadable file fails the run, which then records no key
+            if let Ok(contents) = fs::read(paths::extended_length(file)) {
+                hasher.update(Sha256::digest(contents));
+            }
+        }
+        Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    pub fn process(&self) -> Result<()> {
+        let checked = self.checked_walk.lock().unwrap_or_else(PoisonError::into_inner).take();
+        let mut walked = match checked {
+            Some(walked) => walked,
+            None => {
+                let walked = self.walk()?;
+                self.run_key.set(self.run_key(&walked)?).ok();
+                walked
+            }
+        };
+        if let Some(symbol) = &self.config.symbol {
+            walked = focus::around_symbol(walked, symbol, self.config.symbol_hops)?;
+            if self.verbose {
+                eprintln!("{} files within {} hops of {}", walked.files.len(), self.config.symbol_hops, symbol);
+            }
+        }
+        if !self.config.seeds.is_empty() {
+            let config = self.config;
+            walked = focus::around_seeds(walked, self.source_path, &config.seeds, config.seed_depth, config.seed_token_budget, self.tokenizer.as_deref())?;
+            if self.verbose {
+                eprintln!("{} files around the seeds", walked.files.len());
+            }
+        }
+        self.process_walked(&walked)
+    }
+
+    /// Like `process`, for files and directories the caller selected rather than `walk`.
+    pub fn process_walked(&self, walked: &WalkResult) -> Result<()> {
+        self.failed.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        let files = self.collect_files(walked)?;
+        self.scan_output(&files)?;
+        self.check_free_space(&files)?;
+        let file_count = files.len();
+        let mut manifest = self.manifest(&files, walked)?;
+        let mut tree_content = String::new();
+
+        if self.config.tree {
+            if self.verbose {
+                println!("Generating file tree...");
+            }
+            tree_content = self.anonymize_names(tree::render(self.source_path, &walked.directories, &walked.files));
+        }
+
+        let tree = self.config.tree.then_some(tree_content.as_str());
+        for output in self.config.outputs() {
+            match output {
+                Output::Zip => {
+                    self.create_zip(files.clone(), tree)?;
+                    println!("Successfully zipped {} files to {:?}", file_count, self.output_dir);
+                }
+                Output::Concat => {
+                    let written = self.write_concat(files.clone(), tree)?;
+                    println!("Successfully concatenated {} files to {:?}", written, self.output_dir);
+                }
+                Output::Html => {
+                    let written = self.write_html(&files)?;
+                    println!("Successfully rendered {} files to {:?}", written, self.output_dir);
+                }
+                Output::Sqlite => {
+                    self.write_sqlite(&files)?;
+                    println!("Successfully indexed {} files to {:?}", file_count, self.output_dir);
+                }
+                Output::Folder => {
+                    self.copy_files(files.clone())?;
+                    if let Some(tree) = tree {
+                        let tree_file_path = self.output_dir.join(FILETREE_TXT);
+                        fs::write(&tree_file_path, tree)?;
+                        if self.verbose {
+                            println!("Tree written to {:?}", tree_file_path);
+                        }
+                    }
+                    println!("Successfully processed {} files to {:?}", file_count, self.output_dir);
+                }
+            }
+        }
+
+        if self.config.search_index {
+            self.write_search_index(&files)?;
+        }
+        if let Some(model) = self.model {
+            self.report_cost(model, &files, tree)?;
+        }
+        // files that vanished while the outputs were written are not in them
+        let failed = self.failed.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        let failed_names: HashSet<&str> =
+            files.iter().filter(|file| failed.contains_key(&file.path)).map(|file| file.output_name.as_str()).collect();
+        manifest.files.retain(|file| !failed_names.contains(file.output_name.as_str()));
+        if failed.is_empty() {
+            manifest.run_key = self.run_key.get().cloned();
+        }
+        manifest.save(&self.output_dir)?;
+        self.report_verbose_lines();
+        self.report_failed();
+        self.save_anonymize_map()
+    }
+
+    /// Fails if the outputs of `files` will not fit on the volume of the output folder, going
+    /// by the size of the files: each output that holds their contents needs about that much,
+    /// the folder needs a whole number of blocks for each file it does not have yet unless it
+    /// links them, and `manifest.json` about `MANIFEST_BYTES_PER_FILE` a file.
+    fn check_free_space(&self, files: &[SelectedFile]) -> Result<()> {
+        let Some(space) = disk::space(&self.output_dir) else {
+            return Ok(());
+        };
+        let size = |file: &SelectedFile| fs::metadata(&file.path).map_or(0, |m| m.len());
+        let total: u64 = files.iter().map(size).sum();
+        let mut needed = files.len() as u64 * MANIFEST_BYTES_PER_FILE;
+        for output in self.config.outputs() {
+            needed += match output {
+                Output::Folder if self.config.copy_mode != CopyMode::Copy => 0,
+                Output::Folder => files
+                    .iter()
+                    .filter(|file| !self.output_dir.join(&file.output_name).exists())
+                    .map(|file| size(file).div_ceil(space.block_size).max(1) * space.block_size)
+                    .sum(),
+                Output::Zip | Output::Concat | Output::Html | Output::Sqlite => total,
+            };
+        }
+        let available = space.available;
+        if needed > available {
+            bail!(
+                "The output needs about {} but only {} are free on the volume of {}",
+                disk::megabytes(needed),
+                disk::megabytes(available),
+                self.output_dir.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// The tokens of the files a run would write, by extension and by top-level directory.
+    /// Binary files, which no output includes as text, are left out.
+    pub fn token_report(&self) -> Result<TokenReport> {
+        let files = self.collect_files(&self.walk()?)?;
+        let mut counted = Vec::new();
+        for file in &files {
+            let (contents, _) = self.read_contents(&file.path)?;
+            if !contents.contains(&0) {
+                let relative = paths::to_slash(file.path.strip_prefix(self.source_path)?);
+                counted.push((relative, self.count_tokens(&String::from_utf8_lossy(&contents))));
+            }
+        }
+        Ok(TokenReport::build(counted.iter().map(|(path, tokens)| (path.as_str(), *tokens))))
+    }
+
+    /// Prints what sending `files` and `tree` to `model` once would cost.
+    fn report_cost(&self, model: &Preset, files: &[SelectedFile], tree: Option<&str>) -> Result<()> {
+        let mut tokens = tree.map_or(0, |tree| self.count_tokens(tree));
+        for file in files {
+            let (contents, _) = self.read_contents(&file.path)?;
+            if !contents.contains(&0) {
+                tokens += self.count_tokens(&String::from_utf8_lossy(&contents));
+            }
+        }
+        println!(
+            "Estimated input cost with {} ({}, ${:.2} per million tokens): ~{} tokens, ${:.2}",
+            model.name,
+            model.provider,
+            model.input_per_million,
+            tokens,
+            model.cost(tokens)
+        );
+        Ok(())
+    }
+
+    /// The manifest of an output of `files`, with the tree of `walked` if `tree` is set.
+    fn manifest(&self, files: &[SelectedFile], walked: &WalkResult) -> Result<Manifest> {
+        let display = |path: &Path| -> Result<String> {
+            Ok(self.anonymize_names(paths::to_slash(path.strip_prefix(self.source_path)?)))
+        };
+        let tree = match self.config.tree {
+            true => Some(ManifestTree {
+                directories: walked.directories.iter().map(|dir| display(dir)).collect::<Result<_>>()?,
+                files: walked.files.iter().map(|file| display(file)).collect::<Result<_>>()?,
+            }),
+            false => None,
+        };
+        let mut manifest = Manifest::new(self.manifest_files(files)?, tree);
+        for output in self.config.outputs() {
+            match output {
+                Output::Zip => manifest.output_files.push(self.zip_name.clone()),
+                Output::Concat => manifest.output_files.push(self.concat_name.clone()),
+                Output::Html => manifest.output_files.push(self.html_name.clone()),
+                Output::Sqlite => manifest.output_files.push(self.db_name.clone()),
+                Output::Folder => manifest.folder = true,
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn manifest_files(&self, files: &[SelectedFile]) -> Result<Vec<ManifestFile>> {
+        files
+            .iter()
+            .map(|file| {
+                Ok(ManifestFile {
+                    path: self.anonymize_names(paths::to_slash(file.path.strip_prefix(self.source_path)?)),
+                    output_name: file.output_name.clone(),
+                    outline: self.is_outlined(&file.path),
+                })
+            })
+            .collect()
+    }
+
+    /// Adds `paths`, files or directories whose files pass the filters, to the output that is
+    /// already in the output folder, replacing files it has, and updates the file tree and
+    /// `manifest.json` without writing anything else again. Returns the number of files added.
+    pub fn add(&self, paths: &[PathBuf]) -> Result<usize> {
+        let mut manifest = Manifest::load(&self.output_dir)?;
+        let source = self.source_path.canonicalize()?;
+        let mut walked = None;
+        let mut selected: Vec<PathBuf> = Vec::new();
+        for path in paths {
+            let absolute = path.canonicalize().with_context(|| format!("Could not find {}", path.display()))?;
+            let Ok(relative) = absolute.strip_prefix(&source) else {
+                bail!("{} is not inside {}", path.display(), self.source_path.display());
+            };
+            let path = self.source_path.join(relative);
+            if !path.is_dir() {
+                selected.push(path);
+                continue;
+            }
+            if walked.is_none() {
+                walked = Some(self.walk()?);
+            }
+            let files = walked.as_ref().map_or(&[][..], |walked| walked.files.as_slice());
+            selected.extend(files.iter().filter(|file| file.starts_with(&path)).cloned());
+        }
+        selected.sort();
+        selected.dedup();
+
+        // files the output has keep their names, the others get one no other file has
+        let mut existing = HashMap::new();
+        for file in &manifest.files {
+            existing.insert(file.path.clone(), file.output_name.clone());
+        }
+        let (mut kept, mut new) = (Vec::new(), Vec::new());
+        for path in selected {
+            let name = self.anonymize_names(paths::to_slash(path.strip_prefix(self.source_path)?));
+            match existing.remove(&name) {
+                Some(output_name) => kept.push(SelectedFile { path, output_name }),
+                None => new.push(path),
+            }
+        }
+        let taken = existing.into_values().chain(kept.iter().map(|file| file.output_name.clone())).collect();
+        let names = self.output_names(&new.iter().collect::<Vec<_>>(), taken)?;
+        let mut files = kept;
+        for (path, output_name) in new.into_iter().zip(names) {
+            files.push(SelectedFile { output_name: self.anonymize_names(output_name), path });
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        self.learn_line_endings(&files)?;
+        if self.scans_output() {
+            self.report_findings(&self.scan_files(&files)?)?;
+        }
+        let mut entries = Vec::new();
+        for file in &files {
+            let path = self.anonymize_names(paths::to_slash(file.path.strip_prefix(self.source_path)?));
+            self.verbose_file("Adding file", format_args!("{} -> {}", path, file.output_name));
+            entries.push(ManifestFile { path, output_name: file.output_name.clone(), outline: false });
+        }
+        for entry in &entries {
+            manifest.insert(entry.clone());
+        }
+
+        let added = files.len();
+        // a file the output already has is replaced
+        self.update_output(&manifest, &entries, files)?;
+        manifest.run_key = None;
+        manifest.save(&self.output_dir)?;
+        self.report_verbose_lines();
+        self.report_failed();
+        Ok(added)
+    }
+
+    /// Removes the files whose path matches one of `globs`, such as `tests/**` or `*.json`,
+    /// from the output in the output folder, its file tree and `manifest.json`. Returns the
+    /// number of files removed.
+    pub fn remove(&self, globs: &[String]) -> Result<usize> {
+        let mut manifest = Manifest::load(&self.output_dir)?;
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            builder.add(Glob::new(glob).with_context(|| format!("Invalid glob {:?}", glob))?);
+        }
+        let globs = builder.build()?;
+
+        let removed = manifest.remove(|path| globs.is_match(path));
+        for entry in &removed {
+            self.verbose_file("Removing file", format_args!("{} -> {}", entry.path, entry.output_name));
+        }
+        self.report_verbose_lines();
+        if !removed.is_empty() {
+            self.update_output(&manifest, &removed, Vec::new())?;
+            manifest.run_key = None;
+            manifest.save(&self.output_dir)?;
+        }
+        Ok(removed.len())
+    }
+
+    /// Searches the output in the output folder for `query` and returns at most `limit` files
+    /// with the lines that match. With a search index the files are ranked by it and lines
+    /// match on any term of `query`; without one the files are scanned for `query` as text,
+    /// ignoring case, and the ones with the most matching lines come first.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<Match>> {
+        let manifest = Manifest::load(&self.output_dir)?;
+        let mut contents = OutputContents::open(&self.output_dir, &manifest)?;
+
+        if self.output_dir.join(SEARCH_INDEX_JSON).exists() {
+            let index = SearchIndex::load(&self.output_dir)?;
+            let query_terms: HashSet<String> = search::terms(query).collect();
+            let mut matches = Vec::new();
+            for hit in index.search(query, limit) {
+                let text = contents.read(&self.output_dir, &hit.file.path, &hit.file.output_name)?;
+                matches.push(Match {
+                    path: hit.file.path.clone(),
+                    output_name: hit.file.output_name.clone(),
+                    score: Some(hit.score),
+                    lines: search::matching_lines(&text.unwrap_or_default(), |line| {
+                        search::terms(line).any(|term| query_terms.contains(&term))
+                    }),
+                });
+            }
+            return Ok(matches);
+        }
+
+        let needle = query.to_lowercase();
+        let mut matches = Vec::new();
+        for entry in &manifest.files {
+            let Some(text) = contents.read(&self.output_dir, &entry.path, &entry.output_name)? else {
+                continue;
+            };
+            let lines = search::matching_lines(&text, |line| line.to_lowercase().contains(&needle));
+            if !lines.is_empty() {
+                matches.push(Match { path: entry.path.clone(), output_name: entry.output_name.clone(), score: None, lines });
+            }
+        }
+        // stable, so files with as many lines stay in output order
+        matches.sort_by_key(|found| Reverse(found.lines.len()));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+
+    /// Brings the output in the output folder in line with `manifest` after `add` or `remove`:
+    /// takes out the `removed` entries, writes `files` and the file tree.
+    fn update_output(&self, manifest: &Manifest, removed: &[ManifestFile], files: Vec<SelectedFile>) -> Result<()> {
+        let tree_content = manifest.tree.as_ref().map(|tree| {
+            let (directories, files) = tree.entries();
+            tree::render(Path::new(""), &directories, &files)
+        });
+        for name in &manifest.output_files {
+            let path = self.output_dir.join(name);
+            if name.ends_with(".zip") {
+                self.rewrite_zip(&path, removed, &files, tree_content.as_deref())?;
+            } else if name.ends_with(".html") || name.ends_with(".db") {
+                eprintln!("Warning: {} is not updated, run lm again to render it with the changes", name);
+            } else {
+                self.rewrite_concat(&path, removed, &files, manifest.files.len(), tree_content.as_deref())?;
+            }
+        }
+        if self.output_dir.join(SEARCH_INDEX_JSON).exists() {
+            eprintln!("Warning: {} is not updated, run lm again to index the changes", SEARCH_INDEX_JSON);
+        }
+        if !manifest.folder {
+            return Ok(());
+        }
+
+        for entry in removed {
+            let target_path = self.output_dir.join(&entry.output_name);
+            match fs::remove_file(paths::extended_length(&target_path)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            // with `layout: mirror` the file's directories go too once they are empty
+            for dir in target_path.ancestors().skip(1).take_while(|dir| *dir != self.output_dir) {
+                if fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+        for file in files {
+            self.place_file(&file.path, &file.output_name)?;
+        }
+        if let Some(content) = tree_content {
+            fs::write(self.output_dir.join(FILETREE_TXT), content)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the zip at `zip_path` without the `removed` entries, with `files` and the
+    /// new tree.
+    fn rewrite_zip(
+        &self,
+        zip_path: &Path,
+        removed: &[ManifestFile],
+        files: &[SelectedFile],
+        tree_content: Option<&str>,
+    ) -> Result<()> {
+        let mut dropped: HashSet<&str> = removed.iter().map(|entry| entry.output_name.as_str()).collect();
+        if tree_content.is_some() {
+            dropped.insert(FILETREE_TXT);
+        }
+
+        let temp_path = zip_path.with_extension("zip.tmp");
+        let mut existing = ZipArchive::new(BufReader::new(fs::File::open(zip_path)?))?;
+        let mut zip = ZipWriter::new(BufWriter::new(fs::File::create(&temp_path)?));
+        for i in 0..existing.len() {
+            let entry = existing.by_index_raw(i)?;
+            if !dropped.contains(entry.name()) {
+                zip.raw_copy_file(entry)?;
+            }
+        }
+        self.write_zip_entries(&mut zip, files, false, tree_content)?;
+        zip.finish()?;
+        drop(zip);
+
+        fs::rename(&temp_path, zip_path)?;
+        Ok(())
+    }
+
+    /// Rewrites the document at `concat_path` without the sections of the `removed` entries,
+    /// with sections for `files` before the references if it has them, and the new tree. A
+    /// footer is written again for the `file_count` files now in it.
+    fn rewrite_concat(
+        &self,
+        concat_path: &Path,
+        removed: &[ManifestFile],
+        files: &[SelectedFile],
+        file_count: usize,
+        tree_content: Option<&str>,
+    ) -> Result<()> {
+        let mut document = fs::read_to_string(concat_path)?;
+        let footer = match concat::remove_footer(&document) {
+            Some(rest) => {
+                document = rest.to_string();
+                true
+            }
+            None => false,
+        };
+        for entry in removed {
+            if let Some(rest) = concat::remove_block(&document, &entry.path) {
+                document = rest;
+            }
+        }
+        if let Some(content) = tree_content {
+            document = concat::replace_tree(&document, content);
+        }
+        let (before, after) = match document.rfind(&format!("\n{}\n", references::HEADING)) {
+            Some(at) => document.split_at(at + 1),
+            None => (document.as_str(), ""),
+        };
+
+        let out = BufWriter::new(fs::File::create(concat_path)?);
+        let mut writer = ConcatWriter::new(out).with_tokenizer(self.tokenizer()).with_language_map(&self.config.language_map);
+        writer.write_document(before)?;
+        self.write_concat_files(&mut writer, files, false)?;
+        writer.write_document(after)?;
+        if footer {
+            writer.write_footer(&naming::timestamp(), file_count, &self.filters())?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn copy_files(&self, files: Vec<SelectedFile>) -> Result<()> {
+        if self.verbose {
+            println!("Copying {} files to {:?}", files.len(), self.output_dir);
+        }
+
+        for (name, contents) in self.front_matter(&files)? {
+            fs::write(self.output_dir.join(name), contents)?;
+        }
+        for (name, contents) in self.artifacts(&files)? {
+            fs::write(self.output_dir.join(name), contents)?;
+        }
+        if let Some(references) = self.references() {
+            fs::write(self.output_dir.join(REFERENCES_MD), references)?;
+        }
+
+        let done: HashSet<String> = match self.resume {
+            true => Manifest::load(&self.output_dir)
+                .map(|manifest| manifest.files.into_iter().map(|file| file.output_name).collect())
+                .unwrap_or_default(),
+            false => HashSet::new(),
+        };
+        // saved before copying, so that even a run that runs out of space can be resumed
+        let mut plan = Manifest::new(self.manifest_files(&files)?, None);
+        plan.folder = true;
+        plan.partial = true;
+        plan.run_key = self.run_key.get().cloned();
+        plan.save(&self.output_dir)?;
+
+        let total = files.len();
+        for (completed, file) in files.iter().enumerate() {
+            interrupt::check(completed, total)?;
+            if done.contains(&file.output_name) && self.is_placed(file).unwrap_or(false) {
+                continue;
+            }
+            self.skip_failed(&file.path, self.place_file(&file.path, &file.output_name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `file` is in the output folder in full, judged by its size, as a stopped run
+    /// may have left the file it was copying cut short.
+    fn is_placed(&self, file: &SelectedFile) -> Result<bool> {
+        let Ok(placed) = fs::metadata(paths::extended_length(&self.output_dir.join(&file.output_name))) else {
+            return Ok(false);
+        };
+        let expected = match self.transform(&file.path)? {
+            Some(transformed) => transformed.contents.len() as u64,
+            None => fs::metadata(paths::extended_length(&file.path))?.len(),
+        };
+        Ok(placed.len() == expected)
+    }
+
+    /// Writes the file at `source_path` to `output_name` in the output folder. A file a
+    /// stopped run left there is removed first, as it may be a link to the source that
+    /// writing to it would change.
+    fn place_file(&self, source_path: &Path, output_name: &str) -> Result<()> {
+        let target_path = paths::extended_length(&self.output_dir.join(output_name));
+
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::remove_file(&target_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        if let Some(transformed) = self.transform(source_path)? {
+            // rewritten contents cannot be linked to the source
+            fs::write(&target_path, transformed.contents)?;
+            self.verbose_file("Transformed", format_args!("{:?} to {:?}", source_path, target_path));
+            return Ok(());
+        }
+
+        let mode = copy_mode::place_file(&paths::extended_length(source_path), &target_path, self.config.copy_mode)?;
+
+        if mode != self.config.copy_mode {
+            let unavailable = self.config.copy_mode;
+            self.verbose_file("Copied instead", format_args!("{:?} unavailable for {:?}", unavailable, source_path));
+        }
+        self.verbose_file("Copied", format_args!("{:?} to {:?}", source_path, target_path));
+        Ok(())
+    }
+
+    pub fn write_concat(&self, files: Vec<SelectedFile>, tree_content: Option<&str>) -> Result<usize> {
+        let concat_path = self.output_dir.join(&self.concat_name);
+        let out = BufWriter::new(fs::File::create(&concat_path)?);
+        let (written, _) = self.concat_into(files, tree_content, out)?;
+
+        if self.verbose {
+            println!("Wrote concatenated output to {:?}", concat_path);
+        }
+
+        Ok(written)
+    }
+
+    /// Streams the concatenated document into `out` and returns the number of files written
+    /// together with the flushed writer.
+    pub fn concat_into<W: Write>(
+        &self,
+        files: Vec<SelectedFile>,
+        tree_content: Option<&str>,
+        out: W,
+    ) -> Result<(usize, W)> {
+        let mut writer = ConcatWriter::new(out).with_tokenizer(self.tokenizer()).with_language_map(&self.config.language_map);
+
+        for (_, contents) in self.front_matter(&files)? {
+            writer.write_markdown(&contents)?;
+        }
+        if let Some(content) = tree_content {
+            writer.write_tree(content)?;
+        }
+        for (name, contents) in self.artifacts(&files)? {
+            match &self.config.diff {
+                Some(base) if name == CHANGES_PATCH => writer.write_changes(base, &contents)?,
+                _ => {
+                    writer.write_contents(contents.as_bytes(), name)?;
+                }
+            }
+        }
+
+        let mut written = 0;
+        for (section, files) in self.group_by_section(files) {
+            if let Some(section) = section {
+                writer.write_section(section)?;
+            }
+            written += self.write_concat_files(&mut writer, &files, true)?;
+        }
+        if let Some(references) = self.references() {
+            writer.write_markdown(&references)?;
+        }
+        if self.config.footer {
+            writer.write_footer(&naming::timestamp(), written, &self.filters())?;
+        }
+
+        Ok((written, writer.finish()?))
+    }
+
+    /// The settings that chose the files, for the footer of concatenated output.
+    fn filters(&self) -> String {
+        let config = self.config;
+        let list = |items: &[String]| items.join(", ");
+        let categories = |categories: &[classify::Category]| {
+            categories.iter().map(|category| format!("{:?}", category).to_lowercase()).collect::<Vec<_>>().join(", ")
+        };
+        let mut filters = vec![match config.allowed_extensions.is_empty() {
+            true => "all extensions".to_string(),
+            false => format!("extensions {}", list(&config.allowed_extensions)),
+        }];
+        if !config.exclude.is_empty() {
+            filters.push(format!("exclude {}", list(&config.exclude)));
+        }
+        if !config.only_categories.is_empty() {
+            filters.push(format!("only {}", categories(&config.only_categories)));
+        }
+        if !config.exclude_categories.is_empty() {
+            filters.push(format!("no {}", categories(&config.exclude_categories)));
+        }
+        if !config.include_tests {
+            filters.push("no tests".to_string());
+        }
+        if config.respect_gitignore {
+            filters.push(".gitignore".to_string());
+        }
+        if config.tracked_only {
+            filters.push("tracked only".to_string());
+        }
+        if let Some(base) = &config.diff {
+            filters.push(format!("changed since {}", base));
+        }
+        if let Some(symbol) = &config.symbol {
+            filters.push(format!("symbol {} ({} hops)", symbol, config.symbol_hops));
+        }
+        if !config.seeds.is_empty() {
+            filters.push(format!("seeds {} (depth {})", list(&config.seeds), config.seed_depth));
+        }
+        if let Some(max_filesize) = config.max_filesize {
+            filters.push(format!("max_filesize {}", max_filesize));
+        }
+        if let Some(max_files) = config.max_files {
+            filters.push(format!("max_files {}", max_files));
+        }
+        if let Some(max_total_bytes) = config.max_total_bytes {
+            filters.push(format!("max_total_bytes {}", max_total_bytes));
+        }
+        if let Some(max_tokens) = config.max_tokens {
+            filters.push(format!("max_tokens {}", max_tokens));
+        }
+        if let Some(sample) = config.sample {
+            filters.push(format!("sample {}", sample));
+        }
+        filters.join("; ")
+    }
+
+    /// `files` in the order of `sections`, each group with the name of its section, and the
+    /// files no section takes last as `Other files`. Without `sections`, one unnamed group.
+    fn group_by_section(&self, files: Vec<SelectedFile>) -> Vec<(Option<&str>, Vec<SelectedFile>)> {
+        if self.sections.is_empty() {
+            return vec![(None, files)];
+        }
+        let mut groups: Vec<Vec<SelectedFile>> = vec![Vec::new(); self.sections.len() + 1];
+        for file in files {
+            let relative = file.path.strip_prefix(self.source_path).unwrap_or(&file.path);
+            let at = self.sections.iter().position(|(_, globs)| globs.is_match(relative)).unwrap_or(self.sections.len());
+            groups[at].push(file);
+        }
+        let names = self.sections.iter().map(|(name, _)| name.as_str()).chain(["Other files"]);
+        names.zip(groups).filter(|(_, files)| !files.is_empty()).map(|(name, files)| (Some(name), files)).collect()
+    }
+
+    /// Writes a section for each of `files`, or for each entry of `entries` if `merge`, and
+    /// returns how many were not skipped as binary.
+    fn write_concat_files<W: Write>(
+        &self,
+        writer: &mut ConcatWriter<W>,
+        files: &[SelectedFile],
+        merge: bool,
+    ) -> Result<usize> {
+        let mut written = 0;
+        let entries = self.entries(files, merge);
+        let total = entries.len();
+        for (completed, entry) in entries.iter().enumerate() {
+            interrupt::check(completed, total)?;
+
+            if entry.len() > 1 {
+                let (dir, contents, count) = self.merged(entry)?;
+                if count > 0 {
+                    let dir = self.anonymize_names(paths::to_slash(&dir));
+                    let display_name = format!("{}/ ({} small files)", if dir.is_empty() { "." } else { &dir }, count);
+                    writer.write_contents_as(&contents, &display_name, Some("text"))?;
+                    written += count;
+                }
+                continue;
+            }
+            let SelectedFile { path: source_path, .. } = entry[0];
+
+            let display_name = self.anonymize_names(paths::to_slash(source_path.strip_prefix(self.source_path)?));
+
+            let included = self.transform(source_path).and_then(|transformed| match transformed {
+                Some(transformed) => {
+                    writer.write_contents_as(&transformed.contents, &display_name, transformed.language.as_deref())
+                }
+                None => writer.write_file(&paths::extended_length(source_path), &display_name),
+            });
+            let Some(included) = self.skip_failed(source_path, included)? else {
+                continue;
+            };
+            if included {
+                written += 1;
+            } else {
+                self.verbose_file("Skipping binary file", format_args!("{:?}", source_path));
+            }
+        }
+        Ok(written)
+    }
+
+    /// Writes the HTML page of `files`, with what `front_matter`, `artifacts` and
+    /// `references` add, and returns the number of files that were not skipped as binary.
+    pub fn write_html(&self, files: &[SelectedFile]) -> Result<usize> {
+        let front_matter = self.front_matter(files)?;
+        let artifacts = self.artifacts(files)?;
+        let references = self.references();
+        let display_names = files
+            .iter()
+            .map(|file| Ok(self.anonymize_names(paths::to_slash(file.path.strip_prefix(self.source_path)?))))
+            .collect::<Result<Vec<_>>>()?;
+        let names: Vec<String> = front_matter
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .chain(artifacts.iter().map(|(name, _)| name.to_string()))
+            .chain(display_names.iter().cloned())
+            .chain(references.as_ref().map(|_| REFERENCES_MD.to_string()))
+            .collect();
+        let source = self.source_path.canonicalize()?;
+        let title = source.file_name().map_or("context".into(), |name| name.to_string_lossy());
+
+        let html_path = self.output_dir.join(&self.html_name);
+        let mut writer = HtmlWriter::new(BufWriter::new(fs::File::create(&html_path)?), &title, &names)?;
+        for (name, contents) in &front_matter {
+            writer.write_file(name, contents.as_bytes(), "md")?;
+        }
+        for (name, contents) in &artifacts {
+            let language = match *name {
+                CHANGES_PATCH => "diff",
+                SCHEMA_SQL => "sql",
+                _ => "txt",
+            };
+            writer.write_file(name, contents.as_bytes(), language)?;
+        }
+        let mut written = 0;
+        for (completed, (file, display_name)) in files.iter().zip(&display_names).enumerate() {
+            interrupt::check(completed, files.len())?;
+            let (contents, language) = self.read_contents(&file.path)?;
+            let language = language.or_else(|| extension(&file.path)).unwrap_or_default();
+            if writer.write_file(display_name, &contents, &language)? {
+                written += 1;
+            }
+        }
+        if let Some(references) = references {
+            writer.write_file(REFERENCES_MD, references.as_bytes(), "md")?;
+        }
+        writer.finish()?;
+
+        if self.verbose {
+            println!("Wrote HTML output to {:?}", html_path);
+        }
+        Ok(written)
+    }
+
+    /// Writes the SQLite database of `files`: a `files` table with their contents, sizes,
+    /// token estimates and SHA-256 hashes, and a `symbols` table of what each one defines.
+    /// Binary files are listed without contents.
+    pub fn write_sqlite(&self, files: &[SelectedFile]) -> Result<()> {
+        let mut file_rows = Vec::new();
+        let mut symbol_rows = Vec::new();
+        for (completed, file) in files.iter().enumerate() {
+            interrupt::check(completed, files.len())?;
+            let display_name = self.anonymize_names(paths::to_slash(file.path.strip_prefix(self.source_path)?));
+            let (contents, language) = self.read_contents(&file.path)?;
+            let language = language.or_else(|| extension(&file.path)).unwrap_or_default();
+            let hash = Sha256::digest(&contents).iter().map(|b| format!("{:02x}", b)).collect();
+            let text = (!contents.contains(&0)).then(|| String::from_utf8_lossy(&contents).into_owned());
+
+            let file_id = file_rows.len() as i64 + 1;
+            for symbol in text.as_deref().map(|text| symbols::extract(text, &language)).unwrap_or_default() {
+                symbol_rows.push(vec![
+                    Value::Null,
+                    Value::Integer(file_id),
+                    Value::Text(symbol.name),
+                    Value::Text(symbol.kind.to_string()),
+                    Value::Integer(symbol.line as i64),
+                ]);
+            }
+            file_rows.push(vec![
+                Value::Null,
+                Value::Text(display_name),
+                Value::Text(language),
+                Value::Integer(contents.len() as i64),
+                Value::Integer(text.as_deref().map_or(0, |text| self.count_tokens(text)) as i64),
+                Value::Text(hash),
+                text.map_or(Value::Null, Value::Text),
+            ]);
+        }
+
+        let tables = [
+            Table {
+                name: "files".to_string(),
+                sql: "CREATE TABLE files (id INTEGER PRIMARY KEY, path TEXT NOT NULL, language TEXT NOT NULL, \
+                      size INTEGER NOT NULL, tokens INTEGER NOT NULL, hash TEXT NOT NULL, content TEXT)"
+                    .to_string(),
+                rows: file_rows,
+            },
+            Table {
+                name: "symbols".to_string(),
+                sql: "CREATE TABLE symbols (id INTEGER PRIMARY KEY, file_id INTEGER NOT NULL REFERENCES files (id), \
+                      name TEXT NOT NULL, kind TEXT NOT NULL, line INTEGER NOT NULL)"
+                    .to_string(),
+                rows: symbol_rows,
+            },
+        ];
+        let db_path = self.output_dir.join(&self.db_name);
+        let mut out = BufWriter::new(fs::File::create(&db_path)?);
+        sqlite::write(&mut out, &tables)?;
+        out.flush()?;
+
+        if self.verbose {
+            println!("Wrote SQLite output to {:?}", db_path);
+        }
+        Ok(())
+    }
+
+    /// Writes `search_index.json` of `files` as the outputs have them. Binary files are left
+    /// out.
+    pub fn write_search_index(&self, files: &[SelectedFile]) -> Result<()> {
+        let mut indexed = Vec::new();
+        for (completed, file) in files.iter().enumerate() {
+            interrupt::check(completed, files.len())?;
+            let (contents, _) = self.read_contents(&file.path)?;
+            if contents.contains(&0) {
+                continue;
+            }
+            let display_name = self.anonymize_names(paths::to_slash(file.path.strip_prefix(self.source_path)?));
+            indexed.push((display_name, file.output_name.clone(), String::from_utf8_lossy(&contents).into_owned()));
+        }
+        let index = SearchIndex::build(indexed.iter().map(|(path, name, text)| (path.clone(), name.clone(), text.as_str())));
+        index.save(&self.output_dir)?;
+        if self.verbose {
+            println!("Indexed {} files for search", index.files.len());
+        }
+        Ok(())
+    }
+
+    /// Renders the file tree of everything that passes the filters.
+    pub fn tree(&self) -> Result<String> {
+        let walked = self.walk()?;
+        Ok(tree::render(self.source_path, &walked.directories, &walked.files))
+    }
+
+    /// Builds the concatenated document in memory, for callers embedding lmprep rather than
+    /// writing an output folder.
+    pub fn pack(&self) -> Result<String> {
+        let walked = self.walk()?;
+        let files = self.collect_files(&walked)?;
+        self.scan_output(&files)?;
+        let tree_content = self
+            .config
+            .tree
+            .then(|| self.anonymize_names(tree::render(self.source_path, &walked.directories, &walked.files)));
+        let (_, buffer) = self.concat_into(files, tree_content.as_deref(), Vec::new())?;
+        self.save_anonymize_map()?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    pub fn create_zip(&self, files: Vec<SelectedFile>, tree_content: Option<&str>) -> Result<()> {
+        if self.verbose {
+            println!("Starting to create zip archive");
+        }
+
+        let zip_path = self.output_dir.join(&self.zip_name);
+
+        let zip_file = fs::File::create(&zip_path)?;
+        let mut zip = ZipWriter::new(BufWriter::new(zip_file));
+        let options = zip_options();
+
+        for (name, contents) in self.front_matter(&files)? {
+            zip.start_file(name, options)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        for (name, contents) in self.artifacts(&files)? {
+            zip.start_file(name, options)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        if let Some(references) = self.references() {
+            zip.start_file(REFERENCES_MD, options)?;
+            zip.write_all(references.as_bytes())?;
+        }
+
+        self.write_zip_entries(&mut zip, &files, true, tree_content)?;
+        zip.finish()?;
+
+        if self.verbose {
+            println!("Created zip archive at {:?}", zip_path);
+        }
+
+        Ok(())
+    }
+
+    /// Adds `files`, as the entries of `entries` if `merge`, and the tree file, if it was
+    /// generated, to `zip`.
+    fn write_zip_entries<W: Write + Seek>(
+        &self,
+        zip: &mut ZipWriter<W>,
+        files: &[SelectedFile],
+        merge: bool,
+        tree_content: Option<&str>,
+    ) -> Result<()> {
+        let options = zip_options();
+        let entries = self.entries(files, merge);
+        let total = entries.len();
+        for (completed, entry) in entries.iter().enumerate() {
+            interrupt::check(completed, total)?;
+
+            if entry.len() > 1 {
+                let (dir, contents, count) = self.merged(entry)?;
+                if count > 0 {
+                    let name = self.generate_new_filename(&self.source_path.join(dir).join(MERGED_NAME))?;
+                    zip.start_file(self.anonymize_names(name), options)?;
+                    zip.write_all(&contents)?;
+                }
+                continue;
+            }
+            let SelectedFile { path: source_path, output_name } = entry[0];
+
+            // opened before the entry is started, so that an unreadable file leaves no empty one
+            let opened = self.transform(source_path).and_then(|transformed| match transformed {
+                Some(transformed) => Ok((Some(transformed), None)),
+                None => Ok((None, Some(fs::File::open(paths::extended_length(source_path))?))),
+            });
+            let Some((transformed, file)) = self.skip_failed(source_path, opened)? else {
+                continue;
+            };
+            zip.start_file(output_name, options)?;
+            if let Some(transformed) = transformed {
+                zip.write_all(&transformed.contents)?;
+            }
+            if let Some(mut file) = file {
+                io::copy(&mut file, zip)?;
+            }
+        }
+
+        if let Some(content) = tree_content {
+            zip.start_file(FILETREE_TXT, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// `files` as the entries of the zip or concatenated output: each file alone, or with
+    /// `merge` and `merge_small_files` set, the small files of a directory together.
+    fn entries<'f>(&self, files: &'f [SelectedFile], merge: bool) -> Vec<Vec<&'f SelectedFile>> {
+        let max_bytes = match self.config.merge_small_files {
+            Some(max_bytes) if merge => max_bytes,
+            _ => return files.iter().map(|file| vec![file]).collect(),
+        };
+        let sizes: Vec<(&Path, u64)> = files
+            .iter()
+            .map(|file| {
+                let size = fs::metadata(paths::extended_length(&file.path)).map_or(u64::MAX, |m| m.len());
+                (file.path.as_path(), size)
+            })
+            .collect();
+        merge::group(&sizes, max_bytes).into_iter().map(|entry| entry.into_iter().map(|i| &files[i]).collect()).collect()
+    }
+
+    /// The entry of `files`, small files of one directory, merged: that directory relative to
+    /// the source, the contents of the text files each after a `merge::header`, and how many
+    /// files went into it.
+    fn merged(&self, files: &[&SelectedFile]) -> Result<(PathBuf, Vec<u8>, usize)> {
+        let mut merged = Vec::new();
+        let mut count = 0;
+        for file in files {
+            let Some((contents, _)) = self.skip_failed(&file.path, self.read_contents(&file.path))? else {
+                continue;
+            };
+            if contents.contains(&0) {
+                self.verbose_file("Skipping binary file", format_args!("{:?}", file.path));
+                continue;
+            }
+            if count > 0 {
+                merged.push(b'\n');
+            }
+            let name = self.anonymize_names(paths::to_slash(file.path.strip_prefix(self.source_path)?));
+            merged.extend_from_slice(merge::header(&name).as_bytes());
+            merged.extend_from_slice(&contents);
+            if !contents.is_empty() && !contents.ends_with(b"\n") {
+                merged.push(b'\n');
+            }
+            count += 1;
+        }
+        let dir = files[0].path.parent().unwrap_or(self.source_path);
+        Ok((dir.strip_prefix(self.source_path)?.to_path_buf(), merged, count))
+    }
+
+    /// Whether `secret_scan` or `pii` need to see the output before it is written.
+    pub fn scans_output(&self) -> bool {
+        self.config.secret_scan != SecretScan::Off || matches!(self.config.pii, PiiPolicy::Warn | PiiPolicy::Fail)
+    }
+
+    /// Scans what would be written for `files`, see `report_findings`.
+    pub fn scan_output(&self, files: &[SelectedFile]) -> Result<()> {
+        if !self.scans_output() {
+            return Ok(());
+        }
+
+        let mut findings = self.scan_files(files)?;
+        // removed lines and commit messages only appear here
+        for (name, contents) in self.artifacts(files)? {
+            self.scan_contents(Path::new(name), name, contents.as_bytes(), &mut findings);
+        }
+        self.report_findings(&findings)
+    }
+
+    /// What `secret_scan` and `pii` find in the output contents of `files`.
+    fn scan_files(&self, files: &[SelectedFile]) -> Result<Findings> {
+        let mut findings = Findings::default();
+        for file in files {
+            let (contents, _) = self.read_contents(&file.path)?;
+            let relative = file.path.strip_prefix(self.source_path)?;
+            let display_name = self.anonymize_names(paths::to_slash(relative));
+            self.scan_contents(relative, &display_name, &contents, &mut findings);
+        }
+        Ok(findings)
+    }
+
+    /// What is added to the output about `files`, by name: from git, `changes.patch` with
+    /// `diff` and `history.txt` with `history` or `with_history`, and `schema.sql` with
+    /// `consolidate_migrations`. `pii: redact` and `anonymize` apply to them as they do to the
+    /// files.
+    pub fn artifacts(&self, files: &[SelectedFile]) -> Result<Vec<(&'static str, String)>> {
+        let mut artifacts = Vec::new();
+        if let Some(base) = &self.config.diff {
+            artifacts.push((CHANGES_PATCH, self.changes_patch(base, files)?));
+        }
+        let count = self.config.history.filter(|&count| count > 0);
+        if count.is_some() || self.history_range.is_some() {
+            let revisions = self.history_range.as_deref().unwrap_or("HEAD");
+            let relative = files
+                .iter()
+                .map(|file| file.path.strip_prefix(self.source_path))
+                .collect::<Result<Vec<_>, _>>()?;
+            artifacts.push((HISTORY_TXT, git::history(self.source_path, revisions, count, &relative)?));
+        }
+        let set_aside = self.migrations.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if !set_aside.is_empty() {
+            let mut sources = set_aside
+                .iter()
+                .map(|path| {
+                    let name = paths::to_slash(path.strip_prefix(self.source_path)?);
+                    Ok((name, String::from_utf8_lossy(&fs::read(paths::extended_length(path))?).into_owned()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            sources.sort_by(|(a, _), (b, _)| migrations::compare(a, b));
+            artifacts.push((SCHEMA_SQL, migrations::consolidate(&sources)));
+        }
+
+        for (_, contents) in &mut artifacts {
+            if self.config.pii == PiiPolicy::Redact {
+                *contents = pii::redact(contents);
+            }
+            if let Some(anonymizer) = &self.anonymizer {
+                *contents = String::from_utf8_lossy(&anonymizer.contents(contents.as_bytes(), None)).into_owned();
+            }
+        }
+        Ok(artifacts)
+    }
+
+    /// What goes before everything else in the output about the source with `files` selected,
+    /// by name: `brief.md` with `brief`, `commands.md` with `commands`, `terraform.md` with
+    /// `terraform` and `vendored.md` with `vendored: summarize`, all markdown.
+    /// `pii: redact` and `anonymize` apply to them as they do to the files.
+    pub fn front_matter(&self, files: &[SelectedFile]) -> Result<Vec<(&'static str, String)>> {
+        let mut documents = Vec::new();
+        if self.config.brief {
+            let relative = files.iter().map(|file| Ok(file.path.strip_prefix(self.source_path)?.to_path_buf())).collect::<Result<Vec<_>>>()?;
+            documents.push((BRIEF_MD, brief::render(self.source_path, &relative)));
+        }
+        if self.config.commands {
+            documents.push((COMMANDS_MD, commands::render(&commands::extract(self.source_path))));
+        }
+        if self.config.terraform {
+            let sources = files
+                .iter()
+                .filter(|file| terraform::is_terraform(&file.path))
+                .map(|file| {
+                    let name = paths::to_slash(file.path.strip_prefix(self.source_path)?);
+                    Ok((name, String::from_utf8_lossy(&fs::read(paths::extended_length(&file.path))?).into_owned()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if !sources.is_empty() {
+                documents.push((TERRAFORM_MD, terraform::summarize(&sources)));
+            }
+        }
+        let vendored = self.vendored.read().unwrap_or_else(|e| e.into_inner());
+        if self.config.vendored == VendoredCode::Summarize && !vendored.is_empty() {
+            documents.push((VENDORED_MD, vendored::summarize(self.source_path, &vendored)));
+        }
+
+        for (_, contents) in &mut documents {
+            if self.config.pii == PiiPolicy::Redact {
+                *contents = pii::redact(contents);
+            }
+            if let Some(anonymizer) = &self.anonymizer {
+                *contents = String::from_utf8_lossy(&anonymizer.contents(contents.as_bytes(), None)).into_owned();
+            }
+        }
+        Ok(documents)
+    }
+
+    /// The `references` pages as one markdown document, or `None` if there are none. They are
+    /// fetched on the first call.
+    pub fn references(&self) -> Option<String> {
+        self.fetched_references
+            .get_or_init(|| (!self.config.references.is_empty()).then(|| references::render(&self.config.references)))
+            .clone()
+    }
+
+    /// The diff of `files` against `base`, with new untracked files shown as added.
+    fn changes_patch(&self, base: &str, files: &[SelectedFile]) -> Result<String> {
+        let untracked = if self.config.include_untracked {
+            git::untracked_files(self.source_path)?
+        } else {
+            HashSet::new()
+        };
+        let (mut changed, mut new_files, mut left_out) = (Vec::new(), Vec::new(), Vec::new());
+        for file in files {
+            let relative = file.path.strip_prefix(self.source_path)?;
+            if self.config.terraform && terraform::is_terraform(relative) {
+                // their hunks would show the secrets `terraform::redact` takes out of the files
+                left_out.push(paths::to_slash(relative));
+            } else if untracked.contains(&file.path) {
+                new_files.push(relative);
+            } else {
+                changed.push(relative);
+            }
+        }
+
+        let patch = git::diff(self.source_path, base, &changed, &new_files)?;
+        if left_out.is_empty() {
+            return Ok(patch);
+        }
+        // text before the first `diff --git` line is ignored by `git apply`
+        Ok(format!(
+            "Changes to Terraform files are left out, as they may hold secrets; the files are in the output redacted: {}\n\n{}",
+            left_out.join(", "),
+            patch
+        ))
+    }
+
+    /// Adds what `secret_scan` and `pii` find in the output `contents` of the file at
+    /// `relative` to `findings`. Binary contents are skipped.
+    pub fn scan_contents(&self, relative: &Path, display_name: &str, contents: &[u8], findings: &mut Findings) {
+        if contents.contains(&0) {
+            return;
+        }
+        let text = String::from_utf8_lossy(contents);
+        if self.config.secret_scan != SecretScan::Off {
+            findings.secrets.extend(secrets::scan(display_name, &text));
+        }
+        if matches!(self.config.pii, PiiPolicy::Warn | PiiPolicy::Fail) && self.pii_applies(relative) {
+            findings.pii.extend(pii::scan(display_name, &text));
+        }
+    }
+
+    /// Whether the file at `path` is in `summarize_categories` and at least
+    /// `summarize_min_bytes` long.
+    fn summarizes(&self, path: &Path) -> Result<bool> {
+        let relative = path.strip_prefix(self.source_path)?;
+        if !self.config.summarize_categories.contains(&classify::category(relative)) {
+            return Ok(false);
+        }
+        Ok(fs::metadata(paths::extended_length(path))?.len() >= self.config.summarize_min_bytes)
+    }
+
+    /// The line endings `normalize_line_endings` gives `path`.
+    fn eol(&self, path: &Path) -> Eol {
+        match self.config.normalize_line_endings {
+            true => self.line_endings.read().unwrap_or_else(|e| e.into_inner()).get(path).copied().unwrap_or(Eol::Keep),
+            false => Eol::Keep,
+        }
+    }
+
+    fn pii_applies(&self, relative: &Path) -> bool {
+        self.config.pii_categories.contains(&classify::category(relative))
+    }
+
+    /// Lists `findings` on stderr, and the secrets in `secrets_report`. With
+    /// `secret_scan: block` or `pii: fail`, any such finding is an error, so nothing gets
+    /// written.
+    pub fn report_findings(&self, findings: &Findings) -> Result<()> {
+        if let Some(report) = &self.config.secrets_report {
+            fs::write(report, serde_json::to_string_pretty(&findings.secrets)?)?;
+        }
+
+        for (heading, list) in [("Possible secrets found:", &findings.secrets), ("Possible personal data found:", &findings.pii)] {
+            if list.is_empty() {
+                continue;
+            }
+            eprintln!("{}", heading);
+            for finding in list {
+                eprintln!("  {}:{}  {}  {}", finding.path, finding.line, finding.detector, finding.preview);
+            }
+        }
+        if findings.secrets.is_empty() && findings.pii.is_empty() {
+            return Ok(());
+        }
+        eprintln!("Add `{}` to a line to allow it.", secrets::ALLOW_MARKER);
+
+        if self.config.secret_scan == SecretScan::Block && !findings.secrets.is_empty() {
+            bail!("{} possible secrets found, nothing was written", findings.secrets.len());
+        }
+        if self.config.pii == PiiPolicy::Fail && !findings.pii.is_empty() {
+            bail!("{} possible personal data matches found, nothing was written", findings.pii.len());
+        }
+        Ok(())
+    }
+
+    /// Whether `path` is outlined or summarized and the line endings it gets: what, together
+    /// with the file itself, decides what `read_contents` returns for it. `None` if that also
+    /// depends on the repository, for files annotated with `blame` and possible LFS pointers.
+    pub fn contents_key(&self, path: &Path) -> Result<Option<ContentsKey>> {
+        let relative = path.strip_prefix(self.source_path)?;
+        let maybe_pointer = self.config.lfs != LfsPointers::Skip
+            && fs::metadata(path).is_ok_and(|m| m.len() <= lfs::MAX_POINTER_SIZE);
+        if self.transforms.blames(relative) || maybe_pointer {
+            return Ok(None);
+        }
+        Ok(Some(ContentsKey {
+            outlined: self.is_outlined(path),
+            summarized: self.summarizes(path)?,
+            eol: self.eol(path),
+        }))
+    }
+
+    /// Contents of `path` as they go into the output, with `transforms` applied, and the fence
+    /// language a transform chose for them.
+    pub fn read_contents(&self, path: &Path) -> Result<(Vec<u8>, Option<String>)> {
+        Ok(match self.transform(path)? {
+            Some(transformed) => (transformed.contents, transformed.language),
+            None => (fs::read(paths::extended_length(path))?, None),
+        })
+    }
+
+    /// What goes into the output for the file at `path`, if that is not the file as it is:
+    /// `reshape` with the lines longer than `max_line_length` cut or wrapped.
+    fn transform(&self, path: &Path) -> Result<Option<Transformed>> {
+        let transformed = self.reshape(path)?;
+        let Some(max) = self.config.max_line_length else {
+            return Ok(transformed);
+        };
+        let (contents, language) = match &transformed {
+            Some(transformed) => (transformed.contents.clone(), transformed.language.clone()),
+            None => (fs::read(paths::extended_length(path))?, None),
+        };
+        // binary files are copied as they are
+        let limited = std::str::from_utf8(&contents)
+            .ok()
+            .filter(|text| !text.contains('\0'))
+            .and_then(|text| transforms::limit_lines(text, max, self.config.long_lines));
+        Ok(match limited {
+            Some(limited) => Some(Transformed { contents: limited.into_bytes(), language }),
+            None => transformed,
+        })
+    }
+
+    /// The rewrites of `transform_contents`, summarized if `summarize_categories` applies to
+    /// the file or cut down to the outline if `apply_token_budget` outlined it.
+    fn reshape(&self, path: &Path) -> Result<Option<Transformed>> {
+        let transformed = self.transform_contents(path)?;
+        if self.summarizes(path)? {
+            let contents = match transformed {
+                Some(transformed) => transformed.contents,
+                None => fs::read(paths::extended_length(path))?,
+            };
+            let name = self.anonymize_names(paths::to_slash(path.strip_prefix(self.source_path)?));
+            let summary = assets::summarize(&name, &String::from_utf8_lossy(&contents));
+            return Ok(Some(Transformed { contents: summary.into_bytes(), language: Some("text".to_string()) }));
+        }
+        if !self.is_outlined(path) {
+            return Ok(transformed);
+        }
+        let (contents, language) = match transformed {
+            Some(transformed) => (transformed.contents, transformed.language),
+            None => (fs::read(paths::extended_length(path))?, None),
+        };
+        let outline_language = language.clone().or_else(|| extension(path)).unwrap_or_default();
+        let outline = symbols::outline(&String::from_utf8_lossy(&contents), &outline_language).unwrap_or_default();
+        Ok(Some(Transformed { contents: outline.into_bytes(), language }))
+    }
+
+    /// Reads and rewrites `path` if it is an LFS pointer to resolve, a `transforms` rule
+    /// applies to it, `pii` redacts it, `terraform` redacts its secrets, `anonymize` is on or
+    /// `normalize_line_endings` changes its line endings.
+    fn transform_contents(&self, path: &Path) -> Result<Option<Transformed>> {
+        let relative = path.strip_prefix(self.source_path)?;
+        let redact = self.config.pii == PiiPolicy::Redact && self.pii_applies(relative);
+        let redact_secrets = self.config.terraform && terraform::is_terraform(relative);
+        let maybe_pointer = self.config.lfs != LfsPointers::Skip
+            && fs::metadata(path).is_ok_and(|m| m.len() <= lfs::MAX_POINTER_SIZE);
+        let eol = self.eol(path);
+        let normalize = eol != Eol::Keep;
+        let rewritten =
+            self.transforms.applies_to(relative) || redact || redact_secrets || self.anonymizer.is_some() || normalize;
+        if !rewritten && !maybe_pointer {
+            return Ok(None);
+        }
+        let mut contents = fs::read(paths::extended_length(path))?;
+        let mut resolved = false;
+        if maybe_pointer {
+            if let Some(pointer) = lfs::Pointer::parse(&contents) {
+                contents = self.resolve_lfs(relative, &pointer, contents);
+                resolved = true;
+            }
+        }
+        let mut transformed = self.transforms.apply(relative, &contents)?;
+        if self.transforms.blames(relative) {
+            let (output, language) = match transformed.take() {
+                Some(transformed) => (transformed.contents, transformed.language),
+                None => (contents.clone(), None),
+            };
+            let annotated = blame::annotate(self.source_path, relative, &contents, &output).unwrap_or_else(|e| {
+                eprintln!("Warning: could not blame {}: {}", relative.display(), e);
+                output
+            });
+            transformed = Some(Transformed { contents: annotated, language });
+        }
+        if !redact && !redact_secrets && self.anonymizer.is_none() && !resolved && !normalize {
+            return Ok(transformed);
+        }
+
+        let (mut contents, language) = match transformed {
+            Some(transformed) => (transformed.contents, transformed.language),
+            None => (contents, None),
+        };
+        if redact_secrets {
+            // whatever the other settings, Terraform secrets never reach the output
+            contents = terraform::redact(&paths::to_slash(relative), &String::from_utf8_lossy(&contents)).into_bytes();
+        }
+        if redact {
+            if let Ok(text) = std::str::from_utf8(&contents) {
+                contents = pii::redact(text).into_bytes();
+            }
+        }
+        if let Some(anonymizer) = &self.anonymizer {
+            contents = anonymizer.contents(&contents, extension(path).as_deref());
+        }
+        if let Some(converted) = line_endings::convert(&contents, eol) {
+            contents = converted;
+        }
+        Ok(Some(Transformed { contents, language }))
+    }
+
+    /// What goes into the output for an LFS pointer file: the real content with
+    /// `lfs: fetch` if it is text within the size cap, otherwise the annotated pointer.
+    fn resolve_lfs(&self, relative: &Path, pointer: &lfs::Pointer, contents: Vec<u8>) -> Vec<u8> {
+        if self.config.lfs != LfsPointers::Fetch {
+            return pointer.annotated(&contents, "use --lfs fetch to include it");
+        }
+        let limit = self.config.max_filesize.unwrap_or(lfs::DEFAULT_FETCH_LIMIT);
+        if pointer.size > limit {
+            return pointer.annotated(&contents, &format!("larger than {} bytes", limit));
+        }
+        match lfs::fetch(self.source_path, relative, &contents) {
+            Ok(fetched) if fetched.contains(&0) => pointer.annotated(&contents, "binary content"),
+            Ok(fetched) => fetched,
+            Err(e) => {
+                eprintln!("Warning: could not fetch LFS content of {}: {}", relative.display(), e);
+                pointer.annotated(&contents, "git lfs smudge failed")
+            }
+        }
+    }
+}
+
+/// Options of every zip entry: fixed metadata keeps archives byte-identical across runs and
+/// platforms.
+fn zip_options() -> FileOptions {
+    FileOptions::default().last_modified_time(DateTime::default()).unix_permissions(0o644)
+}
+
+/// Whether `error` is about one file rather than the run: the file is missing, a broken
+/// link, or not readable.
+fn is_file_error(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|e| matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied))
+}
+
+/// Lowercase extension of `path`.
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase())
+}
+
+/// Outlines, then drops, the files of `ranked` that are `in_budget` from the least important
+/// up until their tokens add up to at most `budget`. Dropped files are added to `dropped`.
+fn fit_budget(ranked: &mut Vec<Budgeted>, in_budget: impl Fn(&Budgeted) -> bool, budget: usize, dropped: &mut Vec<PathBuf>) {
+    let mut total: usize = ranked.iter().filter(|budgeted| in_budget(budgeted)).map(Budgeted::tokens).sum();
+    let mut outlined = HashSet::new();
+    for budgeted in ranked.iter_mut().rev().filter(|budgeted| in_budget(budgeted)) {
+        if total <= budget {
+            break;
+        }
+        match budgeted.outline_tokens {
+            Some(outline_tokens) if !budgeted.outline && outline_tokens < budgeted.full_tokens => {
+                total -= budgeted.full_tokens - outline_tokens;
+                budgeted.outline = true;
+                outlined.insert(budgeted.index);
+            }
+            _ => {}
+        }
+    }
+    while total > budget {
+        let Some(at) = ranked.iter().rposition(&in_budget) else {
+            break;
+        };
+        let budgeted = ranked.remove(at);
+        total -= budgeted.tokens();
+        dropped.push(budgeted.file.path);
+    }
+    // dropping a large file can free more than was needed: what fits gets its full contents
+    // back, most important first
+    for budgeted in ranked.iter_mut().filter(|budgeted| outlined.contains(&budgeted.index)) {
+        let restored = total - budgeted.tokens() + budgeted.full_tokens;
+        if restored <= budget {
+            total = restored;
+            budgeted.outline = false;
+        }
+    }
+}
+
+/// Default location of the `anonymize` reverse map for `source`: in the per-user cache, outside
+/// the source, so the map is never picked up as input or packed with the output, and out of
+/// the shared temp directory.
+fn default_anonymize_map(source: &Path) -> Result<PathBuf> {
+    let source = source.canonicalize().unwrap_or_else(|_| source.to_path_buf());
+    cache::path("anonymize", &format!("{}.json", flatten::short_hash(&source.to_string_lossy())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git").arg("-C").arg(dir).args(args).status().unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn changes_patch_leaves_out_terraform_secrets() {
+        let dir = std::env::temp_dir().join(format!("lmprep-test-tfvars-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q"]);
+        fs::write(dir.join("prod.tfvars"), "db_password = \"old\"\n").unwrap();
+        fs::write(dir.join("main.rs"), "fn main() {}\n").unwrap();
+        git(&dir, &["add", "."]);
+        git(&dir, &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-qm", "init"]);
+        fs::write(dir.join("prod.tfvars"), "db_password = \"hunter2-secret\"\n").unwrap();
+        fs::write(dir.join("main.rs"), "fn main() { run(); }\n").unwrap();
+
+        let config = Config { terraform: true, diff: Some("HEAD".to_string()), ..Config::default() };
+        let source = dir.to_string_lossy().into_owned();
+        let processor = FileProcessor::new(&source, &config, false).unwrap();
+        let files: Vec<_> = ["prod.tfvars", "main.rs"]
+            .iter()
+            .map(|name| SelectedFile { path: dir.join(name), output_name: name.to_string() })
+            .collect();
+        let artifacts = processor.artifacts(&files).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (_, patch) = artifacts.iter().find(|(name, _)| *name == CHANGES_PATCH).unwrap();
+        assert!(!patch.contains("hunter2-secret"));
+        assert!(!patch.contains("\"old\""));
+        assert!(patch.contains("prod.tfvars"));
+        assert!(patch.contains("run();"));
+    }
+
+    #[test]
+    fn run_key_follows_a_moving_diff_base() {
+        let dir = std::env::temp_dir().join(format!("lmprep-test-run-key-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q", "-b", "main"]);
+        fs::write(dir.join("main.rs"), "fn main() {}\n").unwrap();
+        git(&dir, &["add", "."]);
+        git(&dir, &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-qm", "init"]);
+        git(&dir, &["checkout", "-qb", "feature"]);
+
+        let config = Config { diff: Some("main".to_string()), ..Config::default() };
+        let source = dir.to_string_lossy().into_owned();
+        let key = || {
+            let processor = FileProcessor::new(&source, &config, false).unwrap();
+            processor.run_key(&processor.walk().unwrap()).unwrap()
+        };
+        let before = key();
+        let unchanged = key();
+        // `main` moves on while the working tree stays the same
+        git(&dir, &["checkout", "-q", "main"]);
+        git(&dir, &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-q", "--allow-empty", "-m", "later"]);
+        git(&dir, &["checkout", "-q", "feature"]);
+        let after = key();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(before, unchanged);
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn place_file_does_not_write_through_a_link_to_the_source() {
+        let dir = std::env::temp_dir().join(format!("lmprep-test-resume-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let contents = format!("const DATA: &str = \"{}\";\n", "x".repeat(200));
+        fs::write(dir.join("main.rs"), &contents).unwrap();
+
+        let config = Config { copy_mode: CopyMode::Hardlink, max_line_length: Some(40), ..Config::default() };
+        let source = dir.to_string_lossy().into_owned();
+        let processor = FileProcessor::new(&source, &config, false).unwrap();
+        // what a stopped run without `max_line_length` left behind
+        fs::create_dir_all(processor.output_dir()).unwrap();
+        fs::hard_link(dir.join("main.rs"), processor.output_dir().join("main.rs")).unwrap();
+        processor.place_file(&dir.join("main.rs"), "main.rs").unwrap();
+        let source_after = fs::read_to_string(dir.join("main.rs")).unwrap();
+        let placed = fs::read_to_string(processor.output_dir().join("main.rs")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(source_after, contents);
+        assert!(placed.len() < contents.len());
+    }
+
+    #[test]
+    fn apply_limits_leaves_out_vanished_files() {
+        let dir = std::env::temp_dir().join(format!("lmprep-test-limits-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("kept.rs"), "fn kept() {}\n").unwrap();
+
+        let config = Config { max_files: Some(5), max_total_bytes: Some(1000), ..Config::default() };
+        let source = dir.to_string_lossy().into_owned();
+        let processor = FileProcessor::new(&source, &config, false).unwrap();
+        let files: Vec<_> = ["kept.rs", "vanished.rs"]
+            .iter()
+            .map(|name| SelectedFile { path: dir.join(name), output_name: name.to_string() })
+            .collect();
+        let kept = processor.apply_limits(files).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].output_name, "kept.rs");
+        assert!(processor.any_failed());
+    }
+
+    #[test]
+    fn sqlite_output_opens_in_sqlite() {
+        let dir = std::env::temp_dir().join(format!("lmprep-test-sqlite-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub struct Config;\n\npub fn load() -> Config {\n    Config\n}\n").unwrap();
+        // larger than a page, so its contents go to overflow pages
+        let large: String = (0..400).map(|i| format!("pub fn f{}() {{}}\n", i)).collect();
+        fs::write(dir.join("src/large.rs"), &large).unwrap();
+
+        let config = Config { outputs: vec![Output::Sqlite], tree: false, ..Config::default() };
+        let source = dir.to_string_lossy().into_owned();
+        let processor = FileProcessor::new(&source, &config, false).unwrap();
+        processor.prepare_output_directory().unwrap();
+        processor.process().unwrap();
+        let db = processor.output_dir().join(&processor.db_name);
+        let query = |sql: &str| match Command::new("sqlite3").arg(&db).arg(sql).output() {
+            Ok(output) => {
+                assert!(output.status.success(), "sqlite3 failed: {}", String::from_utf8_lossy(&output.stderr));
+                Some(String::from_utf8(output.stdout).unwrap().trim_end().to_string())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => panic!("could not run sqlite3: {}", e),
+        };
+        let checked = query("PRAGMA integrity_check;");
+        let files = query("SELECT id, path, language, size, length(content) FROM files ORDER BY id;");
+        let symbols = query(
+            "SELECT f.path, s.name, s.kind, s.line FROM symbols s JOIN files f ON f.id = s.file_id \
+             WHERE f.path = 'src/lib.rs' ORDER BY s.line;",
+        );
+        let large_symbols = query("SELECT count(*) FROM symbols WHERE file_id = 2;");
+        fs::remove_dir_all(&dir).unwrap();
+        let Some(checked) = checked else {
+            eprintln!("sqlite3 is not installed, not checking the sqlite output");
+            return;
+        };
+
+        assert_eq!(checked, "ok");
+        // in output order
+        assert_eq!(files.unwrap(), format!("1|src/lib.rs|rs|59|59\n2|src/large.rs|rs|{0}|{0}", large.len()));
+        assert_eq!(symbols.unwrap(), "src/lib.rs|Config|struct|1\nsrc/lib.rs|load|function|3");
+        assert_eq!(large_symbols.unwrap(), "400");
+    }
+
+    #[test]
+    fn search_finds_files_in_the_output_with_and_without_an_index() {
+        let dir = std::env::temp_dir().join(format!("lmprep-test-search-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/config.rs"), "fn load_config() {}\n").unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+        let mut rendered = Vec::new();
+        for search_index in [true, false] {
+            let config = Config { search_index, tree: false, ..Config::default() };
+            let source = dir.to_string_lossy().into_owned();
+            let processor = FileProcessor::new(&source, &config, false).unwrap();
+            processor.prepare_output_directory().unwrap();
+            processor.process().unwrap();
+            assert_eq!(processor.output_dir().join(SEARCH_INDEX_JSON).exists(), search_index);
+            rendered.push(search::render(&processor.search("load_config", 10).unwrap()));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(rendered[0].starts_with("src/config.rs  (src^config.rs)  "), "{}", rendered[0]);
+        assert!(rendered[0].ends_with("\n      1: fn load_config() {}\n"), "{}", rendered[0]);
+        assert_eq!(rendered[1], "src/config.rs  (src^config.rs)\n      1: fn load_config() {}\n");
+    }
+}
@@ -0,0 +1,12 @@
+//! Which files matter most, used to decide what goes first when a limit forces files out.
+
+use std::cmp::Reverse;
+use std::path::Path;
+
+/// Sort key for a file at `relative` to the source with `priority`, from `priorities`; files
+/// with smaller keys are kept first. Higher priorities win, then shallow files (READMEs,
+/// manifests, entry points), which tend to describe the project while deep ones are details,
+/// and among equally deep files the smaller ones leave room for more.
+pub fn sort_key(relative: &Path, size: u64, priority: i64) -> (Reverse<i64>, usize, u64) {
+    (Reverse(priority), relative.components().count(), size)
+}
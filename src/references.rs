@@ -0,0 +1,228 @@
+//! `references`: documentation pages fetched over HTTP and added to the output as markdown,
+//! next to the code they describe.
+
+use anyhow::{bail, Result};
+use std::io::Read;
+use std::time::Duration;
+
+const USER_AGENT: &str = concat!("lmprep/", env!("CARGO_PKG_VERSION"));
+const TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_PAGE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Heading of the document `render` returns.
+pub const HEADING: &str = "# External references";
+
+/// Elements whose content is never text.
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "head", "noscript", "svg", "template", "nav", "footer"];
+
+/// Elements that start a new paragraph.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p", "div", "section", "article", "main", "header", "table", "tr", "ul", "ol", "dl", "dt", "dd", "blockquote",
+    "figure", "hr",
+];
+
+/// The `# External references` document for `urls`: one section per page, as markdown.
+/// Pages that cannot be fetched are left out with a warning.
+pub fn render(urls: &[String]) -> String {
+    let mut document = format!("{}\n\n", HEADING);
+    for url in urls {
+        match fetch(url) {
+            Ok(text) => document.push_str(&format!("## {}\n\n{}\n\n", url, text.trim())),
+            Err(e) => eprintln!("Warning: could not fetch reference {}: {}", url, e),
+        }
+    }
+    document
+}
+
+/// The page at `url` as markdown: HTML is converted, other text is kept as it is.
+pub fn fetch(url: &str) -> Result<String> {
+    let response = ureq::get(url).set("User-Agent", USER_AGENT).timeout(TIMEOUT).call()?;
+    let content_type = response.content_type().to_lowercase();
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_PAGE_BYTES).read_to_end(&mut body)?;
+    if body.contains(&0) {
+        bail!("{} is not text", content_type);
+    }
+
+    let text = String::from_utf8_lossy(&body);
+    Ok(if content_type.contains("html") { html_to_markdown(&text) } else { text.into_owned() })
+}
+
+/// Converts the text of an HTML page to markdown: headings, paragraphs, list items, links,
+/// inline code and preformatted blocks. Everything else is reduced to its text.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut out = String::new();
+    let mut skipping: Option<String> = None;
+    let mut pre = false;
+    let mut link: Option<String> = None;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            push_text(&mut out, rest, pre, skipping.is_some());
+            break;
+        };
+        push_text(&mut out, &rest[..open], pre, skipping.is_some());
+        rest = &rest[open..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(close) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if let Some(skipped) = &skipping {
+            if closing && name == *skipped {
+                skipping = None;
+            }
+            continue;
+        }
+        if SKIPPED_ELEMENTS.contains(&name.as_str()) && !closing && !tag.ends_with('/') {
+            skipping = Some(name);
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                let level = name[1..].parse().unwrap_or(1);
+                // the page sits below a `##` heading of its own
+                out.push_str(&format!("\n\n{} ", "#".repeat((level + 2).min(6))));
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => out.push_str("\n\n"),
+            ("pre", false) => {
+                out.push_str("\n\n```\n");
+                pre = true;
+            }
+            ("pre", true) => {
+                out.push_str("\n```\n\n");
+                pre = false;
+            }
+            ("code", _) if !pre => out.push('`'),
+            ("li", false) => out.push_str("\n- "),
+            ("br", _) => out.push('\n'),
+            ("td" | "th", false) => out.push_str(" | "),
+            ("a", false) => link = attribute(tag, "href").filter(|href| href.starts_with("http")),
+            ("a", true) => {
+                if let Some(href) = link.take() {
+                    out.push_str(&format!(" ({})", href));
+                }
+            }
+            (name, _) if BLOCK_ELEMENTS.contains(&name) => out.push_str("\n\n"),
+            _ => {}
+        }
+    }
+
+    tidy(&out)
+}
+
+/// Appends decoded text, with whitespace collapsed outside of `<pre>`.
+fn push_text(out: &mut String, text: &str, pre: bool, skipping: bool) {
+    if skipping {
+        return;
+    }
+    let text = decode_entities(text);
+    if pre {
+        out.push_str(&text);
+        return;
+    }
+    if text.starts_with(char::is_whitespace) && !out.is_empty() && !out.ends_with([' ', '\n']) {
+        out.push(' ');
+    }
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if !words.is_empty() {
+        out.push_str(&words.join(" "));
+        if text.ends_with(char::is_whitespace) {
+            out.push(' ');
+        }
+    }
+}
+
+/// The value of `name="..."` in the inside of a tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_lowercase();
+    let at = lower.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[at..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(|c: char| c.is_whitespace() || c == '>').next()?,
+    };
+    Some(decode_entities(value))
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let end = rest.find(';').filter(|&end| end <= 10);
+        let decoded = end.and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                _ => match entity.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => char::from_u32(u32::from_str_radix(&hex[1..], 16).ok()?)?,
+                    Some(decimal) => char::from_u32(decimal.parse().ok()?)?,
+                    None => return None,
+                },
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Trims trailing spaces and collapses runs of blank lines outside of code blocks.
+fn tidy(markdown: &str) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut blank_lines = 0;
+    let mut in_code = false;
+    for line in markdown.lines() {
+        let line = if in_code { line } else { line.trim() };
+        if line.starts_with("```") {
+            in_code = !in_code;
+        }
+        if line.is_empty() && !in_code {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim().to_string()
+}
@@ -0,0 +1,56 @@
+//! `user@host:/path` sources: the remote directory is streamed over `ssh` as a tar archive
+//! and extracted to a temporary directory, so it needs `ssh` locally and `tar` on the host.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+use crate::archive::{self, Extracted};
+use crate::config::Config;
+
+/// A directory on another machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// `host` or `user@host`, as `ssh` takes it.
+    pub host: String,
+    pub path: String,
+}
+
+impl Remote {
+    /// Parses a scp-style `[user@]host:path` source. Anything that exists locally, and
+    /// Windows paths such as `C:\src`, are local.
+    pub fn parse(source: &str) -> Option<Self> {
+        if Path::new(source).exists() {
+            return None;
+        }
+        let (host, path) = source.split_once(':')?;
+        let valid_host = host.len() > 1 && !host.contains(['/', '\\']) && !host.starts_with('-');
+        valid_host.then(|| Self {
+            host: host.to_string(),
+            path: if path.is_empty() { ".".to_string() } else { path.to_string() },
+        })
+    }
+}
+
+/// Copies the files under `remote` into a temporary directory, leaving out
+/// `ignored_directories` and the output folder on the host's side.
+pub fn fetch(remote: &Remote, config: &Config) -> Result<Extracted> {
+    // the remote shell expands `~` only outside of quotes
+    let path = match remote.path.strip_prefix('~') {
+        Some(rest) => format!("~{}", quote(rest)),
+        None => quote(&remote.path),
+    };
+    let mut command = format!("tar -C {} -cf -", path);
+    for name in config.ignored_directories.iter().chain([&config.subfolder]) {
+        command.push_str(&format!(" --exclude={}", quote(name)));
+    }
+    command.push_str(" .");
+
+    archive::extract_output(Command::new("ssh").args(["--", &remote.host, &command]), Path::new(""))
+        .with_context(|| format!("Could not read {}:{} over ssh", remote.host, remote.path))
+}
+
+/// `value` in single quotes for the remote shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
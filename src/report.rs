@@ -0,0 +1,74 @@
+//! `--report`: the estimated tokens of the selected files added up by extension and by
+//! top-level directory, to show what is eating a budget before anything is written.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Groups of files and their tokens, largest first.
+#[derive(Debug, Clone, Default)]
+pub struct TokenReport {
+    pub files: usize,
+    pub tokens: usize,
+    pub by_extension: Vec<Group>,
+    pub by_directory: Vec<Group>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// `.json` or `vendor/`; `(none)` for files without an extension and `(root)` for the
+    /// files at the top of the source.
+    pub name: String,
+    pub files: usize,
+    pub tokens: usize,
+}
+
+impl TokenReport {
+    /// Adds up `files`, as paths relative to the source with `/` separators and their tokens.
+    pub fn build<'a>(files: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        let mut report = Self::default();
+        let mut by_extension: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut by_directory: HashMap<String, (usize, usize)> = HashMap::new();
+        for (path, tokens) in files {
+            report.files += 1;
+            report.tokens += tokens;
+            let name = path.rsplit('/').next().unwrap_or(path);
+            let extension = match name.rsplit_once('.') {
+                Some((stem, extension)) if !stem.is_empty() => format!(".{}", extension.to_lowercase()),
+                _ => "(none)".to_string(),
+            };
+            let directory = match path.split_once('/') {
+                Some((directory, _)) => format!("{}/", directory),
+                None => "(root)".to_string(),
+            };
+            for (groups, key) in [(&mut by_extension, extension), (&mut by_directory, directory)] {
+                let group = groups.entry(key).or_default();
+                group.0 += 1;
+                group.1 += tokens;
+            }
+        }
+        report.by_extension = groups(by_extension);
+        report.by_directory = groups(by_directory);
+        report
+    }
+
+    /// The report as two tables, with each group's share of the tokens.
+    pub fn render(&self) -> String {
+        let mut out = format!("{} files, ~{} tokens\n", self.files, self.tokens);
+        for (title, groups) in [("Extension", &self.by_extension), ("Directory", &self.by_directory)] {
+            let width = groups.iter().map(|group| group.name.len()).chain([title.len()]).max().unwrap_or_default();
+            let _ = writeln!(out, "\n{:<width$}  {:>6}  {:>10}  {:>6}", title, "Files", "Tokens", "Share");
+            for group in groups {
+                let share = group.tokens as f64 * 100.0 / self.tokens.max(1) as f64;
+                let _ = writeln!(out, "{:<width$}  {:>6}  {:>10}  {:>5.1}%", group.name, group.files, group.tokens, share);
+            }
+        }
+        out
+    }
+}
+
+fn groups(groups: HashMap<String, (usize, usize)>) -> Vec<Group> {
+    let mut groups: Vec<Group> =
+        groups.into_iter().map(|(name, (files, tokens))| Group { name, files, tokens }).collect();
+    groups.sort_by(|a, b| b.tokens.cmp(&a.tokens).then_with(|| a.name.cmp(&b.name)));
+    groups
+}
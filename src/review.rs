@@ -0,0 +1,72 @@
+//! `lm review <base>..<head>`: a bundle for reviewing the commits between two revisions,
+//! with the changed files as of `head`, the diff, the commit messages and the unchanged files
+//! that import a changed one.
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::file_filter::FileFilter;
+use crate::focus;
+use crate::git;
+use crate::interrupt;
+use crate::lock;
+use crate::processor::FileProcessor;
+
+/// Writes the review bundle for `range` in the repository at `source` to the usual output
+/// folder there: `context.md`, or `context.zip` with `zip`.
+pub fn run(source: &str, range: &str, config: &Config, verbose: bool) -> Result<()> {
+    let (base, head) = match range.split_once("..") {
+        Some((base, head)) => (base, if head.is_empty() { "HEAD" } else { head }),
+        None => (range, "HEAD"),
+    };
+
+    let source_path = Path::new(source);
+    let worktree = git::Worktree::checkout(source_path, head, "review")?;
+    let checkout = worktree.source()?.to_string_lossy().into_owned();
+
+    let mut config = config.clone();
+    config.diff = None;
+    config.concat = !config.zip;
+    let output_dir = source_path.join(&config.subfolder);
+    let walked = FileFilter::new(Path::new(&checkout), &config).walk(&output_dir)?;
+    let changed = git::changed_files(Path::new(&checkout), base)?;
+    let importers = importers(&walked.files, &changed);
+    if verbose {
+        println!("{} changed files, {} files importing them", changed.len(), importers.len());
+    }
+
+    let selected = walked.only(&changed.union(&importers).cloned().collect());
+
+    // with `diff` set the output gets `changes.patch` and says what it is the diff against
+    config.diff = Some(base.to_string());
+    let processor = FileProcessor::new(&checkout, &config, verbose)?
+        .with_output_dir(output_dir)
+        .with_history(&format!("{}..HEAD", base));
+
+    interrupt::install()?;
+    let _lock = lock::OutputLock::acquire(processor.output_dir())?;
+    processor.prepare_output_directory()?;
+    processor.process_walked(&selected)
+}
+
+/// Which of `files` that are not `changed` have an import line naming a changed file.
+fn importers(files: &[PathBuf], changed: &HashSet<PathBuf>) -> HashSet<PathBuf> {
+    let names: HashSet<String> = changed.iter().filter_map(|path| focus::module_name(path)).collect();
+    if names.is_empty() {
+        return HashSet::new();
+    }
+
+    files
+        .iter()
+        .filter(|file| !changed.contains(*file))
+        .filter(|file| {
+            fs::read(file).is_ok_and(|contents| {
+                focus::imported_names(&String::from_utf8_lossy(&contents)).any(|name| names.contains(name))
+            })
+        })
+        .cloned()
+        .collect()
+}
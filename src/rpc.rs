@@ -0,0 +1,182 @@
+//! JSON-RPC 2.0 protocol spoken by `lm daemon`: one request object per line in, one
+//! response object per line out. Methods are `list`, `pack`, `tree` and `invalidate`, none of
+//! which take parameters. Over stdio the server also sends `ready` and `progress`
+//! notifications and takes `cancel` notifications.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+use crate::daemon::Daemon;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Selection or file access failed while handling a valid request.
+pub const SERVER_ERROR: i64 = -32000;
+/// The request was stopped by a `cancel` notification, as in the Language Server Protocol.
+pub const REQUEST_CANCELLED: i64 = -32800;
+
+/// Stops a request that a `cancel` notification named.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    /// Absent for notifications, which get no response.
+    #[serde(default)]
+    id: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Response<T: Serialize> {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Error>,
+}
+
+#[derive(Serialize)]
+struct Notification<T: Serialize> {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: T,
+}
+
+/// Params of `ready`, sent once before the first response.
+#[derive(Serialize)]
+struct Ready {
+    version: &'static str,
+    source: String,
+    methods: &'static [&'static str],
+}
+
+/// Params of `progress`: how far the request `id` got.
+#[derive(Serialize)]
+struct Progress<'a> {
+    id: &'a Value,
+    done: usize,
+    total: usize,
+}
+
+#[derive(Serialize)]
+struct Error {
+    code: i64,
+    message: String,
+}
+
+/// Result of `tree`.
+#[derive(Serialize)]
+struct Tree {
+    tree: String,
+}
+
+fn success<T: Serialize>(id: Value, result: T) -> serde_json::Result<String> {
+    serde_json::to_string(&Response { jsonrpc: "2.0", id, result: Some(result), error: None })
+}
+
+fn failure(id: Value, code: i64, message: String) -> serde_json::Result<String> {
+    serde_json::to_string(&Response::<()> {
+        jsonrpc: "2.0",
+        id,
+        result: None,
+        error: Some(Error { code, message }),
+    })
+}
+
+fn notification<T: Serialize>(method: &'static str, params: T) -> serde_json::Result<String> {
+    serde_json::to_string(&Notification { jsonrpc: "2.0", method, params })
+}
+
+/// The `ready` notification for a server of `source`.
+pub fn ready(source: &Path) -> serde_json::Result<String> {
+    let source = source.to_string_lossy().into_owned();
+    notification("ready", Ready { version: env!("CARGO_PKG_VERSION"), source, methods: &["list", "pack", "tree", "invalidate"] })
+}
+
+/// The `progress` notification for request `id`, with `done` of `total` files packed.
+pub fn progress(id: &Value, done: usize, total: usize) -> serde_json::Result<String> {
+    notification("progress", Progress { id, done, total })
+}
+
+/// The response to request `id` when it was cancelled before it started.
+pub fn cancelled(id: Value) -> serde_json::Result<String> {
+    failure(id, REQUEST_CANCELLED, Cancelled.to_string())
+}
+
+/// The `id` of a request line, if it parses and has one.
+pub fn request_id(line: &str) -> Option<Value> {
+    serde_json::from_str::<Value>(line).ok()?.get("id").cloned().filter(|id| !id.is_null())
+}
+
+/// A request id written as JSON, so ids of any type compare and hash.
+pub fn id_key(id: &Value) -> String {
+    serde_json::to_string(id).unwrap_or_default()
+}
+
+/// For a `cancel` notification, `{"method": "cancel", "params": {"id": ...}}`, the `id_key`
+/// of the request it names.
+pub fn cancelled_id(line: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    if value.get("method")?.as_str()? != "cancel" {
+        return None;
+    }
+    Some(id_key(value.get("params")?.get("id")?))
+}
+
+/// Handles one request line and returns the response line, or `None` for notifications.
+pub fn handle_line(daemon: &mut Daemon, line: &str) -> serde_json::Result<Option<String>> {
+    handle_line_with_progress(daemon, line, &mut |_, _| Ok(()))
+}
+
+/// `handle_line`, passing `progress` to `pack`; a `Cancelled` error from it answers the
+/// request with `REQUEST_CANCELLED`.
+pub fn handle_line_with_progress(
+    daemon: &mut Daemon,
+    line: &str,
+    progress: &mut dyn FnMut(usize, usize) -> anyhow::Result<()>,
+) -> serde_json::Result<Option<String>> {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return failure(Value::Null, PARSE_ERROR, e.to_string()).map(Some),
+    };
+    let request: Request = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(e) => return failure(Value::Null, INVALID_REQUEST, e.to_string()).map(Some),
+    };
+    let id = request.id.clone().unwrap_or(Value::Null);
+    if request.jsonrpc != "2.0" {
+        return failure(id, INVALID_REQUEST, "jsonrpc must be \"2.0\"".to_string()).map(Some);
+    }
+
+    let response = match request.method.as_str() {
+        "list" => daemon.list().map(|files| success(id.clone(), files)),
+        "pack" => daemon.pack_with_progress(progress).map(|packed| success(id.clone(), packed)),
+        "tree" => daemon.tree().map(|tree| success(id.clone(), Tree { tree })),
+        "invalidate" => {
+            daemon.invalidate();
+            Ok(success(id.clone(), true))
+        }
+        other => Ok(failure(id.clone(), METHOD_NOT_FOUND, format!("unknown method {:?}", other))),
+    };
+    let response = match response {
+        Ok(response) => response?,
+        Err(e) if e.downcast_ref::<Cancelled>().is_some() => failure(id, REQUEST_CANCELLED, e.to_string())?,
+        Err(e) => failure(id, SERVER_ERROR, format!("{:#}", e))?,
+    };
+
+    Ok(request.id.is_some().then_some(response))
+}
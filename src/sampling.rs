@@ -0,0 +1,118 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How `sample` spreads the picked files over the tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleStrata {
+    /// Pick uniformly from all files
+    #[default]
+    None,
+    /// Pick from every directory in proportion to its file count
+    Directory,
+    /// Pick from every file extension in proportion to its file count
+    Language,
+}
+
+/// SplitMix64: tiny, fast and good enough to shuffle file lists reproducibly.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Seed used when none is configured; printed so the sample can be reproduced.
+pub fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+        ^ u64::from(std::process::id())
+}
+
+/// Picks `count` of the files at `relative_paths` (relative to the source) and returns their
+/// indices in ascending order. With strata, every group gets a share proportional to its
+/// size, and every group gets at least one file while `count` allows.
+pub fn sample(relative_paths: &[&Path], count: usize, strata: SampleStrata, seed: u64) -> Vec<usize> {
+    if count >= relative_paths.len() {
+        return (0..relative_paths.len()).collect();
+    }
+
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, path) in relative_paths.iter().enumerate() {
+        let key = match strata {
+            SampleStrata::None => String::new(),
+            SampleStrata::Directory => path.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default(),
+            SampleStrata::Language => path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        };
+        groups.entry(key).or_default().push(i);
+    }
+
+    let quotas = allocate(&groups.values().map(Vec::len).collect::<Vec<_>>(), count);
+    let mut rng = Rng(seed);
+    let mut picked = Vec::with_capacity(count);
+    for (mut members, quota) in groups.into_values().zip(quotas) {
+        // partial Fisher-Yates: the first `quota` slots end up a uniform sample
+        for slot in 0..quota {
+            let j = slot + rng.below(members.len() - slot);
+            members.swap(slot, j);
+        }
+        picked.extend_from_slice(&members[..quota]);
+    }
+
+    picked.sort_unstable();
+    picked
+}
+
+/// Splits `count` over groups of the given sizes: one per group while there is enough to go
+/// around, the rest by largest remainder of each group's proportional share.
+fn allocate(sizes: &[usize], count: usize) -> Vec<usize> {
+    let total: usize = sizes.iter().sum();
+    let mut quotas = vec![0; sizes.len()];
+    let mut remaining = count;
+
+    if count >= sizes.len() {
+        quotas.iter_mut().for_each(|q| *q = 1);
+        remaining -= sizes.len();
+    }
+
+    let capacity: Vec<usize> = sizes.iter().zip(&quotas).map(|(size, q)| size - q).collect();
+    let spare: usize = capacity.iter().sum();
+    let mut remainders = Vec::with_capacity(sizes.len());
+    let mut assigned = 0;
+    for (i, &cap) in capacity.iter().enumerate() {
+        let share = remaining * cap;
+        quotas[i] += share / spare.max(1);
+        assigned += share / spare.max(1);
+        remainders.push((share % spare.max(1), i));
+    }
+
+    // hand out what rounding left over, largest remainder first
+    remainders.sort_by(|a, b| b.cmp(a));
+    for (_, i) in remainders.into_iter().cycle().take(total) {
+        if assigned == remaining {
+            break;
+        }
+        if quotas[i] < sizes[i] {
+            quotas[i] += 1;
+            assigned += 1;
+        }
+    }
+
+    quotas
+}
@@ -0,0 +1,243 @@
+//! `search_index.json`: a full-text index of the files in an output, written next to it with
+//! `search_index: true`, so `lm search` and agent frameworks can find files by keyword
+//! without reading every one. Files are ranked with BM25; without an index, `lm search`
+//! scans the output instead. The index is written here rather than with tantivy, as it has
+//! to build without the `native` feature and stay plain JSON that other tools can read.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+pub const SEARCH_INDEX_JSON: &str = "search_index.json";
+
+/// Version of the index format, bumped when a change would make older indexes rank wrong.
+pub const SEARCH_INDEX_VERSION: u32 = 1;
+
+/// BM25 parameters: how fast repeated terms stop counting, and how much file length does.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchIndex {
+    pub version: u32,
+    pub files: Vec<IndexedFile>,
+    /// Every term, with the files that have it as indexes into `files` and how often they do.
+    pub terms: BTreeMap<String, Vec<(u32, u32)>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedFile {
+    /// Path relative to the source with `/` separators, as the output shows it.
+    pub path: String,
+    /// Name of the file in an output folder or zip.
+    pub output_name: String,
+    /// Number of terms in the file.
+    pub length: u32,
+}
+
+/// Lines shown for each file `lm search` prints.
+pub const MAX_SNIPPETS: usize = 3;
+
+/// A file that matches a query.
+#[derive(Debug, Clone)]
+pub struct Hit<'a> {
+    pub file: &'a IndexedFile,
+    pub score: f64,
+}
+
+/// A file of an output that matches a query, with the lines that do.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub path: String,
+    pub output_name: String,
+    /// BM25 score, if the output has an index to rank it with.
+    pub score: Option<f64>,
+    /// Numbers, from 1, and text of the lines that match.
+    pub lines: Vec<(usize, String)>,
+}
+
+impl SearchIndex {
+    /// Indexes `files`, as path, output name and contents.
+    pub fn build<'a>(files: impl IntoIterator<Item = (String, String, &'a str)>) -> Self {
+        let mut index = Self { version: SEARCH_INDEX_VERSION, files: Vec::new(), terms: BTreeMap::new() };
+        for (path, output_name, contents) in files {
+            let id = index.files.len() as u32;
+            let mut counts: BTreeMap<String, u32> = BTreeMap::new();
+            let mut length = 0;
+            for term in terms(contents).chain(terms(&path)) {
+                *counts.entry(term).or_default() += 1;
+                length += 1;
+            }
+            for (term, count) in counts {
+                index.terms.entry(term).or_default().push((id, count));
+            }
+            index.files.push(IndexedFile { path, output_name, length });
+        }
+        index
+    }
+
+    /// The index in `output_dir`.
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join(SEARCH_INDEX_JSON);
+        let contents = fs::read_to_string(&path).with_context(|| {
+            format!("Could not read {}, run lm with search_index: true to create it", path.display())
+        })?;
+        let index: Self =
+            serde_json::from_str(&contents).with_context(|| format!("Could not parse {}", path.display()))?;
+        if index.version != SEARCH_INDEX_VERSION {
+            bail!("{} is in index version {}, run lm again to rebuild it", path.display(), index.version);
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        fs::write(output_dir.join(SEARCH_INDEX_JSON), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The files with any term of `query`, best first, at most `limit` of them.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<Hit<'_>> {
+        let count = self.files.len() as f64;
+        let average_length = self.files.iter().map(|file| file.length as f64).sum::<f64>() / count.max(1.0);
+        let mut scores = vec![0.0; self.files.len()];
+        let mut query_terms: Vec<String> = terms(query).collect();
+        query_terms.sort();
+        query_terms.dedup();
+        for term in &query_terms {
+            let Some(postings) = self.terms.get(term) else {
+                continue;
+            };
+            let matching = postings.len() as f64;
+            let idf = ((count - matching + 0.5) / (matching + 0.5) + 1.0).ln();
+            for &(id, frequency) in postings {
+                let frequency = frequency as f64;
+                let length = self.files[id as usize].length as f64 / average_length.max(1.0);
+                scores[id as usize] += idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * length));
+            }
+        }
+
+        let mut hits: Vec<Hit> = scores
+            .into_iter()
+            .enumerate()
+            .filter(|(_, score)| *score > 0.0)
+            .map(|(id, score)| Hit { file: &self.files[id], score })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.file.path.cmp(&b.file.path)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+/// What `lm search` prints for `matches`: each file with its output name and score, and
+/// its first `MAX_SNIPPETS` matching lines.
+pub fn render(matches: &[Match]) -> String {
+    let mut out = String::new();
+    for found in matches {
+        match found.score {
+            Some(score) => writeln!(out, "{}  ({})  {:.3}", found.path, found.output_name, score).ok(),
+            None => writeln!(out, "{}  ({})", found.path, found.output_name).ok(),
+        };
+        for (number, line) in found.lines.iter().take(MAX_SNIPPETS) {
+            writeln!(out, "  {:>5}: {}", number, line).ok();
+        }
+        if found.lines.len() > MAX_SNIPPETS {
+            writeln!(out, "         ... and {} more lines", found.lines.len() - MAX_SNIPPETS).ok();
+        }
+    }
+    out
+}
+
+/// The lines of `text` that `matches` takes.
+pub fn matching_lines(text: &str, matches: impl Fn(&str) -> bool) -> Vec<(usize, String)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| matches(line))
+        .map(|(i, line)| (i + 1, line.trim().to_string()))
+        .collect()
+}
+
+/// The terms of `text`: its words, lowercased, and the parts of `snake_case` and `camelCase`
+/// identifiers, so that `config` finds `load_config` and `ConfigLoader`.
+pub fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|word| !word.is_empty()).flat_map(|word| {
+        let parts = identifier_parts(word);
+        let whole = (parts.len() > 1).then(|| word.to_lowercase());
+        whole.into_iter().chain(parts)
+    })
+}
+
+fn identifier_parts(word: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    for piece in word.split('_').filter(|piece| !piece.is_empty()) {
+        let mut part = String::new();
+        let mut previous: Option<char> = None;
+        for c in piece.chars() {
+            if c.is_uppercase() && previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) {
+                parts.push(std::mem::take(&mut part));
+            }
+            part.extend(c.to_lowercase());
+            previous = Some(c);
+        }
+        parts.push(part);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> SearchIndex {
+        SearchIndex::build([
+            ("src/config.rs".to_string(), "src^config.rs".to_string(), "fn load_config() {}\nfn parse_config() {}\n"),
+            ("src/main.rs".to_string(), "src^main.rs".to_string(), "fn main() { load_config(); run(); }\n"),
+            ("README.md".to_string(), "README.md".to_string(), "How to run the tool.\n"),
+        ])
+    }
+
+    #[test]
+    fn terms_split_identifiers_into_their_parts() {
+        let split: Vec<String> = terms("load_config ConfigLoader parseHTTP2Response x").collect();
+        assert_eq!(
+            split,
+            ["load_config", "load", "config", "configloader", "config", "loader", "parsehttp2response", "parse", "http2", "response", "x"]
+        );
+        assert_eq!(terms("a.b-c (d)").collect::<Vec<_>>(), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn search_ranks_files_with_more_matches_first() {
+        let index = index();
+        let hits: Vec<&str> = index.search("config", 10).iter().map(|hit| hit.file.path.as_str()).collect();
+        // `src/config.rs` has the term in its path and twice in its contents
+        assert_eq!(hits, ["src/config.rs", "src/main.rs"]);
+        assert!(index.search("config", 10).windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!(index.search("config", 1).len(), 1);
+        assert!(index.search("missing", 10).is_empty());
+    }
+
+    #[test]
+    fn search_weighs_rare_terms_higher() {
+        let index = index();
+        let hits = index.search("run parse", 10);
+        // `parse` is in one file, `run` in two
+        assert_eq!(hits[0].file.path, "src/config.rs");
+        assert_eq!(hits.len(), 3);
+    }
+
+    #[test]
+    fn render_prints_paths_scores_and_snippets() {
+        let lines = (1..=5).map(|n| (n, format!("line {}", n))).collect();
+        let matches = [
+            Match { path: "src/a.rs".to_string(), output_name: "src^a.rs".to_string(), score: Some(1.5), lines },
+            Match { path: "b.md".to_string(), output_name: "b.md".to_string(), score: None, lines: vec![(7, "b".to_string())] },
+        ];
+        assert_eq!(
+            render(&matches),
+            "src/a.rs  (src^a.rs)  1.500\n      1: line 1\n      2: line 2\n      3: line 3\n         ... and 2 more lines\nb.md  (b.md)\n      7: b\n"
+        );
+    }
+}
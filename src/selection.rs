@@ -0,0 +1,194 @@
+//! Which entries of the source go into the output by their path and contents. The walker in
+//! `file_filter` and `virtual_fs` both decide through `Selection`, so `lm` and the
+//! WebAssembly build pick the same files.
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use regex_automata::meta::Regex;
+use std::path::Path;
+
+use crate::classify;
+use crate::config::{Config, IgnoredDirectories, LfsPointers};
+use crate::lfs;
+use crate::terraform;
+
+/// One pattern of a `Gitignore`.
+struct Pattern {
+    matcher: GlobMatcher,
+    negated: bool,
+    only_dir: bool,
+}
+
+/// Patterns with gitignore semantics, matched against `/`-separated paths relative to the
+/// directory they apply to: the last pattern that matches decides, `!` re-includes, a trailing
+/// `/` only matches directories and a pattern without an inner `/` matches at any depth.
+#[derive(Default)]
+pub struct Gitignore {
+    patterns: Vec<Pattern>,
+}
+
+impl Gitignore {
+    /// Parses the patterns of a `.gitignore` file, skipping lines that are not valid globs.
+    pub fn parse(text: &str) -> Self {
+        let mut gitignore = Self::default();
+        for line in text.lines() {
+            let _ = gitignore.add(line);
+        }
+        gitignore
+    }
+
+    /// Adds one line; blank lines and `#` comments are skipped.
+    pub fn add(&mut self, line: &str) -> Result<()> {
+        let mut line = match line.ends_with("\\ ") {
+            true => line,
+            false => line.trim_end(),
+        };
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let negated = line.starts_with('!');
+        // `\!` and `\#` escape a leading `!` or `#`
+        if negated || line.starts_with("\\!") || line.starts_with("\\#") {
+            line = &line[1..];
+        }
+        let anchored = line.starts_with('/');
+        line = line.trim_start_matches('/');
+        let only_dir = line.ends_with('/');
+        line = line.trim_end_matches('/');
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let mut glob = line.to_string();
+        if !anchored && !glob.contains('/') {
+            glob = format!("**/{}", glob);
+        }
+        // `dir/**` matches what is inside `dir`, not `dir` itself
+        if glob.ends_with("/**") {
+            glob.push_str("/*");
+        }
+        let matcher = GlobBuilder::new(&glob).literal_separator(true).backslash_escape(true).build()?.compile_matcher();
+        self.patterns.push(Pattern { matcher, negated, only_dir });
+        Ok(())
+    }
+
+    /// Whether the entry at `relative` is ignored (`Some(true)`) or re-included
+    /// (`Some(false)`), or `None` if no pattern matches it. Its parents are not checked.
+    pub fn matched(&self, relative: &str, is_dir: bool) -> Option<bool> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| (is_dir || !pattern.only_dir) && pattern.matcher.is_match(relative))
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+/// The `exclude`, `ignored_directories`, `include_tests`, `terraform`, `max_filesize`, `lfs`
+/// and `exclude_content_patterns` settings of a run, compiled once.
+pub struct Selection {
+    excludes: Gitignore,
+    ignored_directories: IgnoredDirectories,
+    content_patterns: Vec<Regex>,
+    include_tests: bool,
+    terraform: bool,
+    max_filesize: Option<u64>,
+    skip_lfs: bool,
+}
+
+impl Selection {
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut excludes = Gitignore::default();
+        for pattern in &config.exclude {
+            excludes.add(pattern).with_context(|| format!("Invalid exclude pattern {:?}", pattern))?;
+        }
+        let content_patterns = config
+            .exclude_content_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).with_context(|| format!("Invalid exclude_content_patterns entry {:?}", pattern))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            excludes,
+            ignored_directories: IgnoredDirectories::new(&config.ignored_directories),
+            content_patterns,
+            include_tests: config.include_tests,
+            terraform: config.terraform,
+            max_filesize: config.max_filesize,
+            skip_lfs: config.lfs == LfsPointers::Skip,
+        })
+    }
+
+    /// Whether the entry at `relative` (to the source, `/`-separated) is kept by its path.
+    /// Its parents are not checked.
+    pub fn keeps(&self, relative: &str, is_dir: bool) -> bool {
+        if self.excludes.matched(relative, is_dir) == Some(true) {
+            return false;
+        }
+        if self.terraform {
+            let name = relative.rsplit('/').next().unwrap_or_default();
+            let ignored = match is_dir {
+                true => terraform::IGNORED_DIRECTORIES.contains(&name),
+                false => terraform::is_state_file(Path::new(relative)),
+            };
+            if ignored {
+                return false;
+            }
+        }
+        !self.ignored_directories.matches(relative)
+            && (self.include_tests || !classify::is_test(Path::new(relative), is_dir))
+    }
+
+    /// Whether a file of `len` bytes is within `max_filesize`.
+    pub fn keeps_size(&self, len: u64) -> bool {
+        self.max_filesize.is_none_or(|max| len <= max)
+    }
+
+    /// Whether `keeps_contents` has anything to check in a file of `len` bytes.
+    pub fn reads_contents(&self, len: u64) -> bool {
+        !self.content_patterns.is_empty() || (self.skip_lfs && len <= lfs::MAX_POINTER_SIZE)
+    }
+
+    /// Whether a file with `contents` is kept: it is not a Git LFS pointer left out by `lfs`
+    /// and matches none of `exclude_content_patterns`.
+    pub fn keeps_contents(&self, contents: &[u8]) -> bool {
+        let pointer = self.skip_lfs
+            && contents.len() as u64 <= lfs::MAX_POINTER_SIZE
+            && lfs::Pointer::parse(contents).is_some();
+        !pointer && !self.content_patterns.iter().any(|pattern| pattern.is_match(contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gitignore(lines: &[&str]) -> Gitignore {
+        let mut gitignore = Gitignore::default();
+        for line in lines {
+            gitignore.add(line).unwrap();
+        }
+        gitignore
+    }
+
+    #[test]
+    fn unanchored_patterns_match_at_any_depth() {
+        let gitignore = gitignore(&["*.log", "/build", "docs/*.md"]);
+        assert_eq!(gitignore.matched("a/b/trace.log", false), Some(true));
+        assert_eq!(gitignore.matched("build", true), Some(true));
+        assert_eq!(gitignore.matched("src/build", true), None);
+        assert_eq!(gitignore.matched("docs/intro.md", false), Some(true));
+        assert_eq!(gitignore.matched("docs/guide/intro.md", false), None);
+    }
+
+    #[test]
+    fn later_patterns_win_and_dir_patterns_skip_files() {
+        let gitignore = gitignore(&["*.md", "!README.md", "out/", "# comment", "", "tmp/**"]);
+        assert_eq!(gitignore.matched("notes.md", false), Some(true));
+        assert_eq!(gitignore.matched("README.md", false), Some(false));
+        assert_eq!(gitignore.matched("out", true), Some(true));
+        assert_eq!(gitignore.matched("out", false), None);
+        assert_eq!(gitignore.matched("tmp", true), None);
+        assert_eq!(gitignore.matched("tmp/a/b", false), Some(true));
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/bcherb2/lmprep/releases/latest";
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+const USER_AGENT: &str = concat!("lmprep/", env!("CARGO_PKG_VERSION"));
+const MAX_DOWNLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Checks GitHub for a newer release and, unless `check_only` is set, downloads the archive
+/// for this platform, verifies it against the release's SHA256SUMS and replaces the running
+/// executable with the binary inside it.
+pub fn run(check_only: bool, force: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = serde_json::from_str(&download_string(RELEASES_URL)?)
+        .context("Could not parse the GitHub release response")?;
+    let latest = release.tag_name.trim_start_matches('v');
+
+    if !force && !is_newer(latest, current) {
+        println!("lm {} is up to date", current);
+        return Ok(());
+    }
+
+    if check_only {
+        println!("lm {} is available (installed: {}), run `lm self-update` to upgrade", latest, current);
+        return Ok(());
+    }
+
+    let exe = env::current_exe()?;
+    if exe.components().any(|c| c.as_os_str() == "site-packages") {
+        bail!("lm was installed with pip, run `pip install --upgrade lmprep` instead");
+    }
+
+    let asset_name = platform_asset()?;
+    let asset = find_asset(&release, asset_name)?;
+    let checksums = find_asset(&release, CHECKSUMS_ASSET).map_err(|_| {
+        anyhow!("Release {} has no {}, refusing to install an unverified binary", release.tag_name, CHECKSUMS_ASSET)
+    })?;
+
+    println!("Downloading {}...", asset.name);
+    let archive = download(&asset.browser_download_url)?;
+    let expected = expected_checksum(&download_string(&checksums.browser_download_url)?, asset_name)?;
+    let actual = to_hex(&Sha256::digest(&archive));
+    if !actual.eq_ignore_ascii_case(&expected) {
+        bail!("Checksum mismatch for {}: expected {}, got {}", asset_name, expected, actual);
+    }
+
+    let binary = extract_binary(&archive, asset_name)?;
+    replace_executable(&exe, &binary)?;
+    println!("Updated lm {} -> {}", current, latest);
+    Ok(())
+}
+
+/// Name of the release archive built for this platform by the release workflow.
+fn platform_asset() -> Result<&'static str> {
+    match (env::consts::OS, env::consts::ARCH) {
+        ("linux", "x86_64") => Ok("lm-manylinux2014_x86_64.tar.gz"),
+        ("windows", "x86_64") => Ok("lm-win_amd64.zip"),
+        ("macos", _) => Ok("lm-macosx_universal2.tar.gz"),
+        (os, arch) => bail!("No prebuilt lm binary for {} {}", os, arch),
+    }
+}
+
+fn find_asset<'r>(release: &'r Release, name: &str) -> Result<&'r Asset> {
+    release
+        .assets
+        .iter()
+        .find(|a| a.name == name)
+        .ok_or_else(|| anyhow!("Release {} has no asset named {}", release.tag_name, name))
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    let response = ureq::get(url).set("User-Agent", USER_AGENT).call()?;
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_DOWNLOAD_BYTES).read_to_end(&mut body)?;
+    Ok(body)
+}
+
+fn download_string(url: &str) -> Result<String> {
+    Ok(String::from_utf8(download(url)?)?)
+}
+
+/// Finds `asset_name` in a `sha256sum`-style listing (`<hex>  <name>`).
+fn expected_checksum(checksums: &str, asset_name: &str) -> Result<String> {
+    checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim().trim_start_matches('*') == asset_name)
+        .map(|(hash, _)| hash.to_string())
+        .ok_or_else(|| anyhow!("{} does not list {}", CHECKSUMS_ASSET, asset_name))
+}
+
+fn extract_binary(archive: &[u8], asset_name: &str) -> Result<Vec<u8>> {
+    let binary_name = if cfg!(windows) { "lm.exe" } else { "lm" };
+    let mut binary = Vec::new();
+
+    if asset_name.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive))?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            if Path::new(entry.name()).file_name().is_some_and(|n| n == binary_name) {
+                entry.read_to_end(&mut binary)?;
+                return Ok(binary);
+            }
+        }
+    } else {
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if entry.path()?.file_name().is_some_and(|n| n == binary_name) {
+                entry.read_to_end(&mut binary)?;
+                return Ok(binary);
+            }
+        }
+    }
+
+    bail!("{} does not contain {}", asset_name, binary_name)
+}
+
+/// Writes the new binary next to the current one and renames it into place. Windows cannot
+/// overwrite a running executable, so the old one is moved aside first.
+fn replace_executable(exe: &Path, binary: &[u8]) -> Result<()> {
+    let file_name = exe.file_name().ok_or_else(|| anyhow!("Invalid executable path {}", exe.display()))?;
+    let staged = exe.with_file_name(format!(".{}.new", file_name.to_string_lossy()));
+    fs::write(&staged, binary)
+        .with_context(|| format!("Could not write to {}", staged.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("exe.old");
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+    }
+
+    fs::rename(&staged, exe)?;
+    Ok(())
+}
+
+/// Compares dotted numeric versions; anything unparsable counts as older.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    fn parse(version: &str) -> Vec<u64> {
+        version
+            .split(['.', '-'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    }
+    parse(candidate) > parse(current)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,356 @@
+//! A writer for SQLite database files, enough for the `sqlite` output: tables without indexes,
+//! written once from rows in memory. SQLite itself, or anything that reads its format, opens
+//! the result; lmprep does not link it.
+
+use anyhow::{ensure, Result};
+use std::io::Write;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Size of the database header at the start of the first page.
+const HEADER_SIZE: usize = 100;
+
+const LEAF_TABLE: u8 = 0x0d;
+const INTERIOR_TABLE: u8 = 0x05;
+
+/// `SQLITE_VERSION_NUMBER` of the version whose format this writes.
+const SQLITE_VERSION: u32 = 3_040_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Text(String),
+}
+
+/// A table and its rows. Rows get rowids from 1 in order; an `INTEGER PRIMARY KEY` column is
+/// the rowid and is stored as `Value::Null`.
+pub struct Table {
+    pub name: String,
+    /// The `CREATE TABLE` statement.
+    pub sql: String,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// Writes a database of `tables` to `out`.
+pub fn write(out: &mut impl Write, tables: &[Table]) -> Result<()> {
+    // page 1 holds the schema table, the others follow it
+    let mut pages = vec![Vec::new()];
+    let mut schema = Vec::new();
+    for table in tables {
+        let root = write_table(&mut pages, &table.rows);
+        schema.push(vec![
+            Value::Text("table".to_string()),
+            Value::Text(table.name.clone()),
+            Value::Text(table.name.clone()),
+            Value::Integer(root as i64),
+            Value::Text(table.sql.clone()),
+        ]);
+    }
+    let cells: Vec<Vec<u8>> =
+        schema.iter().enumerate().map(|(i, row)| leaf_cell(i as i64 + 1, &record(row), &mut pages)).collect();
+    let used: usize = cells.iter().map(|cell| cell.len() + 2).sum();
+    ensure!(HEADER_SIZE + 8 + used <= PAGE_SIZE, "The schema of the database does not fit on one page");
+    pages[0] = vec![0; HEADER_SIZE];
+    pages[0].extend(leaf_page(&cells, HEADER_SIZE));
+    let page_count = pages.len() as u32;
+    write_header(&mut pages[0], page_count);
+
+    for page in &pages {
+        out.write_all(page)?;
+    }
+    Ok(())
+}
+
+fn write_header(page: &mut [u8], page_count: u32) {
+    page[..16].copy_from_slice(b"SQLite format 3\0");
+    page[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+    // file format versions, no reserved bytes, payload fractions
+    page[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    page[24..28].copy_from_slice(&1u32.to_be_bytes());
+    page[28..32].copy_from_slice(&page_count.to_be_bytes());
+    // schema cookie and schema format
+    page[40..44].copy_from_slice(&1u32.to_be_bytes());
+    page[44..48].copy_from_slice(&4u32.to_be_bytes());
+    // UTF-8 text
+    page[56..60].copy_from_slice(&1u32.to_be_bytes());
+    page[92..96].copy_from_slice(&1u32.to_be_bytes());
+    page[96..100].copy_from_slice(&SQLITE_VERSION.to_be_bytes());
+}
+
+/// Adds the b-tree of `rows` to `pages` and returns the number of its root page.
+fn write_table(pages: &mut Vec<Vec<u8>>, rows: &[Vec<Value>]) -> u32 {
+    // leaves, as (page number, largest rowid)
+    let mut children = Vec::new();
+    let mut cells = Vec::new();
+    let mut used = 8;
+    for (i, row) in rows.iter().enumerate() {
+        let rowid = i as i64 + 1;
+        let cell = leaf_cell(rowid, &record(row), pages);
+        if used + cell.len() + 2 > PAGE_SIZE {
+            pages.push(leaf_page(&cells, 0));
+            children.push((pages.len() as u32, rowid - 1));
+            cells.clear();
+            used = 8;
+        }
+        used += cell.len() + 2;
+        cells.push(cell);
+    }
+    pages.push(leaf_page(&cells, 0));
+    children.push((pages.len() as u32, rows.len() as i64));
+
+    while children.len() > 1 {
+        children = interior_level(pages, &children);
+    }
+    children[0].0
+}
+
+/// Adds the interior pages above `children` and returns them the same way.
+fn interior_level(pages: &mut Vec<Vec<u8>>, children: &[(u32, i64)]) -> Vec<(u32, i64)> {
+    let mut parents = Vec::new();
+    let mut start = 0;
+    while start < children.len() {
+        // every child but the rightmost takes a cell of up to 13 bytes and its pointer
+        let mut end = start + 1;
+        let mut used = 12;
+        while end < children.len() && used + 15 <= PAGE_SIZE {
+            used += 15;
+            end += 1;
+        }
+        let group = &children[start..end];
+        let (last, last_key) = group[group.len() - 1];
+        let cells: Vec<Vec<u8>> = group[..group.len() - 1]
+            .iter()
+            .map(|(page, key)| {
+                let mut cell = page.to_be_bytes().to_vec();
+                cell.extend(varint(*key as u64));
+                cell
+            })
+            .collect();
+        let mut page = vec![INTERIOR_TABLE, 0, 0];
+        page.extend((cells.len() as u16).to_be_bytes());
+        page.extend([0, 0, 0]);
+        page.extend(last.to_be_bytes());
+        pages.push(fill_page(page, &cells, 0));
+        parents.push((pages.len() as u32, last_key));
+        start = end;
+    }
+    parents
+}
+
+/// A leaf page holding `cells`, for a page whose b-tree starts `offset` bytes in.
+fn leaf_page(cells: &[Vec<u8>], offset: usize) -> Vec<u8> {
+    let mut page = vec![LEAF_TABLE, 0, 0];
+    page.extend((cells.len() as u16).to_be_bytes());
+    page.extend([0, 0, 0]);
+    fill_page(page, cells, offset)
+}
+
+/// Completes a page from its b-tree `header`: the cell pointers after it, the cells at the end
+/// of the page and the start of the cell content in the header.
+fn fill_page(mut page: Vec<u8>, cells: &[Vec<u8>], offset: usize) -> Vec<u8> {
+    let size = PAGE_SIZE - offset;
+    let mut content = size;
+    let mut pointers = Vec::new();
+    for cell in cells {
+        content -= cell.len();
+        pointers.extend(((content + offset) as u16).to_be_bytes());
+    }
+    page.extend(pointers);
+    page.resize(size, 0);
+    let mut at = size;
+    for cell in cells {
+        at -= cell.len();
+        page[at..at + cell.len()].copy_from_slice(cell);
+    }
+    page[5..7].copy_from_slice(&((content + offset) as u16).to_be_bytes());
+    page
+}
+
+/// The leaf cell of the row `rowid` with `payload`. What does not fit in the cell goes to
+/// overflow pages, added to `pages`.
+fn leaf_cell(rowid: i64, payload: &[u8], pages: &mut Vec<Vec<u8>>) -> Vec<u8> {
+    let mut cell = varint(payload.len() as u64);
+    cell.extend(varint(rowid as u64));
+
+    let usable = PAGE_SIZE;
+    let max_local = usable - 35;
+    if payload.len() <= max_local {
+        cell.extend(payload);
+        return cell;
+    }
+    let min_local = (usable - 12) * 32 / 255 - 23;
+    let local = min_local + (payload.len() - min_local) % (usable - 4);
+    let local = if local <= max_local { local } else { min_local };
+    cell.extend(&payload[..local]);
+
+    let chunks: Vec<&[u8]> = payload[local..].chunks(usable - 4).collect();
+    let first = pages.len() as u32 + 1;
+    cell.extend(first.to_be_bytes());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let next = if i + 1 < chunks.len() { first + i as u32 + 1 } else { 0 };
+        let mut page = next.to_be_bytes().to_vec();
+        page.extend(*chunk);
+        page.resize(PAGE_SIZE, 0);
+        pages.push(page);
+    }
+    cell
+}
+
+/// `values` in the record format: a header of serial types, then the values.
+fn record(values: &[Value]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for value in values {
+        match value {
+            Value::Null => types.extend(varint(0)),
+            Value::Integer(n) => {
+                types.extend(varint(6));
+                body.extend(n.to_be_bytes());
+            }
+            Value::Text(text) => {
+                types.extend(varint(13 + 2 * text.len() as u64));
+                body.extend(text.as_bytes());
+            }
+        }
+    }
+    // the header size counts itself
+    let mut header_size = types.len() + 1;
+    if varint(header_size as u64).len() > 1 {
+        header_size += varint(header_size as u64 + 1).len() - 1;
+    }
+    let mut record = varint(header_size as u64);
+    record.extend(types);
+    record.extend(body);
+    record
+}
+
+/// SQLite's variable-length integer: big-endian, 7 bits per byte and all 8 in a ninth.
+fn varint(value: u64) -> Vec<u8> {
+    if value > 0x00ff_ffff_ffff_ffff {
+        let mut bytes: Vec<u8> = (1..9).rev().map(|i| ((value >> (8 + 7 * (i - 1))) as u8 & 0x7f) | 0x80).collect();
+        bytes.push(value as u8);
+        return bytes;
+    }
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    /// Writes `tables` to a database file named after `name`.
+    fn database(name: &str, tables: &[Table]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lmprep-test-{}-{}.db", name, std::process::id()));
+        let mut out = Vec::new();
+        write(&mut out, tables).unwrap();
+        std::fs::write(&path, out).unwrap();
+        path
+    }
+
+    /// What the `sqlite3` shell prints for `sql` on the database at `path`, or `None` where it
+    /// is not installed.
+    fn query(path: &Path, sql: &str) -> Option<String> {
+        let output = match Command::new("sqlite3").arg(path).arg(sql).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("sqlite3 is not installed, not checking {}", path.display());
+                return None;
+            }
+            Err(e) => panic!("could not run sqlite3: {}", e),
+        };
+        assert!(output.status.success(), "sqlite3 failed: {}", String::from_utf8_lossy(&output.stderr));
+        Some(String::from_utf8(output.stdout).unwrap().trim_end().to_string())
+    }
+
+    fn text_table(name: &str, texts: impl IntoIterator<Item = String>) -> Table {
+        Table {
+            name: name.to_string(),
+            sql: format!("CREATE TABLE {} (id INTEGER PRIMARY KEY, body TEXT)", name),
+            rows: texts.into_iter().map(|text| vec![Value::Null, Value::Text(text)]).collect(),
+        }
+    }
+
+    #[test]
+    fn varint_matches_the_sqlite_encoding() {
+        assert_eq!(varint(0), [0x00]);
+        assert_eq!(varint(0x7f), [0x7f]);
+        assert_eq!(varint(0x80), [0x81, 0x00]);
+        assert_eq!(varint(0x3fff), [0xff, 0x7f]);
+        assert_eq!(varint(0x4000), [0x81, 0x80, 0x00]);
+        assert_eq!(varint(u64::MAX), [0xff; 9]);
+        assert_eq!(varint(0x00ff_ffff_ffff_ffff).len(), 8);
+        assert_eq!(varint(0x0100_0000_0000_0000).len(), 9);
+    }
+
+    #[test]
+    fn record_header_counts_its_own_size() {
+        assert_eq!(record(&[Value::Null, Value::Integer(-1)]), [3, 0, 6, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        // 127 text columns need a two-byte header size
+        let record = record(&vec![Value::Text(String::new()); 127]);
+        assert_eq!(&record[..2], varint(129).as_slice());
+    }
+
+    #[test]
+    fn contents_larger_than_a_page_go_to_overflow_pages() {
+        // around where a payload stops fitting in its cell, and over several overflow pages
+        let sizes: Vec<usize> = (4040..4100).chain([4096 * 3, 100_000]).collect();
+        let texts: Vec<String> =
+            sizes.iter().map(|size| (0..*size).map(|i| (b'a' + (i % 26) as u8) as char).collect()).collect();
+        let path = database("overflow", &[text_table("blobs", texts.clone())]);
+        let checked = query(&path, "PRAGMA integrity_check;");
+        let lengths = query(&path, "SELECT group_concat(length(body)) FROM blobs;");
+        let tail = query(&path, "SELECT substr(body, -5) FROM blobs WHERE id = (SELECT max(id) FROM blobs);");
+        std::fs::remove_file(&path).unwrap();
+        let Some(checked) = checked else {
+            return;
+        };
+
+        assert_eq!(checked, "ok");
+        let expected: Vec<String> = sizes.iter().map(usize::to_string).collect();
+        assert_eq!(lengths.unwrap(), expected.join(","));
+        let last = texts.last().unwrap();
+        assert_eq!(tail.unwrap(), last[last.len() - 5..]);
+    }
+
+    #[test]
+    fn tables_over_many_pages_get_interior_pages() {
+        // about 19 rows a leaf, so over a thousand leaves and two levels of interior pages
+        let texts = (0..20_000).map(|i| format!("{:0>200}", i));
+        let path = database("pages", &[text_table("first", texts), text_table("second", ["after".to_string()])]);
+        let checked = query(&path, "PRAGMA integrity_check;");
+        let rows = query(&path, "SELECT count(*), min(id), max(id), sum(CAST(body AS INTEGER)) FROM first;");
+        let row = query(&path, "SELECT body FROM first WHERE id = 12345;");
+        let second = query(&path, "SELECT * FROM second;");
+        std::fs::remove_file(&path).unwrap();
+        let Some(checked) = checked else {
+            return;
+        };
+
+        assert_eq!(checked, "ok");
+        assert_eq!(rows.unwrap(), format!("20000|1|20000|{}", (0..20_000).sum::<i64>()));
+        assert_eq!(row.unwrap(), format!("{:0>200}", 12344));
+        assert_eq!(second.unwrap(), "1|after");
+    }
+
+    #[test]
+    fn empty_tables_are_valid() {
+        let path = database("empty", &[text_table("unused", [])]);
+        let checked = query(&path, "PRAGMA integrity_check;");
+        let count = query(&path, "SELECT count(*) FROM unused;");
+        std::fs::remove_file(&path).unwrap();
+        if let Some(checked) = checked {
+            assert_eq!(checked, "ok");
+            assert_eq!(count.unwrap(), "0");
+        }
+    }
+}
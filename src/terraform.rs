@@ -0,0 +1,364 @@
+//! `terraform`: infrastructure repositories as a model needs them. Terraform files (`.tf`,
+//! `.tfvars`) are selected whatever `allowed_extensions` says, always with their secrets
+//! redacted; `.terraform/` and state files are left out, since they hold downloaded providers
+//! and plain-text secrets; and `terraform.md` summarizes the providers, modules and
+//! resources the configuration uses.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::secrets;
+
+/// Output name of what `summarize` writes, outside of `concat`.
+pub const TERRAFORM_MD: &str = "terraform.md";
+
+/// Extensions of Terraform files.
+pub const EXTENSIONS: &[&str] = &["tf", "tfvars"];
+
+/// Directories Terraform writes for itself: downloaded providers and modules, and the states
+/// of workspaces.
+pub const IGNORED_DIRECTORIES: &[&str] = &[".terraform", "terraform.tfstate.d"];
+
+/// What a redacted value is replaced with.
+const REDACTED: &str = "\"<redacted>\"";
+
+/// Whether the file at `path` is a Terraform file.
+pub fn is_terraform(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| EXTENSIONS.iter().any(|e| extension.eq_ignore_ascii_case(e)))
+}
+
+/// Whether the file at `path` is a Terraform state or a backup of one, which hold every
+/// attribute of every resource in plain text, secrets included.
+pub fn is_state_file(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    name.ends_with(".tfstate") || name.ends_with(".tfstate.backup")
+}
+
+/// One line of a Terraform file, with how deep in blocks it starts.
+struct Line<'a> {
+    text: &'a str,
+    depth: usize,
+    /// Whether the line is inside a heredoc, and so not HCL.
+    heredoc: bool,
+}
+
+/// The lines of `text` with their depth, following braces outside strings, comments and
+/// heredocs.
+fn lines(text: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut depth: usize = 0;
+    let mut heredoc: Option<String> = None;
+    for text in text.lines() {
+        if let Some(marker) = &heredoc {
+            if text.trim() == marker {
+                heredoc = None;
+            }
+            lines.push(Line { text, depth, heredoc: true });
+            continue;
+        }
+        lines.push(Line { text, depth, heredoc: false });
+        let code = code(text);
+        for c in code.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        heredoc = heredoc_marker(&code);
+    }
+    lines
+}
+
+/// `line` without its comment and with the contents of its strings blanked out, to count
+/// braces and find `=` in.
+fn code(line: &str) -> String {
+    let mut code = String::new();
+    let mut in_string = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+                code.push(' ');
+            }
+            '"' => {
+                in_string = !in_string;
+                code.push(c);
+            }
+            _ if in_string => code.push(' '),
+            '#' => break,
+            '/' if chars.peek() == Some(&'/') => break,
+            _ => code.push(c),
+        }
+    }
+    code
+}
+
+/// The terminator of the heredoc `code` opens, `EOT` for `<<EOT` or `<<-EOT`.
+fn heredoc_marker(code: &str) -> Option<String> {
+    let start = code.find("<<")?;
+    let marker = code[start + 2..].trim_start_matches('-').trim();
+    (!marker.is_empty() && marker.chars().all(|c| c.is_alphanumeric() || c == '_')).then(|| marker.to_string())
+}
+
+/// `key` and where its value starts in `line`, for `key = value`; not for `==`.
+fn assignment(line: &str) -> Option<(String, usize)> {
+    let code = code(line);
+    let at = code.find('=')?;
+    if code[at + 1..].starts_with('=') || code[..at].ends_with(['!', '<', '>']) {
+        return None;
+    }
+    let key = line[..at].trim().trim_matches('"');
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    is_key.then(|| (key.to_string(), at + 1))
+}
+
+/// A block header such as `resource "aws_instance" "web" {`, or an empty block ending in
+/// `{}`: its type and labels.
+fn block_header(line: &str) -> Option<(&str, Vec<&str>)> {
+    let line = line.trim();
+    let header = line.strip_suffix("{}").or_else(|| line.strip_suffix('{'))?.trim_end();
+    let mut words = header.split_whitespace();
+    let kind = words.next()?;
+    if !kind.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    // labels are quoted, unlike the `=` of `tags = {`
+    let labels: Vec<&str> = words
+        .map(|label| label.strip_prefix('"').and_then(|label| label.strip_suffix('"')))
+        .collect::<Option<_>>()?;
+    Some((kind, labels))
+}
+
+/// The string value of `key = "value"` on `line`.
+fn string_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (found, at) = assignment(line)?;
+    let value = line[at..].trim().trim_end_matches(',');
+    (found == key).then(|| value.strip_prefix('"')?.strip_suffix('"')).flatten()
+}
+
+/// `text` with every literal value that may be a secret replaced by `"<redacted>"`: values
+/// of keys named like `password` or `token`, defaults of variables marked `sensitive`, and
+/// whatever `secrets::scan` finds, private keys and heredocs included. References such as
+/// `var.db_password` are kept, as they give nothing away.
+pub fn redact(path: &str, text: &str) -> String {
+    let lines = lines(text);
+    let flagged: Vec<usize> = secrets::scan(path, text).iter().map(|finding| finding.line - 1).collect();
+
+    // variables marked sensitive, by the range of their block
+    let mut sensitive = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.heredoc || line.depth != 0 || block_header(line.text).is_none_or(|(kind, _)| kind != "variable") {
+            continue;
+        }
+        let end = lines[i + 1..].iter().position(|later| later.depth == 0).map_or(lines.len(), |at| i + 1 + at);
+        let marked = lines[i + 1..end].iter().any(|inner| {
+            !inner.heredoc && assignment(inner.text).is_some_and(|(key, at)| key == "sensitive" && inner.text[at..].trim() == "true")
+        });
+        if marked {
+            sensitive.push(i..end);
+        }
+    }
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut skip_until: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(end) = skip_until {
+            if i <= end {
+                continue;
+            }
+            skip_until = None;
+        }
+        let assigned = if line.heredoc { None } else { assignment(line.text) };
+        let secret_key = assigned.as_ref().is_some_and(|(key, _)| {
+            secrets::is_secret_name(key) || (key == "default" && sensitive.iter().any(|range| range.contains(&i)))
+        });
+        let literal = assigned
+            .as_ref()
+            .map(|(_, at)| (*at, line.text[*at..].trim_start()))
+            .filter(|(_, value)| value.starts_with('"') && !value.starts_with("\"${") || value.starts_with("<<"));
+        match literal {
+            Some((at, value)) if secret_key || flagged.contains(&i) => {
+                writeln!(redacted, "{} {}", line.text[..at].trim_end(), REDACTED).ok();
+                if value.starts_with("<<") {
+                    // the heredoc ends at the first line after this one that is not in it
+                    let end = lines[i + 1..].iter().position(|later| !later.heredoc).map_or(lines.len(), |at| i + at);
+                    skip_until = Some(end);
+                }
+            }
+            _ if flagged.contains(&i) => {
+                let indent = &line.text[..line.text.len() - line.text.trim_start().len()];
+                writeln!(redacted, "{}{}", indent, REDACTED).ok();
+                if line.text.contains("-----BEGIN") {
+                    let end = lines[i..].iter().position(|later| later.text.contains("-----END")).map_or(i, |at| i + at);
+                    skip_until = Some(end);
+                }
+            }
+            _ => {
+                redacted.push_str(line.text);
+                redacted.push('\n');
+            }
+        }
+    }
+    if !text.ends_with('\n') {
+        redacted.pop();
+    }
+    redacted
+}
+
+#[derive(Default)]
+struct Provider {
+    source: Option<String>,
+    version: Option<String>,
+    /// Files with a `provider` block for it.
+    configured_in: Vec<String>,
+}
+
+struct Module {
+    name: String,
+    source: String,
+    version: Option<String>,
+    found_in: String,
+}
+
+/// `terraform.md`: the providers, backends, modules, resources and data sources of `files`,
+/// `(path, contents)` pairs of Terraform files.
+pub fn summarize(files: &[(String, String)]) -> String {
+    let mut providers: BTreeMap<String, Provider> = BTreeMap::new();
+    let mut backends: Vec<(String, String)> = Vec::new();
+    let mut modules: Vec<Module> = Vec::new();
+    let mut resources: BTreeMap<String, usize> = BTreeMap::new();
+    let mut data_sources: BTreeMap<String, usize> = BTreeMap::new();
+    let (mut variables, mut outputs) = (0, 0);
+
+    for (path, text) in files {
+        let lines = lines(text);
+        let mut block: Vec<String> = Vec::new();
+        let mut open_module: Option<Module> = None;
+        let mut open_provider: Option<String> = None;
+        for line in lines.iter().filter(|line| !line.heredoc) {
+            block.truncate(line.depth);
+            if line.depth == 0 {
+                if let Some(module) = open_module.take() {
+                    modules.push(module);
+                }
+                open_provider = None;
+            }
+
+            if let Some((kind, labels)) = block_header(line.text) {
+                match (line.depth, kind, labels.as_slice()) {
+                    (0, "resource", [kind, ..]) => *resources.entry(kind.to_string()).or_default() += 1,
+                    (0, "data", [kind, ..]) => *data_sources.entry(kind.to_string()).or_default() += 1,
+                    (0, "variable", _) => variables += 1,
+                    (0, "output", _) => outputs += 1,
+                    (0, "provider", [name, ..]) => {
+                        providers.entry(name.to_string()).or_default().configured_in.push(path.clone());
+                    }
+                    (0, "module", [name, ..]) => {
+                        open_module =
+                            Some(Module { name: name.to_string(), source: String::new(), version: None, found_in: path.clone() });
+                    }
+                    (1, "backend", [name, ..]) if block.first().is_some_and(|outer| outer == "terraform") => {
+                        backends.push((name.to_string(), path.clone()));
+                    }
+                    _ => {}
+                }
+                block.resize(line.depth, String::new());
+                block.push(kind.to_string());
+                continue;
+            }
+
+            let in_required_providers = block.len() >= 2 && block[0] == "terraform" && block[1] == "required_providers";
+            if let Some(module) = &mut open_module {
+                if line.depth == 1 {
+                    if let Some(source) = string_value(line.text, "source") {
+                        module.source = source.to_string();
+                    }
+                    if let Some(version) = string_value(line.text, "version") {
+                        module.version = Some(version.to_string());
+                    }
+                }
+            } else if in_required_providers && line.depth == 2 {
+                // `aws = { source = "hashicorp/aws", version = "~> 5.0" }`, `aws = {` or `aws = "~> 3.0"`
+                let Some((name, at)) = assignment(line.text) else {
+                    continue;
+                };
+                let value = line.text[at..].trim();
+                let provider = providers.entry(name.clone()).or_default();
+                if let Some(version) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+                    provider.version = Some(version.to_string());
+                } else if let Some(object) = value.strip_prefix('{') {
+                    for part in object.trim_end_matches('}').split(',') {
+                        set_provider_field(provider, part.trim());
+                    }
+                    open_provider = (!object.contains('}')).then_some(name);
+                }
+            } else if let (Some(name), true) = (&open_provider, in_required_providers && line.depth == 3) {
+                if let Some(provider) = providers.get_mut(name) {
+                    set_provider_field(provider, line.text.trim());
+                }
+            }
+        }
+        if let Some(module) = open_module.take() {
+            modules.push(module);
+        }
+    }
+
+    let mut document = format!("# Terraform\n\n{} files, {} variables, {} outputs.\n", files.len(), variables, outputs);
+    if !providers.is_empty() {
+        document.push_str("\n## Providers\n\n");
+        for (name, provider) in &providers {
+            let mut details = Vec::new();
+            if let Some(source) = &provider.source {
+                details.push(source.clone());
+            }
+            if let Some(version) = &provider.version {
+                details.push(format!("version {}", version));
+            }
+            if !provider.configured_in.is_empty() {
+                details.push(format!("configured in {}", provider.configured_in.join(", ")));
+            }
+            if details.is_empty() {
+                writeln!(document, "- `{}`", name).ok();
+            } else {
+                writeln!(document, "- `{}`: {}", name, details.join(", ")).ok();
+            }
+        }
+    }
+    if !backends.is_empty() {
+        document.push_str("\n## State\n\n");
+        for (backend, path) in &backends {
+            writeln!(document, "- `{}` backend ({})", backend, path).ok();
+        }
+    }
+    if !modules.is_empty() {
+        document.push_str("\n## Modules\n\n");
+        for module in &modules {
+            let version = module.version.as_ref().map(|version| format!(" version {}", version)).unwrap_or_default();
+            writeln!(document, "- `{}`: `{}`{} ({})", module.name, module.source, version, module.found_in).ok();
+        }
+    }
+    for (heading, counts) in [("Resources", &resources), ("Data sources", &data_sources)] {
+        if counts.is_empty() {
+            continue;
+        }
+        writeln!(document, "\n## {}\n", heading).ok();
+        for (kind, count) in counts {
+            writeln!(document, "- `{}` x{}", kind, count).ok();
+        }
+    }
+    document
+}
+
+/// Sets the `source` or `version` of `provider` from `part`, one `key = "value"` of its
+/// `required_providers` entry.
+fn set_provider_field(provider: &mut Provider, part: &str) {
+    if let Some(source) = string_value(part, "source") {
+        provider.source = Some(source.to_string());
+    }
+    if let Some(version) = string_value(part, "version") {
+        provider.version = Some(version.to_string());
+    }
+}
@@ -0,0 +1,293 @@
+//! `tokenizer`: token counts for open models such as Llama, Mistral and Qwen from their
+//! Hugging Face `tokenizer.json`, read from a path or downloaded once into the cache with
+//! `hf:<model-id>`. Byte-level BPE (Llama 3, Qwen, GPT-2) and SentencePiece-style BPE
+//! (Llama 2, Mistral) are supported. Text is split into words the way their usual
+//! pre-tokenizer patterns do rather than with each model's exact regex, which rarely changes
+//! a count.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::cache;
+use crate::tokens::Tokenize;
+
+const USER_AGENT: &str = concat!("lmprep/", env!("CARGO_PKG_VERSION"));
+const TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_TOKENIZER_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The `tokenizer` value that keeps the fast estimate of `tokens::estimate`, for when
+/// responsiveness matters more than exact counts.
+pub const APPROX: &str = "approx";
+
+/// Prefix of `tokenizer` values that name a model on the Hugging Face Hub.
+const HUB_PREFIX: &str = "hf:";
+
+/// Stands for a space in SentencePiece vocabularies.
+const METASPACE: char = '▁';
+
+/// Words are encoded in chunks of at most this many characters, as merging is quadratic in
+/// their length and minified files can hold very long ones.
+const MAX_WORD_CHARS: usize = 128;
+
+/// A BPE tokenizer read from a `tokenizer.json`.
+pub struct HfTokenizer {
+    vocab: HashMap<String, u32>,
+    /// The ids of every merged pair, with the rank of the merge and the id of the result.
+    merges: HashMap<(u32, u32), (usize, u32)>,
+    /// Whether words are encoded as bytes mapped to characters, rather than as characters
+    /// with `▁` for spaces.
+    byte_level: bool,
+    /// Whether characters not in the vocabulary become one token per UTF-8 byte.
+    byte_fallback: bool,
+    /// Counts of the words encoded so far; code repeats the same ones a lot.
+    counts: Mutex<HashMap<String, usize>>,
+}
+
+/// The tokenizer `spec` names: `hf:<model-id>` or the path of a `tokenizer.json`.
+pub fn load(spec: &str) -> Result<HfTokenizer> {
+    let tokenizer = match spec.strip_prefix(HUB_PREFIX) {
+        Some(model) => hub_tokenizer(model),
+        None => fs::read_to_string(spec)
+            .with_context(|| format!("Could not read {}", spec))
+            .and_then(|json| HfTokenizer::from_json(&json)),
+    };
+    tokenizer.with_context(|| format!("Could not load tokenizer {}", spec))
+}
+
+/// The tokenizer of `model` on the Hub, from the cache or downloaded into it. Gated models
+/// need an access token in `HF_TOKEN`.
+fn hub_tokenizer(model: &str) -> Result<HfTokenizer> {
+    let path = cache::path("tokenizers", &format!("{}.json", model.replace('/', "--")))?;
+    if let Ok(json) = fs::read_to_string(&path) {
+        return HfTokenizer::from_json(&json);
+    }
+
+    let url = format!("https://huggingface.co/{}/resolve/main/tokenizer.json", model);
+    let mut request = ureq::get(&url).set("User-Agent", USER_AGENT).timeout(TIMEOUT);
+    if let Ok(token) = env::var("HF_TOKEN") {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let response = request.call().with_context(|| format!("Could not download {}", url))?;
+    let mut json = String::new();
+    response.into_reader().take(MAX_TOKENIZER_BYTES).read_to_string(&mut json)?;
+    // only what parses is cached
+    let tokenizer = HfTokenizer::from_json(&json)?;
+    fs::write(&path, &json).with_context(|| format!("Could not write {}", path.display()))?;
+    Ok(tokenizer)
+}
+
+/// A token while merging, or a character the vocabulary does not have.
+enum Symbol {
+    Token(u32),
+    Unknown { bytes: usize },
+}
+
+/// The parts of a `tokenizer.json` that decide the count.
+#[derive(Deserialize)]
+struct TokenizerJson {
+    model: Model,
+    #[serde(default)]
+    pre_tokenizer: Option<Component>,
+    #[serde(default)]
+    decoder: Option<Component>,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    vocab: HashMap<String, u32>,
+    merges: Vec<Merge>,
+    #[serde(default)]
+    byte_fallback: bool,
+}
+
+/// `"a b"`, or `["a", "b"]` in newer files.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Merge {
+    Joined(String),
+    Pair(String, String),
+}
+
+/// A pre-tokenizer or decoder, and the steps of a `Sequence`.
+#[derive(Deserialize)]
+struct Component {
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    #[serde(default, alias = "pretokenizers", alias = "decoders")]
+    steps: Vec<Component>,
+}
+
+impl Component {
+    fn has(&self, kind: &str) -> bool {
+        self.kind.as_deref() == Some(kind) || self.steps.iter().any(|step| step.has(kind))
+    }
+}
+
+impl HfTokenizer {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: TokenizerJson = serde_json::from_str(json)?;
+        let model = file.model;
+        match model.kind.as_deref() {
+            Some("BPE") | None => {}
+            Some(other) => bail!("Only BPE tokenizers are supported, this one is {}", other),
+        }
+
+        let mut merges = HashMap::new();
+        for (rank, merge) in model.merges.iter().enumerate() {
+            let (left, right) = match merge {
+                Merge::Joined(merge) => merge.split_once(' ').unwrap_or((merge, "")),
+                Merge::Pair(left, right) => (left.as_str(), right.as_str()),
+            };
+            let ids = (model.vocab.get(left), model.vocab.get(right), model.vocab.get(&format!("{}{}", left, right)));
+            if let (Some(&left), Some(&right), Some(&merged)) = ids {
+                merges.entry((left, right)).or_insert((rank, merged));
+            }
+        }
+
+        let byte_level = [&file.pre_tokenizer, &file.decoder].into_iter().flatten().any(|part| part.has("ByteLevel"));
+        Ok(Self { vocab: model.vocab, merges, byte_level, byte_fallback: model.byte_fallback, counts: Mutex::new(HashMap::new()) })
+    }
+
+    /// Tokens of one word, as the vocabulary writes it.
+    fn count_word(&self, word: &str) -> usize {
+        if let Some(&count) = self.counts.lock().unwrap_or_else(PoisonError::into_inner).get(word) {
+            return count;
+        }
+        let chars: Vec<char> = word.chars().collect();
+        let count = chars.chunks(MAX_WORD_CHARS).map(|chunk| self.encode(chunk)).sum();
+        self.counts.lock().unwrap_or_else(PoisonError::into_inner).insert(word.to_string(), count);
+        count
+    }
+
+    /// Applies the merges to `chars` and returns how many tokens are left.
+    fn encode(&self, chars: &[char]) -> usize {
+        let mut symbols: Vec<Symbol> = chars
+            .iter()
+            .map(|c| match self.vocab.get(c.encode_utf8(&mut [0; 4]) as &str) {
+                Some(&id) => Symbol::Token(id),
+                None => Symbol::Unknown { bytes: c.len_utf8() },
+            })
+            .collect();
+        loop {
+            let best = symbols
+                .windows(2)
+                .enumerate()
+                .filter_map(|(i, pair)| match pair {
+                    [Symbol::Token(left), Symbol::Token(right)] => self.merges.get(&(*left, *right)).map(|&(rank, merged)| (rank, i, merged)),
+                    _ => None,
+                })
+                .min();
+            let Some((_, i, merged)) = best else {
+                break;
+            };
+            symbols[i] = Symbol::Token(merged);
+            symbols.remove(i + 1);
+        }
+        symbols
+            .iter()
+            .map(|symbol| match symbol {
+                Symbol::Unknown { bytes } if self.byte_fallback => *bytes,
+                _ => 1,
+            })
+            .sum()
+    }
+}
+
+impl Tokenize for HfTokenizer {
+    fn count(&self, text: &str) -> usize {
+        if self.byte_level {
+            let table = byte_chars();
+            words(text).map(|word| self.count_word(&word.bytes().map(|b| table[b as usize]).collect::<String>())).sum()
+        } else {
+            let text = format!("{}{}", METASPACE, text.replace(' ', &METASPACE.to_string()));
+            metaspace_words(&text).into_iter().map(|word| self.count_word(word)).sum()
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Class {
+    Space,
+    Letter,
+    Number,
+    Other,
+}
+
+fn class(c: char) -> Class {
+    if c.is_whitespace() {
+        Class::Space
+    } else if c.is_alphabetic() {
+        Class::Letter
+    } else if c.is_numeric() {
+        Class::Number
+    } else {
+        Class::Other
+    }
+}
+
+/// `text` split the way byte-level pre-tokenizers do: runs of letters or of punctuation with
+/// the space before them, numbers of up to three digits, and whitespace, less the space that
+/// goes with the word after it.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let end = |i: usize| chars.get(i).map_or(text.len(), |&(at, _)| at);
+    let mut bounds = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        if chars[i].1 == ' ' && chars.get(i + 1).is_some_and(|&(_, c)| matches!(class(c), Class::Letter | Class::Other)) {
+            i += 1;
+        }
+        let kind = class(chars[i].1);
+        let mut j = i + 1;
+        while j < chars.len() && class(chars[j].1) == kind && !(kind == Class::Number && j - i == 3) {
+            j += 1;
+        }
+        if kind == Class::Space && j < chars.len() && j - i > 1 && chars[j - 1].1 == ' ' {
+            j -= 1;
+        }
+        bounds.push((end(start), end(j)));
+        i = j;
+    }
+    bounds.into_iter().map(move |(start, end)| &text[start..end])
+}
+
+/// `text`, with spaces already `▁`, split before every run of `▁`.
+fn metaspace_words(text: &str) -> Vec<&str> {
+    let mut bounds = vec![0];
+    let mut previous = METASPACE;
+    for (at, c) in text.char_indices() {
+        if c == METASPACE && previous != METASPACE {
+            bounds.push(at);
+        }
+        previous = c;
+    }
+    bounds.push(text.len());
+    bounds.windows(2).map(|bound| &text[bound[0]..bound[1]]).collect()
+}
+
+/// GPT-2's mapping of bytes to printable characters, which byte-level vocabularies are
+/// written in.
+fn byte_chars() -> [char; 256] {
+    let mut table = ['\0'; 256];
+    let mut next = 256;
+    for (byte, slot) in table.iter_mut().enumerate() {
+        let printable = matches!(byte as u8, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff);
+        *slot = match printable {
+            true => char::from(byte as u8),
+            false => {
+                next += 1;
+                char::from_u32(next - 1).unwrap_or_default()
+            }
+        };
+    }
+    table
+}
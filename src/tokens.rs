@@ -0,0 +1,83 @@
+//! Token counts for prepared context: estimated by default, or counted with the vocabulary of
+//! a model the caller passes in, see `tokenizer`.
+
+use std::sync::Arc;
+
+/// Counts the tokens of text the way one model's tokenizer does.
+pub trait Tokenize: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Number of tokens `text` will use: counted by `tokenizer`, or without one estimated.
+pub fn count(tokenizer: Option<&dyn Tokenize>, text: &str) -> usize {
+    match tokenizer {
+        Some(tokenizer) => tokenizer.count(text),
+        None => estimate(text),
+    }
+}
+
+/// Number of tokens `text` will use, roughly estimated for BPE tokenizers such as OpenAI's, at
+/// about four characters per token for code and English prose and never less than one per
+/// word.
+pub fn estimate(text: &str) -> usize {
+    let chars = text.chars().count();
+    let words = text.split_whitespace().count();
+    chars.div_ceil(4).max(words)
+}
+
+/// The same count as `count` for text that arrives in pieces, such as a document while it is
+/// written. Only ASCII whitespace separates words.
+#[derive(Clone)]
+pub struct Counter {
+    chars: usize,
+    words: usize,
+    in_word: bool,
+    tokenizer: Option<Arc<dyn Tokenize>>,
+    /// Tokens the tokenizer counted so far.
+    counted: usize,
+    /// The end of the text since the last whitespace, which the next piece may continue.
+    pending: Vec<u8>,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Counter {
+    /// Counts with `tokenizer`, or estimates without one.
+    pub fn new(tokenizer: Option<Arc<dyn Tokenize>>) -> Self {
+        Self { chars: 0, words: 0, in_word: false, tokenizer, counted: 0, pending: Vec::new() }
+    }
+
+    pub fn add(&mut self, bytes: &[u8]) {
+        if let Some(tokenizer) = &self.tokenizer {
+            self.pending.extend_from_slice(bytes);
+            // whitespace starts the token after it, so the text is cut right before it
+            if let Some(at) = self.pending.iter().rposition(u8::is_ascii_whitespace).filter(|&at| at > 0) {
+                let done: Vec<u8> = self.pending.drain(..at).collect();
+                self.counted += tokenizer.count(&String::from_utf8_lossy(&done));
+            }
+            return;
+        }
+        for &byte in bytes {
+            // UTF-8 continuation bytes are part of the character before them
+            if byte & 0xc0 != 0x80 {
+                self.chars += 1;
+            }
+            let space = byte.is_ascii_whitespace();
+            if !space && !self.in_word {
+                self.words += 1;
+            }
+            self.in_word = !space;
+        }
+    }
+
+    pub fn estimate(&self) -> usize {
+        match &self.tokenizer {
+            Some(tokenizer) => self.counted + tokenizer.count(&String::from_utf8_lossy(&self.pending)),
+            None => self.chars.div_ceil(4).max(self.words),
+        }
+    }
+}
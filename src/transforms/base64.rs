@@ -0,0 +1,48 @@
+use serde::Deserialize;
+
+/// Options of the `base64` transform.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Base64Options {
+    /// Only encode files of up to this many bytes; larger ones are left as they are, and so
+    /// out of the text outputs.
+    pub max_bytes: u64,
+}
+
+impl Default for Base64Options {
+    fn default() -> Self {
+        Self { max_bytes: 16 * 1024 }
+    }
+}
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Characters per line of the encoding, as in MIME.
+const LINE_LENGTH: usize = 76;
+
+/// Whether `contents` are binary: they hold a NUL byte or are not UTF-8.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents.contains(&0) || std::str::from_utf8(contents).is_err()
+}
+
+/// `contents` in standard base64 with padding, in lines of `LINE_LENGTH` characters.
+pub fn encode(contents: &[u8]) -> String {
+    let mut encoded = Vec::with_capacity(contents.len().div_ceil(3) * 4);
+    for chunk in contents.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let group = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize]);
+            } else {
+                encoded.push(b'=');
+            }
+        }
+    }
+    let mut text = String::with_capacity(encoded.len() + encoded.len() / LINE_LENGTH + 1);
+    for line in encoded.chunks(LINE_LENGTH) {
+        text.extend(line.iter().map(|&c| c as char));
+        text.push('\n');
+    }
+    text
+}
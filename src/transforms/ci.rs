@@ -0,0 +1,96 @@
+use serde_yaml::Value;
+
+/// Lines of a `run` script shown before the rest are counted.
+const MAX_RUN_LINES: usize = 3;
+
+/// A GitHub Actions workflow cut down to what it does: its name and triggers, and per job
+/// where it runs, what it needs, its matrix and its steps, with `run` scripts shortened.
+/// `None` if `text` is not a workflow.
+pub fn summarize(text: &str) -> Option<String> {
+    let workflow: Value = serde_yaml::from_str(text).ok()?;
+    let jobs = workflow.get("jobs")?.as_mapping()?;
+
+    let mut summary = String::new();
+    if let Some(name) = workflow.get("name").and_then(Value::as_str) {
+        summary.push_str(&format!("workflow: {}\n", name));
+    }
+    if let Some(on) = workflow.get("on") {
+        summary.push_str(&format!("on: {}\n", triggers(on)));
+    }
+    for (id, job) in jobs {
+        let id = id.as_str().unwrap_or_default();
+        let name = job.get("name").and_then(Value::as_str).filter(|name| *name != id);
+        summary.push_str(&format!("\njob {}{}\n", id, name.map(|name| format!(" ({})", name)).unwrap_or_default()));
+        for key in ["runs-on", "needs", "if", "uses"] {
+            if let Some(value) = job.get(key) {
+                summary.push_str(&format!("  {}: {}\n", key, inline(value)));
+            }
+        }
+        if let Some(matrix) = job.get("strategy").and_then(|strategy| strategy.get("matrix")).and_then(Value::as_mapping) {
+            let axes: Vec<String> = matrix
+                .iter()
+                .filter_map(|(axis, values)| Some(format!("{} {}", axis.as_str()?, inline(values))))
+                .collect();
+            summary.push_str(&format!("  matrix: {}\n", axes.join(", ")));
+        }
+        for step in job.get("steps").and_then(Value::as_sequence).into_iter().flatten() {
+            summary.push_str(&format!("  - {}\n", describe(step)));
+        }
+    }
+    Some(summary)
+}
+
+/// `push (main), pull_request, schedule` from the `on` of a workflow.
+fn triggers(on: &Value) -> String {
+    match on {
+        Value::Mapping(events) => events
+            .iter()
+            .filter_map(|(event, filters)| {
+                let event = event.as_str()?;
+                let branches = filters.get("branches").map(|branches| match branches {
+                    Value::Sequence(names) => names.iter().map(inline).collect::<Vec<_>>().join(", "),
+                    other => inline(other),
+                });
+                Some(branches.map_or(event.to_string(), |branches| format!("{} ({})", event, branches)))
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => inline(other),
+    }
+}
+
+/// One step: `uses: actions/checkout@v4`, or its name and the start of its `run` script.
+fn describe(step: &Value) -> String {
+    let name = step.get("name").and_then(Value::as_str);
+    if let Some(uses) = step.get("uses").and_then(Value::as_str) {
+        return match name {
+            Some(name) => format!("{}: uses {}", name, uses),
+            None => format!("uses {}", uses),
+        };
+    }
+    let Some(run) = step.get("run").and_then(Value::as_str) else {
+        return name.unwrap_or("(step)").to_string();
+    };
+    let lines: Vec<&str> = run.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).collect();
+    let mut shown = lines.iter().take(MAX_RUN_LINES).copied().collect::<Vec<_>>().join("; ");
+    if lines.len() > MAX_RUN_LINES {
+        let more = lines.len() - MAX_RUN_LINES;
+        shown.push_str(&format!("; ... {} more line{}", more, if more == 1 { "" } else { "s" }));
+    }
+    match name {
+        Some(name) => format!("{}: run {}", name, shown),
+        None => format!("run {}", shown),
+    }
+}
+
+/// A scalar as it is, a list as `[a, b]`, and anything else as `...`.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::Sequence(items) => format!("[{}]", items.iter().map(inline).collect::<Vec<_>>().join(", ")),
+        Value::Null => String::new(),
+        _ => "...".to_string(),
+    }
+}
@@ -0,0 +1,198 @@
+//! Removes documentation (docstrings, doc comments, JSDoc blocks) while keeping the code it
+//! documents. Line based: it recognises where documentation starts from the line's opening,
+//! it does not parse the language.
+
+use std::borrow::Cow;
+
+/// How a language writes its documentation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Style {
+    /// `"""docstrings"""` as the first statement of a module, class or function
+    Python,
+    /// `/** ... */` blocks
+    Block,
+    /// `/** */` and `/*! */` blocks, `///` and `//!` lines
+    Rust,
+    /// `/** */` blocks and `///` lines
+    CSharp,
+    /// `//` comments directly above a declaration
+    Go,
+}
+
+fn style(language: &str) -> Option<Style> {
+    Some(match language {
+        "py" | "pyi" | "pyw" | "python" => Style::Python,
+        "rs" | "rust" => Style::Rust,
+        "cs" | "csharp" => Style::CSharp,
+        "go" => Style::Go,
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" | "javascript" | "typescript" | "java"
+        | "kt" | "kts" | "kotlin" | "scala" | "groovy" | "php" | "swift" | "dart" | "c" | "h" | "cpp" | "cc"
+        | "cxx" | "hpp" | "hh" | "m" | "mm" => Style::Block,
+        _ => return None,
+    })
+}
+
+/// `text` without its documentation, or `None` if `language` (an extension, or a fence
+/// language such as `python`) is not supported.
+pub fn strip(text: &str, language: &str) -> Option<String> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let output = match style(language)? {
+        Style::Python => python(&lines),
+        style => c_like(&lines, style)
+            .into_iter()
+            .zip(&lines)
+            .map(|(keep, line)| keep.then_some(Cow::Borrowed(*line)))
+            .collect(),
+    };
+
+    Some(output.into_iter().flatten().collect())
+}
+
+/// Finds the line that closes a block opened on line `start`, given what follows the opener
+/// on that line. Returns `None` if the block is never closed or code follows the closer.
+fn block_end(lines: &[&str], start: usize, after_opener: &str, closer: &str) -> Option<usize> {
+    let mut rest = after_opener;
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        if i > start {
+            rest = line;
+        }
+        if let Some(at) = rest.find(closer) {
+            return rest[at + closer.len()..].trim().is_empty().then_some(i);
+        }
+    }
+    None
+}
+
+/// Which lines of a C-like file to keep.
+fn c_like(lines: &[&str], style: Style) -> Vec<bool> {
+    let mut keep = vec![true; lines.len()];
+    let mut i = 0;
+    while i < lines.len() {
+        let trimmed = lines[i].trim();
+
+        let opener = ["/**", "/*!"]
+            .into_iter()
+            .filter(|opener| *opener == "/**" || style == Style::Rust)
+            .find(|opener| trimmed.starts_with(opener) && !trimmed.starts_with("/**/"));
+        if let Some(opener) = opener {
+            if let Some(end) = block_end(lines, i, &trimmed[opener.len()..], "*/") {
+                keep[i..=end].iter_mut().for_each(|k| *k = false);
+                i = end + 1;
+                continue;
+            }
+        }
+
+        let doc_line = match style {
+            Style::Rust => (trimmed.starts_with("///") && !trimmed.starts_with("////")) || trimmed.starts_with("//!"),
+            Style::CSharp => trimmed.starts_with("///"),
+            _ => false,
+        };
+        if doc_line {
+            keep[i] = false;
+        }
+
+        if style == Style::Go && is_go_comment(trimmed) {
+            let end = (i..lines.len()).find(|&j| !is_go_comment(lines[j].trim())).unwrap_or(lines.len());
+            if end < lines.len() && is_go_declaration(lines[end].trim()) {
+                keep[i..end].iter_mut().for_each(|k| *k = false);
+            }
+            i = end;
+            continue;
+        }
+
+        i += 1;
+    }
+    keep
+}
+
+/// `// text` lines, but not directives such as `//go:build`.
+fn is_go_comment(line: &str) -> bool {
+    line == "//" || line.starts_with("// ")
+}
+
+fn is_go_declaration(line: &str) -> bool {
+    ["func ", "type ", "var ", "const ", "package "].iter().any(|keyword| line.starts_with(keyword))
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// The quotes a docstring on this line opens with, after an optional `r`/`u` prefix.
+fn docstring_quotes(trimmed: &str) -> Option<&'static str> {
+    let unprefixed = trimmed.trim_start_matches(['r', 'R', 'u', 'U']);
+    if trimmed.len() - unprefixed.len() > 1 {
+        return None;
+    }
+    ["\"\"\"", "'''"].into_iter().find(|quotes| unprefixed.starts_with(quotes))
+}
+
+/// Code part of a Python line, without a trailing comment. Good enough for signatures,
+/// which rarely contain `#` inside strings.
+fn python_code(trimmed: &str) -> &str {
+    trimmed.split('#').next().unwrap_or_default().trim_end()
+}
+
+/// Which lines of a Python file to keep; a docstring that is the whole body of its def or
+/// class becomes `...` so the code stays valid.
+fn python<'a>(lines: &[&'a str]) -> Vec<Option<Cow<'a, str>>> {
+    let mut output: Vec<Option<Cow<str>>> = lines.iter().map(|line| Some(Cow::Borrowed(*line))).collect();
+    // indentation of the def or class whose docstring may come next; `None` at module level
+    let mut expecting: Option<Option<usize>> = Some(None);
+    // indentation and bracket depth of a def or class signature being read
+    let mut signature: Option<(usize, i32)> = None;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+
+        if let Some(owner) = expecting {
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                i += 1;
+                continue;
+            }
+            expecting = None;
+
+            if let Some(quotes) = docstring_quotes(trimmed) {
+                let after_opener = &trimmed[trimmed.find(quotes).unwrap_or_default() + quotes.len()..];
+                if let Some(end) = block_end(lines, i, after_opener, quotes) {
+                    output[i..=end].iter_mut().for_each(|line| *line = None);
+                    let next = lines[end + 1..].iter().find(|l| !l.trim().is_empty());
+                    if let Some(owner_indent) = owner {
+                        if next.is_none_or(|next| indent(next) <= owner_indent) {
+                            output[i] = Some(Cow::Owned(format!("{}...\n", &line[..indent(line)])));
+                        }
+                    }
+                    i = end + 1;
+                    continue;
+                }
+            }
+        }
+
+        let unprefixed = trimmed.strip_prefix("async ").unwrap_or(trimmed);
+        if signature.is_none() && (unprefixed.starts_with("def ") || unprefixed.starts_with("class ")) {
+            signature = Some((indent(line), 0));
+        }
+        if let Some((owner, depth)) = &mut signature {
+            let code = python_code(trimmed);
+            for c in code.chars() {
+                match c {
+                    '(' | '[' | '{' => *depth += 1,
+                    ')' | ']' | '}' => *depth -= 1,
+                    _ => {}
+                }
+            }
+            if *depth <= 0 {
+                // `def f(): return 1` has its body on the same line and no docstring
+                if code.ends_with(':') {
+                    expecting = Some(Some(*owner));
+                }
+                signature = None;
+            }
+        }
+
+        i += 1;
+    }
+    output
+}
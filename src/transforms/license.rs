@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use std::ops::Range;
+
+/// Options of the `license_headers` transform.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LicenseHeaderOptions {
+    /// Only strip a header once the same block opens at least this many of the files the rule
+    /// applies to, so a one-off comment is never lost.
+    pub min_files: usize,
+}
+
+impl Default for LicenseHeaderOptions {
+    fn default() -> Self {
+        Self { min_files: 2 }
+    }
+}
+
+const KEYWORDS: &[&str] = &["copyright", "license", "licence", "spdx-license-identifier"];
+
+/// The comment block `text` opens with, if it mentions a license or copyright: the range to
+/// cut (from after a shebang line through the blank lines following the block) and the
+/// block's lines, trimmed, for comparing it across files.
+pub fn leading_header(text: &str) -> Option<(Range<usize>, String)> {
+    let body_start = match text.starts_with("#!") {
+        true => text.find('\n').map_or(text.len(), |i| i + 1),
+        false => 0,
+    };
+
+    let mut offset = body_start;
+    let mut end = None;
+    let mut lines = Vec::new();
+    let mut in_block = false;
+    let mut after_block = false;
+    for line in text[body_start..].split_inclusive('\n') {
+        let trimmed = line.trim();
+        let is_comment = if after_block {
+            false
+        } else if in_block {
+            in_block = !(trimmed.contains("*/") || trimmed.contains("-->"));
+            true
+        } else if let Some(rest) = trimmed.strip_prefix("/*") {
+            in_block = !rest.contains("*/");
+            true
+        } else if let Some(rest) = trimmed.strip_prefix("<!--") {
+            in_block = !rest.contains("-->");
+            true
+        } else {
+            is_line_comment(trimmed)
+        };
+
+        if is_comment {
+            lines.push(trimmed);
+        } else if !trimmed.is_empty() {
+            break;
+        } else if !lines.is_empty() {
+            // blank lines after the block go with it, anything after them stays
+            after_block = true;
+        }
+        offset += line.len();
+        if !lines.is_empty() {
+            end = Some(offset);
+        }
+    }
+
+    let end = end?;
+    let block = lines.join("\n");
+    let lower = block.to_lowercase();
+    KEYWORDS
+        .iter()
+        .any(|keyword| lower.contains(keyword))
+        .then_some((body_start..end, block))
+}
+
+/// `//`, `--`, `;;` and `#` comments, but not directives such as `#include` or `#[derive]`.
+fn is_line_comment(line: &str) -> bool {
+    line.starts_with("//")
+        || line.starts_with("--")
+        || line.starts_with(";;")
+        || line == "#"
+        || line.starts_with("# ")
+        || line.starts_with("##")
+}
+
+/// `text` without the header range returned by `leading_header`.
+pub fn strip(text: &str, header: Range<usize>) -> String {
+    let mut stripped = String::with_capacity(text.len() - header.len());
+    stripped.push_str(&text[..header.start]);
+    stripped.push_str(&text[header.end..]);
+    stripped
+}
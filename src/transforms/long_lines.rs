@@ -0,0 +1,48 @@
+use serde::Deserialize;
+
+/// What `max_line_length` does to a longer line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LongLines {
+    /// Cut it, noting how many characters were left out
+    #[default]
+    Truncate,
+    /// Break it into lines of the maximum length, continuations marked
+    Wrap,
+}
+
+/// Marks where `wrap` broke a line, at the start of the continuation.
+const CONTINUATION: &str = "… ";
+
+/// `text` with every line longer than `max` characters truncated or wrapped, or `None` if
+/// no line is that long. Line endings are kept.
+pub fn limit_lines(text: &str, max: usize, mode: LongLines) -> Option<String> {
+    let max = max.max(1);
+    if !text.lines().any(|line| line.chars().nth(max).is_some()) {
+        return None;
+    }
+    let mut limited = String::with_capacity(text.len().min(text.lines().count() * (max + 32)));
+    for line in text.split_inclusive('\n') {
+        let (content, ending) = match line.strip_suffix("\r\n") {
+            Some(content) => (content, "\r\n"),
+            None => line.strip_suffix('\n').map_or((line, ""), |content| (content, "\n")),
+        };
+        let Some((cut, _)) = content.char_indices().nth(max) else {
+            limited.push_str(line);
+            continue;
+        };
+        match mode {
+            LongLines::Truncate => {
+                let rest = content[cut..].chars().count();
+                limited.push_str(&format!("{}… [{} more characters]{}", &content[..cut], rest, ending));
+            }
+            LongLines::Wrap => {
+                let chars: Vec<char> = content.chars().collect();
+                let pieces: Vec<String> = chars.chunks(max).map(|piece| piece.iter().collect()).collect();
+                limited.push_str(&pieces.join(&format!("\n{}", CONTINUATION)));
+                limited.push_str(ending);
+            }
+        }
+    }
+    Some(limited)
+}
@@ -0,0 +1,191 @@
+//! Content rewrites applied to selected files before they are written out, configured as a
+//! list of rules that each apply to the files matching a glob.
+
+mod base64;
+mod ci;
+mod docs;
+mod license;
+mod long_lines;
+mod notebook;
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobMatcher};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::language;
+
+pub use base64::Base64Options;
+pub use license::LicenseHeaderOptions;
+pub use long_lines::{limit_lines, LongLines};
+pub use notebook::NotebookOptions;
+
+/// How much of each file `learn_headers` needs to see.
+pub const HEADER_BYTES: u64 = 16 * 1024;
+
+/// One `transforms` entry: which files it applies to and what to do with them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransformRule {
+    /// Matched against the path relative to the source; `*.ipynb` matches at any depth.
+    pub glob: String,
+    /// Include binary files as base64 text, for the text outputs that would leave them out.
+    #[serde(default)]
+    pub base64: Option<Base64Options>,
+    /// Render Jupyter notebooks as code with their cells, instead of raw JSON.
+    #[serde(default)]
+    pub notebook: Option<NotebookOptions>,
+    /// Strip license and copyright comment blocks that open many of the matching files.
+    #[serde(default)]
+    pub license_headers: Option<LicenseHeaderOptions>,
+    /// Drop docstrings and doc comments, keeping the code they document.
+    #[serde(default)]
+    pub strip_docs: bool,
+    /// Replace GitHub Actions workflows with a summary of their triggers, jobs and steps.
+    #[serde(default)]
+    pub ci_summary: bool,
+    /// Prefix each line with the short hash and age of the commit that last changed it,
+    /// from `git blame`. Applied after the other rewrites, and only when reading from disk.
+    #[serde(default)]
+    pub blame: bool,
+}
+
+/// The rewritten contents of a file, and the fence language they should get if it differs
+/// from the file's own.
+pub struct Transformed {
+    pub contents: Vec<u8>,
+    pub language: Option<String>,
+}
+
+/// Compiled `transforms` rules.
+pub struct Transforms {
+    rules: Vec<(GlobMatcher, TransformRule)>,
+    /// License headers found by `learn_headers`, per rule.
+    headers: RwLock<Vec<HashSet<String>>>,
+}
+
+impl Transforms {
+    pub fn new(rules: &[TransformRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let glob = Glob::new(&rule.glob)
+                    .with_context(|| format!("Invalid transform glob {:?}", rule.glob))?;
+                Ok((glob.compile_matcher(), rule.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let headers = RwLock::new(vec![HashSet::new(); rules.len()]);
+        Ok(Self { rules, headers })
+    }
+
+    /// Whether `learn_headers` has to see the selected files before `apply` is called.
+    pub fn needs_headers(&self) -> bool {
+        self.rules.iter().any(|(_, rule)| rule.license_headers.is_some())
+    }
+
+    /// Finds the license headers repeated across the selected files, from the first
+    /// `HEADER_BYTES` of each file a `license_headers` rule applies to. Replaces what an
+    /// earlier call found.
+    pub fn learn_headers<'f>(&self, files: impl IntoIterator<Item = (&'f Path, &'f [u8])>) {
+        if !self.needs_headers() {
+            return;
+        }
+        let mut counts: Vec<HashMap<String, usize>> = vec![HashMap::new(); self.rules.len()];
+        for (relative, head) in files {
+            let Some((_, header)) = license::leading_header(&String::from_utf8_lossy(head)) else {
+                continue;
+            };
+            for (i, (glob, rule)) in self.rules.iter().enumerate() {
+                if rule.license_headers.is_some() && glob.is_match(relative) {
+                    *counts[i].entry(header.clone()).or_default() += 1;
+                }
+            }
+        }
+
+        let learned = self
+            .rules
+            .iter()
+            .zip(counts)
+            .map(|((_, rule), counts)| {
+                let min_files = rule.license_headers.as_ref().map_or(usize::MAX, |o| o.min_files.max(1));
+                counts.into_iter().filter(|&(_, n)| n >= min_files).map(|(header, _)| header).collect()
+            })
+            .collect();
+        *self.headers.write().unwrap_or_else(|e| e.into_inner()) = learned;
+    }
+
+    /// Whether any rule applies to the file at `relative`; files without one are copied as
+    /// they are.
+    pub fn applies_to(&self, relative: &Path) -> bool {
+        self.rules.iter().any(|(glob, _)| glob.is_match(relative))
+    }
+
+    /// Whether a rule asks for the file at `relative` to be annotated with `git blame`, which
+    /// the caller does after `apply`.
+    pub fn blames(&self, relative: &Path) -> bool {
+        self.rules.iter().any(|(glob, rule)| rule.blame && glob.is_match(relative))
+    }
+
+    /// Runs every matching rule over `contents` in order. Returns `None` if no rule applies.
+    pub fn apply(&self, relative: &Path, contents: &[u8]) -> Result<Option<Transformed>> {
+        let headers = self.headers.read().unwrap_or_else(|e| e.into_inner());
+        let mut result: Option<Transformed> = None;
+        for (i, (_, rule)) in self.rules.iter().enumerate().filter(|(_, (glob, _))| glob.is_match(relative)) {
+            let current = result.as_ref().map_or(contents, |r| &r.contents);
+            let mut language = result.as_ref().and_then(|r| r.language.clone());
+
+            let mut output = current.to_vec();
+            if let Some(options) = &rule.base64 {
+                if base64::is_binary(&output) {
+                    if output.len() as u64 <= options.max_bytes {
+                        output = base64::encode(&output).into_bytes();
+                        language = Some("base64".to_string());
+                    } else {
+                        eprintln!(
+                            "Warning: {} is over the base64 max_bytes of {}, left as binary",
+                            relative.display(),
+                            options.max_bytes
+                        );
+                    }
+                }
+            }
+            if let Some(options) = &rule.notebook {
+                let (rendered, notebook_language) = notebook::render(current, options)
+                    .with_context(|| format!("Could not read notebook {}", relative.display()))?;
+                output = rendered.into_bytes();
+                language = Some(notebook_language);
+            }
+            if rule.license_headers.is_some() {
+                if let Ok(text) = std::str::from_utf8(&output) {
+                    if let Some((range, header)) = license::leading_header(text) {
+                        if headers[i].contains(&header) {
+                            output = license::strip(text, range).into_bytes();
+                        }
+                    }
+                }
+            }
+            if rule.ci_summary {
+                if let Some(summary) = std::str::from_utf8(&output).ok().and_then(ci::summarize) {
+                    output = summary.into_bytes();
+                }
+            }
+            if rule.strip_docs {
+                let file_language = language.clone().or_else(|| {
+                    relative
+                        .extension()
+                        .map(|e| e.to_string_lossy().to_lowercase())
+                        .or_else(|| language::detect(&output).map(str::to_string))
+                });
+                if let (Some(file_language), Ok(text)) = (file_language, std::str::from_utf8(&output)) {
+                    if let Some(stripped) = docs::strip(text, &file_language) {
+                        output = stripped.into_bytes();
+                    }
+                }
+            }
+
+            result = Some(Transformed { contents: output, language });
+        }
+        Ok(result)
+    }
+}
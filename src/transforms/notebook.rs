@@ -0,0 +1,222 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// How notebook cells are rendered. Image and other rich outputs are always dropped.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotebookOptions {
+    /// What to do with markdown cells: keep them as comments or drop them.
+    pub markdown: MarkdownCells,
+    /// Head every code cell with `In[n]` from its execution count.
+    pub cell_numbers: bool,
+    /// Order code cells by execution count instead of their position in the notebook.
+    pub execution_order: bool,
+    /// Include text outputs (stdout, results, errors) of up to this many lines; longer ones
+    /// are cut. `0` drops all outputs.
+    pub max_output_lines: usize,
+}
+
+impl Default for NotebookOptions {
+    fn default() -> Self {
+        Self { markdown: MarkdownCells::Comment, cell_numbers: true, execution_order: false, max_output_lines: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownCells {
+    /// Keep markdown cells as comments between the code cells
+    Comment,
+    /// Leave markdown cells out
+    Drop,
+}
+
+#[derive(Deserialize)]
+struct Notebook {
+    cells: Vec<Cell>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+#[derive(Default, Deserialize)]
+struct Metadata {
+    #[serde(default)]
+    language_info: Option<LanguageInfo>,
+}
+
+#[derive(Deserialize)]
+struct LanguageInfo {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Cell {
+    cell_type: String,
+    #[serde(default)]
+    source: Text,
+    #[serde(default)]
+    execution_count: Option<u64>,
+    #[serde(default)]
+    outputs: Vec<Output>,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    output_type: String,
+    #[serde(default)]
+    text: Option<Text>,
+    #[serde(default)]
+    data: BTreeMap<String, Text>,
+    #[serde(default)]
+    ename: Option<String>,
+    #[serde(default)]
+    evalue: Option<String>,
+}
+
+/// Notebook text fields are either a string or a list of lines. Rich output data can be
+/// anything else, which is ignored.
+#[derive(Default, Deserialize)]
+#[serde(untagged)]
+enum Text {
+    #[default]
+    Empty,
+    Single(String),
+    Lines(Vec<String>),
+    Other(serde::de::IgnoredAny),
+}
+
+impl Text {
+    fn join(&self) -> String {
+        match self {
+            Text::Empty | Text::Other(_) => String::new(),
+            Text::Single(text) => text.clone(),
+            Text::Lines(lines) => lines.concat(),
+        }
+    }
+}
+
+/// Renders a notebook as source code of its kernel language, returning the code and the
+/// language's fence name.
+pub fn render(contents: &[u8], options: &NotebookOptions) -> Result<(String, String)> {
+    let notebook: Notebook = serde_json::from_slice(contents)?;
+    let language = notebook
+        .metadata
+        .language_info
+        .map(|info| info.name.to_lowercase())
+        .unwrap_or_else(|| "python".to_string());
+    let comment = comment_prefix(&language);
+
+    let mut cells: Vec<&Cell> = notebook.cells.iter().collect();
+    if options.execution_order {
+        // markdown travels with the code cell after it; cells that never ran go last
+        let mut keys = vec![u64::MAX; cells.len()];
+        let mut next = u64::MAX;
+        for (i, cell) in cells.iter().enumerate().rev() {
+            if cell.cell_type == "code" {
+                next = cell.execution_count.unwrap_or(u64::MAX);
+            }
+            keys[i] = next;
+        }
+        let mut keyed: Vec<_> = keys.into_iter().zip(cells).collect();
+        keyed.sort_by_key(|(key, _)| *key);
+        cells = keyed.into_iter().map(|(_, cell)| cell).collect();
+    }
+
+    let mut out = String::new();
+    for cell in cells {
+        let source = cell.source.join();
+        match cell.cell_type.as_str() {
+            "code" => {
+                if options.cell_numbers {
+                    let number = cell.execution_count.map(|n| n.to_string()).unwrap_or_default();
+                    out.push_str(&format!("{} In[{}]:\n", comment, number));
+                }
+                push_block(&mut out, &source);
+
+                let outputs = render_outputs(&cell.outputs, options.max_output_lines);
+                if !outputs.is_empty() {
+                    let number = cell.execution_count.map(|n| n.to_string()).unwrap_or_default();
+                    out.push_str(&format!("{} Out[{}]:\n", comment, number));
+                    push_block(&mut out, &commented(&outputs, comment));
+                }
+            }
+            "markdown" | "raw" if options.markdown == MarkdownCells::Comment => {
+                push_block(&mut out, &commented(&source, comment));
+            }
+            _ => {}
+        }
+    }
+
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    Ok((out, language))
+}
+
+/// Text of a cell's outputs, or nothing if they are longer than `max_lines` allows.
+fn render_outputs(outputs: &[Output], max_lines: usize) -> String {
+    if max_lines == 0 {
+        return String::new();
+    }
+
+    let mut text = String::new();
+    for output in outputs {
+        match output.output_type.as_str() {
+            "stream" => text.push_str(&output.text.as_ref().map(Text::join).unwrap_or_default()),
+            "execute_result" | "display_data" => {
+                // only plain text; images, HTML and widgets are dropped, and so is the text
+                // stand-in of an image such as `<Figure size 640x480>`
+                if output.data.keys().any(|mime| mime.starts_with("image/")) {
+                    continue;
+                }
+                if let Some(plain) = output.data.get("text/plain") {
+                    text.push_str(&plain.join());
+                }
+            }
+            "error" => text.push_str(&format!(
+                "{}: {}",
+                output.ename.as_deref().unwrap_or("Error"),
+                output.evalue.as_deref().unwrap_or_default()
+            )),
+            _ => {}
+        }
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= max_lines {
+        return text;
+    }
+    let mut cut = lines[..max_lines].join("\n");
+    cut.push_str(&format!("\n... ({} more lines)\n", lines.len() - max_lines));
+    cut
+}
+
+fn push_block(out: &mut String, block: &str) {
+    if block.trim().is_empty() {
+        return;
+    }
+    out.push_str(block);
+    if !block.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push('\n');
+}
+
+fn commented(text: &str, comment: &str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { comment.to_string() } else { format!("{} {}", comment, line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn comment_prefix(language: &str) -> &'static str {
+    match language {
+        "javascript" | "typescript" | "java" | "scala" | "kotlin" | "c" | "c++" | "c#" | "go" | "rust" | "swift" => "//",
+        "sql" | "haskell" | "lua" => "--",
+        "matlab" | "octave" => "%",
+        _ => "#",
+    }
+}
@@ -0,0 +1,62 @@
+use crate::flatten;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Renders the kept directories and files as an indented tree rooted at `.`. Directories are
+/// listed before files, each group sorted by name.
+pub fn render(source_path: &Path, directories: &[PathBuf], files: &[PathBuf]) -> String {
+    let mut children: BTreeMap<PathBuf, Vec<(bool, OsString)>> = BTreeMap::new();
+
+    let entries = directories
+        .iter()
+        .map(|d| (d, true))
+        .chain(files.iter().map(|f| (f, false)));
+    for (path, is_dir) in entries {
+        let relative = match path.strip_prefix(source_path) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        if let (Some(parent), Some(name)) = (relative.parent(), relative.file_name()) {
+            // sort key puts directories (false) before files (true)
+            children
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push((!is_dir, name.to_os_string()));
+        }
+    }
+    for entries in children.values_mut() {
+        entries.sort();
+    }
+
+    let mut result = String::from(".\n");
+    render_children(Path::new(""), "    ", &children, &mut result);
+    result
+}
+
+fn render_children(
+    dir: &Path,
+    prefix: &str,
+    children: &BTreeMap<PathBuf, Vec<(bool, OsString)>>,
+    result: &mut String,
+) {
+    let entries = match children.get(dir) {
+        Some(entries) => entries,
+        None => return,
+    };
+
+    for (i, (is_file, name)) in entries.iter().enumerate() {
+        let is_last = i == entries.len() - 1;
+        let display = flatten::escape_non_utf8(name);
+        result.push_str(&format!("{}{}{}\n",
+            prefix,
+            if is_last { "└── " } else { "├── " },
+            if *is_file { display } else { format!("{}/", display) }
+        ));
+
+        if !is_file {
+            let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+            render_children(&dir.join(name), &child_prefix, children, result);
+        }
+    }
+}
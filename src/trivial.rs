@@ -0,0 +1,76 @@
+//! `skip_trivial`: files that add a heading and tokens but no information, such as empty
+//! `__init__.py`s, and with `trivial`, files of only a license header or `"use strict";`.
+
+use anyhow::{Context, Result};
+use regex_automata::meta::Regex;
+
+use crate::html;
+
+/// Larger files are never trivial, and are not read to find out.
+pub const MAX_TRIVIAL_BYTES: u64 = 8192;
+
+/// Lines, trimmed, that say nothing about a file of their own.
+const BOILERPLATE: &[&str] = &[
+    r"^#!",
+    r#"^['"]use strict['"];?$"#,
+    r"^export \{\s*\};?$",
+    r"^package [\w.]+;?$",
+    r"^from __future__ import [\w, ()]+$",
+    r"^pass$",
+    r"^<\?php$",
+    r"^\?>$",
+    r"^---$",
+    r"^\{\s*\}$",
+    r"^\[\s*\]$",
+];
+
+/// Recognizes the lines of a trivial file.
+pub struct Boilerplate {
+    patterns: Vec<Regex>,
+}
+
+impl Boilerplate {
+    /// `BOILERPLATE` and the `trivial_patterns` of the config.
+    pub fn new(trivial_patterns: &[String]) -> Result<Self> {
+        let patterns = BOILERPLATE
+            .iter()
+            .copied()
+            .chain(trivial_patterns.iter().map(String::as_str))
+            .map(|pattern| Regex::new(pattern).with_context(|| format!("Invalid trivial_patterns entry {:?}", pattern)))
+            .collect::<Result<_>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// Whether every line of `text`, a file with `extension`, is blank, a comment or
+    /// boilerplate.
+    pub fn is_trivial(&self, text: &str, extension: &str) -> bool {
+        let syntax = html::syntax(extension);
+        let mut code = text.to_string();
+        if let Some((open, close)) = syntax.and_then(|syntax| syntax.block_comment) {
+            code = without_block_comments(&code, open, close);
+        }
+        let line_comments = syntax.map_or(&[][..], |syntax| syntax.line_comments);
+        code.lines().map(str::trim).all(|line| {
+            line.is_empty()
+                || line_comments.iter().any(|marker| line.starts_with(marker))
+                || self.patterns.iter().any(|pattern| pattern.is_match(line))
+        })
+    }
+}
+
+/// Whether `text` is empty or only whitespace.
+pub fn is_empty(text: &str) -> bool {
+    text.trim().is_empty()
+}
+
+/// `text` without the comments between `open` and `close`.
+fn without_block_comments(text: &str, open: &str, close: &str) -> String {
+    let mut code = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(open) {
+        code.push_str(&rest[..start]);
+        rest = rest[start + open.len()..].split_once(close).map_or("", |(_, after)| after);
+    }
+    code.push_str(rest);
+    code
+}
@@ -0,0 +1,131 @@
+//! `vendored`: third-party code checked into a repository, which is rarely what a model
+//! should read. Directories such as `vendor/` and `third_party/` are vendored by their name,
+//! and copied libraries by a license file of their own that differs from the source's.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Output name of what `summarize` writes, outside of `concat`.
+pub const VENDORED_MD: &str = "vendored.md";
+
+/// Names of directories that hold vendored code.
+pub const VENDOR_DIRECTORIES: &[&str] = &[
+    "vendor", "vendors", "third_party", "third-party", "thirdparty", "3rdparty", "node_modules", "bower_components",
+    "jspm_packages",
+];
+
+/// Names of the license file of a library, the first one found is read.
+const LICENSE_FILES: &[&str] = &[
+    "LICENSE", "LICENSE.md", "LICENSE.txt", "LICENCE", "LICENCE.md", "LICENCE.txt", "COPYING", "LICENSE-MIT",
+    "LICENSE-APACHE",
+];
+
+/// Words of a license shown, enough for its name and copyright line.
+const LICENSE_WORDS: usize = 12;
+
+/// Modules listed per directory before the rest are counted.
+const MAX_MODULES: usize = 20;
+
+/// The license file of the directory at `dir`, with its whitespace normalized so that copies
+/// with different line endings or wrapping compare equal.
+pub fn license(dir: &Path) -> Option<String> {
+    license_in(|name| fs::read_to_string(dir.join(name)).ok())
+}
+
+/// The license file of a directory whose files are looked up by name with `read`,
+/// normalized as by `license`.
+pub fn license_in(read: impl FnMut(&str) -> Option<String>) -> Option<String> {
+    LICENSE_FILES.iter().copied().find_map(read).map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Whether a directory named `name` is one of `VENDOR_DIRECTORIES`.
+pub fn is_vendor_directory(name: &str) -> bool {
+    VENDOR_DIRECTORIES.contains(&name.to_lowercase().as_str())
+}
+
+/// Whether the directory at `dir`, below the source, holds vendored code: it has one of
+/// `VENDOR_DIRECTORIES` as its name, or a license file that differs from `source_license`,
+/// the source's own. Nested repositories are left to `submodules`.
+pub fn is_vendored(dir: &Path, source_license: Option<&str>) -> bool {
+    if dir.file_name().is_some_and(|name| is_vendor_directory(&name.to_string_lossy())) {
+        return true;
+    }
+    if dir.join(".git").exists() {
+        return false;
+    }
+    license(dir).is_some_and(|license| source_license != Some(license.as_str()))
+}
+
+/// `vendored.md`: what each of `dirs` holds, relative to `source`: its size, license,
+/// version and, for Go's `vendor/`, the modules in it.
+pub fn summarize(source: &Path, dirs: &[PathBuf]) -> String {
+    let mut document = String::from("# Vendored code\n\nLeft out of the output as third-party code.\n");
+    for dir in dirs {
+        let relative = dir.strip_prefix(source).unwrap_or(dir);
+        let (files, bytes) = size(dir);
+        writeln!(document, "\n## {}\n\n{} files, {} bytes", relative.display(), files, bytes).ok();
+        if let Some(license) = license(dir) {
+            let words: Vec<&str> = license.split_whitespace().collect();
+            let more = if words.len() > LICENSE_WORDS { "..." } else { "" };
+            writeln!(document, "License: {}{}", words[..words.len().min(LICENSE_WORDS)].join(" "), more).ok();
+        }
+        if let Some(version) = version(dir) {
+            writeln!(document, "Version: {}", version).ok();
+        }
+        let modules = go_modules(dir);
+        if !modules.is_empty() {
+            writeln!(document, "Modules:").ok();
+            for module in modules.iter().take(MAX_MODULES) {
+                writeln!(document, "- {}", module).ok();
+            }
+            if modules.len() > MAX_MODULES {
+                writeln!(document, "- and {} more", modules.len() - MAX_MODULES).ok();
+            }
+        }
+    }
+    document
+}
+
+/// The number of files under `dir` and their total size.
+fn size(dir: &Path) -> (usize, u64) {
+    let mut total = (0, 0);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return total;
+    };
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let (files, bytes) = size(&entry.path());
+            total = (total.0 + files, total.1 + bytes);
+        } else if file_type.is_file() {
+            total = (total.0 + 1, total.1 + entry.metadata().map_or(0, |metadata| metadata.len()));
+        }
+    }
+    total
+}
+
+/// The version in the `package.json` or `Cargo.toml` at the top of `dir`.
+fn version(dir: &Path) -> Option<String> {
+    let manifests = [("package.json", "\"version\""), ("Cargo.toml", "version")];
+    manifests.iter().find_map(|(name, key)| {
+        let text = fs::read_to_string(dir.join(name)).ok()?;
+        text.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(key)?.trim_start().strip_prefix([':', '='])?;
+            Some(value.trim().trim_end_matches(',').trim_matches('"').to_string())
+        })
+    })
+}
+
+/// `github.com/pkg/errors v0.9.1` and the like from the `modules.txt` of a Go `vendor/`.
+fn go_modules(dir: &Path) -> Vec<String> {
+    let Ok(text) = fs::read_to_string(dir.join("modules.txt")) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| line.strip_prefix("# "))
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,225 @@
+//! Selection, naming and concatenation over files supplied in memory rather than read from
+//! disk. This is the part of lmprep that runs without a filesystem, e.g. in WebAssembly.
+
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::concat::ConcatWriter;
+use crate::config::{Config, VendoredCode};
+use crate::flatten::{self, Flattener};
+use crate::language;
+use crate::selection::{Gitignore, Selection};
+use crate::transforms::Transforms;
+use crate::tree;
+use crate::vendored;
+
+/// A file of the virtual tree; `path` is relative to its root and uses `/` separators.
+pub struct VirtualFile {
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+/// Every file the walker of `lm` would keep, sorted by path, together with its name in the
+/// output: the files `Selection` keeps outside vendored code, and the `.gitignore` files
+/// among `files` apply as they would on disk.
+pub fn select<'f>(files: &'f [VirtualFile], config: &Config) -> Result<Vec<(&'f VirtualFile, String)>> {
+    let selection = Selection::new(config)?;
+    let ignore_files = IgnoreFiles::new(files, config);
+    let vendored = match config.vendored {
+        VendoredCode::Include => HashSet::new(),
+        _ => vendored_dirs(files),
+    };
+    let flattener = Flattener::new(&config.delimiter, config.flatten_scheme, config.max_filename_length);
+
+    let mut selected: Vec<&VirtualFile> = files
+        .iter()
+        .filter(|file| {
+            let path = file.path.as_str();
+            directories(path).all(|dir| {
+                selection.keeps(dir, true) && !ignore_files.ignores(dir, true) && !vendored.contains(dir)
+            }) && selection.keeps(path, false)
+                && !ignore_files.ignores(path, false)
+                && config.allows_extension(extension(file).as_deref())
+                && config.allows_category(Path::new(path))
+                && selection.keeps_size(file.contents.len() as u64)
+                && selection.keeps_contents(&file.contents)
+        })
+        .collect();
+    selected.sort_by(|a, b| a.path.cmp(&b.path));
+    let relative: Vec<&Path> = selected.iter().map(|file| Path::new(&file.path)).collect();
+    let names = flatten::output_names(&relative, config, &flattener, HashSet::new());
+    Ok(selected.into_iter().zip(names).collect())
+}
+
+/// The directories `path` is in, outermost first.
+fn directories(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(move |(i, _)| &path[..i])
+}
+
+/// The `.ignore` and `.gitignore` files among the virtual files, with the directory each is
+/// in, deepest first.
+struct IgnoreFiles {
+    hidden: bool,
+    ignore: Vec<(String, Gitignore)>,
+    gitignore: Vec<(String, Gitignore)>,
+}
+
+impl IgnoreFiles {
+    /// `.gitignore` files apply with `respect_gitignore`; `.ignore` files and leaving out
+    /// hidden entries come with `standard_filters`, as with `ignore`'s walker.
+    fn new(files: &[VirtualFile], config: &Config) -> Self {
+        let mut ignore_files = Self { hidden: config.standard_filters, ignore: Vec::new(), gitignore: Vec::new() };
+        for file in files {
+            let (dir, name) = file.path.rsplit_once('/').unwrap_or(("", file.path.as_str()));
+            let list = match name {
+                ".ignore" if config.standard_filters => &mut ignore_files.ignore,
+                ".gitignore" if config.respect_gitignore => &mut ignore_files.gitignore,
+                _ => continue,
+            };
+            list.push((dir.to_string(), Gitignore::parse(&String::from_utf8_lossy(&file.contents))));
+        }
+        // of the files that apply to a path, the deeper ones have the longer directory
+        ignore_files.ignore.sort_by_key(|(dir, _)| Reverse(dir.len()));
+        ignore_files.gitignore.sort_by_key(|(dir, _)| Reverse(dir.len()));
+        ignore_files
+    }
+
+    /// Whether the entry at `path` is hidden or ignored. The deepest file with a pattern that
+    /// matches decides, and `.ignore` files take precedence over `.gitignore` files. Its
+    /// parents are not checked.
+    fn ignores(&self, path: &str, is_dir: bool) -> bool {
+        if self.hidden && path.rsplit('/').next().is_some_and(|name| name.starts_with('.')) {
+            return true;
+        }
+        let matched = |files: &Vec<(String, Gitignore)>| {
+            files.iter().find_map(|(dir, gitignore)| {
+                let relative = match dir.is_empty() {
+                    true => path,
+                    false => path.strip_prefix(dir.as_str())?.strip_prefix('/')?,
+                };
+                gitignore.matched(relative, is_dir)
+            })
+        };
+        matched(&self.ignore).or_else(|| matched(&self.gitignore)) == Some(true)
+    }
+}
+
+/// The directories among `files` that `vendored::is_vendored` would leave out, judged by the
+/// license files of the virtual tree.
+fn vendored_dirs(files: &[VirtualFile]) -> HashSet<&str> {
+    let contents: HashMap<&str, &[u8]> =
+        files.iter().map(|file| (file.path.as_str(), file.contents.as_slice())).collect();
+    let license = |dir: &str| {
+        vendored::license_in(|name| {
+            let path = match dir.is_empty() {
+                true => name.to_string(),
+                false => format!("{}/{}", dir, name),
+            };
+            contents.get(path.as_str()).map(|text| String::from_utf8_lossy(text).into_owned())
+        })
+    };
+    // nested repositories are left to `submodules`
+    let repositories: HashSet<&str> = files
+        .iter()
+        .flat_map(|file| directories(&file.path).filter(|dir| dir.ends_with("/.git")))
+        .map(|dir| &dir[..dir.len() - "/.git".len()])
+        .chain(files.iter().filter_map(|file| file.path.strip_suffix("/.git")))
+        .collect();
+    let source_license = license("");
+    files
+        .iter()
+        .flat_map(|file| directories(&file.path))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .filter(|dir| {
+            let name = dir.rsplit('/').next().unwrap_or_default();
+            vendored::is_vendor_directory(name)
+                || (!repositories.contains(dir)
+                    && license(dir).is_some_and(|license| source_license.as_deref() != Some(license.as_str())))
+        })
+        .collect()
+}
+
+/// The file's extension, or the one its content implies if it has none.
+fn extension(file: &VirtualFile) -> Option<String> {
+    match Path::new(&file.path).extension() {
+        Some(ext) => Some(ext.to_string_lossy().to_lowercase()),
+        None => language::detect(&file.contents).map(str::to_string),
+    }
+}
+
+/// Renders the file tree of the selected files.
+pub fn render_tree(files: &[VirtualFile], config: &Config) -> Result<String> {
+    let (directories, files) = tree_entries(&select(files, config)?);
+    Ok(tree::render(Path::new(""), &directories, &files))
+}
+
+/// Concatenates the selected files into a single markdown document, the same way `lm
+/// --concat` does.
+pub fn pack(files: &[VirtualFile], config: &Config) -> Result<String> {
+    let selected = select(files, config)?;
+    let mut writer = ConcatWriter::new(Vec::new()).with_language_map(&config.language_map);
+
+    if config.tree {
+        let (directories, files) = tree_entries(&selected);
+        writer.write_tree(&tree::render(Path::new(""), &directories, &files))?;
+    }
+    let transforms = Transforms::new(&config.transforms)?;
+    transforms.learn_headers(selected.iter().map(|(file, _)| (Path::new(&file.path), file.contents.as_slice())));
+    for (file, _) in &selected {
+        match transforms.apply(Path::new(&file.path), &file.contents)? {
+            Some(transformed) => {
+                writer.write_contents_as(&transformed.contents, &file.path, transformed.language.as_deref())?
+            }
+            None => writer.write_contents(&file.contents, &file.path)?,
+        };
+    }
+
+    Ok(String::from_utf8_lossy(&writer.finish()?).into_owned())
+}
+
+/// Files and every directory above them, as `tree::render` expects.
+fn tree_entries(selected: &[(&VirtualFile, String)]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let files: Vec<PathBuf> = selected.iter().map(|(f, _)| PathBuf::from(&f.path)).collect();
+    let mut directories: Vec<PathBuf> = files
+        .iter()
+        .flat_map(|f| f.ancestors().skip(1))
+        .filter(|d| !d.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .collect();
+    directories.sort();
+    directories.dedup();
+    (directories, files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, contents: &str) -> VirtualFile {
+        VirtualFile { path: path.to_string(), contents: contents.as_bytes().to_vec() }
+    }
+
+    #[test]
+    fn select_applies_the_walker_filters() {
+        let files = [
+            file("src/main.rs", "fn main() {}"),
+            file("src/gen/out.rs", "fn out() {}"),
+            file("src/key.rs", "const KEY: &str = \"secret\";"),
+            file("notes.log", "started"),
+            file(".gitignore", "*.log\n"),
+            file("vendor/lib/lib.rs", "fn lib() {}"),
+            file("deps/LICENSE", "MIT License"),
+            file("deps/dep.rs", "fn dep() {}"),
+        ];
+        let config = Config {
+            exclude: vec!["gen/".to_string()],
+            exclude_content_patterns: vec!["secret".to_string()],
+            ..Config::default()
+        };
+        let selected: Vec<&str> = select(&files, &config).unwrap().iter().map(|(file, _)| file.path.as_str()).collect();
+        assert_eq!(selected, ["src/main.rs"]);
+    }
+}